@@ -0,0 +1,486 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender,
+    },
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::vm::{OutputMonitor, VmInput};
+
+/// Starts every frame line, in both directions.
+pub const FRAME_MAGIC: &str = "VBXA1";
+pub const AGENT_VERSION: &str = "1";
+/// Frame id of the unsolicited `ready` frame the agent prints on startup.
+pub const READY_FRAME_ID: u64 = 0;
+/// The guest reads requests from a canonical-mode tty, which caps a line at 4095 bytes.
+const PUT_CHUNK_BYTES: usize = 1536;
+const SHORT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One protocol message: `VBXA1 <id> <kind> [key=base64]...` on a single line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub id: u64,
+    pub kind: String,
+    pub fields: BTreeMap<String, Vec<u8>>,
+}
+
+impl Frame {
+    pub fn new(id: u64, kind: &str) -> Self {
+        Self {
+            id,
+            kind: kind.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: impl AsRef<[u8]>) -> Self {
+        self.fields.insert(key.to_string(), value.as_ref().to_vec());
+        self
+    }
+
+    pub fn encode(&self) -> String {
+        let mut line = format!("{FRAME_MAGIC} {} {}", self.id, self.kind);
+        for (key, value) in &self.fields {
+            line.push(' ');
+            line.push_str(key);
+            line.push('=');
+            line.push_str(&STANDARD.encode(value));
+        }
+        line
+    }
+
+    /// Parses one console line; anything that is not a well-formed frame yields `None`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.trim_end_matches(['\r', '\n']).split(' ');
+        if parts.next()? != FRAME_MAGIC {
+            return None;
+        }
+        let id = parts.next()?.parse().ok()?;
+        let kind = parts.next().filter(|kind| !kind.is_empty())?.to_string();
+        let mut fields = BTreeMap::new();
+        for part in parts {
+            let (key, value) = part.split_once('=')?;
+            fields.insert(key.to_string(), STANDARD.decode(value).ok()?);
+        }
+        Some(Self { id, kind, fields })
+    }
+
+    fn bytes(&self, key: &str) -> Result<&[u8], AgentError> {
+        self.fields
+            .get(key)
+            .map(Vec::as_slice)
+            .ok_or_else(|| AgentError::Protocol(format!("'{}' frame without '{key}'", self.kind)))
+    }
+
+    fn text(&self, key: &str) -> Result<String, AgentError> {
+        Ok(String::from_utf8_lossy(self.bytes(key)?).into_owned())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Exec {
+        command: String,
+        cwd: Option<String>,
+    },
+    Put {
+        path: String,
+        data: Vec<u8>,
+        append: bool,
+        mode: Option<u32>,
+    },
+    Get {
+        path: String,
+    },
+    Ip,
+    Health,
+    /// Hands the console to an interactive root shell; the agent resumes when it exits.
+    Console,
+    Shutdown,
+}
+
+impl Request {
+    pub fn to_frame(&self, id: u64) -> Frame {
+        match self {
+            Request::Exec { command, cwd } => {
+                let frame = Frame::new(id, "exec").with("command", command);
+                match cwd {
+                    Some(cwd) => frame.with("cwd", cwd),
+                    None => frame,
+                }
+            }
+            Request::Put {
+                path,
+                data,
+                append,
+                mode,
+            } => {
+                let frame = Frame::new(id, "put")
+                    .with("path", path)
+                    .with("data", data)
+                    .with("append", if *append { "1" } else { "0" });
+                match mode {
+                    Some(mode) => frame.with("mode", format!("{mode:o}")),
+                    None => frame,
+                }
+            }
+            Request::Get { path } => Frame::new(id, "get").with("path", path),
+            Request::Ip => Frame::new(id, "ip"),
+            Request::Health => Frame::new(id, "health"),
+            Request::Console => Frame::new(id, "console"),
+            Request::Shutdown => Frame::new(id, "shutdown"),
+        }
+    }
+
+    pub fn from_frame(frame: &Frame) -> Result<Self, AgentError> {
+        let optional = |key: &str| frame.fields.contains_key(key).then(|| frame.text(key));
+        Ok(match frame.kind.as_str() {
+            "exec" => Request::Exec {
+                command: frame.text("command")?,
+                cwd: optional("cwd").transpose()?,
+            },
+            "put" => Request::Put {
+                path: frame.text("path")?,
+                data: frame.bytes("data")?.to_vec(),
+                append: optional("append").transpose()?.as_deref() == Some("1"),
+                mode: optional("mode")
+                    .transpose()?
+                    .map(|mode| u32::from_str_radix(&mode, 8))
+                    .transpose()
+                    .map_err(|err| AgentError::Protocol(format!("invalid mode: {err}")))?,
+            },
+            "get" => Request::Get {
+                path: frame.text("path")?,
+            },
+            "ip" => Request::Ip,
+            "health" => Request::Health,
+            "console" => Request::Console,
+            "shutdown" => Request::Shutdown,
+            other => return Err(AgentError::Protocol(format!("unknown request '{other}'"))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecOutput {
+    pub code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Ready { version: String },
+    Exit(ExecOutput),
+    Ok,
+    File { data: Vec<u8> },
+    Ip { addr: String },
+    Health { uptime_secs: f64, load: String },
+    Error { message: String },
+}
+
+impl Response {
+    pub fn to_frame(&self, id: u64) -> Frame {
+        match self {
+            Response::Ready { version } => Frame::new(id, "ready").with("version", version),
+            Response::Exit(output) => Frame::new(id, "exit")
+                .with("code", output.code.to_string())
+                .with("stdout", &output.stdout)
+                .with("stderr", &output.stderr),
+            Response::Ok => Frame::new(id, "ok"),
+            Response::File { data } => Frame::new(id, "file").with("data", data),
+            Response::Ip { addr } => Frame::new(id, "ip").with("addr", addr),
+            Response::Health { uptime_secs, load } => Frame::new(id, "health")
+                .with("uptime", uptime_secs.to_string())
+                .with("load", load),
+            Response::Error { message } => Frame::new(id, "error").with("message", message),
+        }
+    }
+
+    pub fn from_frame(frame: &Frame) -> Result<Self, AgentError> {
+        Ok(match frame.kind.as_str() {
+            "ready" => Response::Ready {
+                version: frame.text("version")?,
+            },
+            "exit" => Response::Exit(ExecOutput {
+                code: frame
+                    .text("code")?
+                    .trim()
+                    .parse()
+                    .map_err(|err| AgentError::Protocol(format!("invalid exit code: {err}")))?,
+                stdout: frame.bytes("stdout")?.to_vec(),
+                stderr: frame.bytes("stderr")?.to_vec(),
+            }),
+            "ok" => Response::Ok,
+            "file" => Response::File {
+                data: frame.bytes("data")?.to_vec(),
+            },
+            "ip" => Response::Ip {
+                addr: frame.text("addr")?.trim().to_string(),
+            },
+            "health" => Response::Health {
+                uptime_secs: frame
+                    .text("uptime")?
+                    .trim()
+                    .parse()
+                    .map_err(|err| AgentError::Protocol(format!("invalid uptime: {err}")))?,
+                load: frame.text("load")?.trim().to_string(),
+            },
+            "error" => Response::Error {
+                message: frame.text("message")?,
+            },
+            other => return Err(AgentError::Protocol(format!("unknown response '{other}'"))),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AgentError {
+    #[error("Guest agent request ({request}) timed out after {timeout:?}")]
+    Timeout { request: String, timeout: Duration },
+    #[error("Guest agent error: {0}")]
+    Remote(String),
+    #[error("Guest agent protocol error: {0}")]
+    Protocol(String),
+    #[error("VM console closed while waiting for the guest agent")]
+    ConsoleClosed,
+}
+
+/// Input that asks a running agent to power the guest off; the reply is not awaited.
+pub fn shutdown_input() -> VmInput {
+    VmInput::Bytes(encode_line(&Request::Shutdown.to_frame(u64::MAX)))
+}
+
+/// Input that asks a running agent to give the console to a rescue shell; not awaited either.
+pub fn console_input() -> VmInput {
+    VmInput::Bytes(encode_line(&Request::Console.to_frame(u64::MAX)))
+}
+
+fn encode_line(frame: &Frame) -> Vec<u8> {
+    let mut line = frame.encode();
+    line.push('\n');
+    line.into_bytes()
+}
+
+/// Talks to the guest agent over the console. Requests are answered in order, so callers
+/// must not share one console between concurrent clients.
+pub struct AgentClient<'a> {
+    output_monitor: &'a OutputMonitor,
+    input_tx: &'a Sender<VmInput>,
+    next_id: AtomicU64,
+}
+
+impl<'a> AgentClient<'a> {
+    pub fn new(output_monitor: &'a OutputMonitor, input_tx: &'a Sender<VmInput>) -> Self {
+        Self {
+            output_monitor,
+            input_tx,
+            next_id: AtomicU64::new(READY_FRAME_ID + 1),
+        }
+    }
+
+    pub fn request(&self, request: &Request, timeout: Duration) -> Result<Response, AgentError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let frame = request.to_frame(id);
+        self.input_tx
+            .send(VmInput::Bytes(encode_line(&frame)))
+            .map_err(|_| AgentError::ConsoleClosed)?;
+
+        let reply = self
+            .output_monitor
+            .wait_until(timeout, |buf| take_frame(buf, id));
+        match reply {
+            Some(frame) => match Response::from_frame(&frame)? {
+                Response::Error { message } => Err(AgentError::Remote(message)),
+                response => Ok(response),
+            },
+            None if self.output_monitor.is_closed() => Err(AgentError::ConsoleClosed),
+            None => Err(AgentError::Timeout {
+                request: frame.kind,
+                timeout,
+            }),
+        }
+    }
+
+    pub fn exec(&self, command: &str, timeout: Duration) -> Result<ExecOutput, AgentError> {
+        let request = Request::Exec {
+            command: command.to_string(),
+            cwd: None,
+        };
+        match self.request(&request, timeout)? {
+            Response::Exit(output) => Ok(output),
+            other => Err(unexpected("exec", &other)),
+        }
+    }
+
+    /// Writes `data` to `path` in the guest, split into frames that fit a tty line.
+    pub fn put(&self, path: &str, data: &[u8], mode: Option<u32>) -> Result<(), AgentError> {
+        let mut chunks: Vec<&[u8]> = data.chunks(PUT_CHUNK_BYTES).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let last = chunks.len() - 1;
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let request = Request::Put {
+                path: path.to_string(),
+                data: chunk.to_vec(),
+                append: index > 0,
+                mode: if index == last { mode } else { None },
+            };
+            match self.request(&request, SHORT_REQUEST_TIMEOUT)? {
+                Response::Ok => {}
+                other => return Err(unexpected("put", &other)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, path: &str) -> Result<Vec<u8>, AgentError> {
+        let request = Request::Get {
+            path: path.to_string(),
+        };
+        match self.request(&request, SHORT_REQUEST_TIMEOUT)? {
+            Response::File { data } => Ok(data),
+            other => Err(unexpected("get", &other)),
+        }
+    }
+
+    pub fn ip(&self) -> Result<String, AgentError> {
+        match self.request(&Request::Ip, SHORT_REQUEST_TIMEOUT)? {
+            Response::Ip { addr } => Ok(addr),
+            other => Err(unexpected("ip", &other)),
+        }
+    }
+
+    /// Returns the guest uptime in seconds and its load averages.
+    pub fn health(&self) -> Result<(f64, String), AgentError> {
+        match self.request(&Request::Health, SHORT_REQUEST_TIMEOUT)? {
+            Response::Health { uptime_secs, load } => Ok((uptime_secs, load)),
+            other => Err(unexpected("health", &other)),
+        }
+    }
+
+    pub fn shutdown(&self) -> Result<(), AgentError> {
+        match self.request(&Request::Shutdown, SHORT_REQUEST_TIMEOUT) {
+            Ok(Response::Ok) | Err(AgentError::ConsoleClosed) => Ok(()),
+            Ok(other) => Err(unexpected("shutdown", &other)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn unexpected(request: &str, response: &Response) -> AgentError {
+    AgentError::Protocol(format!("unexpected reply to {request}: {response:?}"))
+}
+
+/// Removes everything up to and including the first complete frame with `id`.
+fn take_frame(buf: &mut String, id: u64) -> Option<Frame> {
+    let mut start = 0;
+    while let Some(offset) = buf[start..].find('\n') {
+        let end = start + offset + 1;
+        if let Some(frame) = Frame::parse(&buf[start..end]).filter(|frame| frame.id == id) {
+            buf.drain(..end);
+            return Some(frame);
+        }
+        start = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread};
+
+    #[test]
+    fn frames_round_trip_binary_fields() {
+        let frame = Frame::new(7, "put")
+            .with("path", "/tmp/a b")
+            .with("data", [0u8, 10, 13, 255]);
+
+        let parsed = Frame::parse(&format!("{}\r\n", frame.encode())).unwrap();
+
+        assert_eq!(parsed, frame);
+        assert_eq!(Frame::parse("echo VBXA1 1 ok"), None);
+        assert_eq!(Frame::parse("VBXA1 x ok"), None);
+        assert_eq!(Frame::parse("VBXA1 1 ok data=%%%"), None);
+    }
+
+    #[test]
+    fn requests_and_responses_survive_encoding() {
+        let requests = [
+            Request::Exec {
+                command: "echo hi".into(),
+                cwd: Some("/root".into()),
+            },
+            Request::Put {
+                path: "/etc/motd".into(),
+                data: b"hello\n".to_vec(),
+                append: true,
+                mode: Some(0o644),
+            },
+            Request::Shutdown,
+        ];
+        for request in requests {
+            assert_eq!(Request::from_frame(&request.to_frame(3)).unwrap(), request);
+        }
+        let response = Response::Exit(ExecOutput {
+            code: 2,
+            stdout: b"out".to_vec(),
+            stderr: Vec::new(),
+        });
+        assert_eq!(
+            Response::from_frame(&response.to_frame(3)).unwrap(),
+            response
+        );
+    }
+
+    #[test]
+    fn client_skips_noise_and_other_frames() {
+        let monitor = OutputMonitor::default();
+        let (tx, rx) = mpsc::channel();
+        let guest_console = &monitor;
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let VmInput::Bytes(bytes) = rx.recv().unwrap() else {
+                    panic!("expected a request");
+                };
+                let request = Frame::parse(&String::from_utf8(bytes).unwrap()).unwrap();
+                assert_eq!(request.kind, "ip");
+                let stale = Response::Ok.to_frame(request.id + 40).encode();
+                let reply = Response::Ip {
+                    addr: "10.0.0.2".into(),
+                }
+                .to_frame(request.id)
+                .encode();
+                guest_console.push(format!("[  3.1] eth0 up\r\n{stale}\r\n{reply}\r\n").as_bytes());
+            });
+
+            let client = AgentClient::new(&monitor, &tx);
+            assert_eq!(client.ip().unwrap(), "10.0.0.2");
+        });
+    }
+
+    #[test]
+    fn remote_errors_and_timeouts_surface() {
+        let monitor = OutputMonitor::default();
+        let (tx, _rx) = mpsc::channel();
+        let client = AgentClient::new(&monitor, &tx);
+        let error = Response::Error {
+            message: "cannot read /nope".into(),
+        };
+        monitor.push(format!("{}\r\n", error.to_frame(1).encode()).as_bytes());
+
+        let err = client.get("/nope").unwrap_err();
+        assert!(matches!(err, AgentError::Remote(ref message) if message.contains("/nope")));
+
+        let err = client
+            .request(&Request::Health, Duration::from_millis(50))
+            .unwrap_err();
+        assert!(matches!(err, AgentError::Timeout { .. }), "got {err}");
+    }
+}
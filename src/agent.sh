@@ -0,0 +1,133 @@
+#!/bin/bash
+# vibebox guest agent: answers framed requests typed into the serial console.
+# Every frame is one line: MAGIC ID KIND [KEY=BASE64]...
+set -u
+
+MAGIC="__AGENT_MAGIC__"
+VERSION="__AGENT_VERSION__"
+WORK_DIR="$(mktemp -d /tmp/vibebox-agent.XXXXXX)"
+
+# Requests are typed into the tty; echoing them back would only add noise.
+stty -echo 2>/dev/null || true
+
+declare -A FIELDS
+
+b64() { base64 -w0; }
+field() { printf '%s=%s' "$1" "$(printf '%s' "$2" | b64)"; }
+field_file() { printf '%s=%s' "$1" "$(b64 <"$2")"; }
+val() { printf '%s' "${FIELDS[$1]:-}" | base64 -d 2>/dev/null; }
+
+emit() {
+  local id="$1" kind="$2"
+  shift 2
+  printf '\n%s %s %s' "$MAGIC" "$id" "$kind"
+  for f in "$@"; do
+    printf ' %s' "$f"
+  done
+  printf '\n'
+}
+
+fail() { emit "$1" error "$(field message "$2")"; }
+
+handle() {
+  local id="$1" kind="$2"
+  case "$kind" in
+    exec)
+      local cmd cwd code
+      cmd="$(val command)"
+      cwd="$(val cwd)"
+      (
+        if [ -n "$cwd" ]; then cd "$cwd" || exit 126; fi
+        exec bash -c "$cmd"
+      ) </dev/null >"$WORK_DIR/stdout" 2>"$WORK_DIR/stderr"
+      code=$?
+      emit "$id" exit "$(field code "$code")" \
+        "$(field_file stdout "$WORK_DIR/stdout")" "$(field_file stderr "$WORK_DIR/stderr")"
+      ;;
+    put)
+      local path mode
+      path="$(val path)"
+      mode="$(val mode)"
+      if ! mkdir -p "$(dirname "$path")"; then
+        fail "$id" "cannot create parent of $path"
+        return
+      fi
+      if [ "$(val append)" = "1" ]; then
+        val data >>"$path"
+      else
+        val data >"$path"
+      fi || { fail "$id" "cannot write $path"; return; }
+      if [ -n "$mode" ] && ! chmod "$mode" "$path"; then
+        fail "$id" "cannot chmod $path"
+        return
+      fi
+      emit "$id" ok
+      ;;
+    get)
+      local path
+      path="$(val path)"
+      if [ -f "$path" ] && [ -r "$path" ]; then
+        emit "$id" file "$(field_file data "$path")"
+      else
+        fail "$id" "cannot read $path"
+      fi
+      ;;
+    ip)
+      local addr
+      addr="$(ip -4 -o addr show scope global 2>/dev/null | awk '{print $4}' | cut -d/ -f1 | head -n 1)"
+      if [ -n "$addr" ]; then
+        emit "$id" ip "$(field addr "$addr")"
+      else
+        fail "$id" "no global IPv4 address"
+      fi
+      ;;
+    health)
+      local uptime load
+      read -r uptime _ </proc/uptime
+      load="$(cut -d' ' -f1-3 /proc/loadavg)"
+      emit "$id" health "$(field uptime "$uptime")" "$(field load "$load")"
+      ;;
+    console)
+      emit "$id" ok
+      stty echo 2>/dev/null || true
+      bash -il
+      stty -echo 2>/dev/null || true
+      emit 0 ready "$(field version "$VERSION")"
+      ;;
+    shutdown)
+      emit "$id" ok
+      systemctl poweroff
+      exit 0
+      ;;
+    *)
+      fail "$id" "unknown request: $kind"
+      ;;
+  esac
+}
+
+emit 0 ready "$(field version "$VERSION")"
+
+while IFS= read -r line; do
+  line="${line%$'\r'}"
+  case "$line" in
+    "$MAGIC "*) ;;
+    # A keystroke shutdown can still arrive if the host had not seen the ready frame yet.
+    "systemctl poweroff")
+      systemctl poweroff
+      exit 0
+      ;;
+    *) continue ;;
+  esac
+  set -f
+  # shellcheck disable=SC2086
+  set -- $line
+  set +f
+  id="$2"
+  kind="$3"
+  shift 3
+  FIELDS=()
+  for kv in "$@"; do
+    FIELDS["${kv%%=*}"]="${kv#*=}"
+  done
+  handle "$id" "$kind"
+done
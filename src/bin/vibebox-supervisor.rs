@@ -1,5 +1,5 @@
 use std::{
-    env,
+    env, fs,
     io::{self, IsTerminal},
 };
 
@@ -9,7 +9,7 @@ use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt;
 use tracing_subscriber::prelude::*;
 
-use vibebox::{config, instance, vm, vm_manager};
+use vibebox::{Sandbox, instance, vm_manager};
 
 fn main() -> Result<()> {
     if env::var("VIBEBOX_INTERNAL").as_deref() != Ok("1") {
@@ -22,22 +22,24 @@ fn main() -> Result<()> {
 
     tracing::info!("starting vm supervisor");
     let cwd = env::current_dir().map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
-    let config = config::load_config(&cwd);
-    let instance_dir = instance::ensure_instance_dir(&cwd)
+    let sandbox = Sandbox::builder(&cwd)
+        .build()
         .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
-    let _ = instance::touch_last_active(&instance_dir);
-    let args = vm::VmArg {
-        cpu_count: config.box_cfg.cpu_count,
-        ram_bytes: config.box_cfg.ram_mb.saturating_mul(1024 * 1024),
-        disk_bytes: config.box_cfg.disk_gb.saturating_mul(1024 * 1024 * 1024),
-        no_default_mounts: false,
-        mounts: config.box_cfg.mounts.clone(),
-    };
+    let config = sandbox.config();
+    // An ephemeral instance is not in the session list and is gone once the manager exits.
+    let (instance_dir, ephemeral) = vm_manager::manager_instance_dir(&cwd);
+    if !ephemeral {
+        fs::create_dir_all(&instance_dir)
+            .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
+        let _ = instance::touch_last_active(&instance_dir);
+    }
     let auto_shutdown_ms = config.supervisor.auto_shutdown_ms;
-    tracing::info!(auto_shutdown_ms, "vm supervisor config");
+    tracing::info!(auto_shutdown_ms, ephemeral, "vm supervisor config");
 
-    let result = vm_manager::run_manager(args, auto_shutdown_ms);
-    let _ = instance::touch_last_active(&instance_dir);
+    let result = vm_manager::run_manager(&cwd, sandbox.vm_args(), auto_shutdown_ms);
+    if !ephemeral {
+        let _ = instance::touch_last_active(&instance_dir);
+    }
     if let Err(err) = result {
         tracing::error!(error = %err, "vm supervisor exited");
         return Err(color_eyre::eyre::eyre!(err.to_string()));
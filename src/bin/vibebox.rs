@@ -1,24 +1,25 @@
 use std::{
-    env,
-    ffi::OsString,
-    fs,
+    env, fs,
     io::{self, IsTerminal, Write},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use clap::Parser;
-use color_eyre::Result;
+use color_eyre::{Report, Result, Section};
 use dialoguer::Confirm;
-use time::OffsetDateTime;
-use time::format_description::well_known::Rfc3339;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::registry::Registry;
 use tracing_subscriber::{EnvFilter, fmt, prelude::*, reload};
 
+use vibebox::error::{ErrorCode, ErrorReport};
+use vibebox::explain::ExplainFormat;
+use vibebox::logs::LogKind;
 use vibebox::tui::{AppState, VmInfo};
 use vibebox::{
-    SessionManager, commands, config, explain, instance, session_manager, tui, vm, vm_manager,
+    Sandbox, SandboxBuilder, SessionManager, VibeboxError, commands, config, console, daemon,
+    dashboard, explain, hooks, instance, logs, overlay, session_manager, ssh_config, tui, vm,
+    vm_manager,
 };
 
 #[derive(Debug, Parser)]
@@ -27,6 +28,12 @@ struct Cli {
     /// Path to vibebox.toml (relative to the current directory)
     #[arg(short = 'c', long = "config", value_name = "PATH", global = true)]
     config: Option<PathBuf>,
+    /// Print errors as JSON with a stable code and hints (also `explain` and `diff` output)
+    #[arg(long, global = true)]
+    json: bool,
+    /// Use this named session of the project, with its own disk, IP and manager
+    #[arg(long, value_name = "NAME")]
+    session: Option<String>,
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -35,72 +42,188 @@ struct Cli {
 enum Command {
     /// List all sessions
     List,
+    /// Live dashboard of all sessions: attach, stop, restart, tail logs or delete
+    Top,
     /// Reset the current project's .vibebox directory
     Reset,
     /// Purge the global cache directory
     PurgeCache,
-    /// Explain mounts and mappings
-    Explain,
+    /// Explain mounts, network, storage, resources and security
+    Explain {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExplainFormat::Table)]
+        format: ExplainFormat,
+        /// Compare against the running VM's mounts, ports, routes and sudo
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Attach to the running VM's serial console (root shell rescue path)
+    Console {
+        /// Key sequence that detaches, e.g. `ctrl-]` or `ctrl-p,ctrl-q`
+        #[arg(long, value_name = "KEYS", default_value = console::DEFAULT_DETACH_KEYS)]
+        detach_keys: String,
+    },
+    /// Show the VM logs of this project or of another session
+    Logs {
+        /// Which log to show
+        #[arg(value_enum, default_value_t = LogKind::Console)]
+        kind: LogKind,
+        /// Keep printing as the log grows
+        #[arg(short, long)]
+        follow: bool,
+        /// Boot to show: 0 is the latest, -1 the one before, and so on
+        #[arg(
+            long,
+            default_value_t = 0,
+            allow_negative_numbers = true,
+            value_parser = clap::value_parser!(i64).range(-(logs::LOG_HISTORY as i64 - 1)..=0)
+        )]
+        boot: i64,
+        /// Read another session's logs: id, id prefix or name (`project` or `project/NAME`) from
+        /// `vibebox list`
+        #[arg(long, value_name = "SESSION")]
+        from: Option<String>,
+    },
+    /// Print an OpenSSH `Host vibebox-<project>` entry for editors, rsync, scp and git
+    SshConfig {
+        /// Add or update the entry in ~/.ssh/config instead of printing it
+        #[arg(long)]
+        install: bool,
+    },
+    /// Allow this project's `[hooks]` to run on the host, exactly as they are now
+    AllowHooks,
+    /// Copy files between host and guest; prefix the guest path with `:`
+    Cp {
+        /// Copy directories recursively
+        #[arg(short, long)]
+        recursive: bool,
+        /// Host path, or `:guest/path`
+        source: String,
+        /// Host path, or `:guest/path`
+        destination: String,
+    },
+    /// Run a `[commands]` entry from vibebox.toml in the guest, e.g. `vibebox run :test`
+    Run {
+        /// Boot a throwaway VM from a temporary copy of the base disk, deleted when the command
+        /// ends; without a command, open a shell in it
+        #[arg(long)]
+        ephemeral: bool,
+        /// Command name, with or without the leading `:`
+        #[arg(required_unless_present = "ephemeral")]
+        command: Option<String>,
+        /// Arguments passed on to the command's script
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// List what the guest changed in an overlay project (`[box].project_mode = "overlay"`)
+    Diff {
+        /// Print unified diffs instead of the changed paths
+        #[arg(long)]
+        patch: bool,
+        /// Only changes at or under these project paths
+        paths: Vec<String>,
+    },
+    /// Copy the guest's overlay changes into the project and drop them from the overlay
+    Apply {
+        /// Only changes at or under these project paths
+        paths: Vec<String>,
+    },
+    /// Drop the guest's overlay changes, all of them unless paths are given
+    Discard {
+        /// Only changes at or under these project paths
+        paths: Vec<String>,
+    },
+    /// Serve the local JSON-RPC API for orchestrators on ~/.vibebox/daemon.sock
+    Daemon {
+        /// Listen here instead
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Connect stdin/stdout to the VM's sshd (used as the ssh ProxyCommand)
+    #[command(hide = true)]
+    SshProxy {
+        #[arg(long, value_name = "DIR")]
+        project: PathBuf,
+    },
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
-    let cwd = env::current_dir().map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
-    let stderr_handle = init_tracing(&cwd);
-
     let cli = Cli::parse();
+    let json = cli.json;
+    let Err(report) = run(cli) else {
+        return Ok(());
+    };
+    let report = ErrorReport::new(report.as_ref());
+    if json {
+        let rendered = serde_json::to_string(&serde_json::json!({ "error": report }))?;
+        eprintln!("{rendered}");
+        std::process::exit(1);
+    }
+    Err(with_hints(report))
+}
+
+fn with_hints(report: ErrorReport) -> Report {
+    let mut err = color_eyre::eyre::eyre!(report.message);
+    for hint in report.hints {
+        err = err.suggestion(hint);
+    }
+    if report.code != ErrorCode::Other {
+        err = err.note(format!("error code: {}", report.code));
+    }
+    err
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let cwd = env::current_dir()?;
+    // Log lines would scribble over the full-screen dashboard, and ssh-proxy's stderr is shown by
+    // the ssh client; cli.log still gets them. ssh-proxy runs from wherever ssh, git or rsync was
+    // started, so it leaves no cli.log there either.
+    let ssh_proxy = matches!(cli.command, Some(Command::SshProxy { .. }));
+    let stderr_logs = !ssh_proxy && !matches!(cli.command, Some(Command::Top));
+    let log_dir = (!ssh_proxy).then_some(cwd.as_path());
+    let stderr_handle = init_tracing(log_dir, stderr_logs);
+
     tracing::debug!(cwd = %cwd.display(), "starting vibebox cli");
     if let Some(command) = cli.command {
-        return handle_command(command, &cwd, cli.config.as_deref());
+        return handle_command(
+            command,
+            &cwd,
+            cli.config.as_deref(),
+            cli.session.as_deref(),
+            cli.json,
+        );
     }
 
-    let config_override = cli.config.clone();
-    let raw_args: Vec<OsString> = env::args_os().collect();
-    let config = config::load_config_with_path(&cwd, config_override.as_deref());
+    let sandbox = open_sandbox(&cwd, cli.config.as_deref(), cli.session.as_deref())?;
+    let config = sandbox.config();
 
     if env::var("VIBEBOX_VM_MANAGER").as_deref() == Ok("1") {
         tracing::info!("starting vm manager mode");
-        let args = vm::VmArg {
-            cpu_count: config.box_cfg.cpu_count,
-            ram_bytes: config.box_cfg.ram_mb.saturating_mul(1024 * 1024),
-            disk_bytes: config.box_cfg.disk_gb.saturating_mul(1024 * 1024 * 1024),
-            no_default_mounts: false,
-            mounts: config.box_cfg.mounts.clone(),
-        };
         let auto_shutdown_ms = config.supervisor.auto_shutdown_ms;
         tracing::info!(auto_shutdown_ms, "vm manager config");
-        if let Err(err) = vm_manager::run_manager(args, auto_shutdown_ms) {
+        if let Err(err) = vm_manager::run_manager(&cwd, sandbox.vm_args(), auto_shutdown_ms) {
             tracing::error!(error = %err, "vm manager exited");
-            return Err(color_eyre::eyre::eyre!(err.to_string()));
+            return Err(VibeboxError::from_boxed(err).into());
         }
         return Ok(());
     }
 
-    vm::ensure_signed();
+    #[cfg(target_os = "macos")]
+    vibebox::virtualization::ensure_signed()?;
 
-    let vm_args = vm::VmArg {
-        cpu_count: config.box_cfg.cpu_count,
-        ram_bytes: config.box_cfg.ram_mb.saturating_mul(1024 * 1024),
-        disk_bytes: config.box_cfg.disk_gb.saturating_mul(1024 * 1024 * 1024),
-        no_default_mounts: false,
-        mounts: config.box_cfg.mounts.clone(),
-    };
+    let vm_args = sandbox.vm_args();
     let auto_shutdown_ms = config.supervisor.auto_shutdown_ms;
+    let status = sandbox.status();
     let vm_info = VmInfo {
         max_memory_mb: vm_args.ram_bytes / (1024 * 1024),
         cpu_cores: vm_args.cpu_count,
         max_disk_gb: (vm_args.disk_bytes as f32) / 1024.0 / 1024.0 / 1024.0,
-        system_name: "Debian".to_string(), // TODO: read system name from the VM.
         auto_shutdown_ms,
+        // A stopped VM's record is from its previous boot; the header is redrawn once it is up.
+        guest: status.guest.filter(|_| status.running),
     };
-    if let Ok(manager) = SessionManager::new() {
-        if let Err(err) = manager.update_global_sessions(&cwd) {
-            tracing::warn!(error = %err, "failed to update a global session list");
-        }
-    } else {
-        tracing::warn!("failed to initialize session manager");
-    }
-    let commands = commands::build_commands();
+    let commands = commands::build_commands(&config.commands);
     let app = Arc::new(Mutex::new(AppState::new(cwd.clone(), vm_info, commands)));
 
     {
@@ -112,22 +235,31 @@ fn main() -> Result<()> {
         writeln!(stdout)?;
         stdout.flush()?;
     }
-    warn_disk_size_mismatch(&cwd, vm_args.disk_bytes);
     if let Some(handle) = stderr_handle {
         let _ = handle.modify(|filter| *filter = LevelFilter::INFO);
     }
 
     tracing::debug!(auto_shutdown_ms, "auto shutdown config");
-    let manager_conn =
-        vm_manager::ensure_manager(&raw_args, auto_shutdown_ms, config_override.as_deref())
-            .map_err(|err| {
-                tracing::error!(error = %err, "failed to ensure vm manager");
-                color_eyre::eyre::eyre!(err.to_string())
-            })?;
-
-    instance::run_with_ssh(manager_conn).map_err(|err| {
-        tracing::error!(error = %err, "failed to ensure vm manager");
-        color_eyre::eyre::eyre!(err.to_string())
+    sandbox.up().inspect_err(|err| {
+        tracing::error!(error = %err, "vibebox session failed");
+    })?;
+    // The guest records what it is on every boot, so redraw the header with this boot's values.
+    {
+        let mut locked = app.lock().expect("app state poisoned");
+        locked.vm_info.guest = instance::read_instance_guest_info(sandbox.instance_dir())
+            .ok()
+            .flatten();
+        tui::render_tui_once(&mut locked)?;
+    }
+    {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+    warn_disk_size_mismatch(sandbox.instance_dir(), vm_args.disk_bytes);
+    warn_hooks_not_allowed(&cwd, &vm_args.hooks);
+    sandbox.shell().inspect_err(|err| {
+        tracing::error!(error = %err, "vibebox session failed");
     })?;
 
     tracing::info!("See you again — keep vibecoding (no SEVs, only vibes) 😈");
@@ -135,7 +267,38 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn handle_command(command: Command, cwd: &Path, config_override: Option<&Path>) -> Result<()> {
+fn handle_command(
+    command: Command,
+    cwd: &Path,
+    config_override: Option<&Path>,
+    session: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    if session.is_some()
+        && !matches!(
+            command,
+            Command::Reset
+                | Command::Explain { .. }
+                | Command::SshConfig { .. }
+                | Command::Console { .. }
+                | Command::Logs { .. }
+                | Command::Cp { .. }
+                | Command::Run { .. }
+                | Command::Diff { .. }
+                | Command::Apply { .. }
+                | Command::Discard { .. }
+                | Command::SshProxy { .. }
+        )
+    {
+        return Err(VibeboxError::InvalidArgument(
+            "--session only applies to the VM commands: reset, explain, ssh-config, console, logs, cp, run, diff, apply and discard"
+                .to_string(),
+        )
+        .into());
+    }
+    if let Some(name) = session {
+        session_manager::validate_session_name(name)?;
+    }
     match command {
         Command::List => {
             let manager = SessionManager::new()?;
@@ -147,10 +310,15 @@ fn handle_command(command: Command, cwd: &Path, config_override: Option<&Path>)
             let rows: Vec<tui::SessionListRow> = sessions
                 .into_iter()
                 .map(|session| tui::SessionListRow {
-                    name: project_name(&session.directory),
+                    system: instance::read_instance_guest_info(&session.instance_dir())
+                        .ok()
+                        .flatten()
+                        .map(|guest| guest.system_label())
+                        .unwrap_or_else(|| "-".to_string()),
+                    name: session.label(),
                     id: session.id,
                     directory: relative_to_home(&session.directory),
-                    last_active: format_last_active(session.last_active.as_deref()),
+                    last_active: tui::format_last_active(session.last_active.as_deref()),
                     active: if session.active {
                         "yes".to_string()
                     } else {
@@ -161,17 +329,33 @@ fn handle_command(command: Command, cwd: &Path, config_override: Option<&Path>)
             tui::render_sessions_table(&rows)?;
             Ok(())
         }
+        Command::Top => {
+            if !io::stdout().is_terminal() {
+                return Err(color_eyre::eyre::eyre!(
+                    "vibebox top needs a terminal; use `vibebox list` in scripts"
+                ));
+            }
+            dashboard::run(SessionManager::new()?)
+        }
         Command::Reset => {
-            let instance_dir = cwd.join(session_manager::INSTANCE_DIR_NAME);
-            if !instance_dir.exists() {
+            let instance_dir = session_manager::session_instance_dir(cwd, session);
+            let named_dir = cwd.join(session_manager::NAMED_SESSIONS_DIR_NAME);
+            let named_too = session.is_none() && named_dir.exists();
+            if !instance_dir.exists() && !named_too {
                 println!("No .vibebox directory found at {}", instance_dir.display());
                 return Ok(());
             }
+            let prompt = if named_too {
+                format!(
+                    "Delete {} and every named session in {}?",
+                    instance_dir.display(),
+                    named_dir.display()
+                )
+            } else {
+                format!("Delete {} and all its contents?", instance_dir.display())
+            };
             let confirmed = Confirm::new()
-                .with_prompt(format!(
-                    "Delete {} and all its contents?",
-                    instance_dir.display()
-                ))
+                .with_prompt(prompt)
                 .default(false)
                 .interact()?;
             if !confirmed {
@@ -179,7 +363,10 @@ fn handle_command(command: Command, cwd: &Path, config_override: Option<&Path>)
                 return Ok(());
             }
             let manager = SessionManager::new()?;
-            let summary = manager.clean_project(cwd)?;
+            let summary = match session {
+                Some(name) => manager.clean_session(cwd, name)?,
+                None => manager.clean_all_sessions(cwd)?,
+            };
             println!(
                 "Deleted {} (removed={}, session_records_removed={})",
                 summary.instance_dir.display(),
@@ -211,27 +398,304 @@ fn handle_command(command: Command, cwd: &Path, config_override: Option<&Path>)
                 "Purged {} file{} totaling {} from {}",
                 file_count,
                 if file_count == 1 { "" } else { "s" },
-                format_bytes(total_bytes),
+                tui::format_bytes(total_bytes),
                 cache_dir.display()
             );
             Ok(())
         }
-        Command::Explain => {
-            let config = config::load_config_with_path(cwd, config_override);
-            let mounts = explain::build_mount_rows(cwd, &config)
+        Command::Explain { format, verify } => {
+            let config = config::try_load_config(cwd, config_override)?;
+            let config_path = config::try_resolve_config_path(cwd, config_override)?;
+            let instance_dir = session_manager::session_instance_dir(cwd, session);
+            let mut explanation =
+                explain::build_explanation(cwd, &instance_dir, &config, &config_path)
+                    .map_err(|err| VibeboxError::from_boxed(err))?;
+            if verify {
+                let state = vibebox::verify::probe(&instance_dir)
+                    .map_err(|err| VibeboxError::from_boxed(err))?;
+                explanation.verification = Some(vibebox::verify::verify(
+                    &explanation.mounts,
+                    &explanation.network,
+                    config.security.profile,
+                    vibebox::verify::expected_git_mask(cwd).as_deref(),
+                    &state,
+                ));
+            }
+            let format = if json { ExplainFormat::Json } else { format };
+            if format == ExplainFormat::Table {
+                tui::render_explain_tables(
+                    &explanation.summary_lines(),
+                    &explanation.mounts,
+                    &explanation.network,
+                    explanation.verification.as_deref().unwrap_or_default(),
+                )?;
+            } else {
+                let rendered = explanation
+                    .render(format)
+                    .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
+                print!("{rendered}");
+            }
+            Ok(())
+        }
+        Command::Logs {
+            kind,
+            follow,
+            boot,
+            from,
+        } => {
+            if follow && boot != 0 {
+                return Err(color_eyre::eyre::eyre!(
+                    "--follow only applies to the latest boot"
+                ));
+            }
+            if from.is_some() && session.is_some() {
+                return Err(VibeboxError::InvalidArgument(
+                    "pick the session with either --session or logs --from".to_string(),
+                )
+                .into());
+            }
+            let instance_dir = match from {
+                Some(query) => SessionManager::new()?.find_session(&query)?.instance_dir(),
+                None => session_manager::session_instance_dir(cwd, session),
+            };
+            let path = logs::log_path(&instance_dir, kind, boot.unsigned_abs() as usize);
+            if !follow && !path.exists() {
+                println!("No {} found at {}", kind.file_name(), path.display());
+                return Ok(());
+            }
+            let mut stdout = io::stdout().lock();
+            let result = if follow {
+                logs::follow(&path, &mut stdout)
+            } else {
+                logs::dump(&path, &mut stdout)
+            };
+            match result {
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err.into()),
+                _ => Ok(()),
+            }
+        }
+        Command::SshConfig { install } => {
+            let project_root = fs::canonicalize(cwd)?;
+            let exe = env::current_exe()?;
+            let block = ssh_config::render_host_block(&project_root, session, &exe)
+                .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
+            if !install {
+                print!("{block}");
+                return Ok(());
+            }
+            let alias = ssh_config::host_alias(&project_root, session);
+            let path = ssh_config::user_ssh_config_path()
                 .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
-            let networks = explain::build_network_rows(cwd)
+            ssh_config::install_host_block(&path, &alias, &block)
                 .map_err(|err| color_eyre::eyre::eyre!(err.to_string()))?;
-            if mounts.is_empty() && networks.is_empty() {
-                println!("No mounts or network info available.");
+            println!(
+                "Installed `{alias}` in {}; try `ssh {alias}`",
+                path.display()
+            );
+            Ok(())
+        }
+        Command::AllowHooks => {
+            let config = config::try_load_config(cwd, config_override)?;
+            let trust_file = hooks::trusted_hooks_path()?;
+            hooks::allow(&trust_file, cwd, &config.hooks)?;
+            let commands: Vec<(&str, &str)> = config::HOOK_NAMES
+                .iter()
+                .zip([
+                    &config.hooks.pre_start,
+                    &config.hooks.post_boot,
+                    &config.hooks.on_attach,
+                    &config.hooks.on_detach,
+                    &config.hooks.post_stop,
+                ])
+                .filter_map(|(name, command)| Some((*name, command.as_deref()?)))
+                .collect();
+            if commands.is_empty() {
+                println!("No [hooks] to allow.");
                 return Ok(());
             }
-            tui::render_explain_tables(&mounts, &networks)?;
+            for (name, command) in commands {
+                println!("{name} = {command}");
+            }
+            println!(
+                "Allowed these hooks for {}; editing them needs `vibebox allow-hooks` again",
+                cwd.display()
+            );
             Ok(())
         }
+        Command::Cp {
+            recursive,
+            source,
+            destination,
+        } => {
+            instance::check_copy_args(&source, &destination)
+                .map_err(VibeboxError::InvalidArgument)?;
+            Ok(open_sandbox(cwd, config_override, session)?.copy(
+                &source,
+                &destination,
+                recursive,
+            )?)
+        }
+        Command::Run {
+            ephemeral,
+            command,
+            args,
+        } => {
+            let mut builder = sandbox_builder(cwd, config_override, session);
+            if ephemeral {
+                builder = builder.ephemeral();
+            }
+            let sandbox = builder.build()?;
+            warn_hooks_not_allowed(cwd, &sandbox.config().hooks);
+            if ephemeral {
+                tracing::info!(
+                    session_id = %sandbox.create()?,
+                    path = %sandbox.instance_dir().display(),
+                    "ephemeral sandbox"
+                );
+            }
+            let Some(command) = command else {
+                return Ok(sandbox.shell()?);
+            };
+            let config = sandbox.config();
+            let name = command.trim_start_matches(':');
+            let Some(spec) = config.commands.get(name) else {
+                let known: Vec<String> = config
+                    .commands
+                    .keys()
+                    .map(|name| format!(":{name}"))
+                    .collect();
+                return Err(color_eyre::eyre::eyre!(
+                    "no command :{name} in [commands]; defined: {}",
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                ));
+            };
+            let project_dir = format!("{}/{}", vm::PROJECT_GUEST_BASE, project_name(cwd));
+            let args: Vec<String> = args.iter().map(|arg| commands::shell_quote(arg)).collect();
+            let line = commands::guest_command_line(name, spec, &project_dir, &args);
+            let code = sandbox.run(&line)?;
+            if code != 0 {
+                // exit() skips destructors, so let an ephemeral sandbox go first.
+                drop(sandbox);
+                std::process::exit(code);
+            }
+            Ok(())
+        }
+        Command::Diff { patch, paths } => {
+            let sandbox = open_sandbox(cwd, config_override, session)?;
+            let changes = sandbox.diff(&paths)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else if patch {
+                print!("{}", sandbox.diff_patch(&changes)?);
+            } else if changes.is_empty() {
+                println!("No changes.");
+            } else {
+                print_changes(&changes);
+            }
+            Ok(())
+        }
+        Command::Apply { paths } => {
+            let changes = open_sandbox(cwd, config_override, session)?.apply(&paths)?;
+            if changes.is_empty() {
+                println!("No changes to apply.");
+                return Ok(());
+            }
+            print_changes(&changes);
+            println!(
+                "Applied {} to {}",
+                plural(changes.len(), "change"),
+                cwd.display()
+            );
+            Ok(())
+        }
+        Command::Discard { paths } => {
+            let sandbox = open_sandbox(cwd, config_override, session)?;
+            if paths.is_empty() {
+                let count = sandbox.diff(&[])?.len();
+                if count == 0 {
+                    println!("No changes to discard.");
+                    return Ok(());
+                }
+                let confirmed = Confirm::new()
+                    .with_prompt(format!(
+                        "Discard all of the guest's changes ({})?",
+                        plural(count, "change")
+                    ))
+                    .default(false)
+                    .interact()?;
+                if !confirmed {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            }
+            let changes = sandbox.discard(&paths)?;
+            if changes.is_empty() {
+                println!("No changes to discard.");
+                return Ok(());
+            }
+            print_changes(&changes);
+            println!("Discarded {}", plural(changes.len(), "change"));
+            Ok(())
+        }
+        Command::Daemon { socket } => {
+            let path = match socket {
+                Some(path) => path,
+                None => daemon::default_socket_path()?,
+            };
+            let listener = daemon::bind(&path)?;
+            tracing::info!(path = %path.display(), "daemon listening");
+            Arc::new(daemon::Daemon::new(SessionManager::new()?)).serve(listener);
+            Ok(())
+        }
+        Command::SshProxy { project } => {
+            Ok(open_sandbox(&project, config_override, session)?.ssh_proxy()?)
+        }
+        Command::Console { detach_keys } => console::attach(
+            &session_manager::session_instance_dir(cwd, session),
+            &detach_keys,
+        )
+        .map_err(|err| VibeboxError::from_boxed(err).into()),
+    }
+}
+
+/// The project's sandbox, with `--config` resolved against the project directory.
+fn open_sandbox(
+    project: &Path,
+    config_override: Option<&Path>,
+    session: Option<&str>,
+) -> Result<Sandbox> {
+    Ok(sandbox_builder(project, config_override, session).build()?)
+}
+
+fn sandbox_builder(
+    project: &Path,
+    config_override: Option<&Path>,
+    session: Option<&str>,
+) -> SandboxBuilder {
+    let mut builder = Sandbox::builder(project);
+    if let Some(path) = config_override {
+        builder = builder.config_path(path);
+    }
+    if let Some(name) = session {
+        builder = builder.session(name);
+    }
+    builder
+}
+
+fn print_changes(changes: &[overlay::Change]) {
+    for change in changes {
+        println!("{change}");
     }
 }
 
+fn plural(count: usize, noun: &str) -> String {
+    format!("{count} {noun}{}", if count == 1 { "" } else { "s" })
+}
+
 fn project_name(directory: &Path) -> String {
     directory
         .file_name()
@@ -302,68 +766,8 @@ fn measure_dir(path: &Path) -> Result<(u64, u64)> {
     Ok((file_count, total_bytes))
 }
 
-fn format_bytes(bytes: u64) -> String {
-    const KB: f64 = 1024.0;
-    const MB: f64 = KB * 1024.0;
-    const GB: f64 = MB * 1024.0;
-
-    let b = bytes as f64;
-    if b >= GB {
-        format!("{:.2} GB", b / GB)
-    } else if b >= MB {
-        format!("{:.1} MB", b / MB)
-    } else if b >= KB {
-        format!("{:.1} KB", b / KB)
-    } else {
-        format!("{} B", bytes)
-    }
-}
-
-fn format_last_active(value: Option<&str>) -> String {
-    let Some(raw) = value else {
-        return "-".to_string();
-    };
-    let parsed = OffsetDateTime::parse(raw, &Rfc3339);
-    let Ok(timestamp) = parsed else {
-        return raw.to_string();
-    };
-    let now = OffsetDateTime::now_utc();
-    let mut seconds = (now - timestamp).whole_seconds();
-    if seconds < 0 {
-        seconds = 0;
-    }
-    let week_seconds = 7 * 24 * 60 * 60;
-    if seconds >= week_seconds {
-        let formatted =
-            match time::format_description::parse("[year]-[month]-[day] [hour]:[minute]Z") {
-                Ok(format) => timestamp
-                    .format(&format)
-                    .unwrap_or_else(|_| raw.to_string()),
-                Err(_) => timestamp
-                    .format(&Rfc3339)
-                    .unwrap_or_else(|_| raw.to_string()),
-            };
-        return formatted;
-    }
-    if seconds < 60 {
-        return "just now".to_string();
-    }
-    if seconds < 60 * 60 {
-        let mins = seconds / 60;
-        return format!("{} min{} ago", mins, if mins == 1 { "" } else { "s" });
-    }
-    if seconds < 60 * 60 * 24 {
-        let hours = seconds / (60 * 60);
-        return format!("{} hour{} ago", hours, if hours == 1 { "" } else { "s" });
-    }
-    let days = seconds / (60 * 60 * 24);
-    format!("{} day{} ago", days, if days == 1 { "" } else { "s" })
-}
-
-fn warn_disk_size_mismatch(cwd: &Path, configured_bytes: u64) {
-    let instance_raw = cwd
-        .join(session_manager::INSTANCE_DIR_NAME)
-        .join("instance.raw");
+fn warn_disk_size_mismatch(instance_dir: &Path, configured_bytes: u64) {
+    let instance_raw = instance_dir.join("instance.raw");
     let Ok(meta) = fs::metadata(&instance_raw) else {
         return;
     };
@@ -381,15 +785,26 @@ disk_gb applies only on init. Run `vibebox reset` to recreate or set disk_gb to
     );
 }
 
+fn warn_hooks_not_allowed(project_root: &Path, config: &config::HooksConfig) {
+    let allowed = hooks::trusted_hooks_path()
+        .map(|path| hooks::is_allowed(&path, project_root, config))
+        .unwrap_or(false);
+    if !allowed {
+        tracing::warn!(
+            "[hooks] in vibebox.toml will not run: review them and run `vibebox allow-hooks`"
+        );
+    }
+}
+
 type StderrHandle = reload::Handle<LevelFilter, Registry>;
 
-fn init_tracing(cwd: &Path) -> Option<StderrHandle> {
+fn init_tracing(log_dir: Option<&Path>, stderr_logs: bool) -> Option<StderrHandle> {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
     let file_filter = filter.clone();
     let stderr_is_tty = std::io::stderr().is_terminal();
     let ansi = stderr_is_tty && env::var("VIBEBOX_LOG_NO_COLOR").is_err();
-    let file = instance::ensure_instance_dir(cwd)
-        .ok()
+    let file = log_dir
+        .and_then(|cwd| instance::ensure_instance_dir(cwd).ok())
         .and_then(|instance_dir| {
             let log_path = instance_dir.join("cli.log");
             std::fs::OpenOptions::new()
@@ -400,7 +815,17 @@ fn init_tracing(cwd: &Path) -> Option<StderrHandle> {
                 .ok()
         });
 
-    if stderr_is_tty {
+    if !stderr_logs {
+        if let Some(file) = file {
+            let file_layer = fmt::layer()
+                .with_target(false)
+                .with_ansi(false)
+                .with_writer(file)
+                .with_filter(file_filter);
+            let _ = tracing_subscriber::registry().with(file_layer).try_init();
+        }
+        None
+    } else if stderr_is_tty {
         let (stderr_filter, handle) = reload::Layer::new(LevelFilter::INFO);
         let stderr_layer = fmt::layer()
             .with_target(false)
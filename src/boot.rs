@@ -0,0 +1,592 @@
+//! Boot preparation shared by VM backends: the base image, per-project disks and the login
+//! actions typed into the console after the guest comes up.
+
+use crate::agent::{AGENT_VERSION, FRAME_MAGIC, READY_FRAME_ID};
+use crate::login_script::{Expectation, LoginAction, OnTimeout};
+use crate::vm::{DirectoryShare, script_command_from_content};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+// Disk images and provisioning only exist for the Virtualization.framework backend; tests still
+// cover the parts that do not touch a real image.
+#[cfg(any(target_os = "macos", test))]
+use crate::{
+    config::ProjectMode,
+    error::VibeboxError,
+    mount_plan, overlay,
+    vm::{PROJECT_GUEST_BASE, VmArg},
+};
+#[cfg(target_os = "macos")]
+use crate::{config::SecurityProfile, session_manager::GLOBAL_CACHE_DIR_NAME};
+#[cfg(target_os = "macos")]
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+#[cfg(any(target_os = "macos", test))]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(any(target_os = "macos", test))]
+const DEBIAN_COMPRESSED_DISK_URL: &str = "https://cloud.debian.org/images/cloud/trixie/20260112-2355/debian-13-nocloud-arm64-20260112-2355.tar.xz";
+#[cfg(target_os = "macos")]
+const DEBIAN_COMPRESSED_SHA: &str = "6ab9be9e6834adc975268367f2f0235251671184345c34ee13031749fdfbf66fe4c3aafd949a2d98550426090e9ac645e79009c51eb0eefc984c15786570bb38";
+#[cfg(target_os = "macos")]
+const DEBIAN_COMPRESSED_SIZE_BYTES: u64 = 280901576;
+pub(crate) const SHARED_DIRECTORIES_TAG: &str = "shared";
+
+const LOGIN_EXPECT_TIMEOUT: Duration = Duration::from_secs(60);
+const LOGIN_EXPECT_RETRIES: usize = 1;
+#[cfg(any(target_os = "macos", test))]
+const PROVISION_EXPECT_TIMEOUT: Duration = Duration::from_secs(900);
+const AGENT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(any(target_os = "macos", test))]
+pub(crate) struct StatusFile {
+    path: PathBuf,
+    cleared: AtomicBool,
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl StatusFile {
+    #[cfg(target_os = "macos")]
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            cleared: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn update(&self, message: &str) {
+        let _ = fs::write(&self.path, message);
+    }
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl Drop for StatusFile {
+    fn drop(&mut self) {
+        if !self.cleared.load(Ordering::SeqCst) {
+            let _ = fs::remove_file(&self.path);
+            self.cleared.store(true, Ordering::SeqCst);
+        }
+    }
+}
+#[cfg(any(target_os = "macos", test))]
+const PROVISION_SCRIPT: &str = include_str!("provision.sh");
+#[cfg(any(target_os = "macos", test))]
+const PROVISION_SCRIPT_NAME: &str = "provision.sh";
+#[cfg(any(target_os = "macos", test))]
+const RESIZE_DISK_SCRIPT: &str = include_str!("resize_disk.sh");
+const AGENT_SCRIPT: &str = include_str!("agent.sh");
+const AGENT_SCRIPT_NAME: &str = "vibebox-agent";
+pub(crate) const GUEST_MISE_DIR: &str = "/root/.local/share/mise";
+#[cfg(any(target_os = "macos", test))]
+const DEFAULT_RAW_NAME: &str = "default.raw";
+pub(crate) const INSTANCE_RAW_NAME: &str = "instance.raw";
+#[cfg(target_os = "macos")]
+const BASE_DISK_RAW_NAME: &str = "disk.raw";
+
+/// Host paths for the shared base images and one project's instance disk.
+#[cfg(target_os = "macos")]
+pub(crate) struct DiskLayout {
+    pub(crate) cache_dir: PathBuf,
+    pub(crate) guest_mise_cache: PathBuf,
+    pub(crate) base_compressed: PathBuf,
+    pub(crate) base_raw: PathBuf,
+    pub(crate) default_raw: PathBuf,
+    pub(crate) instance_dir: PathBuf,
+    pub(crate) instance_raw: PathBuf,
+}
+
+#[cfg(target_os = "macos")]
+impl DiskLayout {
+    pub(crate) fn for_instance(instance_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let home = env::var("HOME").map(PathBuf::from)?;
+        let cache_home = env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| home.join(".cache"));
+        let cache_dir = cache_home.join(GLOBAL_CACHE_DIR_NAME);
+        let instance_dir = instance_dir.to_path_buf();
+
+        let basename_compressed = DEBIAN_COMPRESSED_DISK_URL.rsplit('/').next().unwrap();
+        Ok(Self {
+            guest_mise_cache: cache_dir.join(".guest-mise-cache"),
+            base_compressed: cache_dir.join(basename_compressed),
+            base_raw: cache_dir.join(format!(
+                "{}.raw",
+                basename_compressed.trim_end_matches(".tar.xz")
+            )),
+            default_raw: cache_dir.join(DEFAULT_RAW_NAME),
+            instance_raw: instance_dir.join(INSTANCE_RAW_NAME),
+            cache_dir,
+            instance_dir,
+        })
+    }
+
+    /// The mise cache is shared by every project, so a strict guest only gets to read it.
+    pub(crate) fn mise_directory_share(
+        &self,
+        security: SecurityProfile,
+    ) -> Result<DirectoryShare, Box<dyn std::error::Error>> {
+        DirectoryShare::new(
+            self.guest_mise_cache.clone(),
+            GUEST_MISE_DIR.into(),
+            security == SecurityProfile::Strict,
+        )
+    }
+}
+
+/// Builds the per-boot login actions and shares that follow the root login and mounts.
+#[cfg(any(target_os = "macos", test))]
+pub(crate) fn session_login_actions(
+    args: &VmArg,
+    project_root: &Path,
+    mise_directory_share: DirectoryShare,
+    needs_resize: bool,
+    extra_login_actions: Vec<LoginAction>,
+    extra_directory_shares: Vec<DirectoryShare>,
+) -> Result<(Vec<LoginAction>, Vec<DirectoryShare>), Box<dyn std::error::Error>> {
+    let project_name = project_root
+        .file_name()
+        .ok_or("Project directory has no name")?
+        .to_string_lossy()
+        .into_owned();
+
+    let mut login_actions = Vec::new();
+    let mut directory_shares = Vec::new();
+
+    if !args.no_default_mounts {
+        let project_guest_dir = PathBuf::from(PROJECT_GUEST_BASE).join(&project_name);
+        if args.project_mode == ProjectMode::Overlay {
+            login_actions.push(LoginAction::Send(overlay::boot_command(&project_name)?));
+        }
+        login_actions.push(LoginAction::Send(format!(
+            "cd {}",
+            project_guest_dir.display()
+        )));
+
+        // discourage read/write of .git folder from within the VM. note that this isn't secure, since the VM runs as root and could unmount this.
+        // I couldn't find an alternative way to do this --- the MacOS sandbox doesn't apply to the Apple Virtualization system
+        if project_root.join(".git").exists() {
+            login_actions.push(LoginAction::Send(r"mount -t tmpfs tmpfs .git/".into()));
+        }
+
+        directory_shares.push(mise_directory_share);
+    }
+
+    directory_shares.extend(extra_directory_shares);
+
+    for spec in &args.mounts {
+        directory_shares.push(DirectoryShare::from_mount_spec(spec)?);
+    }
+
+    if needs_resize {
+        let resize_cmd = script_command_from_content("resize_disk", RESIZE_DISK_SCRIPT)?;
+        login_actions.push(LoginAction::Send(resize_cmd));
+    }
+
+    let directory_shares = mount_plan::plan_shares(directory_shares)?;
+    if let Some(motd_action) = motd_login_action(&directory_shares) {
+        login_actions.push(motd_action);
+    }
+
+    login_actions.extend(extra_login_actions);
+
+    Ok((login_actions, directory_shares))
+}
+
+#[cfg(any(target_os = "macos", test))]
+fn motd_login_action(directory_shares: &[DirectoryShare]) -> Option<LoginAction> {
+    if directory_shares.is_empty() {
+        return Some(LoginAction::Send("clear".into()));
+    }
+
+    let host_header = "Host";
+    let guest_header = "Guest";
+    let mode_header = "Mode";
+    let mut host_width = host_header.len();
+    let mut guest_width = guest_header.len();
+    let mut mode_width = mode_header.len();
+    let mut rows = Vec::with_capacity(directory_shares.len());
+
+    for share in directory_shares {
+        let host = share.host().to_string_lossy().into_owned();
+        let guest = share.guest().to_string_lossy().into_owned();
+        let mode = if share.read_only() {
+            "read-only"
+        } else {
+            "read-write"
+        }
+        .to_string();
+        host_width = host_width.max(host.len());
+        guest_width = guest_width.max(guest.len());
+        mode_width = mode_width.max(mode.len());
+        rows.push((host, guest, mode));
+    }
+
+    let mut output = String::new();
+    output.push_str(&format!(
+        "{host_header:<host_width$}  {guest_header:<guest_width$}  {mode_header}\n",
+        host_width = host_width
+    ));
+    output.push_str(&format!(
+        "{:-<host_width$}  {:-<guest_width$}  {:-<mode_width$}\n",
+        "",
+        "",
+        "",
+        host_width = host_width,
+        guest_width = guest_width,
+        mode_width = mode_width
+    ));
+
+    for (host, guest, mode) in rows {
+        output.push_str(&format!(
+            "{host:<host_width$}  {guest:<guest_width$}  {mode}\n"
+        ));
+    }
+
+    let command = format!("clear && cat <<'VIBE_MOTD'\n{output}\nVIBE_MOTD");
+    Some(LoginAction::Send(command))
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn ensure_base_image(
+    base_raw: &Path,
+    base_compressed: &Path,
+    status: Option<&StatusFile>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if base_raw.exists() {
+        return Ok(());
+    }
+
+    if !base_compressed.exists()
+        || std::fs::metadata(base_compressed).map(|m| m.len())? < DEBIAN_COMPRESSED_SIZE_BYTES
+    {
+        if let Some(status) = status {
+            status.update("downloading base image...");
+        }
+        tracing::info!("downloading base image");
+        let status = Command::new("curl")
+            .args([
+                "--continue-at",
+                "-",
+                "--compressed",
+                "--location",
+                "--fail",
+                "-o",
+                &base_compressed.to_string_lossy(),
+                DEBIAN_COMPRESSED_DISK_URL,
+            ])
+            .status()
+            .map_err(|source| VibeboxError::Tool {
+                tool: "curl",
+                source,
+            })?;
+        if !status.success() {
+            return Err(VibeboxError::ImageDownload {
+                url: DEBIAN_COMPRESSED_DISK_URL.to_string(),
+            }
+            .into());
+        }
+    }
+
+    // Check SHA
+    {
+        if let Some(status) = status {
+            status.update("verifying base image...");
+        }
+        let input = format!("{}  {}\n", DEBIAN_COMPRESSED_SHA, base_compressed.display());
+
+        let shasum_failed = |source| VibeboxError::Tool {
+            tool: "shasum",
+            source,
+        };
+        let mut child = Command::new("/usr/bin/shasum")
+            .args(["--algorithm", "512", "--check"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(shasum_failed)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes()).map_err(shasum_failed)?;
+        }
+        let status = child.wait().map_err(shasum_failed)?;
+        if !status.success() {
+            return Err(VibeboxError::ChecksumMismatch {
+                path: base_compressed.to_path_buf(),
+            }
+            .into());
+        }
+    }
+
+    if let Some(status) = status {
+        status.update("decompressing base image...");
+    }
+    tracing::info!("decompressing base image");
+    let status = Command::new("tar")
+        .args([
+            "-xOf",
+            &base_compressed.to_string_lossy(),
+            BASE_DISK_RAW_NAME,
+        ])
+        .stdout(std::fs::File::create(base_raw)?)
+        .status()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "tar",
+            source,
+        })?;
+
+    if !status.success() {
+        let _ = fs::remove_file(base_raw);
+        return Err(VibeboxError::ImageDecompress {
+            path: base_compressed.to_path_buf(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Uploads and starts the guest agent, setting `agent_ready` once it answers. The agent owns
+/// the console from then on, so every later step must be a `LoginAction::Agent`.
+pub(crate) fn agent_login_actions(
+    agent_ready: Arc<AtomicBool>,
+) -> Result<Vec<LoginAction>, Box<dyn std::error::Error>> {
+    let script = AGENT_SCRIPT
+        .replace("__AGENT_MAGIC__", FRAME_MAGIC)
+        .replace("__AGENT_VERSION__", AGENT_VERSION);
+    let command = script_command_from_content(AGENT_SCRIPT_NAME, &script)?;
+    let ready = format!(r"(?m)^{FRAME_MAGIC} {READY_FRAME_ID} ready ");
+    Ok(vec![
+        LoginAction::Send(command),
+        LoginAction::Expect(Expectation::regex(&ready, AGENT_READY_TIMEOUT)?),
+        LoginAction::Hook(Arc::new(move |_| {
+            agent_ready.store(true, Ordering::SeqCst);
+        })),
+    ])
+}
+
+/// The base image and the vibebox release that provisioned it, e.g.
+/// `debian-13-nocloud-arm64-20260112-2355 / vibebox 0.3.0`.
+#[cfg(any(target_os = "macos", test))]
+fn template_version() -> String {
+    let image = DEBIAN_COMPRESSED_DISK_URL
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(".tar.xz");
+    format!("{image} / vibebox {}", env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(any(target_os = "macos", test))]
+pub(crate) fn provision_login_actions() -> Result<Vec<LoginAction>, Box<dyn std::error::Error>> {
+    let script = PROVISION_SCRIPT.replace("__TEMPLATE_VERSION__", &template_version());
+    let provision_command = script_command_from_content(PROVISION_SCRIPT_NAME, &script)?;
+    Ok(vec![
+        LoginAction::Send(provision_command),
+        LoginAction::Expect(
+            Expectation::text("VIBEBOX_PROVISION_OK", PROVISION_EXPECT_TIMEOUT)
+                .failure_text("VIBEBOX_PROVISION_FAILED"),
+        ),
+    ])
+}
+
+#[cfg(any(target_os = "macos", test))]
+pub(crate) fn ensure_instance_disk(
+    instance_raw: &Path,
+    template_raw: &Path,
+    target_bytes: u64,
+    status: Option<&StatusFile>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if instance_raw.exists() {
+        let current_size = fs::metadata(instance_raw)?.len();
+        if current_size != target_bytes {
+            let current_gb = current_size as f64 / (1024.0 * 1024.0 * 1024.0);
+            let target_gb = target_bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+            tracing::warn!(
+                current_bytes = current_size,
+                target_bytes,
+                "instance disk size does not match config (current {:.2} GB, config {:.2} GB); disk_gb applies only on init. Run `vibebox reset` to recreate or set disk_gb to match; using existing disk.",
+                current_gb,
+                target_gb
+            );
+        }
+        return Ok(false);
+    }
+
+    let template_size = fs::metadata(template_raw)?.len();
+    if target_bytes < template_size {
+        return Err(VibeboxError::DiskTooSmall {
+            requested: target_bytes,
+            base: template_size,
+        }
+        .into());
+    }
+    let target_size = target_bytes;
+    let needs_resize = target_size > template_size;
+
+    if let Some(status) = status {
+        status.update("creating instance disk...");
+    }
+    tracing::info!(path = %template_raw.display(), "creating instance disk");
+    std::fs::create_dir_all(instance_raw.parent().unwrap())?;
+    if target_size == template_size {
+        fs::copy(template_raw, instance_raw)?;
+        return Ok(needs_resize);
+    }
+
+    let mut dst = std::fs::File::create(instance_raw)?;
+    dst.set_len(target_size)?;
+    let mut src = std::fs::File::open(template_raw)?;
+    std::io::copy(&mut src, &mut dst)?;
+    Ok(needs_resize)
+}
+
+/// Logs in as root, mounts the shared directories, then runs `login_actions`.
+pub fn boot_login_actions(
+    directory_shares: &[DirectoryShare],
+    login_actions: &[LoginAction],
+) -> Vec<LoginAction> {
+    let mut all_login_actions = vec![
+        // The serial console sometimes swallows the first prompt; an empty line redraws it.
+        LoginAction::Expect(
+            Expectation::text("login: ", LOGIN_EXPECT_TIMEOUT)
+                .retries(LOGIN_EXPECT_RETRIES)
+                .on_timeout(OnTimeout::Send(String::new())),
+        ),
+        LoginAction::Send("root".to_string()),
+        LoginAction::Expect(Expectation::text("~#", LOGIN_EXPECT_TIMEOUT)),
+    ];
+
+    if !directory_shares.is_empty() {
+        all_login_actions.push(LoginAction::Send("mkdir -p /mnt/shared".into()));
+        all_login_actions.push(LoginAction::Send(format!(
+            "mount -t virtiofs {} /mnt/shared",
+            SHARED_DIRECTORIES_TAG
+        )));
+
+        for share in directory_shares {
+            let staging = format!("/mnt/shared/{}", share.tag());
+            let guest = share.guest().to_string_lossy();
+            all_login_actions.push(LoginAction::Send(format!("mkdir -p {}", guest)));
+            all_login_actions.push(LoginAction::Send(format!(
+                "mount --bind {} {}",
+                staging, guest
+            )));
+        }
+    }
+
+    for a in login_actions {
+        all_login_actions.push(a.clone())
+    }
+
+    all_login_actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vm_args() -> VmArg {
+        VmArg {
+            cpu_count: 2,
+            ram_bytes: 2048 * 1024 * 1024,
+            disk_bytes: 5 * 1024 * 1024 * 1024,
+            no_default_mounts: false,
+            mounts: Vec::new(),
+            security: Default::default(),
+            allow_sensitive: false,
+            project_mode: Default::default(),
+            commands: Default::default(),
+            hooks: Default::default(),
+        }
+    }
+
+    fn sent(actions: &[LoginAction]) -> Vec<String> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                LoginAction::Send(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn instance_disk_grows_from_template() {
+        let temp = tempfile::tempdir().unwrap();
+        let template = temp.path().join(DEFAULT_RAW_NAME);
+        fs::write(&template, vec![7u8; 1024]).unwrap();
+        let instance = temp.path().join("instance").join(INSTANCE_RAW_NAME);
+
+        let needs_resize = ensure_instance_disk(&instance, &template, 4096, None).unwrap();
+
+        assert!(needs_resize);
+        let data = fs::read(&instance).unwrap();
+        assert_eq!(data.len(), 4096);
+        assert!(data[..1024].iter().all(|byte| *byte == 7));
+        assert!(!ensure_instance_disk(&instance, &template, 8192, None).unwrap());
+    }
+
+    #[test]
+    fn instance_disk_rejects_size_below_template() {
+        let temp = tempfile::tempdir().unwrap();
+        let template = temp.path().join(DEFAULT_RAW_NAME);
+        fs::write(&template, vec![0u8; 1024]).unwrap();
+        let instance = temp.path().join(INSTANCE_RAW_NAME);
+
+        let err = ensure_instance_disk(&instance, &template, 512, None).unwrap_err();
+
+        assert!(err.to_string().contains("smaller than base image"), "{err}");
+        assert!(!instance.exists());
+    }
+
+    #[test]
+    fn session_actions_enter_project_and_mask_git() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path().join("demo");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        let mise = DirectoryShare::new(temp.path().into(), "/root/.local/share/mise".into(), false)
+            .unwrap();
+
+        let (actions, shares) =
+            session_login_actions(&vm_args(), &project, mise, true, Vec::new(), Vec::new())
+                .unwrap();
+
+        let sent = sent(&actions);
+        assert_eq!(sent[0], format!("cd {PROJECT_GUEST_BASE}/demo"));
+        assert_eq!(sent[1], "mount -t tmpfs tmpfs .git/");
+        assert!(sent[2].contains("/tmp/vibe-scripts/resize_disk.sh"));
+        assert!(sent[3].contains("/root/.local/share/mise"));
+        assert_eq!(shares.len(), 1);
+    }
+
+    #[test]
+    fn overlay_mode_mounts_the_overlay_before_entering_the_project() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path().join("demo");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        let mise = DirectoryShare::new(temp.path().into(), "/root/.local/share/mise".into(), false)
+            .unwrap();
+        let args = VmArg {
+            project_mode: ProjectMode::Overlay,
+            ..vm_args()
+        };
+
+        let (actions, _) =
+            session_login_actions(&args, &project, mise, false, Vec::new(), Vec::new()).unwrap();
+
+        let sent = sent(&actions);
+        assert!(sent[0].contains("/tmp/vibe-scripts/project_overlay.sh"));
+        assert!(sent[0].contains("LOWER=\"/usr/local/vibebox-lower/demo\""));
+        assert!(sent[0].contains(&format!("MERGED=\"{PROJECT_GUEST_BASE}/demo\"")));
+        assert_eq!(sent[1], format!("cd {PROJECT_GUEST_BASE}/demo"));
+        assert_eq!(sent[2], "mount -t tmpfs tmpfs .git/");
+    }
+}
@@ -1,13 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 
+use crate::config::CommandConfig;
+use crate::login_script::LoginAction;
 use crate::tui::{AppState, VibeboxCommands};
 use crate::vm::IoControl;
 
+const GUEST_SHELL_SCRIPT_PATH: &str = "/etc/profile.d/vibebox.sh";
+
 #[derive(Clone, Copy)]
 enum CommandKind {
     Help,
     Exit,
+    /// Answered by the host manager through `vibebox-host`.
+    Host,
 }
 
 struct CommandSpec {
@@ -30,6 +36,36 @@ const COMMAND_SPECS: &[CommandSpec] = &[
         kind: CommandKind::Exit,
         shell_alias: Some("exit"),
     },
+    CommandSpec {
+        name: ":status",
+        description: "Show this session as the host sees it.",
+        kind: CommandKind::Host,
+        shell_alias: Some("vibebox-host status"),
+    },
+    CommandSpec {
+        name: ":explain",
+        description: "Show security, resources, mounts and network.",
+        kind: CommandKind::Host,
+        shell_alias: Some("vibebox-host explain"),
+    },
+    CommandSpec {
+        name: ":pin",
+        description: "Keep the VM running without attached clients.",
+        kind: CommandKind::Host,
+        shell_alias: Some("vibebox-host pin"),
+    },
+    CommandSpec {
+        name: ":unpin",
+        description: "Let the VM stop again once no client is attached.",
+        kind: CommandKind::Host,
+        shell_alias: Some("vibebox-host unpin"),
+    },
+    CommandSpec {
+        name: ":stop",
+        description: "Stop the VM for every client.",
+        kind: CommandKind::Host,
+        shell_alias: Some("vibebox-host stop"),
+    },
 ];
 
 pub struct CommandHandlers {
@@ -60,15 +96,20 @@ impl CommandHandlers {
     }
 }
 
-pub fn build_commands() -> VibeboxCommands {
+pub fn build_commands(user: &BTreeMap<String, CommandConfig>) -> VibeboxCommands {
     let mut commands = VibeboxCommands::new_empty();
     for spec in COMMAND_SPECS {
         commands.add_command(spec.name, spec.description);
     }
+    for (name, command) in user {
+        commands.add_command(format!(":{name}"), &command.description);
+    }
     commands
 }
 
-pub fn render_shell_script() -> String {
+/// The guest's `/etc/profile.d/vibebox.sh`: `vibebox_help`, a function per `[commands]` entry
+/// and the `:name` aliases for all of them.
+pub fn render_shell_script(user: &BTreeMap<String, CommandConfig>, project_dir: &str) -> String {
     let mut lines = Vec::new();
     lines.push("vibebox_help() {".to_string());
     lines.push("  cat <<'VIBEBOX_HELP'".to_string());
@@ -76,16 +117,73 @@ pub fn render_shell_script() -> String {
     for spec in COMMAND_SPECS {
         lines.push(format!("{}  {}", spec.name, spec.description));
     }
+    for (name, command) in user {
+        lines.push(format!(":{name}  {}", command.description));
+    }
     lines.push("VIBEBOX_HELP".to_string());
     lines.push("}".to_string());
+    for (name, command) in user {
+        lines.push(format!("{}() {{", function_name(name)));
+        lines.push(format!(
+            "  {}",
+            guest_command_line(name, command, project_dir, &["\"$@\"".to_string()])
+        ));
+        lines.push("}".to_string());
+    }
     for spec in COMMAND_SPECS {
         if let Some(alias) = spec.shell_alias {
             lines.push(format!("alias {}='{}'", spec.name, alias));
         }
     }
+    for name in user.keys() {
+        lines.push(format!("alias :{name}='{}'", function_name(name)));
+    }
     lines.join("\n")
 }
 
+/// Names differing only in `-` versus `_` map to the same function; config validation rejects them.
+pub(crate) fn function_name(name: &str) -> String {
+    format!("vibebox_cmd_{}", name.replace('-', "_"))
+}
+
+/// A shell command that runs `command` from `project_dir` in a subshell. `args` are appended as
+/// they are, so callers quote them; the script sees `:name` as `$0`.
+pub fn guest_command_line(
+    name: &str,
+    command: &CommandConfig,
+    project_dir: &str,
+    args: &[String],
+) -> String {
+    let mut line = format!(
+        "(cd {} && bash -c {} {}",
+        shell_quote(project_dir),
+        shell_quote(&command.script),
+        shell_quote(&format!(":{name}"))
+    );
+    for arg in args {
+        line.push(' ');
+        line.push_str(arg);
+    }
+    line.push(')');
+    line
+}
+
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\"'\"'"))
+}
+
+/// Installs the rendered shell script in the guest on every boot, so `[commands]` edits apply
+/// after a restart.
+pub(crate) fn login_action(shell_script: String) -> LoginAction {
+    LoginAction::Agent(Arc::new(move |agent, _vars| {
+        agent.put(
+            GUEST_SHELL_SCRIPT_PATH,
+            format!("{shell_script}\n").as_bytes(),
+            Some(0o644),
+        )
+    }))
+}
+
 pub fn build_handlers(app: Arc<Mutex<AppState>>, io_control: Arc<IoControl>) -> CommandHandlers {
     let mut handlers = CommandHandlers::new();
     for spec in COMMAND_SPECS {
@@ -105,7 +203,51 @@ pub fn build_handlers(app: Arc<Mutex<AppState>>, io_control: Arc<IoControl>) ->
                     std::process::exit(0);
                 });
             }
+            CommandKind::Host => {}
         }
     }
     handlers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_commands_become_functions_aliases_and_help() {
+        let user = BTreeMap::from([(
+            "serve-docs".to_string(),
+            CommandConfig {
+                description: "Serve the docs".into(),
+                script: "mdbook serve --port ${1:-3000} 'book'".into(),
+            },
+        )]);
+        let script = render_shell_script(&user, "/usr/local/vibebox-mounts/app");
+        assert!(script.contains(":serve-docs  Serve the docs\nVIBEBOX_HELP"));
+        assert!(script.contains("alias :serve-docs='vibebox_cmd_serve_docs'"));
+        assert!(script.contains(
+            "vibebox_cmd_serve_docs() {\n  (cd '/usr/local/vibebox-mounts/app' && bash -c \
+'mdbook serve --port ${1:-3000} '\"'\"'book'\"'\"'' ':serve-docs' \"$@\")\n}"
+        ));
+
+        let names: Vec<_> = build_commands(&user)
+            .items()
+            .iter()
+            .map(|command| command.name.clone())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ":help",
+                ":exit",
+                ":status",
+                ":explain",
+                ":pin",
+                ":unpin",
+                ":stop",
+                ":serve-docs"
+            ]
+        );
+        assert!(script.contains("alias :stop='vibebox-host stop'"));
+    }
+}
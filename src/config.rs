@@ -1,11 +1,12 @@
 use std::{
+    collections::BTreeMap,
     env, fs, io,
     path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::vm::DirectoryShare;
+use crate::{commands, mount_guard, vm::DirectoryShare};
 
 pub const CONFIG_FILENAME: &str = "vibebox.toml";
 pub const CONFIG_PATH_ENV: &str = "VIBEBOX_CONFIG_PATH";
@@ -14,12 +15,76 @@ const DEFAULT_CPU_COUNT: usize = 2;
 const DEFAULT_RAM_MB: u64 = 2048;
 const DEFAULT_AUTO_SHUTDOWN_MS: u64 = 20000;
 const DEFAULT_DISK_GB: u64 = 5;
+const DEFAULT_HOOK_TIMEOUT_MS: u64 = 30_000;
+/// The `[hooks]` keys that name a command, in the order a session reaches them.
+pub const HOOK_NAMES: &[&str] = &[
+    "pre_start",
+    "post_boot",
+    "on_attach",
+    "on_detach",
+    "post_stop",
+];
+/// Names taken by the built-in `:`-commands.
+const RESERVED_COMMANDS: &[&str] = &["help", "exit", "status", "explain", "pin", "unpin", "stop"];
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(rename = "box")]
     pub box_cfg: BoxConfig,
     pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// Project commands, keyed by name without the leading `:`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, CommandConfig>,
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    pub hooks: HooksConfig,
+}
+
+/// A `[commands.<name>]` entry: `:<name>` in the guest shell and `vibebox run :<name>` on the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandConfig {
+    #[serde(default)]
+    pub description: String,
+    /// Run with bash from the project directory; extra arguments arrive as `$1`, `$2`, ...
+    pub script: String,
+}
+
+/// `[hooks]`: host shell commands the vm manager runs as the session changes state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_boot: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_attach: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_detach: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_stop: Option<String>,
+    /// A hook still running after this long is killed.
+    #[serde(default = "default_hook_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_start: None,
+            post_boot: None,
+            on_attach: None,
+            on_detach: None,
+            post_stop: None,
+            timeout_ms: default_hook_timeout_ms(),
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +93,11 @@ pub struct BoxConfig {
     pub ram_mb: u64,
     pub disk_gb: u64,
     pub mounts: Vec<String>,
+    /// Allows mounts that expose credentials (`~/.ssh`, `~/.aws`, ...) or the whole home.
+    #[serde(default)]
+    pub allow_sensitive: bool,
+    #[serde(default)]
+    pub project_mode: ProjectMode,
 }
 
 impl Default for BoxConfig {
@@ -37,6 +107,27 @@ impl Default for BoxConfig {
             ram_mb: default_ram_mb(),
             disk_gb: default_disk_gb(),
             mounts: default_mounts(),
+            allow_sensitive: false,
+            project_mode: ProjectMode::default(),
+        }
+    }
+}
+
+/// How the guest sees the project: `mount` writes straight to the host, `overlay` shares it
+/// read-only and keeps the guest's changes on the instance disk until `vibebox apply`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectMode {
+    #[default]
+    Mount,
+    Overlay,
+}
+
+impl ProjectMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProjectMode::Mount => "mount",
+            ProjectMode::Overlay => "overlay",
         }
     }
 }
@@ -54,6 +145,58 @@ impl Default for SupervisorConfig {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    pub profile: SecurityProfile,
+}
+
+/// How much the guest user may do: `permissive` gets passwordless sudo, `standard` gets sudo
+/// behind a password, `strict` gets no sudo, read-only extra mounts and no egress beyond the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityProfile {
+    Permissive,
+    #[default]
+    Standard,
+    Strict,
+}
+
+impl SecurityProfile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SecurityProfile::Permissive => "permissive",
+            SecurityProfile::Standard => "standard",
+            SecurityProfile::Strict => "strict",
+        }
+    }
+
+    pub fn summary(self) -> &'static str {
+        match self {
+            SecurityProfile::Permissive => "passwordless sudo, mounts as configured, full network",
+            SecurityProfile::Standard => "sudo with password, mounts as configured, full network",
+            SecurityProfile::Strict => {
+                "no sudo, extra mounts read-only, egress limited to the host"
+            }
+        }
+    }
+
+    pub fn allows_sudo(self) -> bool {
+        self != SecurityProfile::Strict
+    }
+
+    /// Rewrites a `[box].mounts` entry for this profile; strict forces it read-only.
+    pub fn mount_spec(self, spec: &str) -> String {
+        if self != SecurityProfile::Strict {
+            return spec.to_string();
+        }
+        let parts: Vec<&str> = spec.split(':').collect();
+        match parts.as_slice() {
+            [host, guest] | [host, guest, _] => format!("{host}:{guest}:read-only"),
+            _ => spec.to_string(),
+        }
+    }
+}
+
 fn default_cpu_count() -> usize {
     DEFAULT_CPU_COUNT
 }
@@ -66,6 +209,10 @@ fn default_auto_shutdown_ms() -> u64 {
     DEFAULT_AUTO_SHUTDOWN_MS
 }
 
+fn default_hook_timeout_ms() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_MS
+}
+
 fn default_mounts() -> Vec<String> {
     vec![
         "~/.codex:~/.codex:read-write".into(),
@@ -81,18 +228,38 @@ pub fn config_path(project_root: &Path) -> PathBuf {
     project_root.join(CONFIG_FILENAME)
 }
 
+/// Why a project's config could not be resolved, created or loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to resolve project root: {0}")]
+    ProjectRoot(io::Error),
+    #[error("config path must be within {}: {}", root.display(), path.display())]
+    OutsideProject { root: PathBuf, path: PathBuf },
+    #[error("failed to create config: {0}")]
+    Create(io::Error),
+    #[error("failed to read config: {0}")]
+    Read(io::Error),
+    #[error("{0}")]
+    Invalid(String),
+}
+
 pub fn ensure_config_file(
     project_root: &Path,
     override_path: Option<&Path>,
 ) -> Result<PathBuf, io::Error> {
     let path = resolve_config_path(project_root, override_path);
+    create_default_config(&path)?;
+    Ok(path)
+}
+
+fn create_default_config(path: &Path) -> Result<(), io::Error> {
     if !path.exists() {
         let default_config = Config::default();
         let contents = toml::to_string_pretty(&default_config).unwrap_or_default();
-        fs::write(&path, contents)?;
+        fs::write(path, contents)?;
         tracing::info!(path = %path.display(), "created vibebox config");
     }
-    Ok(path)
+    Ok(())
 }
 
 pub fn load_config(project_root: &Path) -> Config {
@@ -100,50 +267,67 @@ pub fn load_config(project_root: &Path) -> Config {
 }
 
 pub fn load_config_with_path(project_root: &Path, override_path: Option<&Path>) -> Config {
-    let path = match ensure_config_file(project_root, override_path) {
-        Ok(path) => path,
-        Err(err) => die(&format!("failed to create config: {err}")),
-    };
-    let raw = match fs::read_to_string(&path) {
-        Ok(raw) => raw,
-        Err(err) => die(&format!("failed to read config: {err}")),
-    };
-    let trimmed = raw.trim();
+    try_load_config(project_root, override_path).unwrap_or_else(|err| die(&err.to_string()))
+}
+
+/// Like [`load_config_with_path`], but returns the error instead of exiting.
+pub fn try_load_config(
+    project_root: &Path,
+    override_path: Option<&Path>,
+) -> Result<Config, ConfigError> {
+    let path = try_resolve_config_path(project_root, override_path)?;
+    create_default_config(&path).map_err(ConfigError::Create)?;
+    let raw = fs::read_to_string(&path).map_err(ConfigError::Read)?;
     tracing::debug!(path = %path.display(), bytes = raw.len(), "loaded vibebox config");
+    parse_config(&raw, &path, project_root)
+}
+
+/// Parses and validates config text; `path` is only used in messages.
+fn parse_config(raw: &str, path: &Path, project_root: &Path) -> Result<Config, ConfigError> {
+    let trimmed = raw.trim();
     if trimmed.is_empty() {
-        die(&format!(
+        return Err(ConfigError::Invalid(format!(
             "config file ({}) is empty. Required fields: [box].cpu_count (integer), [box].ram_mb (integer), [box].disk_gb (integer), [box].mounts (array of strings), [supervisor].auto_shutdown_ms (integer)",
             path.display()
-        ));
+        )));
     }
 
-    let value: toml::Value = match toml::from_str(trimmed) {
-        Ok(value) => value,
-        Err(err) => die(&format!("invalid config: {err}")),
-    };
+    let value: toml::Value = toml::from_str(trimmed)
+        .map_err(|err| ConfigError::Invalid(format!("invalid config: {err}")))?;
     let schema_errors = validate_schema(&value);
     if !schema_errors.is_empty() {
-        let message = format!(
+        return Err(ConfigError::Invalid(format!(
             "config file ({}) is missing or invalid fields:\n- {}",
             path.display(),
             schema_errors.join("\n- ")
-        );
-        die(&message);
+        )));
     }
 
-    let config: Config = match toml::from_str(trimmed) {
-        Ok(config) => config,
-        Err(err) => die(&format!("invalid config: {err}")),
-    };
-    validate_or_exit(&config);
-    config
+    let config: Config = toml::from_str(trimmed)
+        .map_err(|err| ConfigError::Invalid(format!("invalid config: {err}")))?;
+    validate_config(&config, project_root).map_err(ConfigError::Invalid)?;
+    Ok(config)
 }
 
-fn resolve_config_path(project_root: &Path, override_path: Option<&Path>) -> PathBuf {
-    let root = match fs::canonicalize(project_root) {
-        Ok(root) => root,
-        Err(err) => die(&format!("failed to resolve project root: {err}")),
-    };
+/// Reads a project's `vibebox.toml` for display only: never creates, validates or exits.
+pub fn peek_config(project_root: &Path) -> Option<Config> {
+    peek_config_at(&config_path(project_root))
+}
+
+pub fn peek_config_at(path: &Path) -> Option<Config> {
+    let raw = fs::read_to_string(path).ok()?;
+    toml::from_str(&raw).ok()
+}
+
+pub fn resolve_config_path(project_root: &Path, override_path: Option<&Path>) -> PathBuf {
+    try_resolve_config_path(project_root, override_path).unwrap_or_else(|err| die(&err.to_string()))
+}
+
+pub fn try_resolve_config_path(
+    project_root: &Path,
+    override_path: Option<&Path>,
+) -> Result<PathBuf, ConfigError> {
+    let root = fs::canonicalize(project_root).map_err(ConfigError::ProjectRoot)?;
 
     let override_path = override_path
         .map(PathBuf::from)
@@ -160,16 +344,15 @@ fn resolve_config_path(project_root: &Path, override_path: Option<&Path>) -> Pat
 
     let normalized = normalize_path(&raw_path);
     if !normalized.starts_with(&root) {
-        die(&format!(
-            "config path must be within {}: {}",
-            root.display(),
-            normalized.display()
-        ));
+        return Err(ConfigError::OutsideProject {
+            root,
+            path: normalized,
+        });
     }
-    normalized
+    Ok(normalized)
 }
 
-fn normalize_path(path: &Path) -> PathBuf {
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
     let mut normalized = PathBuf::new();
     for component in path.components() {
         match component {
@@ -210,6 +393,12 @@ fn validate_schema(value: &toml::Value) -> Vec<String> {
                     "[box].mounts (array of strings)",
                     &mut errors,
                 );
+                match table.get("project_mode").map(toml::Value::as_str) {
+                    None | Some(Some("mount" | "overlay")) => {}
+                    Some(_) => errors.push(
+                        "invalid [box].project_mode: expected \"mount\" or \"overlay\"".to_string(),
+                    ),
+                }
             }
             None => errors.push("[box] must be a table".to_string()),
         },
@@ -230,9 +419,104 @@ fn validate_schema(value: &toml::Value) -> Vec<String> {
         },
     }
 
+    if let Some(value) = root.get("security") {
+        match value.as_table() {
+            Some(table) => match table.get("profile") {
+                None => errors.push("missing [security].profile (string)".to_string()),
+                Some(value) => match value.as_str() {
+                    Some("permissive" | "standard" | "strict") => {}
+                    _ => errors.push(
+                        "invalid [security].profile: expected \"permissive\", \"standard\" or \"strict\""
+                            .to_string(),
+                    ),
+                },
+            },
+            None => errors.push("[security] must be a table".to_string()),
+        }
+    }
+
+    if let Some(value) = root.get("commands") {
+        match value.as_table() {
+            Some(table) => {
+                let mut functions: BTreeMap<String, &str> = BTreeMap::new();
+                for (name, command) in table {
+                    validate_command(name, command, &mut errors);
+                    let function = commands::function_name(name);
+                    if let Some(other) = functions.get(&function) {
+                        errors.push(format!(
+                            "[commands.{other}] and [commands.{name}] both become the shell function {function}; rename one"
+                        ));
+                    } else {
+                        functions.insert(function, name);
+                    }
+                }
+            }
+            None => errors.push("[commands] must be a table".to_string()),
+        }
+    }
+
+    if let Some(value) = root.get("hooks") {
+        match value.as_table() {
+            Some(table) => validate_hooks(table, &mut errors),
+            None => errors.push("[hooks] must be a table".to_string()),
+        }
+    }
+
     errors
 }
 
+fn validate_command(name: &str, command: &toml::Value, errors: &mut Vec<String>) {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        errors.push(format!(
+            "invalid command name [commands.{name}]: use letters, digits, '-' and '_' (no leading ':')"
+        ));
+    } else if RESERVED_COMMANDS.contains(&name) {
+        errors.push(format!(
+            "[commands.{name}] clashes with the built-in :{name} command"
+        ));
+    }
+    let Some(table) = command.as_table() else {
+        errors.push(format!("[commands.{name}] must be a table"));
+        return;
+    };
+    match table.get("script").map(toml::Value::as_str) {
+        Some(Some(script)) if !script.trim().is_empty() => {}
+        _ => errors.push(format!("missing [commands.{name}].script (string)")),
+    }
+    if table
+        .get("description")
+        .is_some_and(|value| !value.is_str())
+    {
+        errors.push(format!(
+            "invalid [commands.{name}].description: expected string"
+        ));
+    }
+}
+
+fn validate_hooks(table: &toml::value::Table, errors: &mut Vec<String>) {
+    for (key, value) in table {
+        if key == "timeout_ms" {
+            if value.as_integer().is_none() {
+                errors.push("invalid [hooks].timeout_ms: expected integer".to_string());
+            }
+        } else if !HOOK_NAMES.contains(&key.as_str()) {
+            errors.push(format!(
+                "unknown hook [hooks].{key}; expected one of {}",
+                HOOK_NAMES.join(", ")
+            ));
+        } else if value
+            .as_str()
+            .is_none_or(|command| command.trim().is_empty())
+        {
+            errors.push(format!("invalid [hooks].{key}: expected a command string"));
+        }
+    }
+}
+
 fn validate_int(table: &toml::value::Table, key: &str, label: &str, errors: &mut Vec<String>) {
     match table.get(key) {
         None => errors.push(format!("missing {label}")),
@@ -263,23 +547,47 @@ fn validate_string_array(
     }
 }
 
-fn validate_or_exit(config: &Config) {
+/// Checks the values serde accepts but a VM cannot use. Relative mount hosts are checked
+/// against `project_root`, where the vm manager runs.
+pub fn validate_config(config: &Config, project_root: &Path) -> Result<(), String> {
     if config.box_cfg.cpu_count == 0 {
-        die("box.cpu_count must be >= 1");
+        return Err("box.cpu_count must be >= 1".to_string());
     }
     if config.box_cfg.ram_mb == 0 {
-        die("box.ram_mb must be >= 1");
+        return Err("box.ram_mb must be >= 1".to_string());
     }
     if config.box_cfg.disk_gb == 0 {
-        die("box.disk_gb must be >= 1");
+        return Err("box.disk_gb must be >= 1".to_string());
     }
     if config.supervisor.auto_shutdown_ms == 0 {
-        die("supervisor.auto_shutdown_ms must be >= 1");
+        return Err("supervisor.auto_shutdown_ms must be >= 1".to_string());
+    }
+    if config.hooks.timeout_ms == 0 {
+        return Err("hooks.timeout_ms must be >= 1".to_string());
     }
     for spec in &config.box_cfg.mounts {
-        if let Err(err) = DirectoryShare::from_mount_spec(spec) {
-            die(&format!("invalid mount spec '{spec}': {err}"));
+        let resolved = project_mount_spec(spec, project_root);
+        if let Err(err) = DirectoryShare::from_mount_spec(&resolved) {
+            return Err(format!("invalid mount spec '{spec}': {err}"));
+        }
+        if let Some(found) = mount_guard::check_mount_spec(&resolved) {
+            if !config.box_cfg.allow_sensitive {
+                return Err(format!(
+                    "refusing mount '{spec}': host path {found}. Set [box].allow_sensitive = true to mount it anyway"
+                ));
+            }
+            tracing::warn!(spec, reason = %found, "allowing sensitive mount");
+        }
+    }
+    Ok(())
+}
+
+fn project_mount_spec(spec: &str, project_root: &Path) -> String {
+    match spec.split_once(':') {
+        Some((host, rest)) if !host.starts_with('~') && Path::new(host).is_relative() => {
+            format!("{}:{rest}", project_root.join(host).display())
         }
+        _ => spec.to_string(),
     }
 }
 
@@ -287,3 +595,114 @@ fn die(message: &str) -> ! {
     tracing::error!("{message}");
     std::process::exit(1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_profile_defaults_to_standard() {
+        let raw = "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n[supervisor]\nauto_shutdown_ms = 1000\n";
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.security.profile, SecurityProfile::Standard);
+
+        let strict: Config =
+            toml::from_str(&format!("{raw}\n[security]\nprofile = \"strict\"\n")).unwrap();
+        assert_eq!(strict.security.profile, SecurityProfile::Strict);
+
+        let value: toml::Value =
+            toml::from_str(&format!("{raw}\n[security]\nprofile = \"paranoid\"\n")).unwrap();
+        let errors = validate_schema(&value);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].contains("[security].profile"), "{errors:?}");
+    }
+
+    #[test]
+    fn project_mode_defaults_to_mount() {
+        let raw = "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n[supervisor]\nauto_shutdown_ms = 1000\n";
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.box_cfg.project_mode, ProjectMode::Mount);
+
+        let overlay = raw.replace("mounts = []", "mounts = []\nproject_mode = \"overlay\"");
+        let config: Config = toml::from_str(&overlay).unwrap();
+        assert_eq!(config.box_cfg.project_mode, ProjectMode::Overlay);
+        assert!(validate_schema(&toml::from_str(&overlay).unwrap()).is_empty());
+
+        let value: toml::Value =
+            toml::from_str(&raw.replace("mounts = []", "mounts = []\nproject_mode = \"copy\""))
+                .unwrap();
+        let errors = validate_schema(&value);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].contains("[box].project_mode"), "{errors:?}");
+    }
+
+    #[test]
+    fn commands_table_is_validated() {
+        let raw = "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n[supervisor]\nauto_shutdown_ms = 1000\n";
+        let with_commands = format!(
+            "{raw}\n[commands.test]\ndescription = \"Run tests\"\nscript = \"cargo test\"\n"
+        );
+        let config: Config = toml::from_str(&with_commands).unwrap();
+        assert_eq!(config.commands["test"].script, "cargo test");
+        assert!(validate_schema(&toml::from_str(&with_commands).unwrap()).is_empty());
+
+        let value: toml::Value = toml::from_str(&format!(
+            "{raw}\n[commands.help]\nscript = \"x\"\n[commands.\":lint\"]\nscript = \"y\"\n[commands.serve]\ndescription = \"no script\"\n"
+        ))
+        .unwrap();
+        let errors = validate_schema(&value);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].contains("invalid command name [commands.:lint]"));
+        assert!(errors[1].contains("built-in :help"));
+        assert!(errors[2].contains("[commands.serve].script"));
+
+        let value: toml::Value = toml::from_str(&format!(
+            "{raw}\n[commands.serve-docs]\nscript = \"x\"\n[commands.serve_docs]\nscript = \"y\"\n"
+        ))
+        .unwrap();
+        let errors = validate_schema(&value);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(
+            errors[0].contains("[commands.serve-docs] and [commands.serve_docs]"),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn hooks_table_is_validated() {
+        let raw = "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n[supervisor]\nauto_shutdown_ms = 1000\n";
+        let with_hooks =
+            format!("{raw}\n[hooks]\npre_start = \"./proxy.sh &\"\ntimeout_ms = 500\n");
+        let config: Config = toml::from_str(&with_hooks).unwrap();
+        assert_eq!(config.hooks.pre_start.as_deref(), Some("./proxy.sh &"));
+        assert_eq!(config.hooks.timeout_ms, 500);
+        assert!(validate_schema(&toml::from_str(&with_hooks).unwrap()).is_empty());
+        let config: Config = toml::from_str(raw).unwrap();
+        assert!(config.hooks.is_empty());
+        assert_eq!(config.hooks.timeout_ms, DEFAULT_HOOK_TIMEOUT_MS);
+
+        let value: toml::Value = toml::from_str(&format!(
+            "{raw}\n[hooks]\non_exit = \"x\"\npost_boot = 3\ntimeout_ms = \"1s\"\n"
+        ))
+        .unwrap();
+        let errors = validate_schema(&value);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].contains("unknown hook [hooks].on_exit"));
+        assert!(errors[1].contains("[hooks].post_boot"));
+        assert!(errors[2].contains("[hooks].timeout_ms"));
+    }
+
+    #[test]
+    fn strict_profile_forces_mounts_read_only() {
+        let strict = SecurityProfile::Strict;
+        assert_eq!(
+            strict.mount_spec("~/.codex:~/.codex:read-write"),
+            "~/.codex:~/.codex:read-only"
+        );
+        assert_eq!(strict.mount_spec("/data:/data"), "/data:/data:read-only");
+        assert_eq!(
+            SecurityProfile::Standard.mount_spec("~/.codex:~/.codex:read-write"),
+            "~/.codex:~/.codex:read-write"
+        );
+    }
+}
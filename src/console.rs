@@ -0,0 +1,329 @@
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    agent::{self, Frame, READY_FRAME_ID},
+    login_script::LoginAction,
+    session_manager::VM_CONSOLE_SOCKET_NAME,
+    vm::{self, VmInput},
+    vm_manager,
+};
+
+pub const DEFAULT_DETACH_KEYS: &str = "ctrl-]";
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longer than any ready frame; a partial line past this is shell output, not the agent.
+const MAX_FRAME_LINE_BYTES: usize = 4096;
+
+/// The terminal attached to the VM serial console, if any. The vm manager copies every byte of
+/// console output here next to `vm_root.log`.
+#[derive(Default)]
+pub struct ConsoleHub {
+    client: Mutex<Option<UnixStream>>,
+    agent_ready: Arc<AtomicBool>,
+    booted: AtomicBool,
+    /// Console output since the agent handed the console over, until its ready frame is back.
+    handed_over: Mutex<Option<Vec<u8>>>,
+}
+
+impl ConsoleHub {
+    pub fn new(agent_ready: Arc<AtomicBool>) -> Self {
+        Self {
+            agent_ready,
+            ..Self::default()
+        }
+    }
+
+    pub fn forward_output(&self, bytes: &[u8]) {
+        {
+            let mut client = self.client.lock().unwrap();
+            if let Some(stream) = client.as_mut()
+                && stream.write_all(bytes).is_err()
+            {
+                tracing::info!("console client dropped");
+                *client = None;
+            }
+        }
+        self.watch_for_agent(bytes);
+    }
+
+    /// The boot steps own the console until they are done, so clients may only attach after.
+    pub fn boot_finished(&self) {
+        self.booted.store(true, Ordering::SeqCst);
+    }
+
+    fn attach(&self, stream: UnixStream) -> bool {
+        let mut client = self.client.lock().unwrap();
+        if client.is_some() {
+            return false;
+        }
+        let _ = stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+        *client = Some(stream);
+        true
+    }
+
+    fn detach(&self) {
+        *self.client.lock().unwrap() = None;
+    }
+
+    /// Asks the agent to give the console to a root shell, if it owns it.
+    fn hand_over(&self, vm_input_tx: &Mutex<Option<Sender<VmInput>>>) {
+        if !self.agent_ready.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        *self.handed_over.lock().unwrap() = Some(Vec::new());
+        match vm_input_tx.lock().unwrap().as_ref() {
+            Some(tx) if tx.send(agent::console_input()).is_ok() => {
+                tracing::info!("guest agent asked to hand over the console");
+            }
+            _ => {
+                *self.handed_over.lock().unwrap() = None;
+                self.agent_ready.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// The agent prints its ready frame again once the rescue shell exits.
+    fn watch_for_agent(&self, bytes: &[u8]) {
+        let mut handed_over = self.handed_over.lock().unwrap();
+        let Some(pending) = handed_over.as_mut() else {
+            return;
+        };
+        pending.extend_from_slice(bytes);
+        let mut back = false;
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            back |= Frame::parse(&String::from_utf8_lossy(&line))
+                .is_some_and(|frame| frame.id == READY_FRAME_ID && frame.kind == "ready");
+        }
+        if back {
+            *handed_over = None;
+            self.agent_ready.store(true, Ordering::SeqCst);
+            tracing::info!("guest agent took the console back");
+        } else if pending.len() > MAX_FRAME_LINE_BYTES {
+            pending.clear();
+        }
+    }
+}
+
+/// Marks the end of the boot steps, after which `vibebox console` may attach.
+pub(crate) fn boot_finished_action(hub: Arc<ConsoleHub>) -> LoginAction {
+    LoginAction::Hook(Arc::new(move |_| hub.boot_finished()))
+}
+
+/// Accepts `vibebox console` clients on `listener` once the boot steps are done. One client at
+/// a time may attach; its keystrokes are written to the console. If the guest agent owns the
+/// console, it is asked to hand it over to a root shell first, and it counts as ready again
+/// once that shell exits.
+pub(crate) fn serve(
+    listener: UnixListener,
+    hub: Arc<ConsoleHub>,
+    vm_input_tx: Arc<Mutex<Option<Sender<VmInput>>>>,
+) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            break;
+        };
+        if !hub.booted.load(Ordering::SeqCst) {
+            let _ = stream
+                .write_all(b"[vibebox] the VM is still booting; attach again once it is up\r\n");
+            continue;
+        }
+        let Ok(output) = stream.try_clone() else {
+            continue;
+        };
+        if !hub.attach(output) {
+            let _ = stream.write_all(b"[vibebox] another console is already attached\r\n");
+            continue;
+        }
+        tracing::info!("console client attached");
+        hub.hand_over(&vm_input_tx);
+        let hub = hub.clone();
+        let vm_input_tx = vm_input_tx.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            loop {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => match vm_input_tx.lock().unwrap().as_ref() {
+                        Some(tx) => {
+                            let _ = tx.send(VmInput::Bytes(buf[..n].to_vec()));
+                        }
+                        None => tracing::debug!("console input dropped; vm input not ready"),
+                    },
+                }
+            }
+            hub.detach();
+            tracing::info!("console client detached");
+        });
+    }
+}
+
+/// Parses a detach sequence such as `ctrl-]` or `ctrl-p,ctrl-q` into the bytes a terminal sends.
+pub fn parse_detach_keys(spec: &str) -> Result<Vec<u8>, String> {
+    let mut keys = Vec::new();
+    for key in spec.split(',') {
+        let key = key.trim();
+        let byte = match key.strip_prefix("ctrl-") {
+            Some(rest) if rest.len() == 1 => {
+                let c = rest.as_bytes()[0].to_ascii_uppercase();
+                if !(b'@'..=b'_').contains(&c) {
+                    return Err(format!("unsupported detach key '{key}'"));
+                }
+                c - b'@'
+            }
+            None if key.len() == 1 && key.is_ascii() => key.as_bytes()[0],
+            _ => return Err(format!("invalid detach key '{key}'")),
+        };
+        keys.push(byte);
+    }
+    if keys.is_empty() {
+        return Err("detach sequence is empty".into());
+    }
+    Ok(keys)
+}
+
+/// Strips the detach sequence out of keyboard input, holding back a partial match until the
+/// next key decides it.
+struct DetachMatcher {
+    keys: Vec<u8>,
+    matched: usize,
+}
+
+impl DetachMatcher {
+    fn new(keys: Vec<u8>) -> Self {
+        Self { keys, matched: 0 }
+    }
+
+    /// Returns the bytes to forward and whether the full sequence was typed.
+    fn filter(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let mut forward = Vec::with_capacity(input.len());
+        for &byte in input {
+            if byte != self.keys[self.matched] && self.matched > 0 {
+                forward.extend_from_slice(&self.keys[..self.matched]);
+                self.matched = 0;
+            }
+            if byte == self.keys[self.matched] {
+                self.matched += 1;
+                if self.matched == self.keys.len() {
+                    return (forward, true);
+                }
+            } else {
+                forward.push(byte);
+            }
+        }
+        (forward, false)
+    }
+}
+
+/// Attaches this terminal to the running VM's serial console until the detach sequence is
+/// typed or the VM stops. The manager connection is held meanwhile so the VM stays up.
+pub fn attach(instance_dir: &Path, detach_keys: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut matcher = DetachMatcher::new(parse_detach_keys(detach_keys)?);
+    let socket_path = instance_dir.join(VM_CONSOLE_SOCKET_NAME);
+    let mut console = UnixStream::connect(&socket_path).map_err(|err| {
+        format!(
+            "No running VM for {} ({err}); start one with `vibebox` first",
+            instance_dir.display()
+        )
+    })?;
+    let _manager = vm_manager::connect_manager(instance_dir)?;
+
+    eprintln!("[vibebox] attached to the VM console; type {detach_keys} to detach");
+    let raw_guard = if io::stdin().is_terminal() {
+        Some(vm::enable_raw_mode(libc::STDIN_FILENO)?)
+    } else {
+        None
+    };
+
+    let mut fds = [
+        libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: console.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let mut buf = [0u8; 1024];
+    let mut stdin_open = true;
+    let reason = loop {
+        let watched = if stdin_open {
+            &mut fds[..]
+        } else {
+            &mut fds[1..]
+        };
+        let ret = unsafe { libc::poll(watched.as_mut_ptr(), watched.len() as libc::nfds_t, -1) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err.into());
+        }
+        if fds[1].revents != 0 {
+            let n = console.read(&mut buf)?;
+            if n == 0 {
+                break "VM console closed";
+            }
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&buf[..n])?;
+            stdout.flush()?;
+        }
+        if stdin_open && fds[0].revents != 0 {
+            let n = io::stdin().lock().read(&mut buf)?;
+            if n == 0 {
+                stdin_open = false;
+                continue;
+            }
+            let (forward, detached) = matcher.filter(&buf[..n]);
+            if !forward.is_empty() {
+                console.write_all(&forward)?;
+            }
+            if detached {
+                break "detached from the VM console";
+            }
+        }
+    };
+    drop(raw_guard);
+    eprintln!("\r\n[vibebox] {reason}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_detach_keys() {
+        assert_eq!(parse_detach_keys(DEFAULT_DETACH_KEYS), Ok(vec![0x1d]));
+        assert_eq!(parse_detach_keys("ctrl-p, ctrl-q"), Ok(vec![0x10, 0x11]));
+        assert_eq!(parse_detach_keys("ctrl-a,d"), Ok(vec![0x01, b'd']));
+        assert!(parse_detach_keys("").is_err());
+        assert!(parse_detach_keys("ctrl-1").is_err());
+        assert!(parse_detach_keys("alt-x").is_err());
+    }
+
+    #[test]
+    fn detach_matcher_forwards_partial_matches() {
+        let mut matcher = DetachMatcher::new(vec![0x10, 0x11]);
+        assert_eq!(matcher.filter(b"ls\r\x10"), (b"ls\r".to_vec(), false));
+        assert_eq!(matcher.filter(b"x\x10"), (b"\x10x".to_vec(), false));
+        assert_eq!(matcher.filter(b"\x10\x11rest"), (b"\x10".to_vec(), true));
+    }
+}
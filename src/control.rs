@@ -0,0 +1,317 @@
+use std::{
+    fs, io,
+    io::{Read, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::login_script::LoginAction;
+
+/// Host side of the control share, inside the instance directory.
+pub const CONTROL_DIR_NAME: &str = "control";
+/// Where the control share is mounted in the guest.
+pub const CONTROL_GUEST_DIR: &str = "/run/vibebox/control";
+const GUEST_CLIENT_PATH: &str = "/usr/local/bin/vibebox-host";
+const CONTROL_SCRIPT: &str = include_str!("control.sh");
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const GUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longer than the guest waits, so only abandoned answers are swept.
+const RESPONSE_TTL: Duration = Duration::from_secs(30);
+const MAX_REQUEST_BYTES: u64 = 64;
+const MAX_ID_LEN: usize = 64;
+
+/// The only operations a guest can ask of its manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRequest {
+    Status,
+    Explain,
+    Pin,
+    Unpin,
+    Stop,
+}
+
+impl ControlRequest {
+    pub const ALL: [ControlRequest; 5] = [
+        ControlRequest::Status,
+        ControlRequest::Explain,
+        ControlRequest::Pin,
+        ControlRequest::Unpin,
+        ControlRequest::Stop,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ControlRequest::Status => "status",
+            ControlRequest::Explain => "explain",
+            ControlRequest::Pin => "pin",
+            ControlRequest::Unpin => "unpin",
+            ControlRequest::Stop => "stop",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|request| request.as_str() == value.trim())
+    }
+}
+
+/// Answers requests until dropped.
+pub struct ControlServer {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Creates an empty control directory that only the host user can enter; what guest users may
+/// do in it is up to the guest mount.
+pub fn prepare_dir(instance_dir: &Path) -> io::Result<PathBuf> {
+    let dir = instance_dir.join(CONTROL_DIR_NAME);
+    if fs::symlink_metadata(&dir).is_ok() {
+        remove_entry(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    Ok(dir)
+}
+
+/// Polls `dir` for `<id>.req` files and writes each answer to `<id>.resp`: `ok` or `error` on
+/// the first line, then the text for the guest.
+pub fn serve<F>(dir: PathBuf, handler: F) -> ControlServer
+where
+    F: Fn(ControlRequest) -> Result<String, String> + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_for_thread = stop.clone();
+    thread::spawn(move || {
+        while !stop_for_thread.load(Ordering::SeqCst) {
+            if let Err(err) = poll_once(&dir, &handler) {
+                tracing::debug!(path = %dir.display(), error = %err, "control poll failed");
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    ControlServer { stop }
+}
+
+fn poll_once<F>(dir: &Path, handler: &F) -> io::Result<()>
+where
+    F: Fn(ControlRequest) -> Result<String, String>,
+{
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if name.ends_with(".resp") {
+            sweep_response(&path);
+            continue;
+        }
+        let Some(id) = name.strip_suffix(".req").filter(|id| is_valid_id(id)) else {
+            continue;
+        };
+        let request = read_request(&path);
+        let _ = fs::remove_file(&path);
+        let reply = match request {
+            Some(request) => {
+                tracing::info!(request = request.as_str(), "guest control request");
+                handler(request)
+            }
+            None => Err(format!(
+                "unknown request; allowed: {}",
+                allowed_operations(", ")
+            )),
+        };
+        let body = match reply {
+            Ok(text) => format!("ok\n{text}"),
+            Err(text) => format!("error\n{text}"),
+        };
+        let tmp = dir.join(format!("{id}.resp.tmp"));
+        write_response(&tmp, body.as_bytes())?;
+        fs::rename(&tmp, dir.join(format!("{id}.resp")))?;
+    }
+    Ok(())
+}
+
+/// Everything in the directory may have been put there by the guest, so nothing is followed:
+/// whatever sits at `tmp` is removed and the answer goes to a file created here.
+fn write_response(tmp: &Path, body: &[u8]) -> io::Result<()> {
+    if fs::symlink_metadata(tmp).is_ok() {
+        remove_entry(tmp)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(tmp)?;
+    file.set_permissions(fs::Permissions::from_mode(0o644))?;
+    file.write_all(body)
+}
+
+fn remove_entry(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+}
+
+fn read_request(path: &Path) -> Option<ControlRequest> {
+    if !fs::symlink_metadata(path).ok()?.is_file() {
+        return None;
+    }
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+        .ok()?;
+    let mut raw = String::new();
+    file.take(MAX_REQUEST_BYTES).read_to_string(&mut raw).ok()?;
+    ControlRequest::parse(&raw)
+}
+
+fn sweep_response(path: &Path) {
+    let expired = fs::symlink_metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > RESPONSE_TTL);
+    if expired {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn allowed_operations(separator: &str) -> String {
+    ControlRequest::ALL
+        .iter()
+        .map(|request| request.as_str())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn render_client_script() -> String {
+    CONTROL_SCRIPT
+        .replace("__CONTROL_DIR__", CONTROL_GUEST_DIR)
+        .replace(
+            "__CONTROL_TIMEOUT_TENTHS__",
+            &(GUEST_TIMEOUT.as_millis() / 100).to_string(),
+        )
+        .replace("__CONTROL_OPERATIONS__", &allowed_operations("|"))
+        .replace("__CONTROL_USAGE__", &allowed_operations("|"))
+}
+
+/// Installs `vibebox-host`, which the `:status`, `:explain`, `:pin`, `:unpin` and `:stop`
+/// aliases call.
+pub(crate) fn login_action() -> LoginAction {
+    let script = render_client_script();
+    LoginAction::Agent(Arc::new(move |agent, _vars| {
+        agent.put(GUEST_CLIENT_PATH, script.as_bytes(), Some(0o755))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn wait_for_response(dir: &Path, id: &str) -> String {
+        let path = dir.join(format!("{id}.resp"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() {
+            assert!(Instant::now() < deadline, "no answer for {id}");
+            thread::sleep(Duration::from_millis(20));
+        }
+        fs::read_to_string(path).unwrap()
+    }
+
+    /// Like the guest client: requests appear whole.
+    fn send(dir: &Path, id: &str, body: &str) {
+        let tmp = dir.join(format!("{id}.tmp"));
+        fs::write(&tmp, body).unwrap();
+        fs::rename(tmp, dir.join(format!("{id}.req"))).unwrap();
+    }
+
+    #[test]
+    fn answers_allowlisted_requests_only() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = prepare_dir(temp.path()).unwrap();
+        fs::write(dir.join("stale.req"), "status\n").unwrap();
+        let dir = prepare_dir(temp.path()).unwrap();
+        assert!(!dir.join("stale.req").exists());
+
+        let _server = serve(dir.clone(), |request| match request {
+            ControlRequest::Status => Ok("running\n".into()),
+            ControlRequest::Stop => Err("not now\n".into()),
+            other => Ok(format!("{}\n", other.as_str())),
+        });
+        send(&dir, "1-10", "status\n");
+        send(&dir, "1-11", "stop\n");
+        send(&dir, "1-12", "rm -rf /\n");
+        fs::write(dir.join("1-13.tmp"), "status\n").unwrap();
+
+        assert_eq!(wait_for_response(&dir, "1-10"), "ok\nrunning\n");
+        assert_eq!(wait_for_response(&dir, "1-11"), "error\nnot now\n");
+        assert_eq!(
+            wait_for_response(&dir, "1-12"),
+            "error\nunknown request; allowed: status, explain, pin, unpin, stop"
+        );
+        assert!(!dir.join("1-10.req").exists());
+        assert!(dir.join("1-13.tmp").exists());
+        assert!(!dir.join("1-13.resp").exists());
+    }
+
+    #[test]
+    fn never_follows_guest_symlinks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = prepare_dir(temp.path()).unwrap();
+        assert_eq!(
+            fs::metadata(&dir).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        let victim = temp.path().join("victim");
+        fs::write(&victim, "keep\n").unwrap();
+        fs::set_permissions(&victim, fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink(&victim, dir.join("2-1.resp.tmp")).unwrap();
+        std::os::unix::fs::symlink(&victim, dir.join("2-2.req")).unwrap();
+
+        let _server = serve(dir.clone(), |_| Ok("running\n".into()));
+        send(&dir, "2-1", "status\n");
+
+        assert_eq!(wait_for_response(&dir, "2-1"), "ok\nrunning\n");
+        assert_eq!(
+            wait_for_response(&dir, "2-2"),
+            "error\nunknown request; allowed: status, explain, pin, unpin, stop"
+        );
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "keep\n");
+        assert_eq!(
+            fs::metadata(&victim).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    #[test]
+    fn client_script_allows_the_same_operations() {
+        let script = render_client_script();
+        assert!(script.contains("DIR=\"/run/vibebox/control\""));
+        assert!(script.contains("  status|explain|pin|unpin|stop) ;;"));
+        assert!(script.contains("TIMEOUT_TENTHS=\"100\""));
+        assert!(!script.contains("__CONTROL"));
+    }
+}
@@ -0,0 +1,41 @@
+#!/bin/sh
+# vibebox-host: asks the vibebox manager on the host for one allowlisted operation.
+# Requests are files in a directory shared with the host; the manager answers in <id>.resp.
+DIR="__CONTROL_DIR__"
+TIMEOUT_TENTHS="__CONTROL_TIMEOUT_TENTHS__"
+
+case "${1:-}" in
+  __CONTROL_OPERATIONS__) ;;
+  *)
+    echo "usage: vibebox-host __CONTROL_USAGE__" >&2
+    exit 2
+    ;;
+esac
+
+id="$(date +%s%N)-$$"
+if ! printf '%s\n' "$1" >"$DIR/$id.tmp" || ! mv "$DIR/$id.tmp" "$DIR/$id.req"; then
+  echo "vibebox-host: cannot reach the host through $DIR" >&2
+  exit 1
+fi
+
+waited=0
+while [ ! -f "$DIR/$id.resp" ]; do
+  if [ "$waited" -ge "$TIMEOUT_TENTHS" ]; then
+    echo "vibebox-host: the host did not answer" >&2
+    exit 1
+  fi
+  sleep 0.1
+  waited=$((waited + 1))
+done
+
+status="$(head -n 1 "$DIR/$id.resp")"
+if [ "$status" = "ok" ]; then
+  tail -n +2 "$DIR/$id.resp"
+  code=0
+else
+  tail -n +2 "$DIR/$id.resp" >&2
+  code=1
+fi
+# The host sweeps answers nobody picked up, so a failed rm is harmless.
+rm -f "$DIR/$id.resp" 2>/dev/null
+exit "$code"
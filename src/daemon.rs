@@ -0,0 +1,606 @@
+//! Opt-in local JSON-RPC 2.0 endpoint for orchestrators: one JSON object per line on a Unix
+//! socket only the current user can open.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+
+use crate::{
+    Sandbox, SandboxError, SessionError, SessionManager,
+    error::ErrorReport,
+    session_manager::{GLOBAL_DIR_NAME, VM_MANAGER_CLIENTS_NAME, session_instance_dir},
+};
+
+pub const DAEMON_SOCKET_NAME: &str = "daemon.sock";
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any vibebox failure; `data` is its `--json` error report.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+    #[error("a vibebox daemon is already listening on {}", .0.display())]
+    AlreadyRunning(PathBuf),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// `~/.vibebox/daemon.sock`.
+pub fn default_socket_path() -> Result<PathBuf, SessionError> {
+    let home = std::env::var_os("HOME").ok_or(SessionError::MissingHome)?;
+    Ok(PathBuf::from(home)
+        .join(GLOBAL_DIR_NAME)
+        .join(DAEMON_SOCKET_NAME))
+}
+
+/// Binds `socket_path` with mode 0600, replacing a socket no daemon answers on.
+pub fn bind(socket_path: &Path) -> Result<UnixListener, DaemonError> {
+    if let Some(parent) = socket_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let is_socket = fs::symlink_metadata(socket_path)
+        .map(|meta| meta.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(DaemonError::AlreadyRunning(socket_path.to_path_buf()));
+        }
+        fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// One row of `sessions.list`, and the subject of every `session.event`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub directory: PathBuf,
+    /// The project directory's name, followed by `/<name>` for a named session.
+    pub name: String,
+    pub active: bool,
+    pub last_active: Option<String>,
+    /// Clients attached to the vm manager, this daemon included; only known while active.
+    pub clients: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn server(err: &(dyn std::error::Error + 'static)) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: err.to_string(),
+            data: serde_json::to_value(ErrorReport::new(err)).ok(),
+        }
+    }
+}
+
+impl From<SandboxError> for RpcError {
+    fn from(err: SandboxError) -> Self {
+        Self::server(&err)
+    }
+}
+
+impl From<SessionError> for RpcError {
+    fn from(err: SessionError) -> Self {
+        Self::server(&err)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateParams {
+    directory: PathBuf,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    config: Option<PathBuf>,
+    #[serde(default)]
+    cpu_count: Option<usize>,
+    #[serde(default)]
+    ram_mb: Option<u64>,
+    #[serde(default)]
+    disk_gb: Option<u64>,
+    #[serde(default)]
+    auto_shutdown_ms: Option<u64>,
+    #[serde(default)]
+    mounts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SessionParams {
+    session: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExecParams {
+    session: String,
+    command: String,
+}
+
+/// One client connection; requests on it are answered concurrently.
+struct Peer {
+    stream: Mutex<UnixStream>,
+}
+
+impl Peer {
+    fn send(&self, message: &Value) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        let mut stream = self
+            .stream
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        stream.write_all(&line)?;
+        stream.flush()
+    }
+}
+
+/// Serves `sessions.list`, `session.create`, `session.start`, `session.stop`,
+/// `session.status`, `session.exec` and `events.subscribe`. Sessions are named by id, id prefix,
+/// project name or absolute project directory.
+pub struct Daemon {
+    sessions: SessionManager,
+    /// Sandboxes this daemon created or started; a started one holds its VM up until
+    /// `session.stop` or until the daemon exits. Keyed by instance directory.
+    sandboxes: Mutex<HashMap<PathBuf, Arc<Sandbox>>>,
+    subscribers: Mutex<Vec<Arc<Peer>>>,
+    /// The index as subscribers last saw it, while there are any.
+    known: Mutex<Option<BTreeMap<String, SessionInfo>>>,
+}
+
+impl Daemon {
+    pub fn new(sessions: SessionManager) -> Self {
+        Self {
+            sessions,
+            sandboxes: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(Vec::new()),
+            known: Mutex::new(None),
+        }
+    }
+
+    /// Answers connections on `listener` until it fails.
+    pub fn serve(self: Arc<Self>, listener: UnixListener) {
+        let watcher = self.clone();
+        thread::spawn(move || watcher.watch_sessions());
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = self.clone();
+                    thread::spawn(move || daemon.handle_connection(stream));
+                }
+                Err(err) => {
+                    tracing::error!(error = %err, "daemon socket failed");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn handle_connection(self: Arc<Self>, stream: UnixStream) {
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to read daemon client");
+                return;
+            }
+        };
+        let peer = Arc::new(Peer {
+            stream: Mutex::new(stream),
+        });
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let daemon = self.clone();
+            let peer = peer.clone();
+            thread::spawn(move || {
+                if let Some(response) = daemon.handle_line(&line, &peer) {
+                    let _ = peer.send(&response);
+                }
+            });
+        }
+    }
+
+    /// The response to one request line, or `None` for a notification.
+    fn handle_line(&self, line: &str, peer: &Arc<Peer>) -> Option<Value> {
+        let value = match serde_json::from_str::<Value>(line) {
+            Ok(value) => value,
+            Err(err) => {
+                return Some(error_response(
+                    Value::Null,
+                    RpcError::new(PARSE_ERROR, err.to_string()),
+                ));
+            }
+        };
+        let request = match serde_json::from_value::<RpcRequest>(value) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            _ => {
+                return Some(error_response(
+                    Value::Null,
+                    RpcError::new(INVALID_REQUEST, "expected a JSON-RPC 2.0 request"),
+                ));
+            }
+        };
+        tracing::debug!(method = %request.method, "daemon request");
+        let result = self.dispatch(&request.method, request.params, request.id.as_ref(), peer);
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(id, err),
+        })
+    }
+
+    fn dispatch(
+        &self,
+        method: &str,
+        params: Value,
+        id: Option<&Value>,
+        peer: &Arc<Peer>,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "sessions.list" => Ok(json!(self.session_infos()?)),
+            "session.create" => {
+                let params: CreateParams = parse_params(params)?;
+                let mut builder = Sandbox::builder(&params.directory);
+                if let Some(name) = params.name {
+                    builder = builder.session(name);
+                }
+                if let Some(path) = params.config {
+                    builder = builder.config_path(path);
+                }
+                if let Some(cpu_count) = params.cpu_count {
+                    builder = builder.cpu_count(cpu_count);
+                }
+                if let Some(ram_mb) = params.ram_mb {
+                    builder = builder.ram_mb(ram_mb);
+                }
+                if let Some(disk_gb) = params.disk_gb {
+                    builder = builder.disk_gb(disk_gb);
+                }
+                if let Some(auto_shutdown_ms) = params.auto_shutdown_ms {
+                    builder = builder.auto_shutdown_ms(auto_shutdown_ms);
+                }
+                for mount in params.mounts {
+                    builder = builder.mount(mount);
+                }
+                let sandbox = builder.build()?;
+                let id = sandbox.create()?;
+                let directory = sandbox.project_root().to_path_buf();
+                self.lock_sandboxes()
+                    .insert(sandbox.instance_dir().to_path_buf(), Arc::new(sandbox));
+                Ok(json!({ "session": id, "directory": directory }))
+            }
+            "session.start" => {
+                let params: SessionParams = parse_params(params)?;
+                let sandbox = self.sandbox(&params.session)?;
+                sandbox.start()?;
+                Ok(json!(sandbox.status()))
+            }
+            "session.stop" => {
+                let params: SessionParams = parse_params(params)?;
+                self.sandbox(&params.session)?.stop()?;
+                Ok(json!({ "stopped": true }))
+            }
+            "session.status" => {
+                let params: SessionParams = parse_params(params)?;
+                Ok(json!(self.sandbox(&params.session)?.status()))
+            }
+            "session.exec" => {
+                let params: ExecParams = parse_params(params)?;
+                let sandbox = self.sandbox(&params.session)?;
+                let request = id.cloned().unwrap_or(Value::Null);
+                let code = sandbox.exec_streaming(&params.command, |stream, chunk| {
+                    let _ = peer.send(&notification(
+                        "session.output",
+                        json!({ "request": request, "stream": stream, "data": STANDARD.encode(chunk) }),
+                    ));
+                })?;
+                Ok(json!({ "code": code }))
+            }
+            "events.subscribe" => {
+                // Events are relative to the index as it is now, not as of the next poll.
+                let mut known = self.lock_known();
+                if known.is_none() {
+                    *known = Some(self.session_map()?);
+                }
+                self.lock_subscribers().push(peer.clone());
+                Ok(json!({ "subscribed": true }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {method}"),
+            )),
+        }
+    }
+
+    /// The daemon's sandbox for `session`, built from the project's config on first use.
+    fn sandbox(&self, session: &str) -> Result<Arc<Sandbox>, RpcError> {
+        let (directory, name) = if Path::new(session).is_absolute() {
+            (PathBuf::from(session), None)
+        } else {
+            let record = self.sessions.find_session(session)?;
+            (record.directory, record.name)
+        };
+        let directory = fs::canonicalize(&directory).unwrap_or(directory);
+        let instance_dir = session_instance_dir(&directory, name.as_deref());
+        let mut sandboxes = self.lock_sandboxes();
+        if let Some(sandbox) = sandboxes.get(&instance_dir) {
+            return Ok(sandbox.clone());
+        }
+        let mut builder = Sandbox::builder(&directory);
+        if let Some(name) = name {
+            builder = builder.session(name);
+        }
+        let sandbox = Arc::new(builder.build()?);
+        sandboxes.insert(instance_dir, sandbox.clone());
+        Ok(sandbox)
+    }
+
+    fn session_infos(&self) -> Result<Vec<SessionInfo>, SessionError> {
+        Ok(self
+            .sessions
+            .list_sessions()?
+            .into_iter()
+            .map(|record| SessionInfo {
+                name: record.label(),
+                clients: if record.active {
+                    read_client_count(&record.instance_dir())
+                } else {
+                    None
+                },
+                id: record.id,
+                directory: record.directory,
+                active: record.active,
+                last_active: record.last_active,
+            })
+            .collect())
+    }
+
+    /// Polls the session index while anyone is subscribed and sends them what changed.
+    fn watch_sessions(&self) {
+        loop {
+            thread::sleep(EVENT_POLL_INTERVAL);
+            let mut known = self.lock_known();
+            if self.lock_subscribers().is_empty() {
+                *known = None;
+                continue;
+            }
+            let current = match self.session_map() {
+                Ok(current) => current,
+                Err(err) => {
+                    tracing::warn!(error = %err, "failed to list sessions for events");
+                    continue;
+                }
+            };
+            let messages: Vec<Value> = known
+                .as_ref()
+                .map(|known| session_events(known, &current))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(event, info)| {
+                    notification("session.event", json!({ "event": event, "session": info }))
+                })
+                .collect();
+            *known = Some(current);
+            drop(known);
+            for message in messages {
+                self.lock_subscribers()
+                    .retain(|peer| peer.send(&message).is_ok());
+            }
+        }
+    }
+
+    fn session_map(&self) -> Result<BTreeMap<String, SessionInfo>, SessionError> {
+        Ok(self
+            .session_infos()?
+            .into_iter()
+            .map(|info| (info.id.clone(), info))
+            .collect())
+    }
+
+    fn lock_known(&self) -> MutexGuard<'_, Option<BTreeMap<String, SessionInfo>>> {
+        self.known
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_sandboxes(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<Sandbox>>> {
+        self.sandboxes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_subscribers(&self) -> MutexGuard<'_, Vec<Arc<Peer>>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// `created`, `started`, `clients`, `stopped` and `removed`, in that order per session.
+fn session_events<'a>(
+    before: &'a BTreeMap<String, SessionInfo>,
+    after: &'a BTreeMap<String, SessionInfo>,
+) -> Vec<(&'static str, &'a SessionInfo)> {
+    let mut events = Vec::new();
+    for (id, info) in after {
+        let Some(old) = before.get(id) else {
+            events.push(("created", info));
+            if info.active {
+                events.push(("started", info));
+            }
+            continue;
+        };
+        match (old.active, info.active) {
+            (false, true) => events.push(("started", info)),
+            (true, false) => events.push(("stopped", info)),
+            (true, true) if old.clients != info.clients => events.push(("clients", info)),
+            _ => {}
+        }
+    }
+    for (id, info) in before {
+        if !after.contains_key(id) {
+            events.push(("removed", info));
+        }
+    }
+    events
+}
+
+fn read_client_count(instance_dir: &Path) -> Option<usize> {
+    let path = instance_dir.join(VM_MANAGER_CLIENTS_NAME);
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: &str, active: bool, clients: Option<usize>) -> (String, SessionInfo) {
+        (
+            id.to_string(),
+            SessionInfo {
+                id: id.to_string(),
+                directory: PathBuf::from(format!("/work/{id}")),
+                name: id.to_string(),
+                active,
+                last_active: None,
+                clients,
+            },
+        )
+    }
+
+    #[test]
+    fn answers_protocol_errors_and_unknown_sessions() {
+        let temp = tempfile::tempdir().unwrap();
+        let daemon = Daemon::new(SessionManager::with_global_dir(temp.path().to_path_buf()));
+        let (stream, _other) = UnixStream::pair().unwrap();
+        let peer = Arc::new(Peer {
+            stream: Mutex::new(stream),
+        });
+        let call = |line: &str| daemon.handle_line(line, &peer);
+
+        assert_eq!(call("{").unwrap()["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            call(r#"{"id":1,"method":"sessions.list"}"#).unwrap()["error"]["code"],
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":2,"method":"rm"}"#).unwrap()["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":3,"method":"session.stop","params":{}}"#).unwrap()["error"]
+                ["code"],
+            INVALID_PARAMS
+        );
+        let listed = call(r#"{"jsonrpc":"2.0","id":"a","method":"sessions.list"}"#).unwrap();
+        assert_eq!(listed["id"], "a");
+        assert_eq!(listed["result"], json!([]));
+
+        let missing = call(
+            r#"{"jsonrpc":"2.0","id":4,"method":"session.status","params":{"session":"nope"}}"#,
+        )
+        .unwrap();
+        assert_eq!(missing["error"]["code"], SERVER_ERROR);
+        assert_eq!(missing["error"]["data"]["code"], "invalid_argument");
+        assert!(
+            call(r#"{"jsonrpc":"2.0","method":"sessions.list"}"#).is_none(),
+            "notifications get no answer"
+        );
+    }
+
+    #[test]
+    fn session_events_follow_index_changes() {
+        let before = BTreeMap::from([
+            info("a", false, None),
+            info("b", true, Some(1)),
+            info("c", true, Some(1)),
+            info("gone", false, None),
+        ]);
+        let after = BTreeMap::from([
+            info("a", true, Some(1)),
+            info("b", true, Some(2)),
+            info("c", false, None),
+            info("new", true, Some(1)),
+        ]);
+        let events: Vec<(&str, &str)> = session_events(&before, &after)
+            .into_iter()
+            .map(|(event, info)| (event, info.id.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("started", "a"),
+                ("clients", "b"),
+                ("stopped", "c"),
+                ("created", "new"),
+                ("started", "new"),
+                ("removed", "gone"),
+            ]
+        );
+        assert!(session_events(&after, &after).is_empty());
+    }
+}
@@ -0,0 +1,575 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    os::unix::{fs::MetadataExt, net::UnixStream},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use color_eyre::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+};
+
+use crate::{
+    SessionManager, SessionRecord,
+    boot::INSTANCE_RAW_NAME,
+    config, instance,
+    logs::{self, LogKind},
+    session_manager::VM_MANAGER_CLIENTS_NAME,
+    tui, vm_manager,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const INPUT_POLL: Duration = Duration::from_millis(250);
+const HELP: &str =
+    "↑/↓ select  enter attach  s stop  r restart  l logs  d delete  g refresh  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Running,
+    Stopping,
+    Restarting,
+    Stopped,
+}
+
+impl SessionState {
+    fn label(self) -> &'static str {
+        match self {
+            SessionState::Running => "running",
+            SessionState::Stopping => "stopping",
+            SessionState::Restarting => "restarting",
+            SessionState::Stopped => "stopped",
+        }
+    }
+
+    fn style(self) -> Style {
+        match self {
+            SessionState::Running => Style::default().fg(Color::Green),
+            SessionState::Stopping | SessionState::Restarting => Style::default().fg(Color::Yellow),
+            SessionState::Stopped => Style::default().fg(Color::DarkGray),
+        }
+    }
+}
+
+/// One session as `vibebox top` shows it. Everything is read from the project's files, so
+/// looking never keeps a VM alive.
+#[derive(Debug, Clone)]
+pub struct DashboardRow {
+    pub session: SessionRecord,
+    pub clients: Option<usize>,
+    pub vm_ip: Option<String>,
+    pub cpu_count: Option<usize>,
+    pub ram_mb: Option<u64>,
+    /// Bytes allocated on the host and the virtual size of the sparse disk image.
+    pub disk: Option<(u64, u64)>,
+}
+
+impl DashboardRow {
+    pub fn read(session: SessionRecord) -> Self {
+        let instance_dir = session.instance_dir();
+        let config = config::peek_config(&session.directory);
+        let clients = session
+            .active
+            .then(|| fs::read_to_string(instance_dir.join(VM_MANAGER_CLIENTS_NAME)).ok())
+            .flatten()
+            .and_then(|raw| raw.trim().parse().ok());
+        let vm_ip = session
+            .active
+            .then(|| instance::read_instance_vm_ip(&instance_dir).ok().flatten())
+            .flatten();
+        let disk = fs::metadata(instance_dir.join(INSTANCE_RAW_NAME))
+            .ok()
+            .map(|meta| (meta.blocks().saturating_mul(512), meta.len()));
+        Self {
+            clients,
+            vm_ip,
+            cpu_count: config.as_ref().map(|cfg| cfg.box_cfg.cpu_count),
+            ram_mb: config.as_ref().map(|cfg| cfg.box_cfg.ram_mb),
+            disk,
+            session,
+        }
+    }
+
+    fn name(&self) -> String {
+        self.session.label()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Stop,
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Up,
+    Down,
+    Attach,
+    Stop,
+    Restart,
+    ToggleLogs,
+    Delete,
+    Refresh,
+    Quit,
+}
+
+fn action_for(key: KeyEvent) -> Option<Action> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return Some(Action::Quit);
+    }
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => Some(Action::Up),
+        KeyCode::Down | KeyCode::Char('j') => Some(Action::Down),
+        KeyCode::Enter | KeyCode::Char('a') => Some(Action::Attach),
+        KeyCode::Char('s') => Some(Action::Stop),
+        KeyCode::Char('r') => Some(Action::Restart),
+        KeyCode::Char('l') => Some(Action::ToggleLogs),
+        KeyCode::Char('d') => Some(Action::Delete),
+        KeyCode::Char('g') => Some(Action::Refresh),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+struct Dashboard {
+    manager: SessionManager,
+    rows: Vec<DashboardRow>,
+    selected: usize,
+    pending: HashMap<String, Pending>,
+    /// Connections to VMs restarted from here; they keep those VMs up while `top` runs.
+    held: HashMap<String, UnixStream>,
+    show_logs: bool,
+    /// The session a second `d` deletes, pinned by id when the first one was pressed.
+    confirm_delete: Option<String>,
+    message: Option<String>,
+}
+
+impl Dashboard {
+    fn new(manager: SessionManager) -> Self {
+        Self {
+            manager,
+            rows: Vec::new(),
+            selected: 0,
+            pending: HashMap::new(),
+            held: HashMap::new(),
+            show_logs: false,
+            confirm_delete: None,
+            message: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let selected_id = self.selected_row().map(|row| row.session.id.clone());
+        match self.manager.list_sessions() {
+            Ok(sessions) => self.rows = sessions.into_iter().map(DashboardRow::read).collect(),
+            Err(err) => self.message = Some(format!("failed to list sessions: {err}")),
+        }
+        self.selected = selected_id
+            .and_then(|id| self.rows.iter().position(|row| row.session.id == id))
+            .unwrap_or(self.selected)
+            .min(self.rows.len().saturating_sub(1));
+
+        let stopped: Vec<SessionRecord> = self
+            .rows
+            .iter()
+            .filter(|row| !row.session.active)
+            .map(|row| row.session.clone())
+            .collect();
+        for session in stopped {
+            self.held.remove(&session.id);
+            match self.pending.remove(&session.id) {
+                Some(Pending::Restart) => match start_session(&session) {
+                    Ok(stream) => {
+                        self.held.insert(session.id.clone(), stream);
+                        self.message = Some(format!("restarted {}", session.label()));
+                    }
+                    Err(err) => self.message = Some(format!("restart failed: {err}")),
+                },
+                Some(Pending::Stop) => {
+                    self.message = Some(format!("stopped {}", session.label()));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn selected_row(&self) -> Option<&DashboardRow> {
+        self.rows.get(self.selected)
+    }
+
+    fn state_of(&self, row: &DashboardRow) -> SessionState {
+        match (row.session.active, self.pending.get(&row.session.id)) {
+            (_, Some(Pending::Restart)) => SessionState::Restarting,
+            (true, Some(Pending::Stop)) => SessionState::Stopping,
+            (true, None) => SessionState::Running,
+            (false, _) => SessionState::Stopped,
+        }
+    }
+
+    /// Applies `action` to the selected session. Returns the session to attach to, which needs
+    /// the terminal back.
+    fn apply(&mut self, action: Action) -> Option<SessionRecord> {
+        if let Some(id) = self.confirm_delete.take() {
+            self.message = None;
+            if action == Action::Delete {
+                self.delete_session(&id);
+            }
+            return None;
+        }
+        let row = self.selected_row().cloned();
+        match action {
+            Action::Up => self.selected = self.selected.saturating_sub(1),
+            Action::Down => {
+                self.selected = (self.selected + 1).min(self.rows.len().saturating_sub(1))
+            }
+            Action::ToggleLogs => self.show_logs = !self.show_logs,
+            Action::Refresh => self.refresh(),
+            Action::Quit => {}
+            Action::Attach => return row.map(|row| row.session),
+            Action::Stop | Action::Restart => {
+                let row = row?;
+                let id = row.session.id.clone();
+                let pending = if action == Action::Stop {
+                    Pending::Stop
+                } else {
+                    Pending::Restart
+                };
+                if !row.session.active {
+                    if pending == Pending::Restart {
+                        self.pending.insert(id, pending);
+                        self.refresh();
+                    } else {
+                        self.message = Some(format!("{} is not running", row.name()));
+                    }
+                    return None;
+                }
+                self.held.remove(&id);
+                match vm_manager::request_stop(&row.session.instance_dir()) {
+                    Ok(()) => {
+                        self.pending.insert(id, pending);
+                        self.message = None;
+                    }
+                    Err(err) => self.message = Some(format!("stop failed: {err}")),
+                }
+            }
+            Action::Delete => {
+                let row = row?;
+                if row.session.active {
+                    self.message = Some(format!("stop {} before deleting it", row.name()));
+                } else {
+                    self.confirm_delete = Some(row.session.id.clone());
+                    self.message = Some(format!(
+                        "delete {} and its VM disk? press d again to confirm, any other key cancels",
+                        row.session.instance_dir().display()
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    /// Looks the session up again, since it may have started since the delete was asked for.
+    fn delete_session(&mut self, id: &str) {
+        let session = match self.manager.find_session(id) {
+            Ok(session) => session,
+            Err(err) => {
+                self.message = Some(format!("delete failed: {err}"));
+                return;
+            }
+        };
+        if session.active {
+            self.message = Some(format!("stop {} before deleting it", session.label()));
+            return;
+        }
+        let cleaned = match &session.name {
+            Some(name) => self.manager.clean_session(&session.directory, name),
+            None => self.manager.clean_project(&session.directory),
+        };
+        self.message = Some(match cleaned {
+            Ok(summary) => format!("deleted {}", summary.instance_dir.display()),
+            Err(err) => format!("delete failed: {err}"),
+        });
+        self.refresh();
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let logs_height = if self.show_logs {
+            Constraint::Percentage(40)
+        } else {
+            Constraint::Length(0)
+        };
+        let [table_area, logs_area, footer_area] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), logs_height, Constraint::Length(1)])
+            .areas(frame.area());
+
+        let header = Row::new(vec![
+            Cell::from("Name"),
+            Cell::from("State"),
+            Cell::from("Clients"),
+            Cell::from("IP"),
+            Cell::from("CPU"),
+            Cell::from("RAM"),
+            Cell::from("Disk"),
+            Cell::from("Last Active"),
+            Cell::from("Directory"),
+        ])
+        .style(Style::default().fg(Color::Cyan));
+        let rows = self.rows.iter().map(|row| {
+            let state = self.state_of(row);
+            let dash = || "-".to_string();
+            Row::new(vec![
+                Cell::from(row.name()),
+                Cell::from(state.label()).style(state.style()),
+                Cell::from(row.clients.map(|n| n.to_string()).unwrap_or_else(dash)),
+                Cell::from(row.vm_ip.clone().unwrap_or_else(dash)),
+                Cell::from(row.cpu_count.map(|n| n.to_string()).unwrap_or_else(dash)),
+                Cell::from(row.ram_mb.map(|mb| format!("{mb} MB")).unwrap_or_else(dash)),
+                Cell::from(
+                    row.disk
+                        .map(|(used, size)| {
+                            format!("{} / {}", tui::format_bytes(used), tui::format_bytes(size))
+                        })
+                        .unwrap_or_else(dash),
+                ),
+                Cell::from(tui::format_last_active(row.session.last_active.as_deref())),
+                Cell::from(row.session.directory.display().to_string()),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(16),
+                Constraint::Length(10),
+                Constraint::Length(7),
+                Constraint::Length(15),
+                Constraint::Length(3),
+                Constraint::Length(8),
+                Constraint::Length(20),
+                Constraint::Length(14),
+                Constraint::Min(16),
+            ],
+        )
+        .header(header)
+        .block(
+            Block::default()
+                .title(format!("Sessions ({})", self.rows.len()))
+                .borders(Borders::ALL),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .column_spacing(1);
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, table_area, &mut state);
+
+        if self.show_logs {
+            let title = self
+                .selected_row()
+                .map(|row| format!("Console log: {}", row.name()))
+                .unwrap_or_else(|| "Console log".to_string());
+            let lines = self
+                .selected_row()
+                .and_then(|row| {
+                    let instance_dir = row.session.instance_dir();
+                    let path = logs::log_path(&instance_dir, LogKind::Console, 0);
+                    logs::tail(&path, logs_area.height.saturating_sub(2) as usize).ok()
+                })
+                .unwrap_or_default();
+            let paragraph = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+                .block(Block::default().title(title).borders(Borders::ALL));
+            frame.render_widget(paragraph, logs_area);
+        }
+
+        let footer = match &self.message {
+            Some(message) => {
+                Paragraph::new(message.as_str()).style(Style::default().fg(Color::Yellow))
+            }
+            None => Paragraph::new(HELP).style(Style::default().fg(Color::DarkGray)),
+        };
+        frame.render_widget(footer, footer_area);
+    }
+}
+
+/// Starts the vm manager of `session` the way `vibebox` would and returns its connection.
+fn start_session(session: &SessionRecord) -> Result<UnixStream, Box<dyn std::error::Error>> {
+    let config = config::peek_config(&session.directory).unwrap_or_default();
+    vm_manager::ensure_manager(
+        &session.directory,
+        &session.instance_dir(),
+        config.supervisor.auto_shutdown_ms,
+        None,
+    )
+}
+
+/// Hands the terminal to `vibebox` in the session's project until its ssh session ends.
+fn attach(terminal: &mut DefaultTerminal, session: &SessionRecord) -> Result<Option<String>> {
+    ratatui::try_restore()?;
+    let mut cmd = Command::new(env::current_exe()?);
+    if let Some(name) = &session.name {
+        cmd.arg("--session").arg(name);
+    }
+    let status = cmd.current_dir(&session.directory).status();
+    *terminal = ratatui::try_init()?;
+    terminal.clear()?;
+    Ok(match status {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("vibebox exited with {status}")),
+        Err(err) => Some(format!("failed to attach: {err}")),
+    })
+}
+
+/// Runs the full-screen `vibebox top` dashboard until the user quits.
+pub fn run(manager: SessionManager) -> Result<()> {
+    let mut dashboard = Dashboard::new(manager);
+    dashboard.refresh();
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut dashboard);
+    ratatui::try_restore()?;
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, dashboard: &mut Dashboard) -> Result<()> {
+    let mut last_refresh = Instant::now();
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame))?;
+        if event::poll(INPUT_POLL)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && let Some(action) = action_for(key)
+        {
+            if action == Action::Quit && dashboard.confirm_delete.is_none() {
+                return Ok(());
+            }
+            if let Some(session) = dashboard.apply(action) {
+                dashboard.message = attach(terminal, &session)?;
+                dashboard.refresh();
+                last_refresh = Instant::now();
+            }
+        }
+        if last_refresh.elapsed() >= REFRESH_INTERVAL {
+            dashboard.refresh();
+            last_refresh = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_manager::{INSTANCE_DIR_NAME, INSTANCE_FILENAME, VM_MANAGER_PID_NAME};
+    use ratatui::{Terminal, backend::TestBackend};
+    use std::path::Path;
+
+    fn session(temp: &Path, name: &str, active: bool) -> SessionRecord {
+        SessionRecord {
+            directory: temp.join(name),
+            name: None,
+            id: format!("{name}-id"),
+            last_active: None,
+            active,
+        }
+    }
+
+    #[test]
+    fn renders_rows_and_guards_delete_of_running_sessions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut dashboard =
+            Dashboard::new(SessionManager::with_global_dir(temp.path().join("global")));
+        let mut running = DashboardRow::read(session(temp.path(), "api", true));
+        running.clients = Some(2);
+        running.vm_ip = Some("192.168.64.9".into());
+        dashboard.rows = vec![
+            running,
+            DashboardRow::read(session(temp.path(), "web", false)),
+        ];
+
+        let mut terminal = Terminal::new(TestBackend::new(140, 10)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Sessions (2)"));
+        assert!(screen.contains("running"));
+        assert!(screen.contains("192.168.64.9"));
+        assert!(screen.contains("stopped"));
+
+        assert_eq!(dashboard.apply(Action::Delete), None);
+        assert!(dashboard.confirm_delete.is_none());
+        assert_eq!(
+            dashboard.message.as_deref(),
+            Some("stop api before deleting it")
+        );
+
+        dashboard.apply(Action::Down);
+        dashboard.apply(Action::Delete);
+        assert_eq!(dashboard.confirm_delete.as_deref(), Some("web-id"));
+        dashboard.apply(Action::Up);
+        assert!(dashboard.confirm_delete.is_none());
+        assert_eq!(dashboard.selected, 1);
+        assert_eq!(
+            dashboard
+                .apply(Action::Attach)
+                .map(|session| session.directory),
+            Some(temp.path().join("web"))
+        );
+    }
+
+    #[test]
+    fn delete_confirms_the_session_it_was_asked_for_while_it_is_still_stopped() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let manager = SessionManager::with_global_dir(temp.path().join("global"));
+        let mut instance_dirs = Vec::new();
+        for (name, id) in [("api", "aaaa"), ("web", "bbbb")] {
+            let project = temp.path().join(name);
+            let instance_dir = project.join(INSTANCE_DIR_NAME);
+            fs::create_dir_all(&instance_dir).unwrap();
+            fs::write(project.join(config::CONFIG_FILENAME), "").unwrap();
+            fs::write(
+                instance_dir.join(INSTANCE_FILENAME),
+                format!("id = \"{id}\"\n"),
+            )
+            .unwrap();
+            manager.update_global_sessions(&project, None).unwrap();
+            instance_dirs.push(instance_dir);
+        }
+        let mut dashboard = Dashboard::new(manager);
+        dashboard.refresh();
+        let web = dashboard
+            .rows
+            .iter()
+            .position(|row| row.session.id == "bbbb")
+            .unwrap();
+        dashboard.selected = web;
+
+        // The session starts, and the rows move, between the key press and the confirmation.
+        dashboard.apply(Action::Delete);
+        let pid_path = instance_dirs[1].join(VM_MANAGER_PID_NAME);
+        fs::write(&pid_path, std::process::id().to_string()).unwrap();
+        dashboard.selected = 1 - web;
+        dashboard.apply(Action::Delete);
+        assert_eq!(
+            dashboard.message.as_deref(),
+            Some("stop web before deleting it")
+        );
+        assert!(instance_dirs.iter().all(|dir| dir.exists()));
+
+        fs::remove_file(&pid_path).unwrap();
+        dashboard.refresh();
+        dashboard.selected = web;
+        dashboard.apply(Action::Delete);
+        dashboard.selected = 1 - web;
+        dashboard.apply(Action::Delete);
+        assert!(instance_dirs[0].exists());
+        assert!(!instance_dirs[1].exists());
+    }
+}
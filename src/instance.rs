@@ -1,6 +1,6 @@
 use std::{
-    env, fs,
-    io::{self},
+    fs,
+    io::{self, IsTerminal, Write},
     net::{SocketAddr, TcpStream},
     os::unix::{fs::PermissionsExt, net::UnixStream},
     path::{Path, PathBuf},
@@ -15,19 +15,45 @@ use time::{OffsetDateTime, format_description::well_known::Rfc3339};
 use uuid::Uuid;
 
 use crate::{
-    commands,
+    agent::{AgentError, ExecOutput},
+    config::SecurityProfile,
+    error::VibeboxError,
+    guest_info::GuestInfo,
+    login_script::LoginAction,
     session_manager::{INSTANCE_DIR_NAME, INSTANCE_FILENAME},
-    vm::{self, LoginAction},
 };
 
-const SSH_KEY_NAME: &str = "ssh_key";
-#[cfg_attr(feature = "mock-vm", allow(dead_code))]
-pub(crate) const VM_ROOT_LOG_NAME: &str = "vm_root.log";
+pub(crate) const SSH_KEY_NAME: &str = "ssh_key";
 pub(crate) const STATUS_FILE_NAME: &str = "status.txt";
 pub(crate) const DEFAULT_SSH_USER: &str = "vibecoder";
 const SSH_CONNECT_RETRIES: usize = 30;
 const SSH_CONNECT_DELAY_MS: u64 = 500;
 const SSH_SETUP_SCRIPT: &str = include_str!("ssh.sh");
+// ssh.sh installs mise and its tools before reporting the address, so allow as long as the CLI waits.
+const SSH_SETUP_TIMEOUT: Duration = Duration::from_secs(480);
+const IPV4_MARKER_PATTERN: &str = r"VIBEBOX_IPV4=(?P<vm_ipv4>\d{1,3}(?:\.\d{1,3}){3})";
+const SSH_SETUP_GUEST_PATH: &str = "/tmp/vibe-scripts/ssh_setup.sh";
+const SSH_SETUP_LOG_NAME: &str = "ssh_setup.log";
+const HOST_KEY_MARKER_PATTERN: &str =
+    r"(?m)^VIBEBOX_HOSTKEY=(?P<host_key>[a-z0-9@.-]+ [A-Za-z0-9+/]+={0,3})\s*$";
+pub(crate) const KNOWN_HOSTS_NAME: &str = "known_hosts";
+/// Name the pinned key is filed under, whatever address the guest has this boot.
+const HOST_KEY_ALIAS: &str = "vibebox";
+const VM_IPV4_TIMEOUT: Duration = Duration::from_secs(480);
+/// A running VM already has its address; this only covers a manager that is still booting.
+const GUEST_SCRIPT_IPV4_TIMEOUT: Duration = Duration::from_secs(30);
+/// Client options for every connection to the guest, shared by `vibebox` and `vibebox ssh-config`;
+/// see `ssh_options` for the host key ones.
+const SSH_OPTIONS: &[(&str, &str)] = &[
+    ("IdentitiesOnly", "yes"),
+    ("StrictHostKeyChecking", "yes"),
+    ("HostKeyAlias", HOST_KEY_ALIAS),
+    ("GlobalKnownHostsFile", "/dev/null"),
+    ("PasswordAuthentication", "no"),
+    ("BatchMode", "yes"),
+    ("LogLevel", "ERROR"),
+    ("ConnectTimeout", "5"),
+];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct InstanceConfig {
@@ -41,9 +67,18 @@ pub(crate) struct InstanceConfig {
     last_active: Option<String>,
     #[serde(default)]
     pub(crate) vm_ipv4: Option<String>,
+    /// Guest sshd public key (`<type> <base64>`), pinned on first boot.
+    #[serde(default)]
+    pub(crate) ssh_host_key: Option<String>,
+    #[serde(default)]
+    pub(crate) guest: Option<GuestInfo>,
 }
 
 impl InstanceConfig {
+    pub(crate) fn session_id(&self) -> Option<&str> {
+        Some(self.id.as_str()).filter(|id| !id.is_empty())
+    }
+
     pub(crate) fn ssh_user_display(&self) -> String {
         if self.ssh_user.trim().is_empty() {
             DEFAULT_SSH_USER.to_string()
@@ -57,26 +92,414 @@ fn default_ssh_user() -> String {
     DEFAULT_SSH_USER.to_string()
 }
 
-pub fn run_with_ssh(manager_conn: UnixStream) -> Result<(), Box<dyn std::error::Error>> {
-    let project_root = env::current_dir()?;
-    tracing::info!(root = %project_root.display(), "starting ssh session");
-    let instance_dir = ensure_instance_dir(&project_root)?;
-    tracing::debug!(instance_dir = %instance_dir.display(), "instance dir ready");
-    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(&instance_dir)?;
+/// `SSH_OPTIONS` plus the known_hosts file holding this instance's pinned host key.
+pub(crate) fn ssh_options(instance_dir: &Path) -> Vec<(&'static str, String)> {
+    let mut options: Vec<(&'static str, String)> = SSH_OPTIONS
+        .iter()
+        .map(|(key, value)| (*key, value.to_string()))
+        .collect();
+    options.push((
+        "UserKnownHostsFile",
+        instance_dir.join(KNOWN_HOSTS_NAME).display().to_string(),
+    ));
+    options
+}
 
-    let config = load_or_create_instance_config(&instance_dir)?;
+/// Opens an interactive ssh session in the VM serving `instance_dir`. The manager connection is
+/// held until the session ends.
+pub fn run_with_ssh(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!(instance_dir = %instance_dir.display(), "starting ssh session");
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+
+    let config = load_or_create_instance_config(instance_dir)?;
     let ssh_user = config.ssh_user.clone();
     tracing::debug!(ssh_user = %ssh_user, "loaded instance config");
 
     let _manager_conn = manager_conn;
-    wait_for_vm_ipv4(&instance_dir, Duration::from_secs(480))?;
-
-    let ip = load_or_create_instance_config(&instance_dir)?
-        .vm_ipv4
-        .ok_or("VM IPv4 not available")?;
+    let ip = wait_for_vm_ipv4(instance_dir, VM_IPV4_TIMEOUT)?;
     tracing::info!(ip = %ip, "vm ipv4 ready");
 
-    run_ssh_session(ssh_key, ssh_user, ip)
+    run_ssh_session(instance_dir, ssh_key, ssh_user, ip)
+}
+
+/// Pipes stdin/stdout to the guest's sshd, for use as an OpenSSH `ProxyCommand`. The manager
+/// connection is held until the ssh client disconnects.
+pub fn run_ssh_proxy(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!(instance_dir = %instance_dir.display(), "starting ssh proxy");
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, VM_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+    let upstream = TcpStream::connect((ip.as_str(), 22))?;
+    tracing::info!(ip = %ip, "ssh proxy connected");
+
+    let mut to_guest = upstream.try_clone()?;
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin().lock(), &mut to_guest);
+        let _ = to_guest.shutdown(std::net::Shutdown::Write);
+    });
+    let mut from_guest = upstream;
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut from_guest, &mut stdout)?;
+    stdout.flush()?;
+    tracing::info!("ssh proxy closed");
+    Ok(())
+}
+
+/// Copies between host and guest with scp. Exactly one of `source` and `destination` is a guest
+/// path, written with a leading `:`; relative guest paths start in the ssh user's home.
+pub fn run_copy(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    source: &str,
+    destination: &str,
+    recursive: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+    let ssh_user = load_or_create_instance_config(instance_dir)?.ssh_user_display();
+    let (source, destination) = scp_endpoints(source, destination)?;
+
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, VM_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+
+    let scp_arg = |path: CopyPath| match path {
+        CopyPath::Host(path) => path.to_string(),
+        CopyPath::Guest(path) => format!("{ssh_user}@{ip}:{path}"),
+    };
+    let (source, destination) = (scp_arg(source), scp_arg(destination));
+    tracing::info!(source = %source, destination = %destination, recursive, "copying");
+    let mut command = Command::new("scp");
+    command.args(["-p", "-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")]);
+    if recursive {
+        command.arg("-r");
+    }
+    let status = command
+        .args(
+            ssh_options(instance_dir)
+                .into_iter()
+                .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+        )
+        .arg("--")
+        .args([&source, &destination])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "scp",
+            source,
+        })?;
+    if !status.success() {
+        return Err(format!("scp exited with {status}").into());
+    }
+    Ok(())
+}
+
+/// Runs the shell `command` as the ssh user with the terminal attached and returns its exit code.
+pub fn run_guest_command(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    command: &str,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+    let ssh_user = load_or_create_instance_config(instance_dir)?.ssh_user_display();
+
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, VM_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+
+    let status = Command::new("ssh")
+        .args(["-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")])
+        .args(
+            ssh_options(instance_dir)
+                .into_iter()
+                .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+        )
+        .arg(if io::stdin().is_terminal() {
+            "-t"
+        } else {
+            "-T"
+        })
+        .arg(format!("{ssh_user}@{ip}"))
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "ssh",
+            source,
+        })?;
+    match status.code() {
+        Some(255) => Err(VibeboxError::Ssh("could not reach the guest (exit 255)".into()).into()),
+        Some(code) => Ok(code),
+        None => Err(format!("ssh was killed: {status}").into()),
+    }
+}
+
+/// Runs `script` with bash as the ssh user in a VM that is already up and returns its stdout.
+pub fn capture_guest_script(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    script: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+    let ssh_user = load_or_create_instance_config(instance_dir)?.ssh_user_display();
+
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, GUEST_SCRIPT_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+
+    let mut child = Command::new("ssh")
+        .args(["-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")])
+        .args(
+            ssh_options(instance_dir)
+                .into_iter()
+                .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+        )
+        .args(["-o", "BatchMode=yes"])
+        .arg(format!("{ssh_user}@{ip}"))
+        .args(["bash", "-s"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "ssh",
+            source,
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "guest script exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Waits until the VM serving `instance_dir` has an address and accepts ssh, and returns the
+/// address.
+pub fn wait_for_guest(instance_dir: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let ip = wait_for_vm_ipv4(instance_dir, VM_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+    Ok(ip)
+}
+
+/// Runs the shell `command` as the ssh user without a terminal and captures its output.
+pub fn exec_guest_command(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    command: &str,
+) -> Result<ExecOutput, Box<dyn std::error::Error>> {
+    let ssh_user = load_or_create_instance_config(instance_dir)?.ssh_user_display();
+    capture_ssh(instance_dir, manager_conn, &ssh_user, command, None)
+}
+
+/// Runs `operation` of the overlay helper as root, feeding it `input`. The host key may log in
+/// as root only to run that helper, and only in overlay mode.
+pub(crate) fn exec_overlay_helper(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    operation: &str,
+    input: Vec<u8>,
+) -> Result<ExecOutput, Box<dyn std::error::Error>> {
+    capture_ssh(instance_dir, manager_conn, "root", operation, Some(input))
+}
+
+fn capture_ssh(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    user: &str,
+    command: &str,
+    input: Option<Vec<u8>>,
+) -> Result<ExecOutput, Box<dyn std::error::Error>> {
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, GUEST_SCRIPT_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+
+    let mut child = Command::new("ssh")
+        .args(["-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")])
+        .args(
+            ssh_options(instance_dir)
+                .into_iter()
+                .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+        )
+        .arg("-T")
+        .arg(format!("{user}@{ip}"))
+        .arg(command)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "ssh",
+            source,
+        })?;
+    // Written from a thread so a command that answers before reading everything cannot stall.
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => Some(thread::spawn(move || stdin.write_all(&input))),
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    match output.status.code() {
+        Some(255) => Err(VibeboxError::Ssh(format!(
+            "could not reach the guest (exit 255): {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into()),
+        Some(code) => Ok(ExecOutput {
+            code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }),
+        None => Err(format!("ssh was killed: {}", output.status).into()),
+    }
+}
+
+/// Which output of a guest command a chunk came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecStream {
+    Stdout,
+    Stderr,
+}
+
+/// Like [`exec_guest_command`], but hands each chunk of output to `on_output` as it arrives
+/// and returns only the exit code.
+pub fn stream_guest_command(
+    instance_dir: &Path,
+    manager_conn: UnixStream,
+    command: &str,
+    mut on_output: impl FnMut(ExecStream, &[u8]),
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (ssh_key, _ssh_pub) = ensure_ssh_keypair(instance_dir)?;
+    let ssh_user = load_or_create_instance_config(instance_dir)?.ssh_user_display();
+
+    let _manager_conn = manager_conn;
+    let ip = wait_for_vm_ipv4(instance_dir, GUEST_SCRIPT_IPV4_TIMEOUT)?;
+    wait_for_ssh_port(&ip)?;
+
+    let mut child = Command::new("ssh")
+        .args(["-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")])
+        .args(
+            ssh_options(instance_dir)
+                .into_iter()
+                .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+        )
+        .arg("-T")
+        .arg(format!("{ssh_user}@{ip}"))
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| VibeboxError::Tool {
+            tool: "ssh",
+            source,
+        })?;
+    let (chunk_tx, chunk_rx) = std::sync::mpsc::channel();
+    let readers: [(ExecStream, Option<Box<dyn io::Read + Send>>); 2] = [
+        (
+            ExecStream::Stdout,
+            child
+                .stdout
+                .take()
+                .map(|out| Box::new(out) as Box<dyn io::Read + Send>),
+        ),
+        (
+            ExecStream::Stderr,
+            child
+                .stderr
+                .take()
+                .map(|err| Box::new(err) as Box<dyn io::Read + Send>),
+        ),
+    ];
+    for (stream, reader) in readers {
+        let Some(mut reader) = reader else {
+            continue;
+        };
+        let chunk_tx = chunk_tx.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if chunk_tx.send((stream, buf[..n].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+    drop(chunk_tx);
+    for (stream, chunk) in chunk_rx {
+        on_output(stream, &chunk);
+    }
+    let status = child.wait()?;
+    match status.code() {
+        Some(255) => Err(VibeboxError::Ssh("could not reach the guest (exit 255)".into()).into()),
+        Some(code) => Ok(code),
+        None => Err(format!("ssh was killed: {status}").into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CopyPath<'a> {
+    Host(&'a str),
+    Guest(&'a str),
+}
+
+impl<'a> CopyPath<'a> {
+    fn parse(arg: &'a str) -> Self {
+        match arg.strip_prefix(':') {
+            Some("") => CopyPath::Guest("."),
+            Some(guest) => CopyPath::Guest(guest),
+            None => CopyPath::Host(arg),
+        }
+    }
+}
+
+/// Rejects `vibebox cp` arguments that do not name exactly one guest path, before a VM is started.
+pub fn check_copy_args(source: &str, destination: &str) -> Result<(), String> {
+    scp_endpoints(source, destination).map(|_| ())
+}
+
+fn scp_endpoints<'a>(
+    source: &'a str,
+    destination: &'a str,
+) -> Result<(CopyPath<'a>, CopyPath<'a>), String> {
+    match (CopyPath::parse(source), CopyPath::parse(destination)) {
+        (CopyPath::Guest(_), CopyPath::Guest(_)) => {
+            Err("only one side of `vibebox cp` may be a guest path".into())
+        }
+        (CopyPath::Host(_), CopyPath::Host(_)) => {
+            Err("one side of `vibebox cp` must be a guest path, written as `:path`".into())
+        }
+        endpoints => Ok(endpoints),
+    }
 }
 
 pub fn ensure_instance_dir(project_root: &Path) -> Result<PathBuf, io::Error> {
@@ -142,6 +565,8 @@ pub(crate) fn load_or_create_instance_config(
             sudo_password: String::new(),
             last_active: None,
             vm_ipv4: None,
+            ssh_host_key: None,
+            guest: None,
         }
     };
 
@@ -168,7 +593,7 @@ pub(crate) fn load_or_create_instance_config(
     Ok(config)
 }
 
-fn read_instance_config(
+pub(crate) fn read_instance_config(
     instance_dir: &Path,
 ) -> Result<Option<InstanceConfig>, Box<dyn std::error::Error>> {
     let config_path = instance_dir.join(INSTANCE_FILENAME);
@@ -187,6 +612,13 @@ pub fn read_instance_vm_ip(
     Ok(config.and_then(|cfg| cfg.vm_ipv4))
 }
 
+pub fn read_instance_guest_info(
+    instance_dir: &Path,
+) -> Result<Option<GuestInfo>, Box<dyn std::error::Error>> {
+    let config = read_instance_config(instance_dir)?;
+    Ok(config.and_then(|cfg| cfg.guest))
+}
+
 pub fn read_instance_ssh_user(
     instance_dir: &Path,
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
@@ -218,7 +650,6 @@ fn generate_password() -> String {
     Uuid::now_v7().simple().to_string()
 }
 
-#[cfg_attr(feature = "mock-vm", allow(dead_code))]
 pub(crate) fn extract_ipv4(line: &str) -> Option<String> {
     let mut current = String::new();
     let mut best: Option<String> = None;
@@ -241,7 +672,7 @@ pub(crate) fn extract_ipv4(line: &str) -> Option<String> {
 fn wait_for_vm_ipv4(
     instance_dir: &Path,
     timeout: Duration,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<String, Box<dyn std::error::Error>> {
     let start = Instant::now();
     let mut next_log_at = start + Duration::from_secs(10);
     let mut next_status_check = start;
@@ -252,13 +683,13 @@ fn wait_for_vm_ipv4(
     let mut once_hint = false;
     loop {
         let config = load_or_create_instance_config(instance_dir)?;
-        if config.vm_ipv4.is_some() {
+        if let Some(ip) = config.vm_ipv4 {
             let _ = fs::remove_file(&status_path);
-            return Ok(());
+            return Ok(ip);
         }
         if start.elapsed() > timeout {
             let _ = fs::remove_file(&status_path);
-            return Err("Timed out waiting for VM IPv4".into());
+            return Err(VibeboxError::VmIpTimeout.into());
         }
         let now = Instant::now();
         if now >= next_status_check {
@@ -268,8 +699,8 @@ fn wait_for_vm_ipv4(
                     let status = status.trim().to_string();
                     if status.starts_with("error:") {
                         let _ = fs::remove_file(&status_path);
-                        let message = status.trim_start_matches("error:").trim().to_string();
-                        return Err(message.into());
+                        let status = status.trim_start_matches("error:");
+                        return Err(VibeboxError::from_status(status).into());
                     }
                     if !status.is_empty() && last_status.as_deref() != Some(status.as_str()) {
                         tracing::info!("[background]: {}", status);
@@ -301,6 +732,7 @@ fn wait_for_vm_ipv4(
 }
 
 fn run_ssh_session(
+    instance_dir: &Path,
     ssh_key: PathBuf,
     ssh_user: String,
     ip: String,
@@ -318,9 +750,10 @@ fn run_ssh_session(
                 SSH_CONNECT_RETRIES
             );
             if attempts >= SSH_CONNECT_RETRIES {
-                return Err(
-                    format!("ssh port not ready after {SSH_CONNECT_RETRIES} attempts").into(),
-                );
+                return Err(VibeboxError::SshNotReady {
+                    attempts: SSH_CONNECT_RETRIES,
+                }
+                .into());
             }
             thread::sleep(Duration::from_millis(SSH_CONNECT_DELAY_MS));
             continue;
@@ -335,26 +768,12 @@ fn run_ssh_session(
             SSH_CONNECT_RETRIES
         );
         let status = Command::new("ssh")
-            .args([
-                "-i",
-                ssh_key.to_str().unwrap_or(".vibebox/ssh_key"),
-                "-o",
-                "IdentitiesOnly=yes",
-                "-o",
-                "StrictHostKeyChecking=no",
-                "-o",
-                "UserKnownHostsFile=/dev/null",
-                "-o",
-                "GlobalKnownHostsFile=/dev/null",
-                "-o",
-                "PasswordAuthentication=no",
-                "-o",
-                "BatchMode=yes",
-                "-o",
-                "LogLevel=ERROR",
-                "-o",
-                "ConnectTimeout=5",
-            ])
+            .args(["-i", ssh_key.to_str().unwrap_or(".vibebox/ssh_key")])
+            .args(
+                ssh_options(instance_dir)
+                    .into_iter()
+                    .flat_map(|(key, value)| ["-o".to_string(), format!("{key}={value}")]),
+            )
             .env_remove("LC_CTYPE")
             .env_remove("LC_ALL")
             .env_remove("LANG")
@@ -372,7 +791,10 @@ fn run_ssh_session(
             Ok(status) if status.code() == Some(255) => {
                 tracing::warn!(status = %status, "ssh connection failed");
                 if attempts >= SSH_CONNECT_RETRIES {
-                    return Err(format!("ssh failed after {SSH_CONNECT_RETRIES} attempts").into());
+                    return Err(VibeboxError::Ssh(format!(
+                        "could not connect after {SSH_CONNECT_RETRIES} attempts"
+                    ))
+                    .into());
                 }
                 thread::sleep(Duration::from_millis(500));
             }
@@ -382,7 +804,11 @@ fn run_ssh_session(
             }
             Err(err) => {
                 tracing::error!(error = %err, "failed to start ssh");
-                return Err(format!("failed to start ssh: {err}").into());
+                return Err(VibeboxError::Tool {
+                    tool: "ssh",
+                    source: err,
+                }
+                .into());
             }
         }
     }
@@ -390,7 +816,6 @@ fn run_ssh_session(
     Ok(())
 }
 
-#[cfg_attr(feature = "mock-vm", allow(dead_code))]
 fn is_ipv4_candidate(candidate: &str) -> bool {
     let parts: Vec<&str> = candidate.split('.').collect();
     if parts.len() != 4 {
@@ -407,6 +832,21 @@ fn is_ipv4_candidate(candidate: &str) -> bool {
     true
 }
 
+fn wait_for_ssh_port(ip: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut attempts = 0usize;
+    while !ssh_port_open(ip) {
+        attempts += 1;
+        if attempts >= SSH_CONNECT_RETRIES {
+            return Err(VibeboxError::SshNotReady {
+                attempts: SSH_CONNECT_RETRIES,
+            }
+            .into());
+        }
+        thread::sleep(Duration::from_millis(SSH_CONNECT_DELAY_MS));
+    }
+    Ok(())
+}
+
 fn ssh_port_open(ip: &str) -> bool {
     let addr: SocketAddr = match format!("{ip}:22").parse() {
         Ok(addr) => addr,
@@ -415,31 +855,184 @@ fn ssh_port_open(ip: &str) -> bool {
     TcpStream::connect_timeout(&addr, std::time::Duration::from_millis(500)).is_ok()
 }
 
+#[allow(clippy::too_many_arguments)]
+fn render_ssh_setup_script(
+    ssh_user: &str,
+    sudo_password: &str,
+    security: SecurityProfile,
+    project_name: &str,
+    project_guest_dir: &str,
+    key_path: &str,
+    home_links_script: &str,
+    host_key_pinned: bool,
+) -> String {
+    // Without sudo the password is useless, so keep it out of the guest entirely.
+    let sudo_password = if security.allows_sudo() {
+        sudo_password
+    } else {
+        ""
+    };
+    SSH_SETUP_SCRIPT
+        .replace("__SSH_USER__", ssh_user)
+        .replace("__SUDO_PASSWORD__", sudo_password)
+        .replace("__SECURITY_PROFILE__", security.as_str())
+        .replace("__PROJECT_NAME__", project_name)
+        .replace("__PROJECT_GUEST_DIR__", project_guest_dir)
+        .replace("__KEY_PATH__", key_path)
+        .replace(
+            "__HOST_KEY_PINNED__",
+            if host_key_pinned { "1" } else { "" },
+        )
+        .replace("__VIBEBOX_HOME_LINKS__", home_links_script)
+}
+
 pub(crate) fn build_ssh_login_actions(
     config: &Arc<Mutex<InstanceConfig>>,
+    instance_dir: &Path,
     project_name: &str,
     project_guest_dir: &str,
     guest_dir: &str,
-    key_name: &str,
     home_links_script: &str,
+    security: SecurityProfile,
 ) -> Vec<LoginAction> {
     let config_guard = config.lock().expect("config mutex poisoned");
     let ssh_user = config_guard.ssh_user.clone();
     let sudo_password = config_guard.sudo_password.clone();
+    let host_key_pinned = config_guard.ssh_host_key.is_some();
     drop(config_guard);
 
-    let key_path = format!("{guest_dir}/{key_name}.pub");
+    let setup_script = render_ssh_setup_script(
+        &ssh_user,
+        &sudo_password,
+        security,
+        project_name,
+        project_guest_dir,
+        &format!("{guest_dir}/{SSH_KEY_NAME}.pub"),
+        home_links_script,
+        host_key_pinned,
+    );
+    let ipv4_marker = regex::Regex::new(IPV4_MARKER_PATTERN).expect("ipv4 marker pattern is valid");
+    let host_key_marker =
+        regex::Regex::new(HOST_KEY_MARKER_PATTERN).expect("host key marker pattern is valid");
+    let known_hosts = instance_dir.join(KNOWN_HOSTS_NAME);
+    let instance_path = instance_dir.join(INSTANCE_FILENAME);
+    let setup_log = instance_dir.join(SSH_SETUP_LOG_NAME);
+    let config_for_ip = config.clone();
+    vec![LoginAction::Agent(Arc::new(move |agent, vars| {
+        agent.put(SSH_SETUP_GUEST_PATH, setup_script.as_bytes(), Some(0o755))?;
+        let output = agent.exec(SSH_SETUP_GUEST_PATH, SSH_SETUP_TIMEOUT)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Err(err) = fs::write(&setup_log, format!("{stdout}{stderr}")) {
+            tracing::warn!(error = %err, "failed to write ssh setup log");
+        }
+        if output.code != 0 {
+            return Err(AgentError::Remote(format!(
+                "ssh setup exited with status {}: {}",
+                output.code,
+                stderr.trim()
+            )));
+        }
 
-    let setup_script = SSH_SETUP_SCRIPT
-        .replace("__SSH_USER__", &ssh_user)
-        .replace("__SUDO_PASSWORD__", &sudo_password)
-        .replace("__PROJECT_NAME__", project_name)
-        .replace("__PROJECT_GUEST_DIR__", project_guest_dir)
-        .replace("__KEY_PATH__", &key_path)
-        .replace("__VIBEBOX_SHELL_SCRIPT__", &commands::render_shell_script())
-        .replace("__VIBEBOX_HOME_LINKS__", home_links_script);
-    let setup = vm::script_command_from_content("ssh_setup", &setup_script)
-        .expect("ssh setup script contained invalid marker");
+        // Prefer the address ssh.sh verified sshd listens on; fall back to asking the agent.
+        let reported = ipv4_marker
+            .captures(&stdout)
+            .and_then(|captures| extract_ipv4(&captures["vm_ipv4"]));
+        let ip = match reported {
+            Some(ip) => ip,
+            None => extract_ipv4(&agent.ip()?).ok_or_else(|| {
+                AgentError::Remote("guest agent reported no usable IPv4 address".into())
+            })?,
+        };
+        // The console is a private channel, so the key it reports is the one to trust over the network.
+        let host_key = host_key_marker
+            .captures(&stdout)
+            .map(|captures| captures["host_key"].to_string())
+            .ok_or_else(|| AgentError::Remote("ssh setup did not report a host key".into()))?;
+        vars.insert("vm_ipv4".into(), ip.clone());
+        if let Ok(mut cfg) = config_for_ip.lock() {
+            if let Some(pinned) = &cfg.ssh_host_key
+                && pinned != &host_key
+            {
+                return Err(AgentError::Remote(format!(
+                    "guest ssh host key changed (pinned {pinned}, got {host_key}); \
+run `vibebox reset` if the disk was replaced on purpose"
+                )));
+            }
+            if let Err(err) = fs::write(&known_hosts, format!("{HOST_KEY_ALIAS} {host_key}\n")) {
+                tracing::warn!(error = %err, "failed to write known_hosts");
+            }
+            cfg.ssh_host_key = Some(host_key);
+            cfg.vm_ipv4 = Some(ip);
+            if let Err(err) = write_instance_config(&instance_path, &cfg) {
+                tracing::warn!(error = %err, "failed to persist vm ipv4");
+            }
+        }
+        Ok(())
+    }))]
+}
 
-    vec![LoginAction::Send(setup)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scp_endpoints_require_exactly_one_guest_path() {
+        assert_eq!(
+            scp_endpoints("dist/app.tar", ":/tmp/"),
+            Ok((CopyPath::Host("dist/app.tar"), CopyPath::Guest("/tmp/")))
+        );
+        assert_eq!(
+            scp_endpoints(":target/release/app", "."),
+            Ok((CopyPath::Guest("target/release/app"), CopyPath::Host(".")))
+        );
+        assert_eq!(
+            scp_endpoints("notes.md", ":"),
+            Ok((CopyPath::Host("notes.md"), CopyPath::Guest(".")))
+        );
+        assert!(scp_endpoints(":a", ":b").is_err());
+        assert!(scp_endpoints("a", "b").is_err());
+    }
+
+    #[test]
+    fn strict_setup_script_drops_sudo_password() {
+        let render = |security| {
+            render_ssh_setup_script(
+                "vibecoder",
+                "hunter2",
+                security,
+                "demo",
+                "/p/demo",
+                "/k",
+                "",
+                false,
+            )
+        };
+        let standard = render(SecurityProfile::Standard);
+        assert!(standard.contains("SUDO_PASSWORD=\"hunter2\""));
+        assert!(standard.contains("SECURITY_PROFILE=\"standard\""));
+
+        let strict = render(SecurityProfile::Strict);
+        assert!(strict.contains("SUDO_PASSWORD=\"\""));
+        assert!(strict.contains("SECURITY_PROFILE=\"strict\""));
+        assert!(!strict.contains("hunter2"));
+    }
+
+    #[test]
+    fn setup_script_regenerates_host_keys_until_one_is_pinned() {
+        let render = |pinned| {
+            render_ssh_setup_script(
+                "vibecoder",
+                "",
+                SecurityProfile::Standard,
+                "demo",
+                "/p/demo",
+                "/k",
+                "",
+                pinned,
+            )
+        };
+        assert!(render(false).contains("HOST_KEY_PINNED=\"\""));
+        assert!(render(true).contains("HOST_KEY_PINNED=\"1\""));
+    }
 }
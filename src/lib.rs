@@ -1,10 +1,33 @@
+pub mod agent;
+pub mod boot;
 pub mod commands;
+pub mod console;
+pub mod control;
+pub mod daemon;
+pub mod dashboard;
+pub mod error;
 pub mod explain;
+#[cfg(any(test, feature = "mock-vm"))]
+mod fake_guest;
+pub mod guest_info;
+pub mod hooks;
 pub mod instance;
+pub mod login_script;
+pub mod logs;
+pub mod mount_guard;
+pub mod mount_plan;
+pub mod overlay;
+pub mod sandbox;
 pub mod session_manager;
+pub mod ssh_config;
 pub mod tui;
+pub mod verify;
+#[cfg(target_os = "macos")]
+pub mod virtualization;
 pub mod vm;
 pub mod vm_manager;
 
+pub use error::{ErrorCode, VibeboxError};
+pub use sandbox::{Sandbox, SandboxBuilder, SandboxError, SandboxStatus};
 pub use session_manager::{SessionError, SessionManager, SessionRecord};
 pub mod config;
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, mpsc::Sender},
+    time::Duration,
+};
+
+use regex::Regex;
+
+use crate::agent::{AgentClient, AgentError};
+use crate::vm::{OutputMonitor, VmInput};
+
+/// Values captured by named groups in `Expect` steps, keyed by group name.
+pub type Vars = HashMap<String, String>;
+pub type VarsHook = Arc<dyn Fn(&Vars) + Send + Sync>;
+pub type AgentHook = Arc<dyn Fn(&AgentClient, &mut Vars) -> Result<(), AgentError> + Send + Sync>;
+
+#[derive(Clone)]
+pub enum LoginAction {
+    Expect(Expectation),
+    /// Type the text followed by a newline; `{{name}}` placeholders are replaced with captured values.
+    Send(String),
+    /// Hand the captured values to the caller, e.g. to persist a discovered IP.
+    Hook(VarsHook),
+    /// Talk to the guest agent; only valid once an earlier step has seen its ready frame.
+    Agent(AgentHook),
+}
+
+#[derive(Clone, Default)]
+pub enum OnTimeout {
+    #[default]
+    Fail,
+    /// Type the text before each retry, e.g. an empty line to redraw a prompt.
+    Send(String),
+}
+
+#[derive(Clone)]
+pub struct Expectation {
+    label: String,
+    pattern: Regex,
+    failure: Option<Regex>,
+    timeout: Duration,
+    retries: usize,
+    on_timeout: OnTimeout,
+}
+
+impl Expectation {
+    pub fn text(text: &str, timeout: Duration) -> Self {
+        Self::new(text.to_string(), literal(text), timeout)
+    }
+
+    pub fn regex(pattern: &str, timeout: Duration) -> Result<Self, regex::Error> {
+        Ok(Self::new(
+            pattern.to_string(),
+            Regex::new(pattern)?,
+            timeout,
+        ))
+    }
+
+    fn new(label: String, pattern: Regex, timeout: Duration) -> Self {
+        Self {
+            label,
+            pattern,
+            failure: None,
+            timeout,
+            retries: 0,
+            on_timeout: OnTimeout::Fail,
+        }
+    }
+
+    pub fn failure_text(mut self, text: &str) -> Self {
+        self.failure = Some(literal(text));
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn on_timeout(mut self, on_timeout: OnTimeout) -> Self {
+        self.on_timeout = on_timeout;
+        self
+    }
+
+    fn describe(&self) -> String {
+        format!("expect '{}'", self.label)
+    }
+}
+
+fn literal(text: &str) -> Regex {
+    Regex::new(&regex::escape(text)).expect("escaped literal is a valid regex")
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoginError {
+    #[error("Login action ({action}) timed out after {timeout:?}")]
+    Timeout { action: String, timeout: Duration },
+    #[error("Login action ({action}) failed: {reason}")]
+    Failed { action: String, reason: String },
+    #[error("VM console closed while running login actions")]
+    ConsoleClosed,
+}
+
+impl From<AgentError> for LoginError {
+    fn from(err: AgentError) -> Self {
+        match err {
+            AgentError::ConsoleClosed => LoginError::ConsoleClosed,
+            err => LoginError::Failed {
+                action: "guest agent".into(),
+                reason: err.to_string(),
+            },
+        }
+    }
+}
+
+enum Matched {
+    Success(Vars),
+    Failure(String),
+}
+
+/// Runs the actions in order against the console output, returning everything captured.
+pub fn run_login_actions(
+    actions: &[LoginAction],
+    output_monitor: &OutputMonitor,
+    input_tx: &Sender<VmInput>,
+) -> Result<Vars, LoginError> {
+    let mut vars = Vars::new();
+    let agent = AgentClient::new(output_monitor, input_tx);
+    for action in actions {
+        match action {
+            LoginAction::Expect(expectation) => {
+                run_expectation(expectation, output_monitor, input_tx, &mut vars)?;
+            }
+            LoginAction::Send(template) => send_line(input_tx, &render_template(template, &vars))?,
+            LoginAction::Hook(hook) => hook(&vars),
+            LoginAction::Agent(hook) => hook(&agent, &mut vars)?,
+        }
+    }
+    Ok(vars)
+}
+
+fn run_expectation(
+    expectation: &Expectation,
+    output_monitor: &OutputMonitor,
+    input_tx: &Sender<VmInput>,
+    vars: &mut Vars,
+) -> Result<(), LoginError> {
+    for attempt in 0..=expectation.retries {
+        let matched = output_monitor.wait_until(expectation.timeout, |buf| {
+            let (end, matched) =
+                find_match(buf, &expectation.pattern, expectation.failure.as_ref())?;
+            buf.drain(..end);
+            Some(matched)
+        });
+        match matched {
+            Some(Matched::Success(captured)) => {
+                vars.extend(captured);
+                return Ok(());
+            }
+            Some(Matched::Failure(text)) => {
+                return Err(LoginError::Failed {
+                    action: expectation.describe(),
+                    reason: format!("saw failure marker '{text}'"),
+                });
+            }
+            None if output_monitor.is_closed() => return Err(LoginError::ConsoleClosed),
+            None if attempt < expectation.retries => {
+                tracing::debug!(
+                    action = %expectation.describe(),
+                    attempt = attempt + 1,
+                    retries = expectation.retries,
+                    "login action timed out; retrying"
+                );
+                if let OnTimeout::Send(text) = &expectation.on_timeout {
+                    send_line(input_tx, &render_template(text, vars))?;
+                }
+            }
+            None => {}
+        }
+    }
+
+    let attempts = u32::try_from(expectation.retries + 1).unwrap_or(u32::MAX);
+    Err(LoginError::Timeout {
+        action: expectation.describe(),
+        timeout: expectation.timeout.saturating_mul(attempts),
+    })
+}
+
+/// Finds whichever of `pattern` and `failure` matches first, returning the byte offset just past it.
+fn find_match(buf: &str, pattern: &Regex, failure: Option<&Regex>) -> Option<(usize, Matched)> {
+    let success = pattern.captures(buf);
+    let failed = failure.and_then(|failure| failure.find(buf));
+    match (success, failed) {
+        (Some(captures), Some(failed)) if failed.start() < captures.get(0)?.start() => {
+            Some((failed.end(), Matched::Failure(failed.as_str().to_string())))
+        }
+        (Some(captures), _) => {
+            let end = captures.get(0)?.end();
+            let vars = pattern
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    let value = captures.name(name)?;
+                    Some((name.to_string(), value.as_str().to_string()))
+                })
+                .collect();
+            Some((end, Matched::Success(vars)))
+        }
+        (None, Some(failed)) => Some((failed.end(), Matched::Failure(failed.as_str().to_string()))),
+        (None, None) => None,
+    }
+}
+
+/// Replaces `{{name}}` placeholders with captured values; unknown names are left untouched.
+fn render_template(template: &str, vars: &Vars) -> String {
+    let mut rendered = template.to_string();
+    for (name, value) in vars {
+        rendered = rendered.replace(&format!("{{{{{name}}}}}"), value);
+    }
+    rendered
+}
+
+fn send_line(input_tx: &Sender<VmInput>, text: &str) -> Result<(), LoginError> {
+    let mut line = text.to_string();
+    line.push('\n'); // Type the newline so the command is actually submitted.
+    input_tx
+        .send(VmInput::Bytes(line.into_bytes()))
+        .map_err(|_| LoginError::ConsoleClosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, mpsc};
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    fn monitor_with(transcript: &str) -> OutputMonitor {
+        let monitor = OutputMonitor::default();
+        monitor.push(transcript.as_bytes());
+        monitor
+    }
+
+    fn sent_lines(rx: &mpsc::Receiver<VmInput>) -> Vec<String> {
+        rx.try_iter()
+            .filter_map(|input| match input {
+                VmInput::Bytes(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+                VmInput::Shutdown => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn regex_captures_feed_templates_and_hooks() {
+        let monitor = monitor_with(
+            "echo \"VIBEBOX_IPV4=$ip\"\r\nVIBEBOX_SSH_READY\r\nVIBEBOX_IPV4=192.168.64.7\r\n",
+        );
+        let (tx, rx) = mpsc::channel();
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_hook = seen.clone();
+        let actions = vec![
+            LoginAction::Expect(
+                Expectation::regex(r"VIBEBOX_IPV4=(?P<ip>\d+\.\d+\.\d+\.\d+)", SHORT).unwrap(),
+            ),
+            LoginAction::Send("ping -c1 {{ip}} {{missing}}".into()),
+            LoginAction::Hook(Arc::new(move |vars| {
+                *seen_by_hook.lock().unwrap() = vars.get("ip").cloned();
+            })),
+        ];
+
+        let vars = run_login_actions(&actions, &monitor, &tx).unwrap();
+
+        assert_eq!(vars.get("ip").map(String::as_str), Some("192.168.64.7"));
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("192.168.64.7"));
+        assert_eq!(sent_lines(&rx), vec!["ping -c1 192.168.64.7 {{missing}}\n"]);
+    }
+
+    #[test]
+    fn earliest_failure_marker_wins() {
+        let monitor =
+            monitor_with("step 1\r\nVIBEBOX_PROVISION_FAILED\r\nVIBEBOX_PROVISION_OK\r\n");
+        let (tx, _rx) = mpsc::channel();
+        let actions = vec![LoginAction::Expect(
+            Expectation::text("VIBEBOX_PROVISION_OK", SHORT)
+                .failure_text("VIBEBOX_PROVISION_FAILED"),
+        )];
+
+        let err = run_login_actions(&actions, &monitor, &tx).unwrap_err();
+
+        assert!(matches!(err, LoginError::Failed { .. }), "got {err}");
+    }
+
+    #[test]
+    fn matches_consume_output_in_order() {
+        let monitor = monitor_with("login: root\r\nroot@vibebox:~# ");
+        let (tx, rx) = mpsc::channel();
+        let actions = vec![
+            LoginAction::Expect(Expectation::text("login: ", SHORT)),
+            LoginAction::Send("root".into()),
+            LoginAction::Expect(Expectation::text("~#", SHORT)),
+            LoginAction::Expect(Expectation::text("login: ", SHORT)),
+        ];
+
+        let err = run_login_actions(&actions, &monitor, &tx).unwrap_err();
+
+        assert!(matches!(err, LoginError::Timeout { .. }), "got {err}");
+        assert_eq!(sent_lines(&rx), vec!["root\n"]);
+    }
+
+    #[test]
+    fn closed_console_stops_waiting() {
+        let monitor = monitor_with("booting...\r\n");
+        monitor.close();
+        let (tx, _rx) = mpsc::channel();
+        let actions = vec![LoginAction::Expect(
+            Expectation::text("login: ", Duration::from_secs(60)).retries(3),
+        )];
+
+        let err = run_login_actions(&actions, &monitor, &tx).unwrap_err();
+
+        assert!(matches!(err, LoginError::ConsoleClosed), "got {err}");
+    }
+
+    #[test]
+    fn retries_send_on_timeout_before_each_attempt() {
+        let monitor = monitor_with("");
+        let (tx, rx) = mpsc::channel();
+        let actions = vec![LoginAction::Expect(
+            Expectation::text("login: ", SHORT)
+                .retries(2)
+                .on_timeout(OnTimeout::Send(String::new())),
+        )];
+
+        let err = run_login_actions(&actions, &monitor, &tx).unwrap_err();
+
+        match err {
+            LoginError::Timeout { timeout, .. } => assert_eq!(timeout, SHORT * 3),
+            other => panic!("unexpected error: {other}"),
+        }
+        assert_eq!(sent_lines(&rx), vec!["\n", "\n"]);
+    }
+}
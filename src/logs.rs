@@ -0,0 +1,193 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How many boots of each log are kept: the current one plus `LOG_HISTORY - 1` rotated copies.
+pub const LOG_HISTORY: usize = 5;
+pub const VM_MANAGER_LOG_NAME: &str = "vm_manager.log";
+pub const VM_ROOT_LOG_NAME: &str = "vm_root.log";
+pub const PROVISION_LOG_NAME: &str = "provision.log";
+const FOLLOW_POLL: Duration = Duration::from_millis(200);
+const TAIL_WINDOW_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogKind {
+    /// vm manager (supervisor) log
+    Manager,
+    /// VM serial console output
+    Console,
+    /// Base image provisioning output
+    Provision,
+}
+
+impl LogKind {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            LogKind::Manager => VM_MANAGER_LOG_NAME,
+            LogKind::Console => VM_ROOT_LOG_NAME,
+            LogKind::Provision => PROVISION_LOG_NAME,
+        }
+    }
+}
+
+/// Path of `kind`'s log from `boots_ago` boots back; 0 is the current boot.
+pub fn log_path(instance_dir: &Path, kind: LogKind, boots_ago: usize) -> PathBuf {
+    rotated_path(&instance_dir.join(kind.file_name()), boots_ago)
+}
+
+/// Boots that still have a log for `kind`, newest first.
+pub fn available_boots(instance_dir: &Path, kind: LogKind) -> Vec<usize> {
+    (0..LOG_HISTORY)
+        .filter(|boots_ago| log_path(instance_dir, kind, *boots_ago).exists())
+        .collect()
+}
+
+fn rotated_path(path: &Path, generation: usize) -> PathBuf {
+    if generation == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
+
+/// Shifts `path` to `path.1`, `path.1` to `path.2` and so on, dropping the oldest so at most
+/// `keep` generations remain including the one about to be written.
+pub fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let _ = fs::remove_file(rotated_path(path, keep - 1));
+    for generation in (0..keep - 1).rev() {
+        let from = rotated_path(path, generation);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, generation + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// Rotates `path` and opens a fresh log in its place.
+pub fn open_rotated(path: &Path) -> io::Result<File> {
+    if let Err(err) = rotate(path, LOG_HISTORY) {
+        tracing::warn!(path = %path.display(), error = %err, "failed to rotate log");
+    }
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+}
+
+/// Copies `path` to `out`, then keeps copying whatever is appended until `out` fails. A log that
+/// is rotated away by a new boot is reopened from the start.
+pub fn follow(path: &Path, out: &mut impl Write) -> io::Result<()> {
+    let mut current: Option<(File, u64)> = None;
+    let mut buf = [0u8; 8192];
+    loop {
+        if current.is_none() {
+            current = File::open(path)
+                .ok()
+                .and_then(|file| file.metadata().ok().map(|meta| (file, meta.ino())));
+        }
+        if let Some((file, inode)) = current.as_mut() {
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                out.write_all(&buf[..n])?;
+            }
+            out.flush()?;
+            let replaced = match fs::metadata(path) {
+                Ok(meta) => meta.ino() != *inode || meta.len() < file.stream_position()?,
+                Err(_) => true,
+            };
+            if replaced {
+                current = None;
+                continue;
+            }
+        }
+        thread::sleep(FOLLOW_POLL);
+    }
+}
+
+/// Copies the whole of `path` to `out`.
+pub fn dump(path: &Path, out: &mut impl Write) -> io::Result<()> {
+    io::copy(&mut File::open(path)?, out)?;
+    out.flush()
+}
+
+/// The last `max_lines` lines of `path` as plain text, with terminal escape sequences removed.
+pub fn tail(path: &Path, max_lines: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(io::SeekFrom::Start(len.saturating_sub(TAIL_WINDOW_BYTES)))?;
+    let mut raw = Vec::new();
+    file.read_to_end(&mut raw)?;
+    let text = String::from_utf8_lossy(&raw);
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| strip_escapes(line.rsplit('\r').next().unwrap_or(line)))
+        .collect();
+    Ok(lines[lines.len().saturating_sub(max_lines)..].to_vec())
+}
+
+fn strip_escapes(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequences end at the first byte in `@`..=`~`; other escapes are two bytes.
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else if c == '\t' || !c.is_control() {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_last_boots() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join(VM_ROOT_LOG_NAME);
+        for boot in 0..(LOG_HISTORY + 2) {
+            let mut file = open_rotated(&path).unwrap();
+            write!(file, "boot {boot}").unwrap();
+        }
+
+        let boots = available_boots(temp.path(), LogKind::Console);
+        assert_eq!(boots, (0..LOG_HISTORY).collect::<Vec<_>>());
+        let newest = LOG_HISTORY + 1;
+        for boots_ago in boots {
+            let content =
+                fs::read_to_string(log_path(temp.path(), LogKind::Console, boots_ago)).unwrap();
+            assert_eq!(content, format!("boot {}", newest - boots_ago));
+        }
+        assert!(!rotated_path(&path, LOG_HISTORY).exists());
+    }
+
+    #[test]
+    fn tail_keeps_last_lines_without_escapes() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join(VM_ROOT_LOG_NAME);
+        fs::write(&path, "one\n\x1b[32mtwo\x1b[0m\nthr\rthree\n").unwrap();
+        assert_eq!(tail(&path, 2).unwrap(), ["two", "three"]);
+        assert_eq!(tail(&path, 10).unwrap().len(), 3);
+    }
+}
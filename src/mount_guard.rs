@@ -0,0 +1,125 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::vm::expand_tilde_path;
+
+/// Credential locations under `$HOME` that a mount must neither be, sit inside, nor contain.
+const HOME_DENYLIST: &[(&str, &str)] = &[
+    (".ssh", "ssh keys"),
+    (".aws", "AWS credentials"),
+    (".gnupg", "GnuPG keyring"),
+    (".azure", "Azure credentials"),
+    (".kube", "Kubernetes credentials"),
+    (".docker", "Docker credentials"),
+    (".netrc", "netrc credentials"),
+    (".config/gcloud", "Google Cloud credentials"),
+    (".config/gh", "GitHub CLI token"),
+    (".password-store", "pass password store"),
+    ("Library/Keychains", "macOS keychains"),
+];
+const SYSTEM_DENYLIST: &[(&str, &str)] = &[("/Library/Keychains", "macOS keychains")];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensitiveMatch {
+    /// The denylisted location, as written in the denylist (`~/.ssh`, `~`, ...).
+    pub location: String,
+    pub label: &'static str,
+}
+
+impl fmt::Display for SensitiveMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exposes {} ({})", self.location, self.label)
+    }
+}
+
+/// Checks the host side of a `[box].mounts` spec against the denylist, following symlinks.
+pub fn check_mount_spec(spec: &str) -> Option<SensitiveMatch> {
+    let host = spec.split(':').next().unwrap_or(spec);
+    check_host_path(&expand_tilde_path(host))
+}
+
+pub fn check_host_path(host: &Path) -> Option<SensitiveMatch> {
+    let home = env::var_os("HOME").map(PathBuf::from)?;
+    find_sensitive(host, &home)
+}
+
+fn find_sensitive(host: &Path, home: &Path) -> Option<SensitiveMatch> {
+    let host = canonical(host);
+    let home = canonical(home);
+
+    let entries = HOME_DENYLIST
+        .iter()
+        .map(|(rel, label)| (canonical(&home.join(rel)), format!("~/{rel}"), *label))
+        .chain(
+            SYSTEM_DENYLIST
+                .iter()
+                .map(|(path, label)| (canonical(Path::new(path)), path.to_string(), *label)),
+        );
+    for (path, location, label) in entries {
+        if !path.exists() {
+            continue;
+        }
+        if host.starts_with(&path) || path.starts_with(&host) {
+            return Some(SensitiveMatch { location, label });
+        }
+    }
+    // Everything under $HOME is fair game, but not $HOME itself or anything above it.
+    if home.starts_with(&host) {
+        return Some(SensitiveMatch {
+            location: "~".into(),
+            label: "home directory",
+        });
+    }
+    None
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denies_credentials_home_and_symlinks_into_them() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let home = temp.path().join("home");
+        let ssh = home.join(".ssh");
+        let project = home.join("src").join("app");
+        fs::create_dir_all(&ssh).unwrap();
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(home.join(".aws")).unwrap();
+        fs::write(home.join(".aws").join("credentials"), "").unwrap();
+        std::os::unix::fs::symlink(&ssh, project.join("keys")).unwrap();
+
+        let hit = |path: &Path| find_sensitive(path, &home).map(|m| m.to_string());
+        assert_eq!(hit(&ssh).as_deref(), Some("exposes ~/.ssh (ssh keys)"));
+        assert_eq!(
+            hit(&project.join("keys")).as_deref(),
+            Some("exposes ~/.ssh (ssh keys)")
+        );
+        assert_eq!(
+            hit(&home.join(".aws").join("credentials")).as_deref(),
+            Some("exposes ~/.aws (AWS credentials)")
+        );
+        assert_eq!(hit(&home).as_deref(), Some("exposes ~/.ssh (ssh keys)"));
+        assert_eq!(
+            hit(temp.path()).as_deref(),
+            Some("exposes ~/.ssh (ssh keys)")
+        );
+        assert_eq!(hit(&project), None);
+        assert_eq!(hit(&home.join(".claude")), None);
+    }
+
+    #[test]
+    fn denies_home_even_without_credentials() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let home = temp.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+        let found = find_sensitive(&home, &home).unwrap();
+        assert_eq!(found.to_string(), "exposes ~ (home directory)");
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::normalize_path,
+    vm::{DirectoryShare, PROJECT_GUEST_BASE},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MountPlanError {
+    #[error("mounts {first} and {second} both target guest path {guest}")]
+    DuplicateGuest {
+        guest: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Where a `[box].mounts` guest path ends up in the VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestPath {
+    /// Where the share is bind-mounted.
+    pub mount_point: PathBuf,
+    /// Paths in the ssh user's home are mounted under [`PROJECT_GUEST_BASE`] before the user
+    /// exists, then symlinked here.
+    pub home_link: Option<PathBuf>,
+}
+
+/// Resolves `~`, `/home/<ssh_user>`, relative paths (under `/root`) and `.`/`..` components.
+pub fn resolve_guest_path(guest: &str, ssh_user: &str) -> GuestPath {
+    let home = PathBuf::from(format!("/home/{ssh_user}"));
+    let path = if guest == "~" {
+        home.clone()
+    } else if let Some(stripped) = guest.strip_prefix("~/") {
+        home.join(stripped)
+    } else if Path::new(guest).is_absolute() {
+        PathBuf::from(guest)
+    } else {
+        Path::new("/root").join(guest)
+    };
+    let path = normalize_path(&path);
+
+    match path.strip_prefix(&home) {
+        Ok(rel) => GuestPath {
+            mount_point: normalize_path(&Path::new(PROJECT_GUEST_BASE).join(rel)),
+            home_link: Some(path.clone()),
+        },
+        Err(_) => GuestPath {
+            mount_point: path,
+            home_link: None,
+        },
+    }
+}
+
+/// Whether any `host:guest[:mode]` spec resolves to `mount_point`.
+pub fn maps_mount_point(specs: &[String], mount_point: &Path, ssh_user: &str) -> bool {
+    specs.iter().any(|spec| {
+        let parts: Vec<&str> = spec.split(':').collect();
+        parts.len() >= 2 && resolve_guest_path(parts[1], ssh_user).mount_point == mount_point
+    })
+}
+
+/// Rejects two mounts on the same guest path and orders the rest so parents are mounted before
+/// anything nested inside them. `guest` must already be resolved; `label` names a mount in errors.
+pub fn order_mounts<T>(
+    mounts: Vec<T>,
+    guest: impl Fn(&T) -> PathBuf,
+    label: impl Fn(&T) -> String,
+) -> Result<Vec<T>, MountPlanError> {
+    let mut keyed: Vec<(PathBuf, T)> = mounts
+        .into_iter()
+        .map(|mount| (normalize_path(&guest(&mount)), mount))
+        .collect();
+    for (index, (path, mount)) in keyed.iter().enumerate() {
+        if let Some((_, earlier)) = keyed[..index].iter().find(|(other, _)| other == path) {
+            return Err(MountPlanError::DuplicateGuest {
+                guest: path.display().to_string(),
+                first: label(earlier),
+                second: label(mount),
+            });
+        }
+    }
+    keyed.sort_by_key(|(path, _)| path.components().count());
+    Ok(keyed.into_iter().map(|(_, mount)| mount).collect())
+}
+
+/// The boot-time plan: [`order_mounts`] plus a distinct virtiofs tag for every share, since the
+/// same host directory may be shared twice and the hashed tags may collide.
+pub fn plan_shares(shares: Vec<DirectoryShare>) -> Result<Vec<DirectoryShare>, MountPlanError> {
+    let mut shares = order_mounts(
+        shares,
+        |share| share.guest().to_path_buf(),
+        |share| share.host().display().to_string(),
+    )?;
+    let mut seen = HashSet::new();
+    for share in &mut shares {
+        let base = share.tag();
+        let mut tag = base.clone();
+        let mut suffix = 1;
+        while !seen.insert(tag.clone()) {
+            suffix += 1;
+            tag = format!("{base}_{suffix}");
+        }
+        share.set_tag(tag);
+    }
+    Ok(shares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_home_and_relative_guest_paths() {
+        let resolve = |guest| resolve_guest_path(guest, "vibecoder");
+        let base = Path::new(PROJECT_GUEST_BASE);
+
+        let tilde = resolve("~/.codex/");
+        assert_eq!(tilde.mount_point, base.join(".codex"));
+        assert_eq!(
+            tilde.home_link,
+            Some(PathBuf::from("/home/vibecoder/.codex"))
+        );
+        assert_eq!(resolve("/home/vibecoder/./.codex"), tilde);
+        assert_eq!(resolve("~").mount_point, base);
+        assert_eq!(
+            resolve("data/../cache").mount_point,
+            Path::new("/root/cache")
+        );
+        assert_eq!(resolve("/opt/tools").home_link, None);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_mounts_parents_first() {
+        let mounts = vec![
+            ("a", "/usr/local/vibebox-mounts/app"),
+            ("b", "/usr/local/vibebox-mounts"),
+            ("c", "/opt"),
+        ];
+        let ordered = order_mounts(
+            mounts,
+            |(_, guest)| PathBuf::from(guest),
+            |(host, _)| host.to_string(),
+        )
+        .unwrap();
+        let hosts: Vec<_> = ordered.iter().map(|(host, _)| *host).collect();
+        assert_eq!(hosts, ["c", "b", "a"]);
+
+        let err = order_mounts(
+            vec![("a", "/opt/x"), ("b", "/opt/x/")],
+            |(_, guest)| PathBuf::from(guest),
+            |(host, _)| host.to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "mounts a and b both target guest path /opt/x"
+        );
+    }
+
+    #[test]
+    fn shares_of_one_host_get_distinct_tags() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let share = |guest: &str| {
+            DirectoryShare::new(temp.path().to_path_buf(), guest.into(), false).unwrap()
+        };
+        let plan = plan_shares(vec![share("/srv/b"), share("/srv/a")]).unwrap();
+        assert_ne!(plan[0].tag(), plan[1].tag());
+    }
+}
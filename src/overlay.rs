@@ -0,0 +1,401 @@
+//! `[box].project_mode = "overlay"`: the host project is shared read-only and the guest works on
+//! an overlayfs whose upper layer lives on the instance disk. A root helper in the guest lists
+//! and drops upper-layer entries; the host turns the listing into reviewable changes.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{error::ErrorCode, vm::PROJECT_GUEST_BASE};
+#[cfg(any(target_os = "macos", test))]
+use crate::{
+    instance::SSH_KEY_NAME, session_manager::GLOBAL_DIR_NAME, vm::script_command_from_content,
+};
+
+/// Where the read-only host project is shared in overlay mode.
+pub const OVERLAY_LOWER_BASE: &str = "/usr/local/vibebox-lower";
+#[cfg(any(target_os = "macos", test))]
+const STATE_DIR: &str = "/var/lib/vibebox/overlay";
+#[cfg(any(target_os = "macos", test))]
+const HELPER_PATH: &str = "/usr/local/sbin/vibebox-overlay";
+#[cfg(any(target_os = "macos", test))]
+const OVERLAY_SCRIPT: &str = include_str!("overlay.sh");
+#[cfg(any(target_os = "macos", test))]
+const OVERLAY_SCRIPT_NAME: &str = "project_overlay";
+/// Top-level entries masked in the guest; they are never changes.
+const IGNORED: &[&str] = &[".git", ".vibebox", ".vibebox-sessions"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayError {
+    #[error(
+        "[box].project_mode is \"mount\", so the guest writes straight to the project; set it to \"overlay\" and restart the VM to review changes"
+    )]
+    NotEnabled,
+    #[error("invalid path {0:?}: use a path inside the project without `..`")]
+    InvalidPath(String),
+    #[error("unexpected overlay listing from the guest: {0}")]
+    Listing(String),
+    #[error("overlay helper failed in the guest: {0}")]
+    Guest(String),
+    #[error("failed to update {}: {source}", path.display())]
+    Host { path: PathBuf, source: io::Error },
+}
+
+impl OverlayError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            OverlayError::NotEnabled => ErrorCode::ConfigInvalid,
+            OverlayError::InvalidPath(_) => ErrorCode::InvalidArgument,
+            OverlayError::Listing(_) | OverlayError::Guest(_) => ErrorCode::Other,
+            OverlayError::Host { .. } => ErrorCode::Io,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn letter(self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+        }
+    }
+}
+
+/// One path the guest changed, relative to the project root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub path: String,
+    /// The guest's entry is a directory, or for a deletion the host's is.
+    pub dir: bool,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slash = if self.dir { "/" } else { "" };
+        write!(f, "{} {}{slash}", self.kind.letter(), self.path)
+    }
+}
+
+/// Where the read-only host project is shared before the overlay goes on top of it.
+pub fn lower_dir(project_name: &str) -> PathBuf {
+    Path::new(OVERLAY_LOWER_BASE).join(project_name)
+}
+
+/// The project as the guest sees it.
+pub(crate) fn merged_dir(project_name: &str) -> PathBuf {
+    Path::new(PROJECT_GUEST_BASE).join(project_name)
+}
+
+/// The root console command that mounts the overlay and installs the helper the host reaches
+/// with its own key.
+#[cfg(any(target_os = "macos", test))]
+pub(crate) fn boot_command(project_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    // vm_manager shares the instance directory, key pair included, at /root/.vibebox.
+    let key_path = format!("/root/{GLOBAL_DIR_NAME}/{SSH_KEY_NAME}.pub");
+    let script = OVERLAY_SCRIPT
+        .replace("__LOWER_DIR__", &lower_dir(project_name).to_string_lossy())
+        .replace(
+            "__MERGED_DIR__",
+            &merged_dir(project_name).to_string_lossy(),
+        )
+        .replace("__STATE_DIR__", STATE_DIR)
+        .replace("__KEY_PATH__", &key_path)
+        .replace("__HELPER_PATH__", HELPER_PATH);
+    script_command_from_content(OVERLAY_SCRIPT_NAME, &script)
+}
+
+/// `path` relative to the project with `.` and empty components dropped; `""` is the root.
+pub fn clean_path(path: &str) -> Result<String, OverlayError> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return Err(OverlayError::InvalidPath(path.to_string())),
+            part if part.contains(['\0', '\n']) => {
+                return Err(OverlayError::InvalidPath(path.to_string()));
+            }
+            part => parts.push(part),
+        }
+    }
+    if path.starts_with('/') {
+        return Err(OverlayError::InvalidPath(path.to_string()));
+    }
+    Ok(parts.join("/"))
+}
+
+/// The changes at or under any of `paths`; all of them when `paths` is empty.
+pub fn select(changes: Vec<Change>, paths: &[String]) -> Result<Vec<Change>, OverlayError> {
+    let prefixes = paths
+        .iter()
+        .map(|path| clean_path(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    if prefixes.is_empty() || prefixes.iter().any(String::is_empty) {
+        return Ok(changes);
+    }
+    Ok(changes
+        .into_iter()
+        .filter(|change| {
+            prefixes.iter().any(|prefix| {
+                change.path == *prefix
+                    || change
+                        .path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+        })
+        .collect())
+}
+
+/// Turns the helper's `list` output into changes against the host project as it is now.
+pub fn changes_from_listing(
+    listing: &[u8],
+    project_root: &Path,
+) -> Result<Vec<Change>, OverlayError> {
+    let fields: Vec<&[u8]> = listing.split(|byte| *byte == 0).collect();
+    // Every record ends with a NUL, which leaves one empty field behind.
+    let fields = match fields.split_last() {
+        Some((&[], rest)) => rest,
+        _ if listing.is_empty() => &[][..],
+        _ => return Err(OverlayError::Listing("truncated record".to_string())),
+    };
+    if fields.len() % 3 != 0 {
+        return Err(OverlayError::Listing("truncated record".to_string()));
+    }
+
+    let mut changes = BTreeMap::new();
+    let mut listed_dirs = Vec::new();
+    let mut merged = HashSet::new();
+    for record in fields.chunks(3) {
+        let kind = String::from_utf8_lossy(record[0]);
+        let detail = record[1];
+        let Ok(raw_path) = std::str::from_utf8(record[2]) else {
+            tracing::warn!(
+                path = %String::from_utf8_lossy(record[2]),
+                "skipping a guest path that is not UTF-8"
+            );
+            continue;
+        };
+        let path = clean_path(raw_path)
+            .ok()
+            .filter(|path| !path.is_empty() || kind == "M")
+            .ok_or_else(|| OverlayError::Listing(format!("bad path {raw_path:?}")))?;
+        if kind == "M" {
+            listed_dirs.push(path);
+            continue;
+        }
+        if is_ignored(&path) {
+            continue;
+        }
+        if kind == "m" {
+            merged.insert(path);
+            continue;
+        }
+
+        let host = fs::symlink_metadata(project_root.join(&path)).ok();
+        let host_type = host.as_ref().map(fs::Metadata::file_type);
+        let (unchanged, dir) = match kind.as_ref() {
+            "f" => (
+                detail == b"same" && host_type.is_some_and(|t| t.is_file()),
+                false,
+            ),
+            "l" => (
+                fs::read_link(project_root.join(&path))
+                    .is_ok_and(|target| target.as_os_str().as_encoded_bytes() == detail),
+                false,
+            ),
+            "d" => (host_type.is_some_and(|t| t.is_dir()), true),
+            // Whiteouts only hide host entries; the merged listing below finds those.
+            "c" => continue,
+            _ => (false, false),
+        };
+        if unchanged {
+            continue;
+        }
+        let kind = if host.is_some() {
+            ChangeKind::Modified
+        } else {
+            ChangeKind::Added
+        };
+        changes.insert(path.clone(), Change { kind, path, dir });
+    }
+
+    // A host entry missing from the guest's listing of its directory was deleted, whether by a
+    // whiteout or by replacing the whole directory.
+    for dir in listed_dirs {
+        if !dir.is_empty() && (is_ignored(&dir) || host_path(project_root, &dir).is_err()) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(project_root.join(&dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            if is_ignored(&path) || merged.contains(&path) {
+                continue;
+            }
+            let dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            changes.insert(
+                path.clone(),
+                Change {
+                    kind: ChangeKind::Deleted,
+                    path,
+                    dir,
+                },
+            );
+        }
+    }
+    Ok(changes.into_values().collect())
+}
+
+fn is_ignored(path: &str) -> bool {
+    let top = path.split('/').next().unwrap_or_default();
+    IGNORED.contains(&top)
+}
+
+/// `path` under `project_root`, refusing to go through a symlink on the way there.
+pub(crate) fn host_path(project_root: &Path, path: &str) -> Result<PathBuf, OverlayError> {
+    let clean = clean_path(path)?;
+    if clean.is_empty() {
+        return Err(OverlayError::InvalidPath(path.to_string()));
+    }
+    let mut current = project_root.to_path_buf();
+    let mut parts = clean.split('/').peekable();
+    while let Some(part) = parts.next() {
+        current.push(part);
+        if parts.peek().is_some()
+            && fs::symlink_metadata(&current).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            return Err(OverlayError::InvalidPath(path.to_string()));
+        }
+    }
+    Ok(current)
+}
+
+/// Removes a host entry so the guest's version can take its place, or for a deletion.
+pub(crate) fn remove_host_entry(path: &Path) -> Result<(), OverlayError> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    };
+    result.map_err(|source| OverlayError::Host {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// NUL-separated paths for the helper's `reset`.
+pub(crate) fn reset_input(changes: &[Change]) -> Vec<u8> {
+    let mut input = Vec::new();
+    for change in changes {
+        input.extend_from_slice(change.path.as_bytes());
+        input.push(0);
+    }
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kind: &str, detail: &str, path: &str) -> Vec<u8> {
+        [kind, detail, path]
+            .iter()
+            .flat_map(|field| field.bytes().chain([0]))
+            .collect()
+    }
+
+    #[test]
+    fn listing_becomes_added_modified_and_deleted_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("src/lib.rs"), "old").unwrap();
+        fs::write(root.join("src/main.rs"), "same").unwrap();
+        fs::write(root.join("src/gone.rs"), "").unwrap();
+        fs::write(root.join("README"), "").unwrap();
+
+        let listing: Vec<u8> = [
+            record("f", "changed", "src/lib.rs"),
+            record("f", "same", "src/main.rs"),
+            record("f", "changed", "src/new.rs"),
+            record("f", "changed", ".git/HEAD"),
+            record("d", "", "src"),
+            record("d", "", "docs"),
+            record("c", "", "src/gone.rs"),
+            record("M", "", "."),
+            record("m", "", "./README"),
+            record("m", "", "./src"),
+            record("m", "", "./docs"),
+            record("m", "", "./.git"),
+            record("M", "", "./src"),
+            record("m", "", "./src/lib.rs"),
+            record("m", "", "./src/main.rs"),
+            record("m", "", "./src/new.rs"),
+            record("M", "", "./docs"),
+        ]
+        .concat();
+
+        let changes = changes_from_listing(&listing, root).unwrap();
+        let lines: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            ["A docs/", "D src/gone.rs", "M src/lib.rs", "A src/new.rs"]
+        );
+
+        assert!(changes_from_listing(b"f\0same", root).is_err());
+        assert!(changes_from_listing(&record("f", "", "../x"), root).is_err());
+        assert!(changes_from_listing(b"", root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn paths_select_changes_and_stay_inside_the_project() {
+        let change = |path: &str| Change {
+            kind: ChangeKind::Added,
+            path: path.to_string(),
+            dir: false,
+        };
+        let changes = vec![change("src/a.rs"), change("src2/b.rs"), change("top")];
+        let picked = select(changes.clone(), &["./src/".to_string()]).unwrap();
+        assert_eq!(picked, [change("src/a.rs")]);
+        assert_eq!(
+            select(changes.clone(), &[".".to_string()]).unwrap(),
+            changes
+        );
+        assert!(matches!(
+            select(changes, &["../x".to_string()]),
+            Err(OverlayError::InvalidPath(_))
+        ));
+        assert!(clean_path("/etc/passwd").is_err());
+
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir(temp.path().join("real")).unwrap();
+        std::os::unix::fs::symlink("/tmp", temp.path().join("link")).unwrap();
+        assert!(host_path(temp.path(), "real/x").is_ok());
+        assert!(host_path(temp.path(), "link").is_ok());
+        assert!(host_path(temp.path(), "link/x").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+#!/bin/bash
+# vibebox-overlay: mounts the project as an overlay on the read-only host share at boot, then,
+# as the forced command of the host's root key, lists or drops what the guest changed.
+set -euo pipefail
+
+LOWER="__LOWER_DIR__"
+MERGED="__MERGED_DIR__"
+STATE="__STATE_DIR__"
+KEY_PATH="__KEY_PATH__"
+HELPER="__HELPER_PATH__"
+UPPER="$STATE/upper"
+WORK="$STATE/work"
+# Masked with a tmpfs at boot, so never part of a diff.
+MASKED=(.git .vibebox .vibebox-sessions)
+
+fail() { echo "vibebox-overlay: $*" >&2; exit 1; }
+
+is_overlay() {
+  [ "$(findmnt -n -o FSTYPE --mountpoint "$MERGED" 2>/dev/null || true)" = "overlay" ]
+}
+
+escape_option() { printf '%s' "$1" | sed 's/[\\,:]/\\&/g'; }
+
+mount_overlay() {
+  mkdir -p "$MERGED"
+  mount -t overlay overlay \
+    -o "lowerdir=$(escape_option "$LOWER"),upperdir=$(escape_option "$UPPER"),workdir=$(escape_option "$WORK")" \
+    "$MERGED"
+}
+
+# The merged root takes its owner and mode from the upper root.
+match_lower_root() {
+  chown --reference="$LOWER" "$1"
+  chmod --reference="$LOWER" "$1"
+}
+
+boot() {
+  if [ ! -d "$LOWER" ]; then
+    echo "vibebox-overlay: $LOWER is not shared; leaving the project unmounted" >&2
+    return 0
+  fi
+  install -d -m 700 "$STATE"
+  mkdir -p "$UPPER" "$WORK"
+  match_lower_root "$UPPER"
+  if ! is_overlay; then
+    mount_overlay
+  fi
+
+  install -m 700 "$0" "$HELPER"
+  install -d -m 700 /root/.ssh
+  printf 'restrict,command="%s" %s\n' "$HELPER" "$(cat "$KEY_PATH")" >/root/.ssh/authorized_keys
+  chmod 600 /root/.ssh/authorized_keys
+  # Sorts before the base config, whose `PermitRootLogin no` would otherwise win.
+  install -d -m 755 /etc/ssh/sshd_config.d
+  echo "PermitRootLogin forced-commands-only" >/etc/ssh/sshd_config.d/05-vibebox-overlay.conf
+  systemctl reload ssh >/dev/null 2>&1 || true
+}
+
+# NUL-separated `<kind> <detail> <path>` records: `f` files with `same` or `changed` against the
+# host, `l` symlinks with their target, `d` directories and `c` whiteouts; then, for every directory
+# of the upper layer, `M` with its path and `m` for every name the guest sees in it.
+list() {
+  is_overlay || fail "the project is not mounted as an overlay; restart the VM after setting [box].project_mode"
+  cd "$UPPER"
+  find . -mindepth 1 \( -path ./.git -o -path ./.vibebox -o -path ./.vibebox-sessions \) -prune -o -type f -printf '%P\0' |
+    while IFS= read -r -d '' path; do
+      if [ -f "$LOWER/$path" ] && cmp -s -- "$UPPER/$path" "$LOWER/$path"; then
+        printf 'f\0same\0%s\0' "$path"
+      else
+        printf 'f\0changed\0%s\0' "$path"
+      fi
+    done
+  find . -mindepth 1 \( -path ./.git -o -path ./.vibebox -o -path ./.vibebox-sessions \) -prune -o ! -type f -printf '%y\0%l\0%P\0'
+
+  {
+    printf '.\0'
+    find . -mindepth 1 \( -path ./.git -o -path ./.vibebox -o -path ./.vibebox-sessions \) -prune -o -type d -printf './%P\0'
+  } | while IFS= read -r -d '' dir; do
+    if [ -d "$MERGED/$dir" ]; then
+      printf 'M\0\0%s\0' "$dir"
+      (cd "$MERGED" && find "$dir" -mindepth 1 -maxdepth 1 -printf 'm\0\0%p\0')
+    fi
+  done
+}
+
+is_opaque() {
+  if command -v getfattr >/dev/null 2>&1; then
+    [ "$(getfattr --only-values -n trusted.overlay.opaque -- "$1" 2>/dev/null || true)" = "y" ]
+  else
+    # Without getfattr there is no telling; copying the host entry up is harmless either way.
+    return 0
+  fi
+}
+
+# Drops the NUL-separated paths on stdin from the upper layer, or all of it when there are none,
+# and remounts. The upper layer is rebuilt from a copy rather than edited in place, since shells
+# still inside the old mount keep writing to it until they leave the directory.
+reset() {
+  is_overlay || fail "the project is not mounted as an overlay; restart the VM after setting [box].project_mode"
+  local paths=()
+  while IFS= read -r -d '' path; do
+    case "/$path/" in
+      //|*/../*|*/./*|*//*) fail "invalid path: $path" ;;
+    esac
+    paths+=("$path")
+  done
+
+  local masks=()
+  for name in "${MASKED[@]}"; do
+    if mountpoint -q "$MERGED/$name"; then
+      masks+=("$name")
+    fi
+  done
+
+  umount -l "$MERGED"
+  local old="$STATE/upper.old"
+  rm -rf "$old"
+  mv "$UPPER" "$old"
+  mkdir -p "$UPPER"
+  match_lower_root "$UPPER"
+  if [ "${#paths[@]}" -gt 0 ]; then
+    local excludes=()
+    for path in "${paths[@]}"; do
+      excludes+=("--exclude=./$path")
+    done
+    tar -C "$old" --anchored --no-wildcards --xattrs --xattrs-include='trusted.*' \
+      "${excludes[@]}" -cf - . |
+      tar -C "$UPPER" --xattrs --xattrs-include='trusted.*' -xpf -
+    # A dropped entry inside an opaque directory would stay hidden, so bring the host's back.
+    for path in "${paths[@]}"; do
+      local parent
+      parent="$(dirname -- "$path")"
+      if [ "$(realpath -m -- "$UPPER/$parent")" != "$(realpath -m -s -- "$UPPER/$parent")" ]; then
+        continue
+      fi
+      if [ -d "$UPPER/$parent" ] && [ ! -e "$UPPER/$path" ] && [ ! -L "$UPPER/$path" ] &&
+        { [ -e "$LOWER/$path" ] || [ -L "$LOWER/$path" ]; } && is_opaque "$UPPER/$parent"; then
+        cp -a -- "$LOWER/$path" "$UPPER/$path"
+      fi
+    done
+  fi
+  rm -rf "$old" "$WORK"
+  mkdir -p "$WORK"
+  mount_overlay
+  for name in "${masks[@]}"; do
+    mount -t tmpfs tmpfs "$MERGED/$name"
+  done
+}
+
+if [ -z "${SSH_CONNECTION:-}" ]; then
+  boot
+  exit 0
+fi
+
+case "${SSH_ORIGINAL_COMMAND:-}" in
+  list) list ;;
+  reset) reset ;;
+  *)
+    echo "usage: vibebox-overlay list|reset" >&2
+    exit 2
+    ;;
+esac
@@ -97,6 +97,9 @@ systemctl poweroff
 sleep 100 # sleep here so that we don't see the login screen flash up before the shutdown.
 EOF
 
+# Record which template this is so every clone can report it.
+echo "__TEMPLATE_VERSION__" > /etc/vibebox-template
+
 # Done provisioning, power off the VM
 printf "%s%s\n" VIBEBOX_PROVISION_ OK
 systemctl poweroff
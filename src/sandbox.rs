@@ -0,0 +1,777 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    SessionError, SessionManager,
+    agent::ExecOutput,
+    commands::shell_quote,
+    config::{self, Config, ConfigError, ProjectMode},
+    error::{ErrorCode, VibeboxError},
+    guest_info::GuestInfo,
+    instance::{self, ExecStream},
+    overlay::{self, Change, ChangeKind, OverlayError},
+    session_manager::{self, CleanSummary, VM_MANAGER_PID_NAME},
+    vm, vm_manager,
+};
+
+/// Where a sandbox with overrides writes the config its vm manager boots from.
+pub const SANDBOX_CONFIG_NAME: &str = "sandbox.toml";
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("project directory {}: {source}", path.display())]
+    Project { path: PathBuf, source: io::Error },
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Vibebox(#[from] VibeboxError),
+    #[error("the sandbox is not running; call up() first")]
+    NotRunning,
+    #[error("the vm manager did not stop within {}s", STOP_TIMEOUT.as_secs())]
+    StopTimeout,
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Overlay(#[from] OverlayError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl SandboxError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SandboxError::Project { .. } => ErrorCode::InvalidArgument,
+            SandboxError::Config(err) => err.code(),
+            SandboxError::Vibebox(err) => err.code(),
+            SandboxError::NotRunning => ErrorCode::ManagerNotRunning,
+            SandboxError::StopTimeout => ErrorCode::ManagerStopTimeout,
+            SandboxError::Session(err) => err.code(),
+            SandboxError::Overlay(err) => err.code(),
+            SandboxError::Io(_) => ErrorCode::Io,
+        }
+    }
+}
+
+/// What [`Sandbox::status`] found on disk; it never connects to the manager.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SandboxStatus {
+    pub running: bool,
+    pub manager_pid: Option<u32>,
+    pub session_id: Option<String>,
+    /// Only known while running.
+    pub vm_ip: Option<String>,
+    pub guest: Option<GuestInfo>,
+}
+
+/// Settings for a [`Sandbox`]. Without a [`config`](Self::config), the project's
+/// `vibebox.toml` (or [`config_path`](Self::config_path)) is loaded, and created if missing.
+#[derive(Debug, Clone)]
+pub struct SandboxBuilder {
+    project: PathBuf,
+    config_path: Option<PathBuf>,
+    config: Option<Config>,
+    cpu_count: Option<usize>,
+    ram_mb: Option<u64>,
+    disk_gb: Option<u64>,
+    auto_shutdown_ms: Option<u64>,
+    mounts: Vec<String>,
+    session: Option<String>,
+    ephemeral: bool,
+}
+
+impl SandboxBuilder {
+    /// A config file inside the project; relative paths start at the project directory.
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Uses `config` instead of reading a file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn cpu_count(mut self, cpu_count: usize) -> Self {
+        self.cpu_count = Some(cpu_count);
+        self
+    }
+
+    pub fn ram_mb(mut self, ram_mb: u64) -> Self {
+        self.ram_mb = Some(ram_mb);
+        self
+    }
+
+    /// Only applies when the instance disk is first created.
+    pub fn disk_gb(mut self, disk_gb: u64) -> Self {
+        self.disk_gb = Some(disk_gb);
+        self
+    }
+
+    pub fn auto_shutdown_ms(mut self, auto_shutdown_ms: u64) -> Self {
+        self.auto_shutdown_ms = Some(auto_shutdown_ms);
+        self
+    }
+
+    /// Adds a `host:guest[:mode]` mount after the configured ones.
+    pub fn mount(mut self, spec: impl Into<String>) -> Self {
+        self.mounts.push(spec.into());
+        self
+    }
+
+    /// One of the project's named sessions, with its own disk, manager and IP in
+    /// `.vibebox-sessions/<name>`, instead of the default one.
+    pub fn session(mut self, name: impl Into<String>) -> Self {
+        self.session = Some(name.into());
+        self
+    }
+
+    /// Boots from a throwaway copy of the base disk in a temp directory instead of the
+    /// project's `.vibebox`. It gets its own session id, is not listed in `vibebox list`, and is
+    /// deleted once the last client disconnects.
+    pub fn ephemeral(mut self) -> Self {
+        self.ephemeral = true;
+        self
+    }
+
+    fn has_overrides(&self) -> bool {
+        self.config.is_some()
+            || self.cpu_count.is_some()
+            || self.ram_mb.is_some()
+            || self.disk_gb.is_some()
+            || self.auto_shutdown_ms.is_some()
+            || !self.mounts.is_empty()
+    }
+
+    pub fn build(self) -> Result<Sandbox, SandboxError> {
+        let project_root = fs::canonicalize(&self.project)
+            .and_then(|root| {
+                if root.is_dir() {
+                    Ok(root)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::NotADirectory,
+                        "not a directory",
+                    ))
+                }
+            })
+            .map_err(|source| SandboxError::Project {
+                path: self.project.clone(),
+                source,
+            })?;
+        if let Some(name) = &self.session {
+            if self.ephemeral {
+                return Err(VibeboxError::InvalidArgument(
+                    "an ephemeral sandbox cannot be a named session".to_string(),
+                )
+                .into());
+            }
+            session_manager::validate_session_name(name)?;
+        }
+        let instance_dir = if self.ephemeral {
+            ephemeral_instance_dir()
+        } else {
+            session_manager::session_instance_dir(&project_root, self.session.as_deref())
+        };
+        let overridden = self.has_overrides();
+        let (mut config, config_path) = match self.config {
+            Some(config) => (config, instance_dir.join(SANDBOX_CONFIG_NAME)),
+            None => {
+                let path =
+                    config::try_resolve_config_path(&project_root, self.config_path.as_deref())?;
+                let config = config::try_load_config(&project_root, Some(&path))?;
+                let path = if overridden {
+                    instance_dir.join(SANDBOX_CONFIG_NAME)
+                } else {
+                    path
+                };
+                (config, path)
+            }
+        };
+        if let Some(cpu_count) = self.cpu_count {
+            config.box_cfg.cpu_count = cpu_count;
+        }
+        if let Some(ram_mb) = self.ram_mb {
+            config.box_cfg.ram_mb = ram_mb;
+        }
+        if let Some(disk_gb) = self.disk_gb {
+            config.box_cfg.disk_gb = disk_gb;
+        }
+        if let Some(auto_shutdown_ms) = self.auto_shutdown_ms {
+            config.supervisor.auto_shutdown_ms = auto_shutdown_ms;
+        }
+        config.box_cfg.mounts.extend(self.mounts);
+        config::validate_config(&config, &project_root).map_err(ConfigError::Invalid)?;
+        Ok(Sandbox {
+            project_root,
+            instance_dir,
+            config,
+            config_path,
+            overridden,
+            session: self.session,
+            ephemeral: self.ephemeral,
+            connection: Mutex::new(None),
+        })
+    }
+}
+
+/// A fresh directory name under the system temp dir, short enough for the sockets inside it.
+fn ephemeral_instance_dir() -> PathBuf {
+    let id = Uuid::now_v7().simple().to_string();
+    env::temp_dir().join(format!("vibebox-{}", &id[id.len() - 12..]))
+}
+
+/// One project's VM. The VM stays up while this value holds a manager connection, and for
+/// `auto_shutdown_ms` after the last client lets go (right away for an ephemeral one). Nothing
+/// here changes the working directory or exits the process.
+#[derive(Debug)]
+pub struct Sandbox {
+    project_root: PathBuf,
+    instance_dir: PathBuf,
+    config: Config,
+    config_path: PathBuf,
+    overridden: bool,
+    session: Option<String>,
+    ephemeral: bool,
+    connection: Mutex<Option<UnixStream>>,
+}
+
+impl Sandbox {
+    pub fn builder(project: impl Into<PathBuf>) -> SandboxBuilder {
+        SandboxBuilder {
+            project: project.into(),
+            config_path: None,
+            config: None,
+            cpu_count: None,
+            ram_mb: None,
+            disk_gb: None,
+            auto_shutdown_ms: None,
+            mounts: Vec::new(),
+            session: None,
+            ephemeral: false,
+        }
+    }
+
+    pub fn project_root(&self) -> &Path {
+        &self.project_root
+    }
+
+    /// The project's `.vibebox`, a named session's directory in it, or the temp directory of an
+    /// ephemeral sandbox.
+    pub fn instance_dir(&self) -> &Path {
+        &self.instance_dir
+    }
+
+    pub fn session_name(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The config file the vm manager boots from.
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    pub fn vm_args(&self) -> vm::VmArg {
+        let config = &self.config;
+        vm::VmArg {
+            cpu_count: config.box_cfg.cpu_count,
+            ram_bytes: config.box_cfg.ram_mb.saturating_mul(1024 * 1024),
+            disk_bytes: config.box_cfg.disk_gb.saturating_mul(1024 * 1024 * 1024),
+            no_default_mounts: false,
+            mounts: config.box_cfg.mounts.clone(),
+            security: config.security.profile,
+            allow_sensitive: config.box_cfg.allow_sensitive,
+            project_mode: config.box_cfg.project_mode,
+            commands: config.commands.clone(),
+            hooks: config.hooks.clone(),
+        }
+    }
+
+    /// Creates `.vibebox` and lists the project in `vibebox list` without booting, and returns
+    /// the session id. An ephemeral sandbox only creates its temp directory.
+    pub fn create(&self) -> Result<String, SandboxError> {
+        self.ensure_instance_dir()?;
+        let instance =
+            instance::load_or_create_instance_config(&self.instance_dir).map_err(boxed)?;
+        self.write_overrides()?;
+        if !self.ephemeral {
+            SessionManager::new()?
+                .update_global_sessions(&self.project_root, self.session.as_deref())?;
+        }
+        Ok(instance.session_id().unwrap_or_default().to_string())
+    }
+
+    /// Starts the vm manager, or joins the running one, without waiting for the guest.
+    /// Overrides only apply to a VM this call boots.
+    pub fn start(&self) -> Result<(), SandboxError> {
+        self.connection(true).map(drop)
+    }
+
+    /// Like [`Sandbox::start`], then waits until the guest accepts ssh.
+    pub fn up(&self) -> Result<(), SandboxError> {
+        self.start()?;
+        instance::wait_for_guest(&self.instance_dir).map_err(boxed)?;
+        Ok(())
+    }
+
+    /// Runs `command` with `sh` as the ssh user in a running sandbox and captures its output.
+    pub fn exec(&self, command: &str) -> Result<ExecOutput, SandboxError> {
+        let conn = self.connection(false)?;
+        instance::exec_guest_command(&self.instance_dir, conn, command).map_err(boxed)
+    }
+
+    /// Like [`Sandbox::exec`], but passes output to `on_output` as it arrives and returns the
+    /// exit code.
+    pub fn exec_streaming(
+        &self,
+        command: &str,
+        on_output: impl FnMut(ExecStream, &[u8]),
+    ) -> Result<i32, SandboxError> {
+        let conn = self.connection(false)?;
+        instance::stream_guest_command(&self.instance_dir, conn, command, on_output).map_err(boxed)
+    }
+
+    /// Like [`Sandbox::exec`] with the terminal attached, starting the sandbox if needed.
+    /// Returns the command's exit code.
+    pub fn run(&self, command: &str) -> Result<i32, SandboxError> {
+        let conn = self.connection(true)?;
+        instance::run_guest_command(&self.instance_dir, conn, command).map_err(boxed)
+    }
+
+    /// Opens an interactive ssh session, starting the sandbox if needed.
+    pub fn shell(&self) -> Result<(), SandboxError> {
+        let conn = self.connection(true)?;
+        instance::run_with_ssh(&self.instance_dir, conn).map_err(boxed)
+    }
+
+    /// Copies with scp; see [`instance::run_copy`] for the `:guest/path` syntax.
+    pub fn copy(
+        &self,
+        source: &str,
+        destination: &str,
+        recursive: bool,
+    ) -> Result<(), SandboxError> {
+        instance::check_copy_args(source, destination).map_err(VibeboxError::InvalidArgument)?;
+        let conn = self.connection(true)?;
+        instance::run_copy(&self.instance_dir, conn, source, destination, recursive).map_err(boxed)
+    }
+
+    /// Pipes stdin/stdout to the guest's sshd, starting the sandbox if needed.
+    pub fn ssh_proxy(&self) -> Result<(), SandboxError> {
+        let conn = self.connection(true)?;
+        instance::run_ssh_proxy(&self.instance_dir, conn).map_err(boxed)
+    }
+
+    /// What the guest changed under `paths` (everywhere when empty) of an overlay project,
+    /// compared with the host project as it is now. Starts the sandbox if needed.
+    pub fn diff(&self, paths: &[String]) -> Result<Vec<Change>, SandboxError> {
+        self.ensure_overlay()?;
+        let output = self.overlay_helper("list", Vec::new())?;
+        let changes = overlay::changes_from_listing(&output, &self.project_root)?;
+        Ok(overlay::select(changes, paths)?)
+    }
+
+    /// Unified diffs of `changes` from [`Sandbox::diff`], host side first; directories are
+    /// left out.
+    pub fn diff_patch(&self, changes: &[Change]) -> Result<String, SandboxError> {
+        self.ensure_overlay()?;
+        let lower = overlay::lower_dir(&self.project_name());
+        let merged = overlay::merged_dir(&self.project_name());
+        let mut script = Vec::new();
+        for change in changes.iter().filter(|change| !change.dir) {
+            let path = overlay::clean_path(&change.path)?;
+            script.push(format!(
+                "diff -uN --label {} --label {} -- {} {}",
+                shell_quote(&format!("a/{path}")),
+                shell_quote(&format!("b/{path}")),
+                shell_quote(&lower.join(&path).to_string_lossy()),
+                shell_quote(&merged.join(&path).to_string_lossy()),
+            ));
+        }
+        if script.is_empty() {
+            return Ok(String::new());
+        }
+        // diff exits 1 when files differ, so only its output says whether it worked.
+        script.push("true".to_string());
+        let output = self.exec(&script.join("; "))?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Copies the guest's changes under `paths` (all of them when empty) into the host project,
+    /// then drops them from the overlay so the guest sees the host's files again. Returns what
+    /// was applied.
+    pub fn apply(&self, paths: &[String]) -> Result<Vec<Change>, SandboxError> {
+        let changes = self.diff(paths)?;
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        let mut copied = Vec::new();
+        for change in &changes {
+            let host = overlay::host_path(&self.project_root, &change.path)?;
+            let host_is_dir = fs::symlink_metadata(&host).is_ok_and(|meta| meta.is_dir());
+            match change.kind {
+                ChangeKind::Deleted => overlay::remove_host_entry(&host)?,
+                _ if host_is_dir != change.dir => {
+                    overlay::remove_host_entry(&host)?;
+                    copied.push(change.path.as_str());
+                }
+                _ => copied.push(change.path.as_str()),
+            }
+        }
+        if !copied.is_empty() {
+            self.copy_from_overlay(&copied)?;
+        }
+        self.overlay_helper("reset", overlay::reset_input(&changes))?;
+        Ok(changes)
+    }
+
+    /// Drops the guest's changes under `paths` from the overlay, or every change when `paths`
+    /// is empty. Returns what was dropped.
+    pub fn discard(&self, paths: &[String]) -> Result<Vec<Change>, SandboxError> {
+        let changes = self.diff(paths)?;
+        let input = if paths.is_empty() {
+            Vec::new()
+        } else if changes.is_empty() {
+            return Ok(changes);
+        } else {
+            overlay::reset_input(&changes)
+        };
+        self.overlay_helper("reset", input)?;
+        Ok(changes)
+    }
+
+    pub fn status(&self) -> SandboxStatus {
+        let running = session_manager::is_instance_active(&self.instance_dir);
+        let instance = instance::read_instance_config(&self.instance_dir)
+            .ok()
+            .flatten();
+        SandboxStatus {
+            running,
+            manager_pid: session_manager::read_pid(&self.instance_dir.join(VM_MANAGER_PID_NAME))
+                .filter(|_| running),
+            session_id: instance
+                .as_ref()
+                .and_then(|instance| instance.session_id().map(str::to_string)),
+            vm_ip: instance
+                .as_ref()
+                .and_then(|instance| instance.vm_ipv4.clone())
+                .filter(|_| running),
+            guest: instance.and_then(|instance| instance.guest),
+        }
+    }
+
+    /// Powers the VM off even if other clients are attached, and waits for the manager to exit.
+    pub fn stop(&self) -> Result<(), SandboxError> {
+        self.lock_connection().take();
+        if !session_manager::is_instance_active(&self.instance_dir) {
+            return Ok(());
+        }
+        vm_manager::request_stop(&self.instance_dir).map_err(boxed)?;
+        // The manager removes its pid file once the VM is down and cleanup is done.
+        let pid_path = self.instance_dir.join(VM_MANAGER_PID_NAME);
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while pid_path.exists() && session_manager::is_instance_active(&self.instance_dir) {
+            if Instant::now() >= deadline {
+                return Err(SandboxError::StopTimeout);
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Stops the sandbox and deletes its `.vibebox` directory, VM disk included. A named session
+    /// only deletes its own directory.
+    pub fn destroy(self) -> Result<CleanSummary, SandboxError> {
+        self.stop()?;
+        if let Some(name) = &self.session {
+            return Ok(SessionManager::new()?.clean_session(&self.project_root, name)?);
+        }
+        if !self.ephemeral {
+            return Ok(SessionManager::new()?.clean_project(&self.project_root)?);
+        }
+        let removed_instance_dir = match fs::remove_dir_all(&self.instance_dir) {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(CleanSummary {
+            instance_dir: self.instance_dir.clone(),
+            removed_instance_dir,
+            removed_sessions: 0,
+        })
+    }
+
+    fn project_name(&self) -> String {
+        self.project_root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn ensure_overlay(&self) -> Result<(), SandboxError> {
+        if self.config.box_cfg.project_mode != ProjectMode::Overlay {
+            return Err(OverlayError::NotEnabled.into());
+        }
+        Ok(())
+    }
+
+    fn overlay_helper(&self, operation: &str, input: Vec<u8>) -> Result<Vec<u8>, SandboxError> {
+        let conn = self.connection(true)?;
+        let output = instance::exec_overlay_helper(&self.instance_dir, conn, operation, input)
+            .map_err(boxed)?;
+        if output.code != 0 {
+            return Err(OverlayError::Guest(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            )
+            .into());
+        }
+        Ok(output.stdout)
+    }
+
+    /// Streams `paths` from the guest's view of the project as a tar archive into the host
+    /// project; entries are listed parents first, so directories are not recursed into.
+    fn copy_from_overlay(&self, paths: &[&str]) -> Result<(), SandboxError> {
+        let merged = overlay::merged_dir(&self.project_name());
+        let mut command = format!(
+            "tar -C {} --no-recursion -cf - --",
+            shell_quote(&merged.to_string_lossy())
+        );
+        for path in paths {
+            command.push(' ');
+            command.push_str(&shell_quote(path));
+        }
+        let output = self.exec(&command)?;
+        if output.code != 0 {
+            return Err(OverlayError::Guest(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            )
+            .into());
+        }
+        let mut tar = std::process::Command::new("tar")
+            .arg("-C")
+            .arg(&self.project_root)
+            .args(["-xf", "-"])
+            .stdin(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|source| VibeboxError::Tool {
+                tool: "tar",
+                source,
+            })?;
+        if let Some(mut stdin) = tar.stdin.take() {
+            stdin.write_all(&output.stdout)?;
+        }
+        let result = tar.wait_with_output()?;
+        if !result.status.success() {
+            return Err(OverlayError::Host {
+                path: self.project_root.clone(),
+                source: io::Error::other(
+                    String::from_utf8_lossy(&result.stderr).trim().to_string(),
+                ),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn lock_connection(&self) -> std::sync::MutexGuard<'_, Option<UnixStream>> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A handle on the held manager connection; the VM stays up while any copy is open.
+    fn connection(&self, spawn: bool) -> Result<UnixStream, SandboxError> {
+        let mut held = self.lock_connection();
+        if held.is_some() && !session_manager::is_instance_active(&self.instance_dir) {
+            *held = None;
+        }
+        if held.is_none() {
+            let stream = if spawn {
+                self.spawn_manager()?
+            } else {
+                vm_manager::connect_manager(&self.instance_dir)
+                    .map_err(|_| SandboxError::NotRunning)?
+            };
+            *held = Some(stream);
+        }
+        let stream = held.as_ref().ok_or(SandboxError::NotRunning)?;
+        Ok(stream.try_clone()?)
+    }
+
+    fn spawn_manager(&self) -> Result<UnixStream, SandboxError> {
+        self.ensure_instance_dir()?;
+        self.write_overrides()?;
+        if !self.ephemeral {
+            match SessionManager::new() {
+                Ok(manager) => {
+                    if let Err(err) =
+                        manager.update_global_sessions(&self.project_root, self.session.as_deref())
+                    {
+                        tracing::warn!(error = %err, "failed to update a global session list");
+                    }
+                }
+                Err(err) => tracing::warn!(error = %err, "failed to initialize session manager"),
+            }
+        }
+        vm_manager::ensure_manager(
+            &self.project_root,
+            &self.instance_dir,
+            self.config.supervisor.auto_shutdown_ms,
+            Some(&self.config_path),
+        )
+        .map_err(boxed)
+    }
+
+    fn ensure_instance_dir(&self) -> Result<(), SandboxError> {
+        if self.ephemeral || self.session.is_some() {
+            fs::create_dir_all(&self.instance_dir)?;
+        } else {
+            instance::ensure_instance_dir(&self.project_root)?;
+        }
+        Ok(())
+    }
+
+    fn write_overrides(&self) -> Result<(), SandboxError> {
+        if self.overridden {
+            let contents = toml::to_string_pretty(&self.config)
+                .map_err(|err| ConfigError::Invalid(err.to_string()))?;
+            fs::write(&self.config_path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if !self.ephemeral {
+            return;
+        }
+        // A running manager deletes the directory itself once this last connection closes.
+        self.lock_connection().take();
+        if !session_manager::is_instance_active(&self.instance_dir) {
+            let _ = fs::remove_dir_all(&self.instance_dir);
+        }
+    }
+}
+
+fn boxed(err: Box<dyn std::error::Error>) -> SandboxError {
+    VibeboxError::from_boxed(err).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        let config = Config {
+            box_cfg: config::BoxConfig {
+                mounts: Vec::new(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        fs::write(
+            temp.path().join(config::CONFIG_FILENAME),
+            toml::to_string_pretty(&config).unwrap(),
+        )
+        .unwrap();
+        temp
+    }
+
+    #[test]
+    fn builder_applies_overrides_and_validates_them() {
+        let temp = project();
+        fs::create_dir(temp.path().join("data")).unwrap();
+        let sandbox = Sandbox::builder(temp.path())
+            .cpu_count(4)
+            .auto_shutdown_ms(500)
+            .mount("data:~/data:read-only")
+            .build()
+            .unwrap();
+        let root = fs::canonicalize(temp.path()).unwrap();
+        assert_eq!(sandbox.project_root(), root);
+        assert_eq!(sandbox.config().box_cfg.cpu_count, 4);
+        assert_eq!(sandbox.config().supervisor.auto_shutdown_ms, 500);
+        assert_eq!(
+            sandbox.config().box_cfg.mounts.last().map(String::as_str),
+            Some("data:~/data:read-only")
+        );
+        assert_eq!(
+            sandbox.config_path(),
+            root.join(session_manager::INSTANCE_DIR_NAME)
+                .join(SANDBOX_CONFIG_NAME)
+        );
+
+        let err = Sandbox::builder(temp.path()).ram_mb(0).build().unwrap_err();
+        assert!(matches!(err, SandboxError::Config(_)), "{err}");
+        let err = Sandbox::builder(temp.path().join("missing"))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, SandboxError::Project { .. }), "{err}");
+    }
+
+    #[test]
+    fn stopped_sandbox_reports_and_refuses_exec() {
+        let temp = project();
+        let sandbox = Sandbox::builder(temp.path()).build().unwrap();
+        assert_eq!(
+            sandbox.config_path(),
+            fs::canonicalize(temp.path())
+                .unwrap()
+                .join(config::CONFIG_FILENAME)
+        );
+        assert_eq!(sandbox.status(), SandboxStatus::default());
+        assert!(matches!(
+            sandbox.exec("true").unwrap_err(),
+            SandboxError::NotRunning
+        ));
+        sandbox.stop().unwrap();
+    }
+
+    #[test]
+    fn ephemeral_sandboxes_get_their_own_throwaway_instance() {
+        let temp = project();
+        let first = Sandbox::builder(temp.path()).ephemeral().build().unwrap();
+        let second = Sandbox::builder(temp.path()).ephemeral().build().unwrap();
+        assert!(first.is_ephemeral());
+        assert_ne!(first.instance_dir(), second.instance_dir());
+        assert!(first.instance_dir().starts_with(env::temp_dir()));
+        assert_ne!(first.create().unwrap(), second.create().unwrap());
+        assert!(
+            !temp
+                .path()
+                .join(session_manager::INSTANCE_DIR_NAME)
+                .exists()
+        );
+
+        let dir = first.instance_dir().to_path_buf();
+        assert!(dir.join(session_manager::INSTANCE_FILENAME).exists());
+        drop(first);
+        assert!(!dir.exists());
+        let summary = second.destroy().unwrap();
+        assert!(summary.removed_instance_dir);
+        assert!(!summary.instance_dir.exists());
+    }
+}
@@ -15,20 +15,41 @@ pub const GLOBAL_DIR_NAME: &str = ".vibebox";
 pub const INSTANCE_FILENAME: &str = "instance.toml";
 pub const SESSION_TOML_SUFFIX: &str = ".toml";
 pub const VM_MANAGER_SOCKET_NAME: &str = "vm.sock";
+pub const VM_CONSOLE_SOCKET_NAME: &str = "console.sock";
 pub const VM_MANAGER_PID_NAME: &str = "vm.pid";
+pub const VM_MANAGER_CLIENTS_NAME: &str = "vm.clients";
+/// Named sessions keep their state in `.vibebox-sessions/<name>`, beside `.vibebox` rather than
+/// in it: the default guest gets `.vibebox` shared in whole.
+pub const NAMED_SESSIONS_DIR_NAME: &str = ".vibebox-sessions";
 const SESSIONS_DIR_NAME: &str = "sessions";
+const MAX_SESSION_NAME_LEN: usize = 32;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SessionRecord {
     pub directory: PathBuf,
+    /// `None` for the project's default session.
+    pub name: Option<String>,
     pub id: String,
     pub last_active: Option<String>,
     pub active: bool,
 }
 
+impl SessionRecord {
+    pub fn instance_dir(&self) -> PathBuf {
+        session_instance_dir(&self.directory, self.name.as_deref())
+    }
+
+    /// The project directory's name, followed by `/<name>` for a named session.
+    pub fn label(&self) -> String {
+        session_label(&self.directory, self.name.as_deref())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct SessionEntry {
     pub directory: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub id: String,
 }
 
@@ -60,6 +81,14 @@ pub enum SessionError {
     NonAbsoluteDirectory(PathBuf),
     #[error("Session directory does not exist: {0}")]
     MissingDirectory(PathBuf),
+    #[error("No session matches '{0}'; run `vibebox list` to see them")]
+    SessionNotFound(String),
+    #[error("Session '{0}' is ambiguous; use more of its id")]
+    AmbiguousSession(String),
+    #[error(
+        "Invalid session name '{0}': use up to {MAX_SESSION_NAME_LEN} letters, digits, '-', '_' or '.', starting with a letter or digit"
+    )]
+    InvalidSessionName(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -85,8 +114,17 @@ impl SessionManager {
         &self.sessions_dir
     }
 
-    pub fn update_global_sessions(&self, directory: &Path) -> Result<Vec<PathBuf>, SessionError> {
+    /// Indexes the session `name` of `directory` (the default one for `None`), and returns the
+    /// project directories of all indexed sessions.
+    pub fn update_global_sessions(
+        &self,
+        directory: &Path,
+        name: Option<&str>,
+    ) -> Result<Vec<PathBuf>, SessionError> {
         let directory = self.normalize_directory(directory)?;
+        if let Some(name) = name {
+            validate_session_name(name)?;
+        }
         fs::create_dir_all(&self.sessions_dir)?;
 
         let (mut sessions, removed) = self.prune_stale_sessions()?;
@@ -94,10 +132,11 @@ impl SessionManager {
         let mut added = false;
 
         if has_config {
-            let meta = read_instance_metadata(&directory)?;
+            let meta = read_instance_metadata(&session_instance_dir(&directory, name))?;
             if let Some(id) = meta.id {
                 let record = SessionEntry {
                     directory: directory.clone(),
+                    name: name.map(str::to_string),
                     id: id.clone(),
                 };
                 self.write_session_record(&record)?;
@@ -136,8 +175,10 @@ impl SessionManager {
         Ok(sessions.into_iter().map(|s| s.directory).collect())
     }
 
+    /// Every indexed session, grouped by project with the default session first.
     pub fn list_sessions(&self) -> Result<Vec<SessionRecord>, SessionError> {
-        let (sessions, removed) = self.prune_stale_sessions()?;
+        let (mut sessions, removed) = self.prune_stale_sessions()?;
+        sessions.sort_by(|a, b| (&a.directory, &a.name).cmp(&(&b.directory, &b.name)));
         if removed > 0 {
             tracing::info!(
                 path = %self.sessions_dir.display(),
@@ -148,10 +189,12 @@ impl SessionManager {
         }
         let mut records = Vec::with_capacity(sessions.len());
         for session in sessions {
-            let meta = read_instance_metadata(&session.directory)?;
-            let active = is_session_active(&session.directory);
+            let instance_dir = session_instance_dir(&session.directory, session.name.as_deref());
+            let meta = read_instance_metadata(&instance_dir)?;
+            let active = is_instance_active(&instance_dir);
             records.push(SessionRecord {
                 directory: session.directory,
+                name: session.name,
                 id: session.id,
                 last_active: meta.last_active,
                 active,
@@ -160,6 +203,24 @@ impl SessionManager {
         Ok(records)
     }
 
+    /// Finds a session by full id, unique id prefix, or label: the project directory name for a
+    /// default session, `project/name` for a named one.
+    pub fn find_session(&self, query: &str) -> Result<SessionRecord, SessionError> {
+        let sessions = self.list_sessions()?;
+        if let Some(exact) = sessions.iter().find(|s| s.id == query) {
+            return Ok(exact.clone());
+        }
+        let mut matches = sessions
+            .into_iter()
+            .filter(|s| s.id.starts_with(query) || s.label() == query);
+        match (matches.next(), matches.next()) {
+            (Some(found), None) => Ok(found),
+            (Some(_), Some(_)) => Err(SessionError::AmbiguousSession(query.to_string())),
+            (None, _) => Err(SessionError::SessionNotFound(query.to_string())),
+        }
+    }
+
+    /// Deletes the project's default session, leaving its named sessions alone.
     pub fn clean_project(&self, directory: &Path) -> Result<CleanSummary, SessionError> {
         let directory = self.normalize_directory(directory)?;
         let instance_dir = directory.join(INSTANCE_DIR_NAME);
@@ -168,7 +229,53 @@ impl SessionManager {
             fs::remove_dir_all(&instance_dir)?;
             removed_instance_dir = true;
         }
-        let removed_sessions = self.remove_session_records_for_directory(&directory)?;
+        let removed_sessions =
+            self.remove_session_records(&directory, |record| record.name.is_none())?;
+        Ok(CleanSummary {
+            instance_dir,
+            removed_instance_dir,
+            removed_sessions,
+        })
+    }
+
+    /// Deletes every session of the project, named ones included.
+    pub fn clean_all_sessions(&self, directory: &Path) -> Result<CleanSummary, SessionError> {
+        let directory = self.normalize_directory(directory)?;
+        let instance_dir = directory.join(INSTANCE_DIR_NAME);
+        let mut removed_instance_dir = false;
+        if instance_dir.exists() {
+            fs::remove_dir_all(&instance_dir)?;
+            removed_instance_dir = true;
+        }
+        let named_dir = directory.join(NAMED_SESSIONS_DIR_NAME);
+        if named_dir.exists() {
+            fs::remove_dir_all(&named_dir)?;
+        }
+        let removed_sessions = self.remove_session_records(&directory, |_| true)?;
+        Ok(CleanSummary {
+            instance_dir,
+            removed_instance_dir,
+            removed_sessions,
+        })
+    }
+
+    /// Like [`SessionManager::clean_project`] for one named session, leaving the project's other
+    /// sessions alone.
+    pub fn clean_session(
+        &self,
+        directory: &Path,
+        name: &str,
+    ) -> Result<CleanSummary, SessionError> {
+        validate_session_name(name)?;
+        let directory = self.normalize_directory(directory)?;
+        let instance_dir = session_instance_dir(&directory, Some(name));
+        let mut removed_instance_dir = false;
+        if instance_dir.exists() {
+            fs::remove_dir_all(&instance_dir)?;
+            removed_instance_dir = true;
+        }
+        let removed_sessions =
+            self.remove_session_records(&directory, |record| record.name.as_deref() == Some(name))?;
         Ok(CleanSummary {
             instance_dir,
             removed_instance_dir,
@@ -218,7 +325,11 @@ impl SessionManager {
                 continue;
             }
             let record = read_session_file(&path)?;
-            if !is_vibebox_dir(&record.directory) {
+            // A named session is gone once its directory is, e.g. after `vibebox reset`.
+            if !is_vibebox_dir(&record.directory)
+                || (record.name.is_some()
+                    && !session_instance_dir(&record.directory, record.name.as_deref()).is_dir())
+            {
                 let _ = fs::remove_file(&path);
                 removed += 1;
                 continue;
@@ -236,9 +347,10 @@ impl SessionManager {
         Ok((sessions, removed))
     }
 
-    fn remove_session_records_for_directory(
+    fn remove_session_records(
         &self,
         directory: &Path,
+        matches: impl Fn(&SessionEntry) -> bool,
     ) -> Result<usize, SessionError> {
         if !self.sessions_dir.exists() {
             return Ok(0);
@@ -251,7 +363,7 @@ impl SessionManager {
                 continue;
             }
             let record = read_session_file(&path)?;
-            if record.directory == directory {
+            if record.directory == directory && matches(&record) {
                 fs::remove_file(&path)?;
                 removed += 1;
             }
@@ -267,6 +379,42 @@ impl SessionManager {
     }
 }
 
+/// Where the state of `directory`'s session `name` lives; the default session uses `.vibebox`.
+pub fn session_instance_dir(directory: &Path, name: Option<&str>) -> PathBuf {
+    match name {
+        Some(name) => directory.join(NAMED_SESSIONS_DIR_NAME).join(name),
+        None => directory.join(INSTANCE_DIR_NAME),
+    }
+}
+
+pub fn session_label(directory: &Path, name: Option<&str>) -> String {
+    let project = directory
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match name {
+        Some(name) => format!("{project}/{name}"),
+        None => project,
+    }
+}
+
+/// Session names become directory names and live in socket paths, so they stay short and plain.
+pub fn validate_session_name(name: &str) -> Result<(), SessionError> {
+    let valid = name.len() <= MAX_SESSION_NAME_LEN
+        && name
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SessionError::InvalidSessionName(name.to_string()))
+    }
+}
+
 fn is_vibebox_dir(directory: &Path) -> bool {
     if !directory.is_absolute() {
         return false;
@@ -274,8 +422,8 @@ fn is_vibebox_dir(directory: &Path) -> bool {
     directory.join(CONFIG_FILENAME).is_file()
 }
 
-fn is_session_active(directory: &Path) -> bool {
-    let instance_dir = directory.join(INSTANCE_DIR_NAME);
+/// Whether a vm manager is serving `instance_dir`.
+pub(crate) fn is_instance_active(instance_dir: &Path) -> bool {
     let pid_path = instance_dir.join(VM_MANAGER_PID_NAME);
     let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
 
@@ -293,7 +441,7 @@ fn is_session_active(directory: &Path) -> bool {
     true
 }
 
-fn read_pid(path: &Path) -> Option<u32> {
+pub(crate) fn read_pid(path: &Path) -> Option<u32> {
     let content = fs::read_to_string(path).ok()?;
     content.trim().parse::<u32>().ok()
 }
@@ -320,8 +468,8 @@ fn read_session_file(path: &Path) -> Result<SessionEntry, SessionError> {
     Ok(record)
 }
 
-fn read_instance_metadata(directory: &Path) -> Result<InstanceMetadata, SessionError> {
-    let instance_path = directory.join(INSTANCE_DIR_NAME).join(INSTANCE_FILENAME);
+fn read_instance_metadata(instance_dir: &Path) -> Result<InstanceMetadata, SessionError> {
+    let instance_path = instance_dir.join(INSTANCE_FILENAME);
     if !instance_path.exists() {
         return Ok(InstanceMetadata::default());
     }
@@ -376,7 +524,11 @@ mod tests {
     }
 
     fn write_instance(project_dir: &Path, id: &str, last_active: &str) {
-        let instance_dir = project_dir.join(INSTANCE_DIR_NAME);
+        write_named_instance(project_dir, None, id, last_active);
+    }
+
+    fn write_named_instance(project_dir: &Path, name: Option<&str>, id: &str, last_active: &str) {
+        let instance_dir = session_instance_dir(project_dir, name);
         fs::create_dir_all(&instance_dir).unwrap();
         let content = format!("id = \"{id}\"\nlast_active = \"{last_active}\"\n");
         fs::write(instance_dir.join(INSTANCE_FILENAME), content).unwrap();
@@ -394,7 +546,7 @@ mod tests {
             "2026-02-07T05:00:00Z",
         );
 
-        let dirs = mgr.update_global_sessions(&project_dir).unwrap();
+        let dirs = mgr.update_global_sessions(&project_dir, None).unwrap();
 
         assert_eq!(dirs.len(), 1);
         assert_eq!(dirs[0], project_dir.canonicalize().unwrap());
@@ -418,7 +570,7 @@ mod tests {
             "019bf290-cccc-7c23-ba1d-dce7e6d40693",
             "2026-02-07T05:00:00Z",
         );
-        let _ = mgr.update_global_sessions(&project_dir).unwrap();
+        let _ = mgr.update_global_sessions(&project_dir, None).unwrap();
 
         fs::remove_file(project_dir.join(CONFIG_FILENAME)).unwrap();
         let sessions = mgr.list_sessions().unwrap();
@@ -457,7 +609,7 @@ mod tests {
             "019bf290-cccc-7c23-ba1d-dce7e6d40693",
             "2026-02-07T05:00:00Z",
         );
-        let _ = mgr.update_global_sessions(&project_dir).unwrap();
+        let _ = mgr.update_global_sessions(&project_dir, None).unwrap();
 
         let sessions = mgr.list_sessions().unwrap();
         assert_eq!(sessions.len(), 1);
@@ -467,4 +619,117 @@ mod tests {
             Some("2026-02-07T05:00:00Z")
         );
     }
+
+    #[test]
+    fn find_session_matches_id_prefix_and_project_name() {
+        let temp = TempDir::new().unwrap();
+        let mgr = manager(&temp);
+        let project_dir = create_project_dir(&temp);
+        fs::write(project_dir.join(CONFIG_FILENAME), "").unwrap();
+        write_instance(
+            &project_dir,
+            "019bf290-cccc-7c23-ba1d-dce7e6d40693",
+            "2026-02-07T05:00:00Z",
+        );
+        let _ = mgr.update_global_sessions(&project_dir, None).unwrap();
+
+        let by_prefix = mgr.find_session("019bf290").unwrap();
+        assert_eq!(by_prefix.directory, project_dir);
+        let by_name = mgr.find_session("project").unwrap();
+        assert_eq!(by_name.id, "019bf290-cccc-7c23-ba1d-dce7e6d40693");
+        assert!(matches!(
+            mgr.find_session("nope"),
+            Err(SessionError::SessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn named_sessions_are_indexed_next_to_the_default_one() {
+        let temp = TempDir::new().unwrap();
+        let mgr = manager(&temp);
+        let project_dir = create_project_dir(&temp);
+        fs::write(project_dir.join(CONFIG_FILENAME), "").unwrap();
+        write_named_instance(
+            &project_dir,
+            Some("feature-x"),
+            "bbbb",
+            "2026-02-07T06:00:00Z",
+        );
+        write_instance(&project_dir, "aaaa", "2026-02-07T05:00:00Z");
+        mgr.update_global_sessions(&project_dir, Some("feature-x"))
+            .unwrap();
+        mgr.update_global_sessions(&project_dir, None).unwrap();
+
+        let sessions = mgr.list_sessions().unwrap();
+        let labels: Vec<String> = sessions.iter().map(SessionRecord::label).collect();
+        assert_eq!(labels, ["project", "project/feature-x"]);
+        assert_eq!(
+            sessions[1].instance_dir(),
+            project_dir.join(NAMED_SESSIONS_DIR_NAME).join("feature-x")
+        );
+        assert_eq!(mgr.find_session("project").unwrap().id, "aaaa");
+        assert_eq!(mgr.find_session("project/feature-x").unwrap().id, "bbbb");
+
+        let summary = mgr.clean_session(&project_dir, "feature-x").unwrap();
+        assert!(summary.removed_instance_dir);
+        assert_eq!(summary.removed_sessions, 1);
+        assert!(
+            project_dir
+                .join(INSTANCE_DIR_NAME)
+                .join(INSTANCE_FILENAME)
+                .exists()
+        );
+        assert_eq!(mgr.list_sessions().unwrap().len(), 1);
+
+        // Deleting a named session's directory drops it from the index.
+        write_named_instance(&project_dir, Some("other"), "cccc", "2026-02-07T07:00:00Z");
+        mgr.update_global_sessions(&project_dir, Some("other"))
+            .unwrap();
+        fs::remove_dir_all(session_instance_dir(&project_dir, Some("other"))).unwrap();
+        assert_eq!(mgr.list_sessions().unwrap().len(), 1);
+
+        // Cleaning the default session keeps the named ones; only the full wipe takes them too.
+        write_named_instance(&project_dir, Some("kept"), "dddd", "2026-02-07T08:00:00Z");
+        mgr.update_global_sessions(&project_dir, Some("kept"))
+            .unwrap();
+        let summary = mgr.clean_project(&project_dir).unwrap();
+        assert!(summary.removed_instance_dir);
+        assert_eq!(summary.removed_sessions, 1);
+        let labels: Vec<String> = mgr
+            .list_sessions()
+            .unwrap()
+            .iter()
+            .map(SessionRecord::label)
+            .collect();
+        assert_eq!(labels, ["project/kept"]);
+
+        let summary = mgr.clean_all_sessions(&project_dir).unwrap();
+        assert_eq!(summary.removed_sessions, 1);
+        assert!(!project_dir.join(NAMED_SESSIONS_DIR_NAME).exists());
+        assert!(mgr.list_sessions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn session_names_stay_plain() {
+        for name in ["feature-x", "a", "v1.2_b"] {
+            validate_session_name(name).unwrap();
+        }
+        for name in [
+            "",
+            "-x",
+            ".hidden",
+            "a/b",
+            "..",
+            "with space",
+            &"x".repeat(33),
+        ] {
+            assert!(
+                matches!(
+                    validate_session_name(name),
+                    Err(SessionError::InvalidSessionName(_))
+                ),
+                "{name}"
+            );
+        }
+    }
 }
@@ -3,9 +3,11 @@ set -eu
 
 SSH_USER="__SSH_USER__"
 SUDO_PASSWORD="__SUDO_PASSWORD__"
+SECURITY_PROFILE="__SECURITY_PROFILE__"
 PROJECT_NAME="__PROJECT_NAME__"
 PROJECT_GUEST_DIR="__PROJECT_GUEST_DIR__"
 KEY_PATH="__KEY_PATH__"
+HOST_KEY_PINNED="__HOST_KEY_PINNED__"
 
 diag() { echo "[vibebox][diag] $*" >&2; }
 
@@ -50,18 +52,37 @@ dump_diag() {
   fi
 }
 
-# 1) tmpfs mount
-TARGET="${PROJECT_GUEST_DIR}/.vibebox"
-if [ -d "$TARGET" ] && ! mountpoint -q "$TARGET"; then
-  mount -t tmpfs tmpfs "$TARGET"
-fi
+# 1) tmpfs mounts over the host-side session state in the project
+for TARGET in "${PROJECT_GUEST_DIR}/.vibebox" "${PROJECT_GUEST_DIR}/.vibebox-sessions"; do
+  if [ -d "$TARGET" ] && ! mountpoint -q "$TARGET"; then
+    mount -t tmpfs tmpfs "$TARGET"
+  fi
+done
 
 # 2) user + authorized_keys
 if ! id -u "$SSH_USER" >/dev/null 2>&1; then
   useradd -m -s /bin/bash -U "$SSH_USER"
-  usermod -aG sudo "$SSH_USER" || true
 fi
 
+# Privileges follow [security].profile on every boot, so switching profiles takes effect.
+SUDOERS_FILE=/etc/sudoers.d/vibebox
+case "$SECURITY_PROFILE" in
+  strict)
+    gpasswd -d "$SSH_USER" sudo >/dev/null 2>&1 || true
+    rm -f "$SUDOERS_FILE"
+    passwd -l "$SSH_USER" >/dev/null
+    ;;
+  permissive)
+    usermod -aG sudo "$SSH_USER" || true
+    echo "${SSH_USER} ALL=(ALL) NOPASSWD:ALL" > "$SUDOERS_FILE"
+    chmod 440 "$SUDOERS_FILE"
+    ;;
+  *)
+    usermod -aG sudo "$SSH_USER" || true
+    rm -f "$SUDOERS_FILE"
+    ;;
+esac
+
 if [ -n "$SUDO_PASSWORD" ]; then
   echo "${SSH_USER}:${SUDO_PASSWORD}" | chpasswd
 fi
@@ -77,12 +98,8 @@ fi
 # Home mount links (config-driven)
 __VIBEBOX_HOME_LINKS__
 
-# Vibebox shell commands
+# Vibebox shell commands: /etc/profile.d/vibebox.sh is installed by the host after this script.
 install -d -m 755 /etc/profile.d
-cat > /etc/profile.d/vibebox.sh <<'VIBEBOX_SHELL_EOF'
-__VIBEBOX_SHELL_SCRIPT__
-VIBEBOX_SHELL_EOF
-chmod 644 /etc/profile.d/vibebox.sh
 
 # Auto-cd into project for interactive shells
 cat > /etc/profile.d/vibebox-project.sh <<'VIBEBOX_PROJECT_EOF'
@@ -154,6 +171,16 @@ MISE
 
 mise_install || true
 
+# Every instance is cloned from the same base image, and so are its host keys; give this one its
+# own before the host pins anything.
+if [ -z "$HOST_KEY_PINNED" ]; then
+  rm -f /etc/ssh/ssh_host_*
+  ssh-keygen -A
+  if systemctl is-active --quiet ssh; then
+    systemctl restart ssh
+  fi
+fi
+
 # 3) start ssh (don't swallow failures)
 # If ssh is already active, don't force start/restart.
 if ! systemctl is-active --quiet ssh; then
@@ -220,11 +247,57 @@ if ! listens_ok; then
   exit 1
 fi
 
+# 6) report the host key so the host can pin it instead of trusting whoever answers at $ip
+hostkey=""
+for f in /etc/ssh/ssh_host_ed25519_key.pub /etc/ssh/ssh_host_ecdsa_key.pub /etc/ssh/ssh_host_rsa_key.pub; do
+  if [ -r "$f" ]; then
+    hostkey="$(awk '{print $1" "$2}' "$f")"
+    break
+  fi
+done
+
+if [ -z "$hostkey" ]; then
+  diag "no ssh host key found in /etc/ssh"
+  dump_diag
+  exit 1
+fi
+
 ip a
 ip link
 curl -s https://api.ipify.org ; echo
 
 cat /etc/machine-id
 
+# 7) strict profile: only the host (the NAT gateway, which also answers DNS) and ssh replies stay
+# reachable; other VMs on the same NAT subnet do not.
+EGRESS_TABLE=vibebox_egress
+if command -v nft >/dev/null 2>&1; then
+  nft delete table inet "$EGRESS_TABLE" >/dev/null 2>&1 || true
+fi
+if [ "$SECURITY_PROFILE" = "strict" ]; then
+  if ! command -v nft >/dev/null 2>&1; then
+    diag "strict profile needs nft to restrict egress"
+    exit 1
+  fi
+  gateway_rule=""
+  if [ -n "$gw" ]; then
+    gateway_rule="ip daddr ${gw} accept"
+  else
+    diag "no default gateway; strict egress allows no outbound traffic"
+  fi
+  nft -f - <<NFT
+table inet ${EGRESS_TABLE} {
+  chain output {
+    type filter hook output priority 0; policy drop;
+    oifname "lo" accept
+    ct state established,related accept
+    ${gateway_rule}
+  }
+}
+NFT
+  diag "egress restricted to ${gw:-nothing}"
+fi
+
 echo VIBEBOX_SSH_READY
 echo "VIBEBOX_IPV4=$ip"
+echo "VIBEBOX_HOSTKEY=$hostkey"
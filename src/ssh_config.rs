@@ -0,0 +1,279 @@
+use std::{
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    instance::{ensure_ssh_keypair, load_or_create_instance_config, ssh_options},
+    session_manager::session_instance_dir,
+};
+
+const HOST_PREFIX: &str = "vibebox-";
+
+/// `Host` alias for a project, e.g. `vibebox-my-app`, or `vibebox-my-app+feature-x` for a named
+/// session; sanitizing never produces a `+`, so the two cannot collide.
+pub fn host_alias(project_root: &Path, session: Option<&str>) -> String {
+    let name = project_root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match session {
+        Some(name) => format!("{HOST_PREFIX}{sanitized}+{name}"),
+        None => format!("{HOST_PREFIX}{sanitized}"),
+    }
+}
+
+/// Renders the managed `Host` block for `project_root`'s session. Connections go through
+/// `vibebox ssh-proxy`, which starts the VM if needed and keeps it up while connected.
+pub fn render_host_block(
+    project_root: &Path,
+    session: Option<&str>,
+    vibebox_exe: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let instance_dir = session_instance_dir(project_root, session);
+    fs::create_dir_all(&instance_dir)?;
+    let (ssh_key, _) = ensure_ssh_keypair(&instance_dir)?;
+    let ssh_user = load_or_create_instance_config(&instance_dir)?.ssh_user_display();
+    let alias = host_alias(project_root, session);
+
+    let mut block = format!("{}\nHost {alias}\n", start_marker(&alias));
+    block.push_str(&format!("    HostName {alias}\n"));
+    block.push_str(&format!("    User {ssh_user}\n"));
+    block.push_str(&format!("    IdentityFile {}\n", quote(&ssh_key)));
+    for (key, value) in ssh_options(&instance_dir) {
+        block.push_str(&format!("    {key} {}\n", quote_value(&value)));
+    }
+    let session_arg = session
+        .map(|name| format!(" --session {name}"))
+        .unwrap_or_default();
+    block.push_str(&format!(
+        "    ProxyCommand {}{session_arg} ssh-proxy --project {}\n",
+        quote(vibebox_exe),
+        quote(project_root)
+    ));
+    block.push_str(&end_marker(&alias));
+    block.push('\n');
+    Ok(block)
+}
+
+pub fn user_ssh_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let home = env::var_os("HOME").ok_or("HOME environment variable is not set")?;
+    Ok(PathBuf::from(home).join(".ssh").join("config"))
+}
+
+/// Writes `block` into the ssh config at `path`, replacing an earlier block for the same alias.
+pub fn install_host_block(
+    path: &Path,
+    alias: &str,
+    block: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent)?;
+        fs::set_permissions(parent, fs::Permissions::from_mode(0o700))?;
+    }
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    fs::write(path, merge_host_block(&existing, alias, block)?)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+/// New blocks go first: ssh takes the first value it sees, so they must precede any `Host *`.
+/// A block for another project whose directory has the same name is left alone.
+fn merge_host_block(
+    existing: &str,
+    alias: &str,
+    block: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let start = start_marker(alias);
+    let end = end_marker(alias);
+    if let Some(begin) = existing.find(&start)
+        && let Some(offset) = existing[begin..].find(&end)
+    {
+        let mut finish = begin + offset + end.len();
+        if let (Some(installed), Some(project)) = (
+            proxied_project(&existing[begin..finish]),
+            proxied_project(block),
+        ) && installed != project
+        {
+            return Err(format!(
+                "`{alias}` in the ssh config already belongs to the project at {installed}; remove that entry first"
+            )
+            .into());
+        }
+        if existing[finish..].starts_with('\n') {
+            finish += 1;
+        }
+        return Ok(format!(
+            "{}{block}{}",
+            &existing[..begin],
+            &existing[finish..]
+        ));
+    }
+    Ok(if existing.is_empty() {
+        block.to_string()
+    } else {
+        format!("{block}\n{existing}")
+    })
+}
+
+/// The quoted `--project` a block's ProxyCommand starts the VM for.
+fn proxied_project(block: &str) -> Option<&str> {
+    block
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix("ProxyCommand "))
+        .and_then(|command| command.rsplit_once(" --project "))
+        .map(|(_, project)| project)
+}
+
+fn start_marker(alias: &str) -> String {
+    format!("# >>> {alias} (managed by `vibebox ssh-config --install`) >>>")
+}
+
+fn end_marker(alias: &str) -> String {
+    format!("# <<< {alias} <<<")
+}
+
+/// Quotes a path for ssh_config, where `%` starts a token.
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.display().to_string().replace('%', "%%"))
+}
+
+fn quote_value(value: &str) -> String {
+    if value.contains(['/', ' ', '%']) {
+        quote(Path::new(value))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_alias_sanitizes_project_name() {
+        assert_eq!(host_alias(Path::new("/src/my app"), None), "vibebox-my-app");
+        assert_eq!(host_alias(Path::new("/src/api.v2"), None), "vibebox-api.v2");
+        assert_eq!(
+            host_alias(Path::new("/src/my+app"), Some("x")),
+            "vibebox-my-app+x"
+        );
+    }
+
+    #[test]
+    fn render_points_proxy_command_at_project() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let project = temp.path().join("demo");
+        let instance_dir = session_instance_dir(&project, None);
+        fs::create_dir_all(&instance_dir).unwrap();
+        fs::write(instance_dir.join("ssh_key"), "").unwrap();
+        fs::write(instance_dir.join("ssh_key.pub"), "").unwrap();
+        let block =
+            render_host_block(&project, None, Path::new("/opt/vibebox 1%/vibebox")).unwrap();
+
+        assert!(block.contains("Host vibebox-demo\n"), "{block}");
+        assert!(block.contains("    User vibecoder\n"), "{block}");
+        assert!(block.contains("IdentitiesOnly yes"), "{block}");
+        assert!(block.contains("StrictHostKeyChecking yes"), "{block}");
+        let known_hosts = format!(
+            "    UserKnownHostsFile \"{}\"\n",
+            instance_dir.join("known_hosts").display()
+        );
+        assert!(block.contains(&known_hosts), "{block}");
+        let proxy = format!(
+            "    ProxyCommand \"/opt/vibebox 1%%/vibebox\" ssh-proxy --project \"{}\"\n",
+            project.display()
+        );
+        assert!(block.contains(&proxy), "{block}");
+    }
+
+    #[test]
+    fn render_keeps_a_named_session_apart() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let project = temp.path().join("demo");
+        let instance_dir = session_instance_dir(&project, Some("feature-x"));
+        fs::create_dir_all(&instance_dir).unwrap();
+        fs::write(instance_dir.join("ssh_key"), "").unwrap();
+        fs::write(instance_dir.join("ssh_key.pub"), "").unwrap();
+        let block =
+            render_host_block(&project, Some("feature-x"), Path::new("/bin/vibebox")).unwrap();
+
+        assert!(block.contains("Host vibebox-demo+feature-x\n"), "{block}");
+        let identity = format!(
+            "    IdentityFile \"{}\"\n",
+            instance_dir.join("ssh_key").display()
+        );
+        assert!(block.contains(&identity), "{block}");
+        let proxy = format!(
+            "    ProxyCommand \"/bin/vibebox\" --session feature-x ssh-proxy --project \"{}\"\n",
+            project.display()
+        );
+        assert!(block.contains(&proxy), "{block}");
+    }
+
+    #[test]
+    fn merge_replaces_existing_block_and_keeps_the_rest() {
+        let old = format!(
+            "{}\nHost vibebox-demo\n    User old\n{}\n",
+            start_marker("vibebox-demo"),
+            end_marker("vibebox-demo")
+        );
+        let new = old.replace("old", "new");
+        let existing =
+            format!("Host github.com\n    User git\n\n{old}Host *\n    ForwardAgent no\n");
+
+        let merged = merge_host_block(&existing, "vibebox-demo", &new).unwrap();
+        assert_eq!(
+            merged,
+            format!("Host github.com\n    User git\n\n{new}Host *\n    ForwardAgent no\n")
+        );
+        assert_eq!(
+            merge_host_block(&merged, "vibebox-demo", &new).unwrap(),
+            merged
+        );
+
+        let fresh =
+            merge_host_block("Host *\n    ForwardAgent no\n", "vibebox-demo", &new).unwrap();
+        assert!(fresh.starts_with(&new), "{fresh}");
+    }
+
+    #[test]
+    fn merge_keeps_a_block_for_another_project_with_the_same_name() {
+        let block = |exe: &str, project: &str| {
+            format!(
+                "{}\nHost vibebox-app\n    ProxyCommand \"{exe}\" ssh-proxy --project \"{project}\"\n{}\n",
+                start_marker("vibebox-app"),
+                end_marker("vibebox-app")
+            )
+        };
+        let existing = block("/bin/vibebox", "/b/app");
+
+        let err = merge_host_block(&existing, "vibebox-app", &block("/bin/vibebox", "/a/app"))
+            .unwrap_err();
+        assert!(err.to_string().contains("\"/b/app\""), "{err}");
+
+        // A new vibebox binary for the same project still replaces it.
+        let upgraded = block("/opt/vibebox", "/b/app");
+        assert_eq!(
+            merge_host_block(&existing, "vibebox-app", &upgraded).unwrap(),
+            upgraded
+        );
+    }
+}
@@ -23,7 +23,10 @@ use ratatui::{
     widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, Widget},
 };
 
-use crate::vm;
+use serde::Serialize;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339, macros::format_description};
+
+use crate::{guest_info::GuestInfo, vm};
 
 // https://patorjk.com/software/taag/#p=display&f=ANSI+Shadow&t=VIBEBOX&x=none&v=4&h=4&w=80&we=false
 const ASCII_BANNER: [&str; 7] = [
@@ -35,15 +38,16 @@ const ASCII_BANNER: [&str; 7] = [
     "  ╚═══╝  ╚═╝╚═════╝ ╚══════╝╚═════╝  ╚═════╝ ╚═╝  ╚═╝",
     "",
 ];
-const INFO_LINE_COUNT: u16 = 5;
+const INFO_LINE_COUNT: u16 = 6;
 
 #[derive(Debug, Clone)]
 pub struct VmInfo {
     pub max_memory_mb: u64,
     pub cpu_cores: usize,
     pub max_disk_gb: f32,
-    pub system_name: String,
     pub auto_shutdown_ms: u64,
+    /// What the guest reported on its latest boot; `None` until one has finished.
+    pub guest: Option<GuestInfo>,
 }
 
 #[derive(Debug)]
@@ -109,22 +113,46 @@ pub struct SessionListRow {
     pub last_active: String,
     pub active: String,
     pub id: String,
+    /// OS and kernel from the latest boot, or `-`.
+    pub system: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MountListRow {
     pub host: String,
     pub guest: String,
+    /// Where the share is actually bind-mounted in the guest.
+    pub mount_point: String,
     pub mode: String,
     pub default_mount: String,
+    pub guard: String,
 }
 
-#[derive(Debug, Clone)]
+/// One `vibebox explain --verify` comparison between config and the running guest.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckRow {
+    pub item: String,
+    pub expected: String,
+    pub actual: String,
+    pub status: CheckStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Diff,
+    /// The probe could not tell, e.g. egress while the host itself is offline.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct NetworkListRow {
     pub network_type: String,
     pub vm_ip: String,
     pub host_to_vm: String,
     pub vm_to_host: String,
+    pub egress: String,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -221,6 +249,7 @@ pub fn render_sessions_table(rows: &[SessionListRow]) -> Result<()> {
         Cell::from("Name"),
         Cell::from("Last Active"),
         Cell::from("Active"),
+        Cell::from("System"),
         Cell::from("ID"),
         Cell::from("Directory"),
     ])
@@ -231,6 +260,7 @@ pub fn render_sessions_table(rows: &[SessionListRow]) -> Result<()> {
             Cell::from(row.name.clone()),
             Cell::from(row.last_active.clone()),
             Cell::from(row.active.clone()),
+            Cell::from(row.system.clone()),
             Cell::from(row.id.clone()),
             Cell::from(row.directory.clone()),
         ])
@@ -242,6 +272,7 @@ pub fn render_sessions_table(rows: &[SessionListRow]) -> Result<()> {
             Constraint::Length(16),
             Constraint::Length(14),
             Constraint::Length(8),
+            Constraint::Min(24),
             Constraint::Length(36),
             Constraint::Min(24),
         ],
@@ -278,11 +309,17 @@ pub fn render_mounts_table(rows: &[MountListRow]) -> Result<()> {
     Ok(())
 }
 
-pub fn render_explain_tables(mounts: &[MountListRow], networks: &[NetworkListRow]) -> Result<()> {
+pub fn render_explain_tables(
+    summary: &[(&str, String)],
+    mounts: &[MountListRow],
+    networks: &[NetworkListRow],
+    checks: &[CheckRow],
+) -> Result<()> {
     let (width, _) = crossterm::terminal::size()?;
     if width == 0 {
         return Ok(());
     }
+    let summary_height = (summary.len() as u16).saturating_add(1);
 
     let mounts_height = if mounts.is_empty() {
         0
@@ -299,15 +336,29 @@ pub fn render_explain_tables(mounts: &[MountListRow], networks: &[NetworkListRow
     } else {
         0
     };
-    let total_height = mounts_height
+    let checks_height = if checks.is_empty() {
+        0
+    } else {
+        (checks.len() as u16).saturating_add(4)
+    };
+    let total_height = summary_height
+        .saturating_add(mounts_height)
         .saturating_add(gap)
-        .saturating_add(networks_height);
-    if total_height == 0 {
-        return Ok(());
-    }
+        .saturating_add(networks_height)
+        .saturating_add(checks_height);
 
     let mut buffer = Buffer::empty(Rect::new(0, 0, width, total_height));
-    let mut y = 0u16;
+    let summary_lines: Vec<Line> = summary
+        .iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(format!("{label}: "), Style::default().fg(Color::Cyan)),
+                Span::raw(value.clone()),
+            ])
+        })
+        .collect();
+    Paragraph::new(summary_lines).render(Rect::new(0, 0, width, summary.len() as u16), &mut buffer);
+    let mut y = summary_height;
 
     if mounts_height > 0 {
         let area = Rect::new(0, y, width, mounts_height);
@@ -318,6 +369,12 @@ pub fn render_explain_tables(mounts: &[MountListRow], networks: &[NetworkListRow
     if networks_height > 0 {
         let area = Rect::new(0, y, width, networks_height);
         render_networks_table_into(networks, area, &mut buffer);
+        y = y.saturating_add(networks_height);
+    }
+
+    if checks_height > 0 {
+        let area = Rect::new(0, y.saturating_add(1), width, checks_height - 1);
+        render_checks_table_into(checks, area, &mut buffer);
     }
 
     let mut stdout = io::stdout();
@@ -334,6 +391,7 @@ fn render_mounts_table_into(rows: &[MountListRow], area: Rect, buffer: &mut Buff
         Cell::from("Mode"),
         Cell::from(""),
         Cell::from("Default"),
+        Cell::from("Guard"),
     ])
     .style(Style::default().fg(Color::Cyan));
 
@@ -344,6 +402,7 @@ fn render_mounts_table_into(rows: &[MountListRow], area: Rect, buffer: &mut Buff
             Cell::from(row.mode.clone()),
             Cell::from(""),
             Cell::from(row.default_mount.clone()),
+            Cell::from(row.guard.clone()),
         ])
     });
 
@@ -355,6 +414,7 @@ fn render_mounts_table_into(rows: &[MountListRow], area: Rect, buffer: &mut Buff
             Constraint::Length(10),
             Constraint::Length(1),
             Constraint::Length(8),
+            Constraint::Min(4),
         ],
     )
     .header(header)
@@ -364,12 +424,57 @@ fn render_mounts_table_into(rows: &[MountListRow], area: Rect, buffer: &mut Buff
     table.render(area, buffer);
 }
 
+fn render_checks_table_into(rows: &[CheckRow], area: Rect, buffer: &mut Buffer) {
+    let header = Row::new(vec![
+        Cell::from("Check"),
+        Cell::from("Expected"),
+        Cell::from("Actual"),
+        Cell::from("Status"),
+    ])
+    .style(Style::default().fg(Color::Cyan));
+
+    let table_rows = rows.iter().map(|row| {
+        let (status, style) = match row.status {
+            CheckStatus::Ok => ("ok", Style::default()),
+            CheckStatus::Diff => ("DIFF", Style::default().fg(Color::Red)),
+            CheckStatus::Unknown => ("unknown", Style::default().fg(Color::Yellow)),
+        };
+        Row::new(vec![
+            Cell::from(row.item.clone()),
+            Cell::from(row.expected.clone()),
+            Cell::from(row.actual.clone()),
+            Cell::from(status),
+        ])
+        .style(style)
+    });
+
+    let table = Table::new(
+        table_rows,
+        [
+            Constraint::Min(24),
+            Constraint::Min(20),
+            Constraint::Min(20),
+            Constraint::Length(6),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title("Verification (running guest)")
+            .borders(Borders::ALL),
+    )
+    .column_spacing(1);
+
+    table.render(area, buffer);
+}
+
 fn render_networks_table_into(rows: &[NetworkListRow], area: Rect, buffer: &mut Buffer) {
     let header = Row::new(vec![
         Cell::from("Type"),
         Cell::from("VM IP"),
         Cell::from("Host \u{2192} VM"),
         Cell::from("VM \u{2192} Host"),
+        Cell::from("Egress"),
     ])
     .style(Style::default().fg(Color::Cyan));
 
@@ -379,6 +484,7 @@ fn render_networks_table_into(rows: &[NetworkListRow], area: Rect, buffer: &mut
             Cell::from(row.vm_ip.clone()),
             Cell::from(row.host_to_vm.clone()),
             Cell::from(row.vm_to_host.clone()),
+            Cell::from(row.egress.clone()),
         ])
     });
 
@@ -389,6 +495,7 @@ fn render_networks_table_into(rows: &[NetworkListRow], area: Rect, buffer: &mut
             Constraint::Length(16),
             Constraint::Min(24),
             Constraint::Min(20),
+            Constraint::Length(10),
         ],
     )
     .header(header)
@@ -398,6 +505,57 @@ fn render_networks_table_into(rows: &[NetworkListRow], area: Rect, buffer: &mut
     table.render(area, buffer);
 }
 
+pub fn format_bytes(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
+    const GB: f64 = MB * 1024.0;
+
+    let b = bytes as f64;
+    if b >= GB {
+        format!("{:.2} GB", b / GB)
+    } else if b >= MB {
+        format!("{:.1} MB", b / MB)
+    } else if b >= KB {
+        format!("{:.1} KB", b / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+pub fn format_last_active(value: Option<&str>) -> String {
+    let Some(raw) = value else {
+        return "-".to_string();
+    };
+    let parsed = OffsetDateTime::parse(raw, &Rfc3339);
+    let Ok(timestamp) = parsed else {
+        return raw.to_string();
+    };
+    let now = OffsetDateTime::now_utc();
+    let mut seconds = (now - timestamp).whole_seconds();
+    if seconds < 0 {
+        seconds = 0;
+    }
+    let week_seconds = 7 * 24 * 60 * 60;
+    if seconds >= week_seconds {
+        return timestamp
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]Z"))
+            .unwrap_or_else(|_| raw.to_string());
+    }
+    if seconds < 60 {
+        return "just now".to_string();
+    }
+    if seconds < 60 * 60 {
+        let mins = seconds / 60;
+        return format!("{} min{} ago", mins, if mins == 1 { "" } else { "s" });
+    }
+    if seconds < 60 * 60 * 24 {
+        let hours = seconds / (60 * 60);
+        return format!("{} hour{} ago", hours, if hours == 1 { "" } else { "s" });
+    }
+    let days = seconds / (60 * 60 * 24);
+    format!("{} day{} ago", days, if days == 1 { "" } else { "s" })
+}
+
 pub fn passthrough_vm_io(
     app: Arc<Mutex<AppState>>,
     output_monitor: Arc<vm::OutputMonitor>,
@@ -561,6 +719,12 @@ fn render_header(buffer: &mut Buffer, area: Rect, app: &AppState) {
         .border_style(Style::default().fg(Color::DarkGray))
         .title_style(Style::default().fg(Color::Reset))
         .title("Session");
+    let guest = app.vm_info.guest.as_ref();
+    let secondary = Style::default().fg(Color::DarkGray);
+    let limits = format!(
+        "{} cores / {} MB / {} GB",
+        app.vm_info.cpu_cores, app.vm_info.max_memory_mb, app.vm_info.max_disk_gb
+    );
     let info_lines = vec![
         Line::from(vec![
             Span::raw("Directory: "),
@@ -568,18 +732,43 @@ fn render_header(buffer: &mut Buffer, area: Rect, app: &AppState) {
         ]),
         Line::from(vec![
             Span::raw("System: "),
-            Span::styled(&app.vm_info.system_name, Style::default().fg(Color::Green)),
+            match guest {
+                Some(guest) => {
+                    Span::styled(guest.system_label(), Style::default().fg(Color::Green))
+                }
+                None => Span::styled("unknown until the first boot", secondary),
+            },
         ]),
         Line::from(vec![
-            Span::raw("CPU / Memory / Disk: "),
+            Span::raw("Template: "),
             Span::styled(
-                format!(
-                    "{} cores / {} MB / {} GB",
-                    app.vm_info.cpu_cores, app.vm_info.max_memory_mb, app.vm_info.max_disk_gb
-                ),
+                guest
+                    .and_then(|guest| guest.template.clone())
+                    .unwrap_or_else(|| "unknown".to_string()),
                 Style::default().fg(Color::Green),
             ),
         ]),
+        Line::from(match guest {
+            Some(guest) => vec![
+                Span::raw("CPU / Memory / Disk: "),
+                Span::styled(
+                    format!(
+                        "{} cores / {} MB / {} of {} used",
+                        guest.cpu_count,
+                        guest.memory_mb,
+                        format_bytes(guest.disk_used_bytes),
+                        format_bytes(guest.disk_bytes)
+                    ),
+                    Style::default().fg(Color::Green),
+                ),
+                Span::styled(format!("  (limits {limits})"), secondary),
+            ],
+            None => vec![
+                Span::raw("CPU / Memory / Disk: "),
+                Span::styled(limits, Style::default().fg(Color::Green)),
+                Span::styled("  (configured)", secondary),
+            ],
+        }),
         Line::from(vec![
             Span::raw("Auto Shutdown: "),
             Span::styled(
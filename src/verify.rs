@@ -0,0 +1,379 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::SecurityProfile, instance, tui, vm::PROJECT_GUEST_BASE, vm_manager};
+
+/// Runs as the ssh user; each `## name` line starts a section read by [`GuestState::parse`].
+const PROBE_SCRIPT: &str = r#"
+echo '## mounts'; cat /proc/self/mounts
+echo '## listen'; ss -H -lnt 2>/dev/null | awk '{print $4}'
+echo '## addresses'; ip -4 -o addr show scope global 2>/dev/null | awk '{print $4}' | cut -d/ -f1
+echo '## routes'; ip -4 route 2>/dev/null
+echo '## sudo'
+if sudo -n true 2>/dev/null; then echo passwordless
+elif id -nG | tr ' ' '\n' | grep -qx sudo; then echo password
+else echo none; fi
+echo '## egress'
+if timeout 3 bash -c 'exec 3<>/dev/tcp/1.1.1.1/443' 2>/dev/null; then echo open; else echo blocked; fi
+"#;
+const EGRESS_PROBE_TARGET: &str = "1.1.1.1:443";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GuestMount {
+    target: PathBuf,
+    fstype: String,
+    read_only: bool,
+}
+
+/// What the guest reported about itself.
+#[derive(Debug, Clone, Default)]
+pub struct GuestState {
+    mounts: Vec<GuestMount>,
+    listening: Vec<String>,
+    addresses: Vec<String>,
+    routes: Vec<String>,
+    sudo: String,
+    egress_open: bool,
+}
+
+impl GuestState {
+    fn parse(output: &str) -> Self {
+        let mut state = GuestState::default();
+        let mut section = "";
+        for line in output.lines() {
+            if let Some(name) = line.strip_prefix("## ") {
+                section = name.trim();
+                continue;
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match section {
+                "mounts" => state.mounts.extend(parse_mount_line(line)),
+                "listen" => state.listening.push(line.to_string()),
+                "addresses" => state.addresses.push(line.to_string()),
+                "routes" => state.routes.push(line.to_string()),
+                "sudo" => state.sudo = line.to_string(),
+                "egress" => state.egress_open = line == "open",
+                _ => {}
+            }
+        }
+        state
+    }
+
+    /// The mount that is visible at `target`: the last one stacked there.
+    fn mount_at(&self, target: &Path) -> Option<&GuestMount> {
+        self.mounts
+            .iter()
+            .rev()
+            .find(|mount| mount.target == target)
+    }
+
+    /// Ports listened on beyond loopback, deduplicated across IPv4 and IPv6.
+    fn exposed_ports(&self) -> Vec<String> {
+        let mut ports: Vec<String> = self
+            .listening
+            .iter()
+            .filter(|addr| {
+                !(addr.starts_with("127.") || addr.starts_with("[::1]") || addr.starts_with("::1"))
+            })
+            .filter_map(|addr| addr.rsplit(':').next().map(str::to_string))
+            .collect();
+        ports.sort_by_key(|port| port.parse::<u16>().unwrap_or(u16::MAX));
+        ports.dedup();
+        ports
+    }
+}
+
+/// `/proc/mounts` escapes spaces and friends as octal, e.g. `\040`.
+fn parse_mount_line(line: &str) -> Option<GuestMount> {
+    let mut fields = line.split_whitespace();
+    let _source = fields.next()?;
+    let target = unescape_octal(fields.next()?);
+    let fstype = fields.next()?.to_string();
+    let options = fields.next().unwrap_or_default();
+    Some(GuestMount {
+        target: PathBuf::from(target),
+        fstype,
+        read_only: options.split(',').any(|option| option == "ro"),
+    })
+}
+
+fn unescape_octal(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(digits) = bytes.get(i + 1..i + 4)
+            && let Some(code) = std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            out.push(code);
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Asks the VM serving `instance_dir` for its actual state. Fails rather than booting one.
+pub fn probe(instance_dir: &Path) -> Result<GuestState, Box<dyn Error + Send + Sync>> {
+    let manager = vm_manager::connect_manager(instance_dir).map_err(|err| {
+        format!("--verify needs a running VM; start one with `vibebox` first ({err})")
+    })?;
+    let output = instance::capture_guest_script(instance_dir, manager, PROBE_SCRIPT)
+        .map_err(|err| format!("failed to query the guest: {err}"))?;
+    Ok(GuestState::parse(&output))
+}
+
+/// Where boot masks the project's `.git` with a tmpfs, if it has one.
+pub fn expected_git_mask(project_root: &Path) -> Option<PathBuf> {
+    let name = project_root.file_name()?;
+    project_root
+        .join(".git")
+        .exists()
+        .then(|| Path::new(PROJECT_GUEST_BASE).join(name).join(".git"))
+}
+
+/// Diffs the expected mount and network rows against what the guest reported.
+pub fn verify(
+    mounts: &[tui::MountListRow],
+    network: &[tui::NetworkListRow],
+    profile: SecurityProfile,
+    git_mask: Option<&Path>,
+    state: &GuestState,
+) -> Vec<tui::CheckRow> {
+    let mut checks = Vec::new();
+
+    for row in mounts {
+        let mount_point = Path::new(&row.mount_point);
+        let expected_ro = row.mode == "read-only";
+        let (actual, ok) = match state.mount_at(mount_point) {
+            None => ("not mounted".to_string(), false),
+            Some(mount) if row.mode == "overlay" => (
+                mount.fstype.clone(),
+                mount.fstype == "overlay" && !mount.read_only,
+            ),
+            // virtiofs shares keep `rw` in the guest even when the host enforces read-only,
+            // so only a read-only mount where read-write was configured is a deviation.
+            Some(mount) => (
+                format!(
+                    "{} {}",
+                    mount.fstype,
+                    if mount.read_only { "ro" } else { "rw" }
+                ),
+                !mount.read_only || expected_ro,
+            ),
+        };
+        checks.push(check(
+            format!("mount {}", row.mount_point),
+            format!("{} from {}", row.mode, row.host),
+            actual,
+            ok,
+        ));
+    }
+
+    if let Some(git) = git_mask {
+        let actual = state.mount_at(git).map(|mount| mount.fstype.clone());
+        checks.push(check(
+            format!("mask {}", git.display()),
+            "tmpfs".to_string(),
+            actual.clone().unwrap_or_else(|| "not masked".to_string()),
+            actual.as_deref() == Some("tmpfs"),
+        ));
+    }
+
+    let ports = state.exposed_ports();
+    checks.push(check(
+        "listening ports".to_string(),
+        "22".to_string(),
+        if ports.is_empty() {
+            "none".to_string()
+        } else {
+            ports.join(", ")
+        },
+        ports == ["22"],
+    ));
+
+    for network in network {
+        if network.vm_ip != "-" {
+            checks.push(check(
+                "vm ip".to_string(),
+                network.vm_ip.clone(),
+                state.addresses.join(", "),
+                state.addresses.contains(&network.vm_ip),
+            ));
+        }
+        let default_route = state
+            .routes
+            .iter()
+            .find(|route| route.starts_with("default"))
+            .cloned();
+        checks.push(check(
+            "default route".to_string(),
+            "present".to_string(),
+            default_route.clone().unwrap_or_else(|| "none".to_string()),
+            default_route.is_some(),
+        ));
+        let item = format!("egress to {EGRESS_PROBE_TARGET}");
+        if state.egress_open || network.egress != "open" {
+            let actual_egress = if state.egress_open {
+                "open"
+            } else {
+                "host only"
+            };
+            checks.push(check(
+                item,
+                network.egress.clone(),
+                actual_egress.to_string(),
+                network.egress == actual_egress,
+            ));
+        } else {
+            // A failed probe may only mean the host is offline, so it proves no restriction.
+            checks.push(tui::CheckRow {
+                item,
+                expected: network.egress.clone(),
+                actual: "unreachable".to_string(),
+                status: tui::CheckStatus::Unknown,
+            });
+        }
+    }
+
+    let expected_sudo = match profile {
+        SecurityProfile::Permissive => "passwordless",
+        SecurityProfile::Standard => "password",
+        SecurityProfile::Strict => "none",
+    };
+    checks.push(check(
+        "sudo".to_string(),
+        expected_sudo.to_string(),
+        state.sudo.clone(),
+        state.sudo == expected_sudo,
+    ));
+
+    checks
+}
+
+fn check(item: String, expected: String, actual: String, ok: bool) -> tui::CheckRow {
+    tui::CheckRow {
+        item,
+        expected,
+        actual,
+        status: if ok {
+            tui::CheckStatus::Ok
+        } else {
+            tui::CheckStatus::Diff
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    const PROBE_OUTPUT: &str = "## mounts
+/dev/vda1 / ext4 rw,relatime 0 0
+shared /usr/local/vibebox-mounts/my\\040app virtiofs rw,relatime 0 0
+shared /root/.local/share/mise virtiofs ro,relatime 0 0
+## listen
+0.0.0.0:22
+[::]:22
+127.0.0.53%lo:53
+0.0.0.0:8080
+## addresses
+192.168.64.7
+## routes
+default via 192.168.64.1 dev enp0s1
+## sudo
+password
+## egress
+open
+";
+
+    #[test]
+    fn flags_deviations_from_expected_rows() {
+        let mount = |guest: &str, mount_point: &str| tui::MountListRow {
+            host: "~/src".into(),
+            guest: guest.into(),
+            mount_point: mount_point.into(),
+            mode: "read-write".into(),
+            default_mount: "yes".into(),
+            guard: "ok".into(),
+        };
+        let mounts = [
+            mount("~/my app", "/usr/local/vibebox-mounts/my app"),
+            mount("/root/.local/share/mise", "/root/.local/share/mise"),
+        ];
+        let network = [tui::NetworkListRow {
+            network_type: "NAT".into(),
+            vm_ip: "192.168.64.7".into(),
+            host_to_vm: "ssh: 192.168.64.7:22".into(),
+            vm_to_host: "none".into(),
+            egress: "open".into(),
+        }];
+        let state = GuestState::parse(PROBE_OUTPUT);
+        let git_mask = Path::new("/usr/local/vibebox-mounts/my app/.git");
+        let checks = verify(
+            &mounts,
+            &network,
+            config::SecurityProfile::Standard,
+            Some(git_mask),
+            &state,
+        );
+        let failed: Vec<_> = checks
+            .iter()
+            .filter(|check| check.status == tui::CheckStatus::Diff)
+            .map(|check| (check.item.as_str(), check.actual.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                ("mount /root/.local/share/mise", "virtiofs ro"),
+                ("mask /usr/local/vibebox-mounts/my app/.git", "not masked"),
+                ("listening ports", "22, 8080"),
+            ]
+        );
+        assert_eq!(checks.len(), 8);
+
+        let offline =
+            GuestState::parse(&PROBE_OUTPUT.replace("## egress\nopen", "## egress\nblocked"));
+        let checks = verify(
+            &mounts,
+            &network,
+            config::SecurityProfile::Standard,
+            None,
+            &offline,
+        );
+        let egress = checks
+            .iter()
+            .find(|check| check.item.starts_with("egress"))
+            .unwrap();
+        assert_eq!(egress.status, tui::CheckStatus::Unknown);
+        assert_eq!(egress.actual, "unreachable");
+
+        let strict_network = [tui::NetworkListRow {
+            egress: "host only".into(),
+            ..network[0].clone()
+        }];
+        let checks = verify(
+            &[],
+            &strict_network,
+            config::SecurityProfile::Strict,
+            None,
+            &state,
+        );
+        let egress = checks
+            .iter()
+            .find(|check| check.item.starts_with("egress"))
+            .unwrap();
+        assert_eq!(egress.status, tui::CheckStatus::Diff);
+    }
+}
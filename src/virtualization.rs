@@ -0,0 +1,585 @@
+//! Apple Virtualization.framework backend. Everything that talks to objc2 lives here so the
+//! rest of the crate builds on any Unix host.
+use crate::boot::{self, DiskLayout, SHARED_DIRECTORIES_TAG, StatusFile};
+use crate::config::SecurityProfile;
+use crate::error::VibeboxError;
+use crate::instance::STATUS_FILE_NAME;
+use crate::login_script::{self, LoginAction, LoginError};
+use crate::logs::PROVISION_LOG_NAME;
+use crate::vm::{
+    DirectoryShare, IoContext, OutputMonitor, VmArg, VmInput, create_pipe, spawn_vm_io,
+    spawn_vm_io_with_log,
+};
+use std::{
+    env, fs, io,
+    os::unix::{
+        io::{IntoRawFd, OwnedFd},
+        process::CommandExt,
+    },
+    path::Path,
+    process::Command,
+    sync::{Arc, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use block2::RcBlock;
+use dispatch2::DispatchQueue;
+use objc2::{AnyThread, rc::Retained, runtime::ProtocolObject};
+use objc2_foundation::*;
+use objc2_virtualization::*;
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+const DEFAULT_CPU_COUNT: usize = 2;
+const DEFAULT_RAM_MB: u64 = 2048;
+const DEFAULT_RAM_BYTES: u64 = DEFAULT_RAM_MB * BYTES_PER_MB;
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn run_with_args<F>(
+    project_root: &Path,
+    instance_dir: &Path,
+    args: VmArg,
+    io_handler: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
+{
+    run_with_args_and_extras(
+        project_root,
+        instance_dir,
+        args,
+        io_handler,
+        Vec::new(),
+        Vec::new(),
+    )
+}
+
+pub(crate) fn run_with_args_and_extras<F>(
+    project_root: &Path,
+    instance_dir: &Path,
+    args: VmArg,
+    io_handler: F,
+    extra_login_actions: Vec<LoginAction>,
+    extra_directory_shares: Vec<DirectoryShare>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
+{
+    ensure_signed()?;
+
+    let layout = DiskLayout::for_instance(instance_dir)?;
+    fs::create_dir_all(&layout.instance_dir)?;
+    let status_file = StatusFile::new(layout.instance_dir.join(STATUS_FILE_NAME));
+    status_file.update("preparing VM image...");
+    let provision_log = layout.instance_dir.join(PROVISION_LOG_NAME);
+
+    // Prepare system-wide directories
+    fs::create_dir_all(&layout.cache_dir)?;
+    fs::create_dir_all(&layout.guest_mise_cache)?;
+
+    // Provisioning is vibebox's own script and fills the cache; sessions follow the profile.
+    let provision_mise_share = layout.mise_directory_share(SecurityProfile::Standard)?;
+    let mise_directory_share = layout.mise_directory_share(args.security)?;
+
+    ensure_default_image(
+        &layout.base_raw,
+        &layout.base_compressed,
+        &layout.default_raw,
+        std::slice::from_ref(&provision_mise_share),
+        Some(&status_file),
+        Some(&provision_log),
+    )?;
+    let _ = boot::ensure_instance_disk(
+        &layout.instance_raw,
+        &layout.default_raw,
+        args.disk_bytes,
+        Some(&status_file),
+    )?;
+    let base_size = fs::metadata(&layout.default_raw)?.len();
+    let instance_size = fs::metadata(&layout.instance_raw)?.len();
+    let needs_resize = instance_size > base_size;
+
+    let (login_actions, directory_shares) = boot::session_login_actions(
+        &args,
+        project_root,
+        mise_directory_share,
+        needs_resize,
+        extra_login_actions,
+        extra_directory_shares,
+    )?;
+
+    run_vm_with_io(
+        &layout.instance_raw,
+        &login_actions,
+        &directory_shares[..],
+        args.cpu_count,
+        args.ram_bytes,
+        Some(&status_file),
+        io_handler,
+    )
+}
+
+enum VmOutput {
+    LoginActionFailed(LoginError),
+}
+
+fn ensure_default_image(
+    base_raw: &Path,
+    base_compressed: &Path,
+    default_raw: &Path,
+    directory_shares: &[DirectoryShare],
+    status: Option<&StatusFile>,
+    provision_log: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if default_raw.exists() {
+        return Ok(());
+    }
+
+    boot::ensure_base_image(base_raw, base_compressed, status)?;
+
+    if let Some(status) = status {
+        status.update("configuring base image...");
+    }
+    tracing::info!("configuring base image");
+    fs::copy(base_raw, default_raw)?;
+
+    let provision_actions = boot::provision_login_actions()?;
+    let provision_result = if let Some(log_path) = provision_log {
+        let log_path = log_path.to_path_buf();
+        run_vm_with_io(
+            default_raw,
+            &provision_actions,
+            directory_shares,
+            DEFAULT_CPU_COUNT,
+            DEFAULT_RAM_BYTES,
+            None,
+            move |output_monitor, vm_output_fd, vm_input_fd| {
+                spawn_vm_io_with_log(output_monitor, vm_output_fd, vm_input_fd, log_path)
+            },
+        )
+    } else {
+        run_vm(
+            default_raw,
+            &provision_actions,
+            directory_shares,
+            DEFAULT_CPU_COUNT,
+            DEFAULT_RAM_BYTES,
+            None,
+        )
+    };
+
+    if let Err(err) = provision_result {
+        let _ = fs::remove_file(default_raw);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn create_vm_configuration(
+    disk_path: &Path,
+    directory_shares: &[DirectoryShare],
+    vm_reads_from_fd: OwnedFd,
+    vm_writes_to_fd: OwnedFd,
+    cpu_count: usize,
+    ram_bytes: u64,
+) -> Result<Retained<VZVirtualMachineConfiguration>, Box<dyn std::error::Error>> {
+    unsafe {
+        let platform =
+            VZGenericPlatformConfiguration::init(VZGenericPlatformConfiguration::alloc());
+
+        let boot_loader = VZEFIBootLoader::init(VZEFIBootLoader::alloc());
+        let variable_store = load_efi_variable_store()?;
+        boot_loader.setVariableStore(Some(&variable_store));
+
+        let config = VZVirtualMachineConfiguration::new();
+        config.setPlatform(&platform);
+        config.setBootLoader(Some(&boot_loader));
+        config.setCPUCount(cpu_count as NSUInteger);
+        config.setMemorySize(ram_bytes);
+
+        config.setNetworkDevices(&NSArray::from_retained_slice(&[{
+            let network_device = VZVirtioNetworkDeviceConfiguration::new();
+            network_device.setAttachment(Some(&VZNATNetworkDeviceAttachment::new()));
+            Retained::into_super(network_device)
+        }]));
+
+        config.setEntropyDevices(&NSArray::from_retained_slice(&[Retained::into_super(
+            VZVirtioEntropyDeviceConfiguration::new(),
+        )]));
+
+        ////////////////////////////
+        // Disks
+        {
+            let disk_attachment = VZDiskImageStorageDeviceAttachment::initWithURL_readOnly_cachingMode_synchronizationMode_error(
+            VZDiskImageStorageDeviceAttachment::alloc(),
+            &nsurl_from_path(disk_path).unwrap(),
+            false,
+            VZDiskImageCachingMode::Automatic,
+            VZDiskImageSynchronizationMode::Full,
+        ).unwrap();
+
+            let disk_device = VZVirtioBlockDeviceConfiguration::initWithAttachment(
+                VZVirtioBlockDeviceConfiguration::alloc(),
+                &disk_attachment,
+            );
+
+            let storage_devices: Retained<NSArray<_>> =
+                NSArray::from_retained_slice(&[Retained::into_super(disk_device)]);
+
+            config.setStorageDevices(&storage_devices);
+        };
+
+        ////////////////////////////
+        // Directory shares
+
+        if !directory_shares.is_empty() {
+            let directories: Retained<NSMutableDictionary<NSString, VZSharedDirectory>> =
+                NSMutableDictionary::new();
+
+            for share in directory_shares.iter() {
+                assert!(
+                    share.host().is_dir(),
+                    "path does not exist or is not a directory: {:?}",
+                    share.host()
+                );
+
+                let url = nsurl_from_path(share.host())?;
+                let shared_directory = VZSharedDirectory::initWithURL_readOnly(
+                    VZSharedDirectory::alloc(),
+                    &url,
+                    share.read_only(),
+                );
+
+                let key = NSString::from_str(&share.tag());
+                directories.setObject_forKey(&*shared_directory, ProtocolObject::from_ref(&*key));
+            }
+
+            let multi_share = VZMultipleDirectoryShare::initWithDirectories(
+                VZMultipleDirectoryShare::alloc(),
+                &directories,
+            );
+
+            let device = VZVirtioFileSystemDeviceConfiguration::initWithTag(
+                VZVirtioFileSystemDeviceConfiguration::alloc(),
+                &NSString::from_str(SHARED_DIRECTORIES_TAG),
+            );
+            device.setShare(Some(&multi_share));
+
+            let share_devices = NSArray::from_retained_slice(&[device.into_super()]);
+            config.setDirectorySharingDevices(&share_devices);
+        }
+
+        ////////////////////////////
+        // Serial port
+        {
+            let ns_read_handle = NSFileHandle::initWithFileDescriptor_closeOnDealloc(
+                NSFileHandle::alloc(),
+                vm_reads_from_fd.into_raw_fd(),
+                true,
+            );
+
+            let ns_write_handle = NSFileHandle::initWithFileDescriptor_closeOnDealloc(
+                NSFileHandle::alloc(),
+                vm_writes_to_fd.into_raw_fd(),
+                true,
+            );
+
+            let serial_attach =
+                VZFileHandleSerialPortAttachment::initWithFileHandleForReading_fileHandleForWriting(
+                    VZFileHandleSerialPortAttachment::alloc(),
+                    Some(&ns_read_handle),
+                    Some(&ns_write_handle),
+                );
+            let serial_port = VZVirtioConsoleDeviceSerialPortConfiguration::new();
+            serial_port.setAttachment(Some(&serial_attach));
+
+            let serial_ports: Retained<NSArray<_>> =
+                NSArray::from_retained_slice(&[Retained::into_super(serial_port)]);
+
+            config.setSerialPorts(&serial_ports);
+        }
+
+        ////////////////////////////
+        // Validate
+        config.validateWithError().map_err(|e| {
+            io::Error::other(format!(
+                "Invalid VM configuration: {:?}",
+                e.localizedDescription()
+            ))
+        })?;
+
+        Ok(config)
+    }
+}
+
+fn load_efi_variable_store() -> Result<Retained<VZEFIVariableStore>, Box<dyn std::error::Error>> {
+    unsafe {
+        let temp_dir = std::env::temp_dir();
+        let temp_path = temp_dir.join(format!("efi_variable_store_{}.efivars", std::process::id()));
+        let url = nsurl_from_path(&temp_path)?;
+        let options = VZEFIVariableStoreInitializationOptions::AllowOverwrite;
+        let store = VZEFIVariableStore::initCreatingVariableStoreAtURL_options_error(
+            VZEFIVariableStore::alloc(),
+            &url,
+            options,
+        )?;
+        Ok(store)
+    }
+}
+
+fn spawn_login_actions_thread(
+    login_actions: Vec<LoginAction>,
+    output_monitor: Arc<OutputMonitor>,
+    input_tx: mpsc::Sender<VmInput>,
+    vm_output_tx: mpsc::Sender<VmOutput>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        if let Err(err) =
+            login_script::run_login_actions(&login_actions, &output_monitor, &input_tx)
+        {
+            let _ = vm_output_tx.send(VmOutput::LoginActionFailed(err));
+        }
+    })
+}
+
+fn run_vm_with_io<F>(
+    disk_path: &Path,
+    login_actions: &[LoginAction],
+    directory_shares: &[DirectoryShare],
+    cpu_count: usize,
+    ram_bytes: u64,
+    status: Option<&StatusFile>,
+    io_handler: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
+{
+    let (vm_reads_from, we_write_to) = create_pipe();
+    let (we_read_from, vm_writes_to) = create_pipe();
+
+    let config = create_vm_configuration(
+        disk_path,
+        directory_shares,
+        vm_reads_from,
+        vm_writes_to,
+        cpu_count,
+        ram_bytes,
+    )?;
+
+    let queue = DispatchQueue::main();
+
+    let vm = unsafe {
+        VZVirtualMachine::initWithConfiguration_queue(VZVirtualMachine::alloc(), &config, queue)
+    };
+
+    let (tx, rx) = mpsc::channel::<Result<(), String>>();
+    let completion_handler = RcBlock::new(move |error: *mut NSError| {
+        if error.is_null() {
+            let _ = tx.send(Ok(()));
+        } else {
+            let err = unsafe { &*error };
+            let _ = tx.send(Err(format!("{:?}", err.localizedDescription())));
+        }
+    });
+
+    unsafe {
+        vm.startWithCompletionHandler(&completion_handler);
+    }
+
+    let start_deadline = Instant::now() + START_TIMEOUT;
+    while Instant::now() < start_deadline {
+        unsafe {
+            NSRunLoop::mainRunLoop().runMode_beforeDate(
+                NSDefaultRunLoopMode,
+                &NSDate::dateWithTimeIntervalSinceNow(0.1),
+            )
+        };
+
+        match rx.try_recv() {
+            Ok(result) => {
+                result.map_err(VibeboxError::VmStart)?;
+                break;
+            }
+            Err(mpsc::TryRecvError::Empty) => continue,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                return Err(VibeboxError::VmStart("start channel disconnected".into()).into());
+            }
+        }
+    }
+
+    if Instant::now() >= start_deadline {
+        return Err(
+            VibeboxError::VmStart(format!("timed out after {}s", START_TIMEOUT.as_secs())).into(),
+        );
+    }
+
+    if let Some(status) = status {
+        status.update("vm booting... go vibecoder!");
+    }
+    tracing::info!("vm booting");
+
+    let output_monitor = Arc::new(OutputMonitor::default());
+    let io_ctx = io_handler(output_monitor.clone(), we_read_from, we_write_to);
+
+    let all_login_actions = boot::boot_login_actions(directory_shares, login_actions);
+
+    let (vm_output_tx, vm_output_rx) = mpsc::channel::<VmOutput>();
+    let login_actions_thread = spawn_login_actions_thread(
+        all_login_actions,
+        output_monitor.clone(),
+        io_ctx.input_tx.clone(),
+        vm_output_tx,
+    );
+
+    let mut last_state = None;
+    let mut exit_result = Ok(());
+    loop {
+        unsafe {
+            NSRunLoop::mainRunLoop().runMode_beforeDate(
+                NSDefaultRunLoopMode,
+                &NSDate::dateWithTimeIntervalSinceNow(0.2),
+            )
+        };
+
+        let state = unsafe { vm.state() };
+        if last_state != Some(state) {
+            //eprintln!("[state] {:?}", state);
+            last_state = Some(state);
+        }
+        match vm_output_rx.try_recv() {
+            Ok(VmOutput::LoginActionFailed(err)) => {
+                tracing::error!(error = %err, "login action failed; shutting down");
+                exit_result = Err(VibeboxError::Login(err).into());
+                unsafe {
+                    if vm.canRequestStop() {
+                        if let Err(err) = vm.requestStopWithError() {
+                            tracing::error!(error = ?err, "failed to request VM stop");
+                        }
+                    } else if vm.canStop() {
+                        let handler = RcBlock::new(|_error: *mut NSError| {});
+                        vm.stopWithCompletionHandler(&handler);
+                    }
+                }
+                break;
+            }
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => {}
+        }
+        if state != objc2_virtualization::VZVirtualMachineState::Running {
+            //eprintln!("VM stopped with state: {:?}", state);
+            break;
+        }
+    }
+
+    output_monitor.close();
+    let _ = login_actions_thread.join();
+
+    io_ctx.shutdown();
+
+    exit_result
+}
+
+fn run_vm(
+    disk_path: &Path,
+    login_actions: &[LoginAction],
+    directory_shares: &[DirectoryShare],
+    cpu_count: usize,
+    ram_bytes: u64,
+    status: Option<&StatusFile>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_vm_with_io(
+        disk_path,
+        login_actions,
+        directory_shares,
+        cpu_count,
+        ram_bytes,
+        status,
+        spawn_vm_io,
+    )
+}
+
+fn nsurl_from_path(path: &Path) -> Result<Retained<NSURL>, Box<dyn std::error::Error>> {
+    let abs_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    let ns_path = NSString::from_str(
+        abs_path
+            .to_str()
+            .ok_or("Non-UTF8 path encountered while building NSURL")?,
+    );
+    Ok(NSURL::fileURLWithPath(&ns_path))
+}
+
+// Ensure the running binary has com.apple.security.virtualization entitlements by checking and, if not, signing and relaunching.
+pub fn ensure_signed() -> Result<(), VibeboxError> {
+    if std::env::var("VIBEBOX_SKIP_CODESIGN").as_deref() == Ok("1") {
+        return Ok(());
+    }
+    let exe = std::env::current_exe()?;
+    let exe_str = exe
+        .to_str()
+        .ok_or_else(|| VibeboxError::EntitlementMissing("exe path is not valid UTF-8".into()))?;
+
+    let has_required_entitlements = {
+        let output = Command::new("codesign")
+            .args(["-d", "--entitlements", "-", "--xml", exe_str])
+            .output();
+
+        match output {
+            Ok(o) if o.status.success() => {
+                let stdout = String::from_utf8_lossy(&o.stdout);
+                stdout.contains("com.apple.security.virtualization")
+            }
+            _ => false,
+        }
+    };
+
+    if has_required_entitlements {
+        return Ok(());
+    }
+
+    const ENTITLEMENTS: &str = include_str!("entitlements.plist");
+    let entitlements_path = std::env::temp_dir().join("entitlements.plist");
+    std::fs::write(&entitlements_path, ENTITLEMENTS)?;
+
+    let output = Command::new("codesign")
+        .args([
+            "--sign",
+            "-",
+            "--force",
+            "--entitlements",
+            &entitlements_path.to_string_lossy(),
+            exe_str,
+        ])
+        .output();
+
+    let _ = std::fs::remove_file(&entitlements_path);
+
+    match output {
+        Ok(o) if o.status.success() => {
+            let stderr = String::from_utf8_lossy(&o.stderr);
+            if !stderr.trim().is_empty() {
+                tracing::debug!(codesign_stderr = %stderr.trim(), "codesign output");
+            }
+            let err = Command::new(&exe).args(std::env::args_os().skip(1)).exec();
+            Err(VibeboxError::EntitlementMissing(format!(
+                "failed to relaunch after signing: {err}"
+            )))
+        }
+        Ok(o) => {
+            let stderr = String::from_utf8_lossy(&o.stderr);
+            Err(VibeboxError::EntitlementMissing(format!(
+                "codesign exited with {}: {}",
+                o.status,
+                stderr.trim()
+            )))
+        }
+        Err(source) => Err(VibeboxError::Tool {
+            tool: "codesign",
+            source,
+        }),
+    }
+}
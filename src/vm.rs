@@ -1,125 +1,69 @@
-use crate::instance::STATUS_FILE_NAME;
-use crate::session_manager::{GLOBAL_CACHE_DIR_NAME, INSTANCE_DIR_NAME};
 use std::{
-    env, fs,
+    collections::BTreeMap,
+    env,
     io::{self, Write},
     os::{
         fd::RawFd,
         unix::{
-            io::{AsRawFd, IntoRawFd, OwnedFd},
+            io::{AsRawFd, OwnedFd},
             net::UnixStream,
-            process::CommandExt,
         },
     },
     path::{Path, PathBuf},
-    process::{Command, Stdio},
     sync::{
         Arc, Condvar, Mutex,
         atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, Sender},
     },
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use block2::RcBlock;
-use dispatch2::DispatchQueue;
-use objc2::{AnyThread, rc::Retained, runtime::ProtocolObject};
-use objc2_foundation::*;
-use objc2_virtualization::*;
+use crate::{
+    config::{CommandConfig, HooksConfig, ProjectMode, SecurityProfile},
+    error::VibeboxError,
+    logs,
+};
 
-const DEBIAN_COMPRESSED_DISK_URL: &str = "https://cloud.debian.org/images/cloud/trixie/20260112-2355/debian-13-nocloud-arm64-20260112-2355.tar.xz";
-const DEBIAN_COMPRESSED_SHA: &str = "6ab9be9e6834adc975268367f2f0235251671184345c34ee13031749fdfbf66fe4c3aafd949a2d98550426090e9ac645e79009c51eb0eefc984c15786570bb38";
-const DEBIAN_COMPRESSED_SIZE_BYTES: u64 = 280901576;
-const SHARED_DIRECTORIES_TAG: &str = "shared";
 pub const PROJECT_GUEST_BASE: &str = "/usr/local/vibebox-mounts";
 
-const BYTES_PER_MB: u64 = 1024 * 1024;
-const DEFAULT_CPU_COUNT: usize = 2;
-const DEFAULT_RAM_MB: u64 = 2048;
-const DEFAULT_RAM_BYTES: u64 = DEFAULT_RAM_MB * BYTES_PER_MB;
-const START_TIMEOUT: Duration = Duration::from_secs(60);
-const LOGIN_EXPECT_TIMEOUT: Duration = Duration::from_secs(120);
-const PROVISION_EXPECT_TIMEOUT: Duration = Duration::from_secs(900);
-
-struct StatusFile {
-    path: PathBuf,
-    cleared: AtomicBool,
-}
-
-impl StatusFile {
-    fn new(path: PathBuf) -> Self {
-        Self {
-            path,
-            cleared: AtomicBool::new(false),
-        }
-    }
-
-    fn update(&self, message: &str) {
-        let _ = fs::write(&self.path, message);
-    }
-}
-
-impl Drop for StatusFile {
-    fn drop(&mut self) {
-        if !self.cleared.load(Ordering::SeqCst) {
-            let _ = fs::remove_file(&self.path);
-            self.cleared.store(true, Ordering::SeqCst);
-        }
-    }
-}
-const PROVISION_SCRIPT: &str = include_str!("provision.sh");
-const PROVISION_SCRIPT_NAME: &str = "provision.sh";
-const RESIZE_DISK_SCRIPT: &str = include_str!("resize_disk.sh");
-const DEFAULT_RAW_NAME: &str = "default.raw";
-const INSTANCE_RAW_NAME: &str = "instance.raw";
-const BASE_DISK_RAW_NAME: &str = "disk.raw";
-
-#[derive(Clone)]
-pub(crate) enum LoginAction {
-    Expect {
-        text: String,
-        timeout: Duration,
-    },
-    ExpectEither {
-        success: String,
-        failure: String,
-        timeout: Duration,
-    },
-    Send(String),
-}
-use LoginAction::*;
-
 #[derive(Clone)]
-pub(crate) struct DirectoryShare {
+pub struct DirectoryShare {
     host: PathBuf,
     guest: PathBuf,
     read_only: bool,
+    tag: String,
 }
 
 impl DirectoryShare {
-    pub(crate) fn new(
+    pub fn new(
         host: PathBuf,
         mut guest: PathBuf,
         read_only: bool,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         if !host.exists() {
-            return Err(format!("Host path does not exist: {}", host.display()).into());
+            return Err(VibeboxError::InvalidMount(format!(
+                "Host path does not exist: {}",
+                host.display()
+            ))
+            .into());
         }
         if !guest.is_absolute() {
             guest = PathBuf::from("/root").join(guest);
         }
+        let tag = host_tag(&host);
         Ok(Self {
             host,
             guest,
             read_only,
+            tag,
         })
     }
 
-    pub(crate) fn from_mount_spec(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn from_mount_spec(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let parts: Vec<&str> = spec.split(':').collect();
         if parts.len() < 2 || parts.len() > 3 {
-            return Err(format!("Invalid mount spec: {spec}").into());
+            return Err(VibeboxError::InvalidMount(format!("Invalid mount spec: {spec}")).into());
         }
         let host = expand_tilde_path(parts[0]);
         let guest = PathBuf::from(parts[1]);
@@ -128,10 +72,10 @@ impl DirectoryShare {
                 "read-only" => true,
                 "read-write" => false,
                 _ => {
-                    return Err(format!(
+                    return Err(VibeboxError::InvalidMount(format!(
                         "Invalid mount mode '{}'; expected read-only or read-write",
                         parts[2]
-                    )
+                    ))
                     .into());
                 }
             }
@@ -141,21 +85,42 @@ impl DirectoryShare {
         DirectoryShare::new(host, guest, read_only)
     }
 
-    fn tag(&self) -> String {
-        let path_str = self.host.to_string_lossy();
-        let hash = path_str
-            .bytes()
-            .fold(5381u64, |h, b| h.wrapping_mul(33).wrapping_add(b as u64));
-        let base_name = self
-            .host
-            .file_name()
-            .map(|s| s.to_string_lossy())
-            .unwrap_or("share".into());
-        format!("{}_{:016x}", base_name, hash)
+    pub fn host(&self) -> &Path {
+        &self.host
+    }
+
+    pub fn guest(&self) -> &Path {
+        &self.guest
     }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Name of the share inside the `shared` virtiofs device. Derived from the host path;
+    /// [`mount_plan::plan_shares`](crate::mount_plan::plan_shares) makes it unique per boot.
+    pub fn tag(&self) -> String {
+        self.tag.clone()
+    }
+
+    pub(crate) fn set_tag(&mut self, tag: String) {
+        self.tag = tag;
+    }
+}
+
+fn host_tag(host: &Path) -> String {
+    let path_str = host.to_string_lossy();
+    let hash = path_str
+        .bytes()
+        .fold(5381u64, |h, b| h.wrapping_mul(33).wrapping_add(b as u64));
+    let base_name = host
+        .file_name()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or("share".into());
+    format!("{}_{:016x}", base_name, hash)
 }
 
-fn expand_tilde_path(value: &str) -> PathBuf {
+pub(crate) fn expand_tilde_path(value: &str) -> PathBuf {
     if let Some(stripped) = value.strip_prefix("~/") {
         if let Ok(home) = env::var("HOME") {
             return PathBuf::from(home).join(stripped);
@@ -174,124 +139,11 @@ pub struct VmArg {
     pub disk_bytes: u64,
     pub no_default_mounts: bool,
     pub mounts: Vec<String>,
-}
-
-pub fn run_with_args<F>(args: VmArg, io_handler: F) -> Result<(), Box<dyn std::error::Error>>
-where
-    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
-{
-    run_with_args_and_extras(args, io_handler, Vec::new(), Vec::new())
-}
-
-pub(crate) fn run_with_args_and_extras<F>(
-    args: VmArg,
-    io_handler: F,
-    extra_login_actions: Vec<LoginAction>,
-    extra_directory_shares: Vec<DirectoryShare>,
-) -> Result<(), Box<dyn std::error::Error>>
-where
-    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
-{
-    ensure_signed();
-
-    let project_root = env::current_dir()?;
-    let project_name = project_root
-        .file_name()
-        .ok_or("Project directory has no name")?
-        .to_string_lossy()
-        .into_owned();
-
-    let home = env::var("HOME").map(PathBuf::from)?;
-    let cache_home = env::var("XDG_CACHE_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| home.join(".cache"));
-    let cache_dir = cache_home.join(GLOBAL_CACHE_DIR_NAME);
-    let guest_mise_cache = cache_dir.join(".guest-mise-cache");
-
-    let instance_dir = project_root.join(INSTANCE_DIR_NAME);
-    fs::create_dir_all(&instance_dir)?;
-    let status_file = StatusFile::new(instance_dir.join(STATUS_FILE_NAME));
-    status_file.update("preparing VM image...");
-    let provision_log = instance_dir.join("provision.log");
-
-    let basename_compressed = DEBIAN_COMPRESSED_DISK_URL.rsplit('/').next().unwrap();
-    let base_compressed = cache_dir.join(basename_compressed);
-    let base_raw = cache_dir.join(format!(
-        "{}.raw",
-        basename_compressed.trim_end_matches(".tar.xz")
-    ));
-
-    let default_raw = cache_dir.join(DEFAULT_RAW_NAME);
-    let instance_raw = instance_dir.join(INSTANCE_RAW_NAME);
-
-    // Prepare system-wide directories
-    fs::create_dir_all(&cache_dir)?;
-    fs::create_dir_all(&guest_mise_cache)?;
-
-    let mise_directory_share =
-        DirectoryShare::new(guest_mise_cache, "/root/.local/share/mise".into(), false)?;
-
-    ensure_default_image(
-        &base_raw,
-        &base_compressed,
-        &default_raw,
-        std::slice::from_ref(&mise_directory_share),
-        Some(&status_file),
-        Some(&provision_log),
-    )?;
-    let _ = ensure_instance_disk(
-        &instance_raw,
-        &default_raw,
-        args.disk_bytes,
-        Some(&status_file),
-    )?;
-    let base_size = fs::metadata(&default_raw)?.len();
-    let instance_size = fs::metadata(&instance_raw)?.len();
-    let needs_resize = instance_size > base_size;
-    let disk_path = instance_raw;
-
-    let mut login_actions = Vec::new();
-    let mut directory_shares = Vec::new();
-
-    if !args.no_default_mounts {
-        let project_guest_dir = PathBuf::from(PROJECT_GUEST_BASE).join(project_name);
-        login_actions.push(Send(format!("cd {}", project_guest_dir.display())));
-
-        // discourage read/write of .git folder from within the VM. note that this isn't secure, since the VM runs as root and could unmount this.
-        // I couldn't find an alternative way to do this --- the MacOS sandbox doesn't apply to the Apple Virtualization system
-        if project_root.join(".git").exists() {
-            login_actions.push(Send(r"mount -t tmpfs tmpfs .git/".into()));
-        }
-
-        directory_shares.push(mise_directory_share);
-    }
-
-    directory_shares.extend(extra_directory_shares);
-
-    for spec in &args.mounts {
-        directory_shares.push(DirectoryShare::from_mount_spec(spec)?);
-    }
-
-    if needs_resize {
-        let resize_cmd = script_command_from_content("resize_disk", RESIZE_DISK_SCRIPT)?;
-        login_actions.push(Send(resize_cmd));
-    }
-
-    if let Some(motd_action) = motd_login_action(&directory_shares) {
-        login_actions.push(motd_action);
-    }
-
-    login_actions.extend(extra_login_actions);
-
-    run_vm_with_io(
-        &disk_path,
-        &login_actions,
-        &directory_shares[..],
-        args.cpu_count,
-        args.ram_bytes,
-        Some(&status_file),
-        io_handler,
-    )
+    pub security: SecurityProfile,
+    pub allow_sensitive: bool,
+    pub project_mode: ProjectMode,
+    pub commands: BTreeMap<String, CommandConfig>,
+    pub hooks: HooksConfig,
 }
 
 pub(crate) fn script_command_from_content(
@@ -312,89 +164,20 @@ pub(crate) fn script_command_from_content(
     Ok(command)
 }
 
-fn motd_login_action(directory_shares: &[DirectoryShare]) -> Option<LoginAction> {
-    if directory_shares.is_empty() {
-        return Some(Send("clear".into()));
-    }
-
-    let host_header = "Host";
-    let guest_header = "Guest";
-    let mode_header = "Mode";
-    let mut host_width = host_header.len();
-    let mut guest_width = guest_header.len();
-    let mut mode_width = mode_header.len();
-    let mut rows = Vec::with_capacity(directory_shares.len());
-
-    for share in directory_shares {
-        let host = share.host.to_string_lossy().into_owned();
-        let guest = share.guest.to_string_lossy().into_owned();
-        let mode = if share.read_only {
-            "read-only"
-        } else {
-            "read-write"
-        }
-        .to_string();
-        host_width = host_width.max(host.len());
-        guest_width = guest_width.max(guest.len());
-        mode_width = mode_width.max(mode.len());
-        rows.push((host, guest, mode));
-    }
-
-    let mut output = String::new();
-    output.push_str(&format!(
-        "{host_header:<host_width$}  {guest_header:<guest_width$}  {mode_header}\n",
-        host_width = host_width
-    ));
-    output.push_str(&format!(
-        "{:-<host_width$}  {:-<guest_width$}  {:-<mode_width$}\n",
-        "",
-        "",
-        "",
-        host_width = host_width,
-        guest_width = guest_width,
-        mode_width = mode_width
-    ));
-
-    for (host, guest, mode) in rows {
-        output.push_str(&format!(
-            "{host:<host_width$}  {guest:<guest_width$}  {mode}\n"
-        ));
-    }
-
-    let command = format!("clear && cat <<'VIBE_MOTD'\n{output}\nVIBE_MOTD");
-    Some(Send(command))
-}
-
-#[derive(PartialEq, Eq)]
-enum WaitResult {
-    Timeout,
-    Found,
-}
-
-#[derive(PartialEq, Eq)]
-enum WaitAnyResult {
-    Timeout,
-    Found(usize),
-}
-
 pub enum VmInput {
     Bytes(Vec<u8>),
     Shutdown,
 }
 
-enum VmOutput {
-    LoginActionTimeout { action: String, timeout: Duration },
-    LoginActionFailed { action: String, reason: String },
-}
-
 #[derive(Default)]
 pub struct OutputMonitor {
     buffer: Mutex<String>,
     condvar: Condvar,
+    closed: AtomicBool,
 }
 
 impl OutputMonitor {
-    fn push(&self, bytes: &[u8]) {
+    pub(crate) fn push(&self, bytes: &[u8]) {
         self.buffer
             .lock()
             .unwrap()
@@ -402,60 +185,33 @@ impl OutputMonitor {
         self.condvar.notify_all();
     }
 
-    fn wait_for(&self, needle: &str, timeout: Duration) -> WaitResult {
-        let (_unused, timeout_result) = self
-            .condvar
-            .wait_timeout_while(self.buffer.lock().unwrap(), timeout, |buf| {
-                if let Some((_, remaining)) = buf.split_once(needle) {
-                    *buf = remaining.to_string();
-                    false
-                } else {
-                    true
-                }
-            })
-            .unwrap();
+    /// Marks the console as gone so pending waits return instead of running out their timeout.
+    pub fn close(&self) {
+        let _buffer = self.buffer.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.condvar.notify_all();
+    }
 
-        if timeout_result.timed_out() {
-            WaitResult::Timeout
-        } else {
-            WaitResult::Found
-        }
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
     }
 
-    fn wait_for_any(&self, needles: &[&str], timeout: Duration) -> WaitAnyResult {
-        let mut found: Option<usize> = None;
-        let (_unused, timeout_result) = self
+    /// Re-runs `consume` on the buffered output until it yields a value, `timeout` elapses or the console closes.
+    pub(crate) fn wait_until<T>(
+        &self,
+        timeout: Duration,
+        mut consume: impl FnMut(&mut String) -> Option<T>,
+    ) -> Option<T> {
+        let mut found = None;
+        let _unused = self
             .condvar
             .wait_timeout_while(self.buffer.lock().unwrap(), timeout, |buf| {
-                if let Some((pos, idx, len)) = find_any(buf, needles) {
-                    *buf = buf[(pos + len)..].to_string();
-                    found = Some(idx);
-                    false
-                } else {
-                    true
-                }
+                found = consume(buf);
+                found.is_none() && !self.is_closed()
             })
             .unwrap();
-
-        if timeout_result.timed_out() {
-            WaitAnyResult::Timeout
-        } else {
-            WaitAnyResult::Found(found.unwrap_or(0))
-        }
-    }
-}
-
-fn find_any(buf: &str, needles: &[&str]) -> Option<(usize, usize, usize)> {
-    let mut best: Option<(usize, usize, usize)> = None; // (pos, idx, len)
-    for (idx, needle) in needles.iter().enumerate() {
-        if let Some(pos) = buf.find(needle) {
-            let candidate = (pos, idx, needle.len());
-            if best.is_none_or(|b| candidate.0 < b.0) {
-                best = Some(candidate);
-            }
-        }
+        found
     }
-    best
 }
 
 #[derive(Debug)]
@@ -486,11 +242,11 @@ impl IoControl {
         self.restore_terminal.store(true, Ordering::SeqCst);
     }
 
-    fn forward_input(&self) -> bool {
+    pub(crate) fn forward_input(&self) -> bool {
         self.forward_input.load(Ordering::SeqCst)
     }
 
-    fn forward_output(&self) -> bool {
+    pub(crate) fn forward_output(&self) -> bool {
         self.forward_output.load(Ordering::SeqCst)
     }
 
@@ -501,196 +257,6 @@ impl IoControl {
     }
 }
 
-fn ensure_base_image(
-    base_raw: &Path,
-    base_compressed: &Path,
-    status: Option<&StatusFile>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if base_raw.exists() {
-        return Ok(());
-    }
-
-    if !base_compressed.exists()
-        || std::fs::metadata(base_compressed).map(|m| m.len())? < DEBIAN_COMPRESSED_SIZE_BYTES
-    {
-        if let Some(status) = status {
-            status.update("downloading base image...");
-        }
-        tracing::info!("downloading base image");
-        let status = Command::new("curl")
-            .args([
-                "--continue-at",
-                "-",
-                "--compressed",
-                "--location",
-                "--fail",
-                "-o",
-                &base_compressed.to_string_lossy(),
-                DEBIAN_COMPRESSED_DISK_URL,
-            ])
-            .status()?;
-        if !status.success() {
-            return Err("Failed to download base image".into());
-        }
-    }
-
-    // Check SHA
-    {
-        if let Some(status) = status {
-            status.update("verifying base image...");
-        }
-        let input = format!("{}  {}\n", DEBIAN_COMPRESSED_SHA, base_compressed.display());
-
-        let mut child = Command::new("/usr/bin/shasum")
-            .args(["--algorithm", "512", "--check"])
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("failed to spawn shasum");
-
-        child
-            .stdin
-            .take()
-            .expect("failed to open stdin")
-            .write_all(input.as_bytes())
-            .expect("failed to write to stdin");
-
-        let status = child.wait().expect("failed to wait on child");
-        if !status.success() {
-            return Err(format!("SHA validation failed for {DEBIAN_COMPRESSED_DISK_URL}").into());
-        }
-    }
-
-    if let Some(status) = status {
-        status.update("decompressing base image...");
-    }
-    tracing::info!("decompressing base image");
-    let status = Command::new("tar")
-        .args([
-            "-xOf",
-            &base_compressed.to_string_lossy(),
-            BASE_DISK_RAW_NAME,
-        ])
-        .stdout(std::fs::File::create(base_raw)?)
-        .status()?;
-
-    if !status.success() {
-        return Err("Failed to decompress base image".into());
-    }
-
-    Ok(())
-}
-
-fn ensure_default_image(
-    base_raw: &Path,
-    base_compressed: &Path,
-    default_raw: &Path,
-    directory_shares: &[DirectoryShare],
-    status: Option<&StatusFile>,
-    provision_log: Option<&Path>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if default_raw.exists() {
-        return Ok(());
-    }
-
-    ensure_base_image(base_raw, base_compressed, status)?;
-
-    if let Some(status) = status {
-        status.update("configuring base image...");
-    }
-    tracing::info!("configuring base image");
-    fs::copy(base_raw, default_raw)?;
-
-    let provision_command = script_command_from_content(PROVISION_SCRIPT_NAME, PROVISION_SCRIPT)?;
-    let provision_actions = [
-        Send(provision_command),
-        ExpectEither {
-            success: "VIBEBOX_PROVISION_OK".to_string(),
-            failure: "VIBEBOX_PROVISION_FAILED".to_string(),
-            timeout: PROVISION_EXPECT_TIMEOUT,
-        },
-    ];
-    let provision_result = if let Some(log_path) = provision_log {
-        let log_path = log_path.to_path_buf();
-        run_vm_with_io(
-            default_raw,
-            &provision_actions,
-            directory_shares,
-            DEFAULT_CPU_COUNT,
-            DEFAULT_RAM_BYTES,
-            None,
-            move |output_monitor, vm_output_fd, vm_input_fd| {
-                spawn_vm_io_with_log(output_monitor, vm_output_fd, vm_input_fd, log_path)
-            },
-        )
-    } else {
-        run_vm(
-            default_raw,
-            &provision_actions,
-            directory_shares,
-            DEFAULT_CPU_COUNT,
-            DEFAULT_RAM_BYTES,
-            None,
-        )
-    };
-
-    if let Err(err) = provision_result {
-        let _ = fs::remove_file(default_raw);
-        return Err(err);
-    }
-
-    Ok(())
-}
-
-fn ensure_instance_disk(
-    instance_raw: &Path,
-    template_raw: &Path,
-    target_bytes: u64,
-    status: Option<&StatusFile>,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    if instance_raw.exists() {
-        let current_size = fs::metadata(instance_raw)?.len();
-        if current_size != target_bytes {
-            let current_gb = current_size as f64 / (1024.0 * 1024.0 * 1024.0);
-            let target_gb = target_bytes as f64 / (1024.0 * 1024.0 * 1024.0);
-            tracing::warn!(
-                current_bytes = current_size,
-                target_bytes,
-                "instance disk size does not match config (current {:.2} GB, config {:.2} GB); disk_gb applies only on init. Run `vibebox reset` to recreate or set disk_gb to match; using existing disk.",
-                current_gb,
-                target_gb
-            );
-        }
-        return Ok(false);
-    }
-
-    let template_size = fs::metadata(template_raw)?.len();
-    if target_bytes < template_size {
-        return Err(format!(
-            "Requested disk size {} bytes is smaller than base image size {} bytes",
-            target_bytes, template_size
-        )
-        .into());
-    }
-    let target_size = target_bytes;
-    let needs_resize = target_size > template_size;
-
-    if let Some(status) = status {
-        status.update("creating instance disk...");
-    }
-    tracing::info!(path = %template_raw.display(), "creating instance disk");
-    std::fs::create_dir_all(instance_raw.parent().unwrap())?;
-    if target_size == template_size {
-        fs::copy(template_raw, instance_raw)?;
-        return Ok(needs_resize);
-    }
-
-    let mut dst = std::fs::File::create(instance_raw)?;
-    dst.set_len(target_size)?;
-    let mut src = std::fs::File::open(template_raw)?;
-    std::io::copy(&mut src, &mut dst)?;
-    Ok(needs_resize)
-}
-
 pub struct IoContext {
     pub input_tx: Sender<VmInput>,
     wakeup_write: OwnedFd,
@@ -881,6 +447,7 @@ where
                     }
                 }
             }
+            output_monitor.close();
         }
     });
 
@@ -936,17 +503,14 @@ pub fn spawn_vm_io(
     spawn_vm_io_with_line_handler(output_monitor, vm_output_fd, vm_input_fd, |_| false)
 }
 
-fn spawn_vm_io_with_log(
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn spawn_vm_io_with_log(
     output_monitor: Arc<OutputMonitor>,
     vm_output_fd: OwnedFd,
     vm_input_fd: OwnedFd,
     log_path: PathBuf,
 ) -> IoContext {
-    let log_file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&log_path)
+    let log_file = logs::open_rotated(&log_path)
         .ok()
         .map(|file| Arc::new(Mutex::new(file)));
 
@@ -976,424 +540,7 @@ impl IoContext {
     }
 }
 
-fn create_vm_configuration(
-    disk_path: &Path,
-    directory_shares: &[DirectoryShare],
-    vm_reads_from_fd: OwnedFd,
-    vm_writes_to_fd: OwnedFd,
-    cpu_count: usize,
-    ram_bytes: u64,
-) -> Result<Retained<VZVirtualMachineConfiguration>, Box<dyn std::error::Error>> {
-    unsafe {
-        let platform =
-            VZGenericPlatformConfiguration::init(VZGenericPlatformConfiguration::alloc());
-
-        let boot_loader = VZEFIBootLoader::init(VZEFIBootLoader::alloc());
-        let variable_store = load_efi_variable_store()?;
-        boot_loader.setVariableStore(Some(&variable_store));
-
-        let config = VZVirtualMachineConfiguration::new();
-        config.setPlatform(&platform);
-        config.setBootLoader(Some(&boot_loader));
-        config.setCPUCount(cpu_count as NSUInteger);
-        config.setMemorySize(ram_bytes);
-
-        config.setNetworkDevices(&NSArray::from_retained_slice(&[{
-            let network_device = VZVirtioNetworkDeviceConfiguration::new();
-            network_device.setAttachment(Some(&VZNATNetworkDeviceAttachment::new()));
-            Retained::into_super(network_device)
-        }]));
-
-        config.setEntropyDevices(&NSArray::from_retained_slice(&[Retained::into_super(
-            VZVirtioEntropyDeviceConfiguration::new(),
-        )]));
-
-        ////////////////////////////
-        // Disks
-        {
-            let disk_attachment = VZDiskImageStorageDeviceAttachment::initWithURL_readOnly_cachingMode_synchronizationMode_error(
-            VZDiskImageStorageDeviceAttachment::alloc(),
-            &nsurl_from_path(disk_path).unwrap(),
-            false,
-            VZDiskImageCachingMode::Automatic,
-            VZDiskImageSynchronizationMode::Full,
-        ).unwrap();
-
-            let disk_device = VZVirtioBlockDeviceConfiguration::initWithAttachment(
-                VZVirtioBlockDeviceConfiguration::alloc(),
-                &disk_attachment,
-            );
-
-            let storage_devices: Retained<NSArray<_>> =
-                NSArray::from_retained_slice(&[Retained::into_super(disk_device)]);
-
-            config.setStorageDevices(&storage_devices);
-        };
-
-        ////////////////////////////
-        // Directory shares
-
-        if !directory_shares.is_empty() {
-            let directories: Retained<NSMutableDictionary<NSString, VZSharedDirectory>> =
-                NSMutableDictionary::new();
-
-            for share in directory_shares.iter() {
-                assert!(
-                    share.host.is_dir(),
-                    "path does not exist or is not a directory: {:?}",
-                    share.host
-                );
-
-                let url = nsurl_from_path(&share.host)?;
-                let shared_directory = VZSharedDirectory::initWithURL_readOnly(
-                    VZSharedDirectory::alloc(),
-                    &url,
-                    share.read_only,
-                );
-
-                let key = NSString::from_str(&share.tag());
-                directories.setObject_forKey(&*shared_directory, ProtocolObject::from_ref(&*key));
-            }
-
-            let multi_share = VZMultipleDirectoryShare::initWithDirectories(
-                VZMultipleDirectoryShare::alloc(),
-                &directories,
-            );
-
-            let device = VZVirtioFileSystemDeviceConfiguration::initWithTag(
-                VZVirtioFileSystemDeviceConfiguration::alloc(),
-                &NSString::from_str(SHARED_DIRECTORIES_TAG),
-            );
-            device.setShare(Some(&multi_share));
-
-            let share_devices = NSArray::from_retained_slice(&[device.into_super()]);
-            config.setDirectorySharingDevices(&share_devices);
-        }
-
-        ////////////////////////////
-        // Serial port
-        {
-            let ns_read_handle = NSFileHandle::initWithFileDescriptor_closeOnDealloc(
-                NSFileHandle::alloc(),
-                vm_reads_from_fd.into_raw_fd(),
-                true,
-            );
-
-            let ns_write_handle = NSFileHandle::initWithFileDescriptor_closeOnDealloc(
-                NSFileHandle::alloc(),
-                vm_writes_to_fd.into_raw_fd(),
-                true,
-            );
-
-            let serial_attach =
-                VZFileHandleSerialPortAttachment::initWithFileHandleForReading_fileHandleForWriting(
-                    VZFileHandleSerialPortAttachment::alloc(),
-                    Some(&ns_read_handle),
-                    Some(&ns_write_handle),
-                );
-            let serial_port = VZVirtioConsoleDeviceSerialPortConfiguration::new();
-            serial_port.setAttachment(Some(&serial_attach));
-
-            let serial_ports: Retained<NSArray<_>> =
-                NSArray::from_retained_slice(&[Retained::into_super(serial_port)]);
-
-            config.setSerialPorts(&serial_ports);
-        }
-
-        ////////////////////////////
-        // Validate
-        config.validateWithError().map_err(|e| {
-            io::Error::other(format!(
-                "Invalid VM configuration: {:?}",
-                e.localizedDescription()
-            ))
-        })?;
-
-        Ok(config)
-    }
-}
-
-fn load_efi_variable_store() -> Result<Retained<VZEFIVariableStore>, Box<dyn std::error::Error>> {
-    unsafe {
-        let temp_dir = std::env::temp_dir();
-        let temp_path = temp_dir.join(format!("efi_variable_store_{}.efivars", std::process::id()));
-        let url = nsurl_from_path(&temp_path)?;
-        let options = VZEFIVariableStoreInitializationOptions::AllowOverwrite;
-        let store = VZEFIVariableStore::initCreatingVariableStoreAtURL_options_error(
-            VZEFIVariableStore::alloc(),
-            &url,
-            options,
-        )?;
-        Ok(store)
-    }
-}
-
-fn spawn_login_actions_thread(
-    login_actions: Vec<LoginAction>,
-    output_monitor: Arc<OutputMonitor>,
-    input_tx: mpsc::Sender<VmInput>,
-    vm_output_tx: mpsc::Sender<VmOutput>,
-) -> thread::JoinHandle<()> {
-    thread::spawn(move || {
-        for a in login_actions {
-            match a {
-                Expect { text, timeout } => {
-                    if WaitResult::Timeout == output_monitor.wait_for(&text, timeout) {
-                        let _ = vm_output_tx.send(VmOutput::LoginActionTimeout {
-                            action: format!("expect '{}'", text),
-                            timeout,
-                        });
-                        return;
-                    }
-                }
-                ExpectEither {
-                    success,
-                    failure,
-                    timeout,
-                } => match output_monitor.wait_for_any(&[&success, &failure], timeout) {
-                    WaitAnyResult::Found(0) => {}
-                    WaitAnyResult::Found(_) => {
-                        let _ = vm_output_tx.send(VmOutput::LoginActionFailed {
-                            action: format!("expect '{}'", success),
-                            reason: format!("saw failure marker '{}'", failure),
-                        });
-                        return;
-                    }
-                    WaitAnyResult::Timeout => {
-                        let _ = vm_output_tx.send(VmOutput::LoginActionTimeout {
-                            action: format!("expect '{}'", success),
-                            timeout,
-                        });
-                        return;
-                    }
-                },
-                Send(mut text) => {
-                    text.push('\n'); // Type the newline so the command is actually submitted.
-                    input_tx.send(VmInput::Bytes(text.into_bytes())).unwrap();
-                }
-            }
-        }
-    })
-}
-
-fn run_vm_with_io<F>(
-    disk_path: &Path,
-    login_actions: &[LoginAction],
-    directory_shares: &[DirectoryShare],
-    cpu_count: usize,
-    ram_bytes: u64,
-    status: Option<&StatusFile>,
-    io_handler: F,
-) -> Result<(), Box<dyn std::error::Error>>
-where
-    F: FnOnce(Arc<OutputMonitor>, OwnedFd, OwnedFd) -> IoContext,
-{
-    let (vm_reads_from, we_write_to) = create_pipe();
-    let (we_read_from, vm_writes_to) = create_pipe();
-
-    let config = create_vm_configuration(
-        disk_path,
-        directory_shares,
-        vm_reads_from,
-        vm_writes_to,
-        cpu_count,
-        ram_bytes,
-    )?;
-
-    let queue = DispatchQueue::main();
-
-    let vm = unsafe {
-        VZVirtualMachine::initWithConfiguration_queue(VZVirtualMachine::alloc(), &config, queue)
-    };
-
-    let (tx, rx) = mpsc::channel::<Result<(), String>>();
-    let completion_handler = RcBlock::new(move |error: *mut NSError| {
-        if error.is_null() {
-            let _ = tx.send(Ok(()));
-        } else {
-            let err = unsafe { &*error };
-            let _ = tx.send(Err(format!("{:?}", err.localizedDescription())));
-        }
-    });
-
-    unsafe {
-        vm.startWithCompletionHandler(&completion_handler);
-    }
-
-    let start_deadline = Instant::now() + START_TIMEOUT;
-    while Instant::now() < start_deadline {
-        unsafe {
-            NSRunLoop::mainRunLoop().runMode_beforeDate(
-                NSDefaultRunLoopMode,
-                &NSDate::dateWithTimeIntervalSinceNow(0.1),
-            )
-        };
-
-        match rx.try_recv() {
-            Ok(result) => {
-                result.map_err(|e| format!("Failed to start VM: {}", e))?;
-                break;
-            }
-            Err(mpsc::TryRecvError::Empty) => continue,
-            Err(mpsc::TryRecvError::Disconnected) => {
-                return Err("VM start channel disconnected".into());
-            }
-        }
-    }
-
-    if Instant::now() >= start_deadline {
-        return Err("Timed out waiting for VM to start".into());
-    }
-
-    if let Some(status) = status {
-        status.update("vm booting... go vibecoder!");
-    }
-    tracing::info!("vm booting");
-
-    let output_monitor = Arc::new(OutputMonitor::default());
-    let io_ctx = io_handler(output_monitor.clone(), we_read_from, we_write_to);
-
-    let mut all_login_actions = vec![
-        Expect {
-            text: "login: ".to_string(),
-            timeout: LOGIN_EXPECT_TIMEOUT,
-        },
-        Send("root".to_string()),
-        Expect {
-            text: "~#".to_string(),
-            timeout: LOGIN_EXPECT_TIMEOUT,
-        },
-    ];
-
-    if !directory_shares.is_empty() {
-        all_login_actions.push(Send("mkdir -p /mnt/shared".into()));
-        all_login_actions.push(Send(format!(
-            "mount -t virtiofs {} /mnt/shared",
-            SHARED_DIRECTORIES_TAG
-        )));
-
-        for share in directory_shares {
-            let staging = format!("/mnt/shared/{}", share.tag());
-            let guest = share.guest.to_string_lossy();
-            all_login_actions.push(Send(format!("mkdir -p {}", guest)));
-            all_login_actions.push(Send(format!("mount --bind {} {}", staging, guest)));
-        }
-    }
-
-    for a in login_actions {
-        all_login_actions.push(a.clone())
-    }
-
-    let (vm_output_tx, vm_output_rx) = mpsc::channel::<VmOutput>();
-    let login_actions_thread = spawn_login_actions_thread(
-        all_login_actions,
-        output_monitor.clone(),
-        io_ctx.input_tx.clone(),
-        vm_output_tx,
-    );
-
-    let mut last_state = None;
-    let mut exit_result = Ok(());
-    loop {
-        unsafe {
-            NSRunLoop::mainRunLoop().runMode_beforeDate(
-                NSDefaultRunLoopMode,
-                &NSDate::dateWithTimeIntervalSinceNow(0.2),
-            )
-        };
-
-        let state = unsafe { vm.state() };
-        if last_state != Some(state) {
-            //eprintln!("[state] {:?}", state);
-            last_state = Some(state);
-        }
-        match vm_output_rx.try_recv() {
-            Ok(VmOutput::LoginActionTimeout { action, timeout }) => {
-                exit_result = Err(format!(
-                    "Login action ({}) timed out after {:?}; shutting down.",
-                    action, timeout
-                )
-                .into());
-                unsafe {
-                    if vm.canRequestStop() {
-                        if let Err(err) = vm.requestStopWithError() {
-                            tracing::error!(error = ?err, "failed to request VM stop");
-                        }
-                    } else if vm.canStop() {
-                        let handler = RcBlock::new(|_error: *mut NSError| {});
-                        vm.stopWithCompletionHandler(&handler);
-                    }
-                }
-                break;
-            }
-            Ok(VmOutput::LoginActionFailed { action, reason }) => {
-                exit_result = Err(format!(
-                    "Login action ({}) failed: {}; shutting down.",
-                    action, reason
-                )
-                .into());
-                unsafe {
-                    if vm.canRequestStop() {
-                        if let Err(err) = vm.requestStopWithError() {
-                            tracing::error!(error = ?err, "failed to request VM stop");
-                        }
-                    } else if vm.canStop() {
-                        let handler = RcBlock::new(|_error: *mut NSError| {});
-                        vm.stopWithCompletionHandler(&handler);
-                    }
-                }
-                break;
-            }
-            Err(mpsc::TryRecvError::Empty) => {}
-            Err(mpsc::TryRecvError::Disconnected) => {}
-        }
-        if state != objc2_virtualization::VZVirtualMachineState::Running {
-            //eprintln!("VM stopped with state: {:?}", state);
-            break;
-        }
-    }
-
-    let _ = login_actions_thread.join();
-
-    io_ctx.shutdown();
-
-    exit_result
-}
-
-fn run_vm(
-    disk_path: &Path,
-    login_actions: &[LoginAction],
-    directory_shares: &[DirectoryShare],
-    cpu_count: usize,
-    ram_bytes: u64,
-    status: Option<&StatusFile>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    run_vm_with_io(
-        disk_path,
-        login_actions,
-        directory_shares,
-        cpu_count,
-        ram_bytes,
-        status,
-        spawn_vm_io,
-    )
-}
-
-fn nsurl_from_path(path: &Path) -> Result<Retained<NSURL>, Box<dyn std::error::Error>> {
-    let abs_path = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        env::current_dir()?.join(path)
-    };
-    let ns_path = NSString::from_str(
-        abs_path
-            .to_str()
-            .ok_or("Non-UTF8 path encountered while building NSURL")?,
-    );
-    Ok(NSURL::fileURLWithPath(&ns_path))
-}
-
-fn enable_raw_mode(fd: i32) -> io::Result<RawModeGuard> {
+pub(crate) fn enable_raw_mode(fd: i32) -> io::Result<RawModeGuard> {
     let mut attributes: libc::termios = unsafe { std::mem::zeroed() };
 
     if unsafe { libc::tcgetattr(fd, &mut attributes) } != 0 {
@@ -1416,7 +563,7 @@ fn enable_raw_mode(fd: i32) -> io::Result<RawModeGuard> {
     Ok(RawModeGuard { fd, original })
 }
 
-struct RawModeGuard {
+pub(crate) struct RawModeGuard {
     fd: i32,
     original: libc::termios,
 }
@@ -1428,68 +575,3 @@ impl Drop for RawModeGuard {
         }
     }
 }
-
-// Ensure the running binary has com.apple.security.virtualization entitlements by checking and, if not, signing and relaunching.
-pub fn ensure_signed() {
-    if std::env::var("VIBEBOX_SKIP_CODESIGN").as_deref() == Ok("1") {
-        return;
-    }
-    let exe = std::env::current_exe().expect("failed to get current exe path");
-    let exe_str = exe.to_str().expect("exe path not valid utf-8");
-
-    let has_required_entitlements = {
-        let output = Command::new("codesign")
-            .args(["-d", "--entitlements", "-", "--xml", exe.to_str().unwrap()])
-            .output();
-
-        match output {
-            Ok(o) if o.status.success() => {
-                let stdout = String::from_utf8_lossy(&o.stdout);
-                stdout.contains("com.apple.security.virtualization")
-            }
-            _ => false,
-        }
-    };
-
-    if has_required_entitlements {
-        return;
-    }
-
-    const ENTITLEMENTS: &str = include_str!("entitlements.plist");
-    let entitlements_path = std::env::temp_dir().join("entitlements.plist");
-    std::fs::write(&entitlements_path, ENTITLEMENTS).expect("failed to write entitlements");
-
-    let output = Command::new("codesign")
-        .args([
-            "--sign",
-            "-",
-            "--force",
-            "--entitlements",
-            entitlements_path.to_str().unwrap(),
-            exe_str,
-        ])
-        .output();
-
-    let _ = std::fs::remove_file(&entitlements_path);
-
-    match output {
-        Ok(o) if o.status.success() => {
-            let stderr = String::from_utf8_lossy(&o.stderr);
-            if !stderr.trim().is_empty() {
-                tracing::debug!(codesign_stderr = %stderr.trim(), "codesign output");
-            }
-            let err = Command::new(&exe).args(std::env::args_os().skip(1)).exec();
-            tracing::error!(error = %err, "failed to re-exec after signing");
-            std::process::exit(1);
-        }
-        Ok(o) => {
-            let stderr = String::from_utf8_lossy(&o.stderr);
-            tracing::error!(status = %o.status, codesign_stderr = %stderr.trim(), "codesign failed");
-            std::process::exit(1);
-        }
-        Err(e) => {
-            tracing::error!(error = %e, "failed to run codesign");
-            std::process::exit(1);
-        }
-    }
-}
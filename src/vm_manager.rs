@@ -3,34 +3,61 @@ use std::{
     io::{Read, Write},
     os::unix::{
         fs::FileTypeExt,
+        fs::MetadataExt,
         fs::PermissionsExt,
-        io::AsRawFd,
+        io::{AsRawFd, RawFd},
         net::{UnixListener, UnixStream},
         process::CommandExt,
     },
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::{Arc, Mutex, mpsc},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
+#[cfg(target_os = "macos")]
+use crate::virtualization;
 use crate::{
-    config::CONFIG_PATH_ENV,
+    agent, boot,
+    commands::{self, shell_quote},
+    config::{self, CONFIG_PATH_ENV, ProjectMode},
+    console::{self, ConsoleHub},
+    control::{self, CONTROL_GUEST_DIR, ControlRequest},
+    error::VibeboxError,
+    explain, guest_info,
+    hooks::{self, HookEvent, Hooks},
     instance::STATUS_FILE_NAME,
-    instance::VM_ROOT_LOG_NAME,
     instance::{
-        DEFAULT_SSH_USER, InstanceConfig, build_ssh_login_actions, ensure_instance_dir,
-        ensure_ssh_keypair, extract_ipv4, load_or_create_instance_config, write_instance_config,
+        DEFAULT_SSH_USER, InstanceConfig, build_ssh_login_actions, ensure_ssh_keypair,
+        load_or_create_instance_config, write_instance_config,
     },
+    login_script::LoginAction,
+    logs::{self, VM_MANAGER_LOG_NAME, VM_ROOT_LOG_NAME},
+    mount_guard, mount_plan, overlay,
     session_manager::{
-        GLOBAL_DIR_NAME, INSTANCE_FILENAME, VM_MANAGER_PID_NAME, VM_MANAGER_SOCKET_NAME,
+        self, GLOBAL_DIR_NAME, INSTANCE_DIR_NAME, INSTANCE_FILENAME, NAMED_SESSIONS_DIR_NAME,
+        VM_CONSOLE_SOCKET_NAME, VM_MANAGER_CLIENTS_NAME, VM_MANAGER_PID_NAME,
+        VM_MANAGER_SOCKET_NAME,
     },
-    vm::{self, DirectoryShare, LoginAction, PROJECT_GUEST_BASE, VmInput},
+    vm::{self, DirectoryShare, PROJECT_GUEST_BASE, VmInput},
+};
+#[cfg(any(test, feature = "mock-vm"))]
+use crate::{
+    fake_guest::FakeGuest,
+    login_script::{self, LoginError},
 };
 
+/// Set on a manager whose instance directory is a throwaway one outside the project.
+pub const EPHEMERAL_DIR_ENV: &str = "VIBEBOX_EPHEMERAL_DIR";
+/// Set on a manager serving one of the project's named sessions.
+pub const SESSION_NAME_ENV: &str = "VIBEBOX_SESSION";
 const VM_MANAGER_LOCK_NAME: &str = "vm.lock";
-const VM_MANAGER_LOG_NAME: &str = "vm_manager.log";
+const STOP_REQUEST: &str = "stop\n";
 const SHUTDOWN_RETRY_MS: u64 = 500;
 #[cfg(test)]
 const HARD_SHUTDOWN_TIMEOUT_MS: u64 = 1_000;
@@ -47,14 +74,18 @@ fn force_exit(reason: &str) -> ! {
     panic!("{reason}");
 }
 
+/// Connects to the vm manager serving `instance_dir`, spawning one for `project_root` first if
+/// none is running. An `instance_dir` that is neither the project's `.vibebox` nor a named
+/// session in it gets an ephemeral manager, which deletes it on exit.
 pub fn ensure_manager(
-    raw_args: &[std::ffi::OsString],
+    project_root: &Path,
+    instance_dir: &Path,
     auto_shutdown_ms: u64,
     config_path: Option<&Path>,
 ) -> Result<UnixStream, Box<dyn std::error::Error>> {
-    let project_root = env::current_dir()?;
-    tracing::debug!(root = %project_root.display(), "ensure vm manager");
-    let instance_dir = ensure_instance_dir(&project_root)?;
+    tracing::debug!(root = %project_root.display(), instance_dir = %instance_dir.display(), "ensure vm manager");
+    fs::create_dir_all(instance_dir)?;
+    let instance_dir = instance_dir.to_path_buf();
     cleanup_stale_manager(&instance_dir);
     let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
 
@@ -68,7 +99,7 @@ pub fn ensure_manager(
     let mut lock_file = acquire_spawn_lock(&lock_path)?;
     if lock_file.is_some() {
         tracing::info!(path = %socket_path.display(), "spawning vm manager");
-        spawn_manager_process(raw_args, auto_shutdown_ms, &instance_dir, config_path)?;
+        spawn_manager_process(project_root, auto_shutdown_ms, &instance_dir, config_path)?;
     } else {
         tracing::info!(
             path = %socket_path.display(),
@@ -98,12 +129,8 @@ pub fn ensure_manager(
                         drop(lock_file.take());
                         let _ = fs::remove_file(&lock_path);
                     }
-                    return Err(format!(
-                        "Timed out waiting for vm manager socket: {} ({})",
-                        socket_path.display(),
-                        err
-                    )
-                    .into());
+                    tracing::warn!(error = %err, "vm manager socket never accepted");
+                    return Err(VibeboxError::ManagerStartTimeout { path: socket_path }.into());
                 }
                 thread::sleep(Duration::from_millis(100));
             }
@@ -111,48 +138,133 @@ pub fn ensure_manager(
     }
 }
 
+/// Connects to an already running vm manager without spawning one. The VM stays up at least
+/// as long as the returned stream is open.
+pub fn connect_manager(instance_dir: &Path) -> Result<UnixStream, Box<dyn std::error::Error>> {
+    let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
+    let stream = UnixStream::connect(&socket_path).map_err(|err| {
+        tracing::debug!(error = %err, "vm manager socket refused");
+        VibeboxError::ManagerNotRunning { path: socket_path }
+    })?;
+    send_client_pid(&stream);
+    Ok(stream)
+}
+
+/// Asks a running vm manager to power its VM off now, even with clients attached.
+pub fn request_stop(instance_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).map_err(|err| {
+        tracing::debug!(error = %err, "vm manager socket refused");
+        VibeboxError::ManagerNotRunning { path: socket_path }
+    })?;
+    stream.write_all(STOP_REQUEST.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
 pub fn run_manager(
+    project_root: &Path,
     args: vm::VmArg,
     auto_shutdown_ms: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let project_root = env::current_dir()?;
     tracing::info!(root = %project_root.display(), "vm manager starting");
-    let _pid_guard = ensure_pid_file(&project_root)?;
-    #[cfg(feature = "mock-vm")]
-    tracing::info!("vm manager using mock executor");
-    let executor: &dyn VmExecutor = {
-        #[cfg(feature = "mock-vm")]
-        {
-            &MockVmExecutor
-        }
-        #[cfg(not(feature = "mock-vm"))]
-        {
-            &RealVmExecutor
-        }
+    let (backend, options) = default_backend()?;
+    run_manager_in(project_root, args, auto_shutdown_ms, backend, options)
+}
+
+/// Like [`run_manager`], but boots the guest with `backend`, in this process and without
+/// detaching it from its terminal.
+pub fn run_manager_with_backend(
+    project_root: &Path,
+    args: vm::VmArg,
+    auto_shutdown_ms: u64,
+    backend: &dyn VmBackend,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!(root = %project_root.display(), "vm manager starting");
+    let options = ManagerOptions {
+        ensure_signed: false,
+        detach: false,
+        prepare_vm: true,
     };
-    let options = {
-        #[cfg(feature = "mock-vm")]
-        {
-            ManagerOptions {
-                ensure_signed: false,
-                detach: true,
-                prepare_vm: false,
-            }
-        }
-        #[cfg(not(feature = "mock-vm"))]
-        {
-            ManagerOptions {
-                ensure_signed: true,
-                detach: true,
-                prepare_vm: true,
-            }
+    run_manager_in(project_root, args, auto_shutdown_ms, backend, options)
+}
+
+/// Where a manager started in `project_root` keeps its state, and whether that is an ephemeral
+/// directory it should delete on exit.
+pub fn manager_instance_dir(project_root: &Path) -> (PathBuf, bool) {
+    if let Some(dir) = env::var_os(EPHEMERAL_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return (PathBuf::from(dir), true);
+    }
+    let name = env::var(SESSION_NAME_ENV)
+        .ok()
+        .filter(|name| session_manager::validate_session_name(name).is_ok());
+    (
+        session_manager::session_instance_dir(project_root, name.as_deref()),
+        false,
+    )
+}
+
+fn run_manager_in(
+    project_root: &Path,
+    args: vm::VmArg,
+    auto_shutdown_ms: u64,
+    backend: &dyn VmBackend,
+    options: ManagerOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (instance_dir, ephemeral) = manager_instance_dir(project_root);
+    let pid_guard = ensure_pid_file(&instance_dir)?;
+    // Nobody can reconnect to an ephemeral VM later, so it goes as soon as the last client does.
+    let auto_shutdown_ms = if ephemeral { 0 } else { auto_shutdown_ms };
+    let result = run_manager_with(
+        project_root,
+        &instance_dir,
+        args,
+        auto_shutdown_ms,
+        backend,
+        options,
+    );
+    drop(pid_guard);
+    if ephemeral {
+        tracing::info!(path = %instance_dir.display(), "removing ephemeral instance");
+        if let Err(err) = fs::remove_dir_all(&instance_dir) {
+            tracing::warn!(path = %instance_dir.display(), error = %err, "failed to remove ephemeral instance");
         }
+    }
+    result
+}
+
+#[cfg(feature = "mock-vm")]
+fn default_backend() -> Result<(&'static dyn VmBackend, ManagerOptions), Box<dyn std::error::Error>>
+{
+    tracing::info!("vm manager using mock backend");
+    let options = ManagerOptions {
+        ensure_signed: false,
+        detach: true,
+        prepare_vm: false,
     };
-    run_manager_with(&project_root, args, auto_shutdown_ms, executor, options)
+    Ok((&MockVmBackend, options))
 }
 
+#[cfg(all(not(feature = "mock-vm"), target_os = "macos"))]
+fn default_backend() -> Result<(&'static dyn VmBackend, ManagerOptions), Box<dyn std::error::Error>>
+{
+    let options = ManagerOptions {
+        ensure_signed: true,
+        detach: true,
+        prepare_vm: true,
+    };
+    Ok((&AppleVmBackend, options))
+}
+
+#[cfg(all(not(feature = "mock-vm"), not(target_os = "macos")))]
+fn default_backend() -> Result<(&'static dyn VmBackend, ManagerOptions), Box<dyn std::error::Error>>
+{
+    Err(VibeboxError::UnsupportedPlatform.into())
+}
+
+/// The manager takes its project from the working directory it starts in.
 fn spawn_manager_process(
-    raw_args: &[std::ffi::OsString],
+    project_root: &Path,
     auto_shutdown_ms: u64,
     instance_dir: &Path,
     config_path: Option<&Path>,
@@ -168,9 +280,7 @@ fn spawn_manager_process(
         cmd.arg0("vibebox-supervisor");
         cmd
     };
-    if raw_args.len() > 1 {
-        cmd.args(&raw_args[1..]);
-    }
+    cmd.current_dir(project_root);
     cmd.env("VIBEBOX_INTERNAL", "1");
     if !use_supervisor {
         cmd.env("VIBEBOX_VM_MANAGER", "1");
@@ -180,12 +290,30 @@ fn spawn_manager_process(
     if let Some(path) = config_path {
         cmd.env(CONFIG_PATH_ENV, path);
     }
+    cmd.env_remove(EPHEMERAL_DIR_ENV)
+        .env_remove(SESSION_NAME_ENV);
+    let named_dir = project_root.join(NAMED_SESSIONS_DIR_NAME);
+    if instance_dir != project_root.join(INSTANCE_DIR_NAME) {
+        match instance_dir
+            .strip_prefix(&named_dir)
+            .ok()
+            .and_then(Path::to_str)
+        {
+            Some(name) if session_manager::validate_session_name(name).is_ok() => {
+                cmd.env(SESSION_NAME_ENV, name);
+            }
+            _ => {
+                cmd.env(EPHEMERAL_DIR_ENV, instance_dir);
+            }
+        }
+    }
     tracing::debug!(auto_shutdown_ms, "vm manager process spawn requested");
+    // Appended to, not rotated: a manager that finds the instance taken must not rotate the log
+    // of the one running. The manager that wins rotates it itself.
     let log_path = instance_dir.join(VM_MANAGER_LOG_NAME);
     let log_file = fs::OpenOptions::new()
         .create(true)
-        .write(true)
-        .truncate(true)
+        .append(true)
         .open(&log_path)
         .ok();
     if let Some(file) = log_file {
@@ -202,8 +330,8 @@ fn spawn_manager_process(
     Ok(())
 }
 
-fn ensure_pid_file(project_root: &Path) -> Result<PidFileGuard, Box<dyn std::error::Error>> {
-    let instance_dir = ensure_instance_dir(project_root)?;
+fn ensure_pid_file(instance_dir: &Path) -> Result<PidFileGuard, Box<dyn std::error::Error>> {
+    fs::create_dir_all(instance_dir)?;
     let pid_path = instance_dir.join(VM_MANAGER_PID_NAME);
     let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
     if let Ok(content) = fs::read_to_string(&pid_path)
@@ -211,7 +339,7 @@ fn ensure_pid_file(project_root: &Path) -> Result<PidFileGuard, Box<dyn std::err
         && pid_is_alive(pid)
     {
         if is_socket_path(&socket_path) {
-            return Err(format!("vm manager already running (pid {pid})").into());
+            return Err(VibeboxError::ManagerAlreadyRunning { pid }.into());
         }
         tracing::warn!(
             pid,
@@ -236,28 +364,35 @@ fn cleanup_stale_manager(instance_dir: &Path) {
     let _ = fs::remove_file(&pid_path);
 }
 
+/// Shares the project at `~/<name>`, unless a configured mount already claims that spot. In
+/// overlay mode the share is read-only and out of the way, and boot mounts the overlay at the
+/// usual place, so the returned home link stands in for the one the share would have had.
 fn inject_project_mount(
     mounts: &mut Vec<String>,
     project_root: &Path,
     ssh_user: &str,
     project_name: &str,
-) {
-    let guest_tilde = format!("~/{project_name}");
-    let guest_home = format!("/home/{ssh_user}/{project_name}");
-    let guest_base = format!("{PROJECT_GUEST_BASE}/{project_name}");
-    let already_mapped = mounts.iter().any(|spec| {
-        let parts: Vec<&str> = spec.split(':').collect();
-        if parts.len() < 2 {
-            return false;
-        }
-        let guest = parts[1];
-        guest == guest_tilde || guest == guest_home || guest == guest_base
-    });
-    if already_mapped {
-        return;
+    project_mode: ProjectMode,
+) -> Option<HomeLink> {
+    let project_mount = Path::new(PROJECT_GUEST_BASE).join(project_name);
+    if mount_plan::maps_mount_point(mounts, &project_mount, ssh_user) {
+        return None;
     }
     let host = project_root.display();
-    mounts.insert(0, format!("{host}:{guest_tilde}:read-write"));
+    match project_mode {
+        ProjectMode::Mount => {
+            mounts.insert(0, format!("{host}:~/{project_name}:read-write"));
+            None
+        }
+        ProjectMode::Overlay => {
+            let lower = overlay::lower_dir(project_name);
+            mounts.insert(0, format!("{host}:{}:read-only", lower.display()));
+            Some(HomeLink {
+                source: project_mount.display().to_string(),
+                target: format!("/home/{ssh_user}/{project_name}"),
+            })
+        }
+    }
 }
 
 fn is_socket_path(path: &Path) -> bool {
@@ -266,8 +401,11 @@ fn is_socket_path(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-fn prepare_mounts_and_links(mut args: vm::VmArg, ssh_user: &str) -> (vm::VmArg, String) {
-    let mut links = Vec::new();
+fn prepare_mounts_and_links(
+    mut args: vm::VmArg,
+    ssh_user: &str,
+    mut links: Vec<HomeLink>,
+) -> (vm::VmArg, String) {
     let mut mounts = Vec::with_capacity(args.mounts.len());
     for spec in args.mounts {
         let (rewritten, link) = rewrite_mount_spec(&spec, ssh_user);
@@ -292,50 +430,17 @@ fn rewrite_mount_spec(spec: &str, ssh_user: &str) -> (String, Option<HomeLink>)
         return (spec.to_string(), None);
     }
     let host = parts[0];
-    let guest = parts[1];
-    let mode = parts.get(2).copied();
-
-    let home_prefix = format!("/home/{ssh_user}");
-    let (rel, is_home) = if guest == "~" {
-        (String::new(), true)
-    } else if let Some(stripped) = guest.strip_prefix("~/") {
-        (stripped.to_string(), true)
-    } else if guest == home_prefix {
-        (String::new(), true)
-    } else if let Some(stripped) = guest.strip_prefix(&(home_prefix.clone() + "/")) {
-        (stripped.to_string(), true)
-    } else {
-        (String::new(), false)
-    };
-
-    if !is_home {
-        return (spec.to_string(), None);
-    }
-
-    let root_base = PROJECT_GUEST_BASE;
-    let root_path = if rel.is_empty() {
-        root_base.to_string()
-    } else {
-        format!("{root_base}/{rel}")
-    };
-    let target = if rel.is_empty() {
-        home_prefix
-    } else {
-        format!("{home_prefix}/{rel}")
-    };
-
-    let rewritten = match mode {
-        Some(mode) => format!("{host}:{root_path}:{mode}"),
-        None => format!("{host}:{root_path}"),
+    let resolved = mount_plan::resolve_guest_path(parts[1], ssh_user);
+    let mount_point = resolved.mount_point.display().to_string();
+    let rewritten = match parts.get(2) {
+        Some(mode) => format!("{host}:{mount_point}:{mode}"),
+        None => format!("{host}:{mount_point}"),
     };
-
-    (
-        rewritten,
-        Some(HomeLink {
-            source: root_path,
-            target,
-        }),
-    )
+    let link = resolved.home_link.map(|target| HomeLink {
+        source: mount_point,
+        target: target.display().to_string(),
+    });
+    (rewritten, link)
 }
 
 fn render_home_links_script(links: &[HomeLink], ssh_user: &str) -> String {
@@ -361,18 +466,13 @@ fn render_home_links_script(links: &[HomeLink], ssh_user: &str) -> String {
     ));
     lines.push("}".to_string());
     for link in links {
-        let src = shell_escape(&link.source);
-        let dest = shell_escape(&link.target);
+        let src = shell_quote(&link.source);
+        let dest = shell_quote(&link.target);
         lines.push(format!("link_home {src} {dest}"));
     }
     lines.join("\n")
 }
 
-fn shell_escape(value: &str) -> String {
-    let escaped = value.replace('\'', "'\"'\"'");
-    format!("'{escaped}'")
-}
-
 struct PidFileGuard {
     path: PathBuf,
 }
@@ -398,6 +498,27 @@ fn detach_from_terminal() {
     }
 }
 
+/// Rotates `path` and points `fd` at the fresh log, if `fd` is currently writing to `path`.
+fn rotate_log_onto(path: &Path, fd: RawFd) -> bool {
+    let Ok(current) = fs::metadata(path) else {
+        return false;
+    };
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0
+        || stat.st_dev as u64 != current.dev()
+        || stat.st_ino as u64 != current.ino()
+    {
+        return false;
+    }
+    match logs::open_rotated(path) {
+        Ok(file) => unsafe { libc::dup2(file.as_raw_fd(), fd) >= 0 },
+        Err(err) => {
+            tracing::warn!(path = %path.display(), error = %err, "failed to rotate manager log");
+            false
+        }
+    }
+}
+
 fn wait_for_disconnect(mut stream: UnixStream) {
     let mut buf = [0u8; 64];
     loop {
@@ -471,7 +592,26 @@ fn read_lock_pid(lock_path: &Path) -> Option<u32> {
     line.strip_prefix("pid=")?.trim().parse::<u32>().ok()
 }
 
-fn read_client_pid(stream: &UnixStream) -> Option<u32> {
+/// The first line a connection sends: `pid=<n>` from a client, or `stop` from [`request_stop`].
+enum ClientHello {
+    Client(Option<u32>),
+    Stop,
+}
+
+fn read_client_hello(stream: &UnixStream) -> ClientHello {
+    let Some(line) = read_client_line(stream) else {
+        return ClientHello::Client(None);
+    };
+    if line == STOP_REQUEST.trim() {
+        return ClientHello::Stop;
+    }
+    ClientHello::Client(
+        line.strip_prefix("pid=")
+            .and_then(|value| value.parse::<u32>().ok()),
+    )
+}
+
+fn read_client_line(stream: &UnixStream) -> Option<String> {
     let mut stream = stream.try_clone().ok()?;
     let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
     let mut buf = [0u8; 64];
@@ -495,124 +635,223 @@ fn read_client_pid(stream: &UnixStream) -> Option<u32> {
         return None;
     }
     let line = String::from_utf8_lossy(&buf[..len]);
-    let trimmed = line.trim();
-    if let Some(value) = trimmed.strip_prefix("pid=") {
-        value.parse::<u32>().ok()
-    } else {
-        None
+    Some(line.trim().to_string())
+}
+
+/// Published for `vibebox top`, which cannot ask the manager without becoming a client itself.
+fn write_client_count(path: &Path, count: usize) {
+    if let Err(err) = fs::write(path, format!("{count}\n")) {
+        tracing::debug!(path = %path.display(), error = %err, "failed to write client count");
     }
 }
 
-#[cfg_attr(feature = "mock-vm", allow(dead_code))]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 fn spawn_manager_io(
-    config: Arc<Mutex<InstanceConfig>>,
     instance_dir: PathBuf,
     output_monitor: Arc<vm::OutputMonitor>,
     vm_output_fd: std::os::unix::io::OwnedFd,
     vm_input_fd: std::os::unix::io::OwnedFd,
+    console: Arc<ConsoleHub>,
 ) -> vm::IoContext {
     let log_path = instance_dir.join(VM_ROOT_LOG_NAME);
-    let log_file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&log_path)
+    let log_file = logs::open_rotated(&log_path)
         .ok()
         .map(|file| Arc::new(Mutex::new(file)));
 
-    let instance_path = instance_dir.join(INSTANCE_FILENAME);
-    let config_for_output = config.clone();
-    let log_for_output = log_file.clone();
-    let mut line_buf = String::new();
-
     let on_output = move |bytes: &[u8]| {
-        if let Some(log) = &log_for_output
+        if let Some(log) = &log_file
             && let Ok(mut file) = log.lock()
         {
             let _ = file.write_all(bytes);
         }
-
-        let text = String::from_utf8_lossy(bytes);
-        line_buf.push_str(&text);
-
-        while let Some(pos) = line_buf.find('\n') {
-            let mut line = line_buf[..pos].to_string();
-            if line.ends_with('\r') {
-                line.pop();
-            }
-            line_buf.drain(..=pos);
-
-            let cleaned = line.trim_start_matches(['\r', ' ']);
-            if let Some(pos) = cleaned.find("VIBEBOX_IPV4=") {
-                let ip_raw = &cleaned[(pos + "VIBEBOX_IPV4=".len())..];
-                let ip = extract_ipv4(ip_raw).unwrap_or_default();
-                if !ip.is_empty()
-                    && let Ok(mut cfg) = config_for_output.lock()
-                    && cfg.vm_ipv4.as_deref() != Some(ip.as_str())
-                {
-                    cfg.vm_ipv4 = Some(ip.clone());
-                    let _ = write_instance_config(&instance_path, &cfg);
-                }
-            }
-        }
+        console.forward_output(bytes);
     };
 
     vm::spawn_vm_io_with_hooks(
         output_monitor,
         vm_output_fd,
         vm_input_fd,
-        vm::IoControl::new(),
+        manager_io_control(),
         |_| false,
         on_output,
     )
 }
 
+/// The manager is detached from any terminal; console output only goes to the log.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn manager_io_control() -> Arc<vm::IoControl> {
+    let io_control = vm::IoControl::new();
+    io_control.set_forward_input(false);
+    io_control.set_forward_output(false);
+    io_control
+}
+
 enum ManagerEvent {
     Inc(Option<u32>),
     Dec(Option<u32>),
+    Stop,
+    /// A pinned VM stays up without clients until it is unpinned or stopped.
+    Pin(bool),
     VmExited(Option<String>),
 }
 
+/// What the manager shares with guest requests arriving on the control share.
+struct ControlContext {
+    project_root: PathBuf,
+    instance_dir: PathBuf,
+    started: Instant,
+    clients: Arc<AtomicUsize>,
+    pinned: Arc<AtomicBool>,
+    config: Arc<Mutex<InstanceConfig>>,
+    auto_shutdown_ms: u64,
+    event_tx: mpsc::Sender<ManagerEvent>,
+}
+
+impl ControlContext {
+    fn into_handler(self) -> impl Fn(ControlRequest) -> Result<String, String> + Send + 'static {
+        move |request| self.handle(request)
+    }
+
+    fn handle(&self, request: ControlRequest) -> Result<String, String> {
+        match request {
+            ControlRequest::Status => Ok(self.status()),
+            ControlRequest::Explain => self.explain(),
+            ControlRequest::Pin => {
+                self.pinned.store(true, Ordering::SeqCst);
+                self.send(ManagerEvent::Pin(true))?;
+                Ok("Pinned: the VM keeps running without clients until :unpin or :stop.\n".into())
+            }
+            ControlRequest::Unpin => {
+                self.pinned.store(false, Ordering::SeqCst);
+                self.send(ManagerEvent::Pin(false))?;
+                Ok(format!(
+                    "Unpinned: the VM stops {} ms after the last client disconnects.\n",
+                    self.auto_shutdown_ms
+                ))
+            }
+            ControlRequest::Stop => {
+                self.send(ManagerEvent::Stop)?;
+                Ok("Stopping the VM.\n".into())
+            }
+        }
+    }
+
+    fn send(&self, event: ManagerEvent) -> Result<(), String> {
+        self.event_tx
+            .send(event)
+            .map_err(|_| "the vm manager is shutting down".to_string())
+    }
+
+    fn status(&self) -> String {
+        let (vm_ip, system) = match self.config.lock() {
+            Ok(cfg) => (
+                cfg.vm_ipv4.clone(),
+                cfg.guest.as_ref().map(|guest| guest.system_label()),
+            ),
+            Err(_) => (None, None),
+        };
+        let uptime = self.started.elapsed().as_secs();
+        let lines = [
+            ("Project", self.project_root.display().to_string()),
+            (
+                "Uptime",
+                format!("{}h {}m {}s", uptime / 3600, uptime / 60 % 60, uptime % 60),
+            ),
+            ("Clients", self.clients.load(Ordering::SeqCst).to_string()),
+            (
+                "Pinned",
+                if self.pinned.load(Ordering::SeqCst) {
+                    "yes"
+                } else {
+                    "no"
+                }
+                .to_string(),
+            ),
+            (
+                "Auto-shutdown",
+                format!(
+                    "{} ms after the last client disconnects",
+                    self.auto_shutdown_ms
+                ),
+            ),
+            ("VM IP", vm_ip.unwrap_or_else(|| "-".into())),
+            ("System", system.unwrap_or_else(|| "-".into())),
+        ];
+        lines
+            .iter()
+            .map(|(label, value)| format!("{label}: {value}\n"))
+            .collect()
+    }
+
+    /// Read fresh from the host, so it reflects `vibebox.toml` as it is now.
+    fn explain(&self) -> Result<String, String> {
+        let config_path = config::resolve_config_path(&self.project_root, None);
+        let config = config::peek_config_at(&config_path)
+            .ok_or_else(|| format!("cannot read {} on the host", config_path.display()))?;
+        explain::build_explanation(
+            &self.project_root,
+            &self.instance_dir,
+            &config,
+            &config_path,
+        )
+        .map(|explanation| explanation.plain_text())
+        .map_err(|err| err.to_string())
+    }
+}
+
 struct ManagerOptions {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     ensure_signed: bool,
     detach: bool,
     prepare_vm: bool,
 }
 
-trait VmExecutor {
+/// Boots a guest and drives its serial console for the vm manager.
+///
+/// `run_vm` blocks until the guest stops. Once the console accepts input it must publish the
+/// sender in `vm_input_tx`; the manager types `systemctl poweroff` into it when the last client
+/// disconnects. Console output must also be copied to `console` for `vibebox console`.
+pub trait VmBackend {
+    #[allow(clippy::too_many_arguments)]
     fn run_vm(
         &self,
         args: vm::VmArg,
         extra_login_actions: Vec<LoginAction>,
         extra_shares: Vec<DirectoryShare>,
-        config: Arc<Mutex<InstanceConfig>>,
+        project_root: &Path,
         instance_dir: PathBuf,
         vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+        console: Arc<ConsoleHub>,
     ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
+#[cfg(target_os = "macos")]
 #[cfg_attr(feature = "mock-vm", allow(dead_code))]
-struct RealVmExecutor;
+struct AppleVmBackend;
 
-impl VmExecutor for RealVmExecutor {
+#[cfg(target_os = "macos")]
+impl VmBackend for AppleVmBackend {
     fn run_vm(
         &self,
         args: vm::VmArg,
         extra_login_actions: Vec<LoginAction>,
         extra_shares: Vec<DirectoryShare>,
-        config: Arc<Mutex<InstanceConfig>>,
+        project_root: &Path,
         instance_dir: PathBuf,
         vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+        console: Arc<ConsoleHub>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        vm::run_with_args_and_extras(
+        virtualization::run_with_args_and_extras(
+            project_root,
+            &instance_dir,
             args,
             |output_monitor, vm_output_fd, vm_input_fd| {
                 let io_ctx = spawn_manager_io(
-                    config.clone(),
                     instance_dir.clone(),
                     output_monitor,
                     vm_output_fd,
                     vm_input_fd,
+                    console.clone(),
                 );
                 *vm_input_tx.lock().unwrap() = Some(io_ctx.input_tx.clone());
                 io_ctx
@@ -623,52 +862,96 @@ impl VmExecutor for RealVmExecutor {
     }
 }
 
-#[cfg(feature = "mock-vm")]
-struct MockVmExecutor;
+#[cfg(any(test, feature = "mock-vm"))]
+struct MockVmBackend;
 
-#[cfg(feature = "mock-vm")]
-impl VmExecutor for MockVmExecutor {
+#[cfg(any(test, feature = "mock-vm"))]
+impl VmBackend for MockVmBackend {
     fn run_vm(
         &self,
-        _args: vm::VmArg,
-        _extra_login_actions: Vec<LoginAction>,
-        _extra_shares: Vec<DirectoryShare>,
-        _config: Arc<Mutex<InstanceConfig>>,
-        _instance_dir: PathBuf,
+        args: vm::VmArg,
+        extra_login_actions: Vec<LoginAction>,
+        mut extra_shares: Vec<DirectoryShare>,
+        _project_root: &Path,
+        instance_dir: PathBuf,
         vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+        console: Arc<ConsoleHub>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let (tx, rx) = mpsc::channel::<VmInput>();
-        *vm_input_tx.lock().unwrap() = Some(tx);
-        tracing::info!("mock vm executor running");
-        while let Ok(input) = rx.recv() {
-            match input {
-                VmInput::Shutdown => break,
-                VmInput::Bytes(bytes) => {
-                    let text = String::from_utf8_lossy(&bytes);
-                    if text.contains("systemctl poweroff") {
-                        break;
-                    }
-                }
-            }
+        for spec in &args.mounts {
+            extra_shares.push(DirectoryShare::from_mount_spec(spec)?);
         }
-        tracing::info!("mock vm executor exiting");
-        Ok(())
+        let extra_shares = mount_plan::plan_shares(extra_shares)?;
+        let login_actions = boot::boot_login_actions(&extra_shares, &extra_login_actions);
+        tracing::info!("mock vm backend running");
+        let result = run_scripted_guest(
+            FakeGuest::from_env(),
+            login_actions,
+            instance_dir,
+            vm_input_tx,
+            console,
+        );
+        tracing::info!("mock vm backend exiting");
+        result
     }
 }
 
+/// Boots a `FakeGuest` over the same pipes and IO threads the real VM console uses.
+#[cfg(any(test, feature = "mock-vm"))]
+fn run_scripted_guest(
+    guest: FakeGuest,
+    login_actions: Vec<LoginAction>,
+    instance_dir: PathBuf,
+    vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+    console: Arc<ConsoleHub>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (guest_reads_from, we_write_to) = vm::create_pipe();
+    let (we_read_from, guest_writes_to) = vm::create_pipe();
+    let guest_handle = guest.spawn(guest_reads_from, guest_writes_to);
+
+    let output_monitor = Arc::new(vm::OutputMonitor::default());
+    let io_ctx = spawn_manager_io(
+        instance_dir,
+        output_monitor.clone(),
+        we_read_from,
+        we_write_to,
+        console,
+    );
+    *vm_input_tx.lock().unwrap() = Some(io_ctx.input_tx.clone());
+
+    let login_result =
+        login_script::run_login_actions(&login_actions, &output_monitor, &io_ctx.input_tx);
+    let result = match login_result {
+        Ok(_) => {
+            let _ = guest_handle.join();
+            Ok(())
+        }
+        // The guest powered off before boot finished, e.g. a shutdown requested mid-boot.
+        Err(LoginError::ConsoleClosed) => {
+            let _ = guest_handle.join();
+            Ok(())
+        }
+        Err(err) => Err(format!("{err}; shutting down.").into()),
+    };
+    *vm_input_tx.lock().unwrap() = None;
+    io_ctx.shutdown();
+    result
+}
+
 fn run_manager_with(
     project_root: &Path,
+    instance_dir: &Path,
     mut args: vm::VmArg,
     auto_shutdown_ms: u64,
-    executor: &dyn VmExecutor,
+    backend: &dyn VmBackend,
     options: ManagerOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(target_os = "macos")]
     if options.ensure_signed {
         let _had_skip = env::var("VIBEBOX_SKIP_CODESIGN").ok();
         unsafe {
             env::remove_var("VIBEBOX_SKIP_CODESIGN");
         }
-        vm::ensure_signed();
+        virtualization::ensure_signed()?;
         unsafe {
             env::set_var("VIBEBOX_SKIP_CODESIGN", "1");
         }
@@ -682,41 +965,101 @@ fn run_manager_with(
         .ok_or("Project directory has no name")?
         .to_string_lossy()
         .into_owned();
-    let instance_dir = ensure_instance_dir(project_root)?;
+    let instance_dir = instance_dir.to_path_buf();
     if options.prepare_vm {
         let _ = ensure_ssh_keypair(&instance_dir)?;
     }
 
     let mut config = load_or_create_instance_config(&instance_dir)?;
-    if config.vm_ipv4.is_some() {
+    if config.vm_ipv4.is_some() || config.guest.is_some() {
         config.vm_ipv4 = None;
+        config.guest = None;
         write_instance_config(&instance_dir.join(INSTANCE_FILENAME), &config)?;
     }
+    let hooks = Hooks::new(
+        hooks::allowed_or_none(project_root, args.hooks.clone()),
+        project_root,
+        &instance_dir,
+        config.session_id(),
+    );
     let config = Arc::new(Mutex::new(config));
     let ssh_user = config
         .lock()
         .map(|cfg| cfg.ssh_user_display())
         .unwrap_or_else(|_| DEFAULT_SSH_USER.to_string());
+    let security = args.security;
+    args.mounts = args
+        .mounts
+        .iter()
+        .map(|spec| security.mount_spec(spec))
+        .collect();
+    let mut project_link = Vec::new();
     if !args.no_default_mounts {
-        inject_project_mount(&mut args.mounts, project_root, &ssh_user, &project_name);
+        if let Some(found) = mount_guard::check_host_path(project_root)
+            && !args.allow_sensitive
+        {
+            return Err(format!(
+                "refusing to mount project {}: it {found}. Set [box].allow_sensitive = true to mount it anyway",
+                project_root.display()
+            )
+            .into());
+        }
+        project_link.extend(inject_project_mount(
+            &mut args.mounts,
+            project_root,
+            &ssh_user,
+            &project_name,
+            args.project_mode,
+        ));
     }
-    let (args, home_links_script) = prepare_mounts_and_links(args, &ssh_user);
+    tracing::info!(
+        profile = security.as_str(),
+        project_mode = args.project_mode.as_str(),
+        "security profile"
+    );
+    let (args, home_links_script) = prepare_mounts_and_links(args, &ssh_user, project_link);
 
     let project_guest_dir = format!("{PROJECT_GUEST_BASE}/{project_name}");
     let ssh_guest_dir = format!("/root/{}", GLOBAL_DIR_NAME);
-    let extra_shares = vec![DirectoryShare::new(
+    let mut extra_shares = vec![DirectoryShare::new(
         instance_dir.clone(),
         ssh_guest_dir.clone().into(),
         true,
     )?];
-    let extra_login_actions = build_ssh_login_actions(
+    let agent_ready = Arc::new(AtomicBool::new(false));
+    let console_hub = Arc::new(ConsoleHub::new(agent_ready.clone()));
+    let mut extra_login_actions = boot::agent_login_actions(agent_ready.clone())?;
+    // Before the address is recorded, so a client that sees it also sees this boot's guest info.
+    extra_login_actions.push(guest_info::login_action(&config, &instance_dir));
+    extra_login_actions.extend(build_ssh_login_actions(
         &config,
+        &instance_dir,
         &project_name,
         &project_guest_dir,
         ssh_guest_dir.as_str(),
-        "ssh_key",
         &home_links_script,
-    );
+        security,
+    ));
+    extra_login_actions.push(commands::login_action(commands::render_shell_script(
+        &args.commands,
+        &project_guest_dir,
+    )));
+    extra_login_actions.push(control::login_action());
+    {
+        let hooks = hooks.clone();
+        let config = config.clone();
+        extra_login_actions.push(LoginAction::Hook(Arc::new(move |_vars| {
+            let mut env = Vec::new();
+            if let Ok(cfg) = config.lock() {
+                env.push(("VIBEBOX_SSH_USER", cfg.ssh_user_display()));
+                if let Some(ip) = &cfg.vm_ipv4 {
+                    env.push(("VIBEBOX_VM_IP", ip.clone()));
+                }
+            }
+            hooks.spawn(HookEvent::PostBoot, env);
+        })));
+    }
+    extra_login_actions.push(console::boot_finished_action(console_hub.clone()));
 
     let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
     if let Ok(stream) = UnixStream::connect(&socket_path) {
@@ -726,22 +1069,68 @@ fn run_manager_with(
     if socket_path.exists() {
         let _ = fs::remove_file(&socket_path);
     }
+    // Only now that no other manager owns this instance is it safe to clear the control share
+    // and start this boot's log.
+    rotate_log_onto(
+        &instance_dir.join(VM_MANAGER_LOG_NAME),
+        std::io::stderr().as_raw_fd(),
+    );
+    let control_dir = control::prepare_dir(&instance_dir)?;
+    extra_shares.push(DirectoryShare::new(
+        control_dir.clone(),
+        CONTROL_GUEST_DIR.into(),
+        false,
+    )?);
 
     let listener = UnixListener::bind(&socket_path)?;
     let _ = fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600));
     tracing::info!(path = %socket_path.display(), "vm manager socket bound");
+    let console_socket_path = instance_dir.join(VM_CONSOLE_SOCKET_NAME);
+    let _ = fs::remove_file(&console_socket_path);
+    let console_listener = UnixListener::bind(&console_socket_path)?;
+    let _ = fs::set_permissions(&console_socket_path, fs::Permissions::from_mode(0o600));
 
     let (event_tx, event_rx) = mpsc::channel::<ManagerEvent>();
     let event_tx_accept = event_tx.clone();
+    let clients_path = instance_dir.join(VM_MANAGER_CLIENTS_NAME);
+    write_client_count(&clients_path, 0);
+    let clients = Arc::new(AtomicUsize::new(0));
+    let _control_server = control::serve(
+        control_dir,
+        ControlContext {
+            project_root: project_root.to_path_buf(),
+            instance_dir: instance_dir.clone(),
+            started: Instant::now(),
+            clients: clients.clone(),
+            pinned: Arc::new(AtomicBool::new(false)),
+            config: config.clone(),
+            auto_shutdown_ms,
+            event_tx: event_tx.clone(),
+        }
+        .into_handler(),
+    );
+    let clients_path_accept = clients_path.clone();
     thread::spawn(move || {
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
                     let event_tx_conn = event_tx_accept.clone();
+                    let clients = clients.clone();
+                    let clients_path = clients_path_accept.clone();
                     thread::spawn(move || {
-                        let pid = read_client_pid(&stream);
+                        let pid = match read_client_hello(&stream) {
+                            ClientHello::Stop => {
+                                let _ = event_tx_conn.send(ManagerEvent::Stop);
+                                return;
+                            }
+                            ClientHello::Client(pid) => pid,
+                        };
+                        let count = clients.fetch_add(1, Ordering::SeqCst) + 1;
+                        write_client_count(&clients_path, count);
                         let _ = event_tx_conn.send(ManagerEvent::Inc(pid));
                         wait_for_disconnect(stream);
+                        let count = clients.fetch_sub(1, Ordering::SeqCst) - 1;
+                        write_client_count(&clients_path, count);
                         let _ = event_tx_conn.send(ManagerEvent::Dec(pid));
                     });
                 }
@@ -751,31 +1140,54 @@ fn run_manager_with(
     });
 
     let vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>> = Arc::new(Mutex::new(None));
+    {
+        let console_hub = console_hub.clone();
+        let vm_input_tx = vm_input_tx.clone();
+        thread::spawn(move || console::serve(console_listener, console_hub, vm_input_tx));
+    }
     let vm_input_for_loop = vm_input_tx.clone();
-    let event_loop_handle =
-        thread::spawn(move || manager_event_loop(event_rx, vm_input_for_loop, auto_shutdown_ms));
+    let agent_ready_for_loop = agent_ready.clone();
+    let hooks_for_loop = hooks.clone();
+    let event_loop_handle = thread::spawn(move || {
+        manager_event_loop(
+            event_rx,
+            vm_input_for_loop,
+            agent_ready_for_loop,
+            auto_shutdown_ms,
+            &hooks_for_loop,
+        )
+    });
 
+    hooks.run(HookEvent::PreStart, &[]);
     tracing::info!("vm manager launching vm");
-    let vm_result = executor.run_vm(
+    let vm_result = backend.run_vm(
         args,
         extra_login_actions,
         extra_shares,
-        config.clone(),
+        project_root,
         instance_dir.clone(),
         vm_input_tx.clone(),
+        console_hub,
     );
     tracing::info!("vm manager vm run completed");
-    let vm_err = vm_result.err().map(|e| e.to_string());
-    if let Some(err) = &vm_err {
+    if let Err(err) = &vm_result {
         let status_path = instance_dir.join(STATUS_FILE_NAME);
-        let _ = fs::write(&status_path, format!("error: {err}"));
+        let _ = fs::write(&status_path, VibeboxError::status_line(err.as_ref()));
     }
+    let vm_err = vm_result.err().map(|e| e.to_string());
     let _ = event_tx.send(ManagerEvent::VmExited(vm_err.clone()));
     let event_loop_result: Result<(), String> = event_loop_handle
         .join()
         .unwrap_or_else(|_| Err("vm manager event loop panicked".into()))
         .map_err(|err| err.to_string());
     let _ = fs::remove_file(&socket_path);
+    let _ = fs::remove_file(&console_socket_path);
+    let _ = fs::remove_file(&clients_path);
+    let mut stop_env = Vec::new();
+    if let Some(err) = &vm_err {
+        stop_env.push(("VIBEBOX_VM_ERROR", err.clone()));
+    }
+    hooks.run(HookEvent::PostStop, &stop_env);
     if let Err(err) = &event_loop_result {
         tracing::error!(error = %err, "vm manager exiting due to event loop error");
         return Err(err.to_string().into());
@@ -788,12 +1200,23 @@ fn run_manager_with(
     Ok(event_loop_result?)
 }
 
+fn client_env(ref_count: usize, pid: Option<u32>) -> Vec<(&'static str, String)> {
+    let mut env = vec![("VIBEBOX_CLIENTS", ref_count.to_string())];
+    if let Some(pid) = pid {
+        env.push(("VIBEBOX_CLIENT_PID", pid.to_string()));
+    }
+    env
+}
+
 fn manager_event_loop(
     event_rx: mpsc::Receiver<ManagerEvent>,
     vm_input_tx: Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+    agent_ready: Arc<AtomicBool>,
     auto_shutdown_ms: u64,
+    hooks: &Hooks,
 ) -> Result<(), String> {
     let mut ref_count: usize = 0;
+    let mut pinned = false;
     let mut shutdown_deadline: Option<Instant> = None;
     let mut shutdown_sent = false;
     let mut hard_deadline: Option<Instant> = None;
@@ -820,6 +1243,7 @@ fn manager_event_loop(
                     pid_known = pid.is_some(),
                     "vm manager refcount increment"
                 );
+                hooks.spawn(HookEvent::OnAttach, client_env(ref_count, pid));
                 shutdown_deadline = None;
                 shutdown_sent = false;
                 hard_deadline = None;
@@ -832,11 +1256,33 @@ fn manager_event_loop(
                     pid_known = pid.is_some(),
                     "vm manager refcount decrement"
                 );
-                if ref_count == 0 {
+                hooks.spawn(HookEvent::OnDetach, client_env(ref_count, pid));
+                if ref_count == 0 && pinned {
+                    tracing::info!("vm pinned; shutdown not scheduled");
+                } else if ref_count == 0 {
+                    shutdown_deadline = Some(Instant::now() + grace);
+                    tracing::info!(grace_ms = auto_shutdown_ms, "shutdown scheduled");
+                }
+            }
+            Ok(ManagerEvent::Pin(pin)) => {
+                pinned = pin;
+                tracing::info!(pinned, ref_count, "vm pin changed");
+                if shutdown_sent {
+                    continue;
+                }
+                if pinned {
+                    shutdown_deadline = None;
+                    hard_deadline = None;
+                } else if ref_count == 0 && shutdown_deadline.is_none() {
                     shutdown_deadline = Some(Instant::now() + grace);
                     tracing::info!(grace_ms = auto_shutdown_ms, "shutdown scheduled");
                 }
             }
+            Ok(ManagerEvent::Stop) => {
+                tracing::info!(ref_count, "stop requested");
+                shutdown_deadline = Some(Instant::now());
+                shutdown_sent = false;
+            }
             Ok(ManagerEvent::VmExited(err)) => {
                 if let Some(err) = err {
                     tracing::error!(error = %err, "vm exited with an error");
@@ -853,10 +1299,12 @@ fn manager_event_loop(
                     }
                     let mut sent = false;
                     if let Some(tx) = vm_input_tx.lock().unwrap().clone() {
-                        if tx
-                            .send(VmInput::Bytes(b"systemctl poweroff\n".to_vec()))
-                            .is_ok()
-                        {
+                        let shutdown = if agent_ready.load(Ordering::SeqCst) {
+                            agent::shutdown_input()
+                        } else {
+                            VmInput::Bytes(b"systemctl poweroff\n".to_vec())
+                        };
+                        if tx.send(shutdown).is_ok() {
                             sent = true;
                         } else {
                             tracing::warn!("shutdown command failed to send");
@@ -902,8 +1350,433 @@ fn manager_event_loop(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        config::SecurityProfile,
+        fake_guest::{FAKE_GUEST_HOST_KEY, FAKE_GUEST_IPV4, FAKE_GUEST_OS, GuestBehavior},
+        instance::{KNOWN_HOSTS_NAME, read_instance_guest_info, read_instance_vm_ip},
+        login_script::Expectation,
+    };
     use std::{sync::mpsc, thread, time::Duration};
 
+    fn wait_for_vm_input(
+        vm_input_tx: &Arc<Mutex<Option<mpsc::Sender<VmInput>>>>,
+    ) -> mpsc::Sender<VmInput> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(tx) = vm_input_tx.lock().unwrap().clone() {
+                return tx;
+            }
+            assert!(Instant::now() < deadline, "vm input never became ready");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn manager_io_keeps_the_console_off_its_own_stdio() {
+        let io_control = manager_io_control();
+        assert!(!io_control.forward_input());
+        assert!(!io_control.forward_output());
+    }
+
+    #[test]
+    fn scripted_guest_agent_records_ipv4_and_powers_off() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let instance_dir = temp.path().to_path_buf();
+        let config = Arc::new(Mutex::new(
+            load_or_create_instance_config(&instance_dir).expect("instance config"),
+        ));
+        let agent_ready = Arc::new(AtomicBool::new(false));
+        let mut extra_actions = boot::agent_login_actions(agent_ready.clone()).unwrap();
+        extra_actions.push(guest_info::login_action(&config, &instance_dir));
+        extra_actions.extend(build_ssh_login_actions(
+            &config,
+            &instance_dir,
+            "project",
+            "/usr/local/vibebox-mounts/project",
+            "/root/.vibebox",
+            "",
+            SecurityProfile::Standard,
+        ));
+        let login_actions = boot::boot_login_actions(&[], &extra_actions);
+        let vm_input_tx = Arc::new(Mutex::new(None));
+        let vm_input_for_guest = vm_input_tx.clone();
+        let dir_for_guest = instance_dir.clone();
+
+        let guest_thread = thread::spawn(move || {
+            run_scripted_guest(
+                FakeGuest::default(),
+                login_actions,
+                dir_for_guest,
+                vm_input_for_guest,
+                Arc::default(),
+            )
+            .map_err(|err| err.to_string())
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while read_instance_vm_ip(&instance_dir).unwrap().is_none() {
+            assert!(Instant::now() < deadline, "vm ipv4 was never recorded");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(
+            read_instance_vm_ip(&instance_dir).unwrap().as_deref(),
+            Some(FAKE_GUEST_IPV4)
+        );
+        let guest = read_instance_guest_info(&instance_dir).unwrap().unwrap();
+        assert_eq!(guest.os, FAKE_GUEST_OS);
+        assert_eq!(guest.memory_mb, 2048);
+
+        assert!(agent_ready.load(Ordering::SeqCst));
+
+        wait_for_vm_input(&vm_input_tx)
+            .send(agent::shutdown_input())
+            .unwrap();
+        guest_thread.join().unwrap().expect("scripted boot");
+
+        let console_log = fs::read_to_string(instance_dir.join(VM_ROOT_LOG_NAME)).unwrap();
+        assert!(console_log.contains("vibebox login: "), "{console_log}");
+        let setup_log = fs::read_to_string(instance_dir.join("ssh_setup.log")).unwrap();
+        assert!(setup_log.contains("VIBEBOX_SSH_READY"), "{setup_log}");
+        let known_hosts = fs::read_to_string(instance_dir.join(KNOWN_HOSTS_NAME)).unwrap();
+        assert_eq!(known_hosts, format!("vibebox {FAKE_GUEST_HOST_KEY}\n"));
+        let config = load_or_create_instance_config(&instance_dir).unwrap();
+        assert_eq!(config.ssh_host_key.as_deref(), Some(FAKE_GUEST_HOST_KEY));
+    }
+
+    #[test]
+    fn scripted_guest_refuses_changed_host_key() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let instance_dir = temp.path().to_path_buf();
+        let mut config = load_or_create_instance_config(&instance_dir).expect("instance config");
+        config.ssh_host_key = Some("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAISomeoneElse".into());
+        let config = Arc::new(Mutex::new(config));
+        let mut extra_actions = boot::agent_login_actions(Arc::default()).unwrap();
+        extra_actions.extend(build_ssh_login_actions(
+            &config,
+            &instance_dir,
+            "project",
+            "/project",
+            "/root/.vibebox",
+            "",
+            SecurityProfile::Standard,
+        ));
+
+        let err = run_scripted_guest(
+            FakeGuest::default(),
+            boot::boot_login_actions(&[], &extra_actions),
+            instance_dir.clone(),
+            Arc::new(Mutex::new(None)),
+            Arc::default(),
+        )
+        .unwrap_err();
+
+        assert!(
+            err.to_string().contains("guest ssh host key changed"),
+            "unexpected error: {err}"
+        );
+        assert_eq!(read_instance_vm_ip(&instance_dir).unwrap(), None);
+        assert!(!instance_dir.join(KNOWN_HOSTS_NAME).exists());
+    }
+
+    fn read_console_until(stream: &mut UnixStream, needle: &str) -> String {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut seen = Vec::new();
+        let mut buf = [0u8; 256];
+        while !String::from_utf8_lossy(&seen).contains(needle) {
+            let n = stream.read(&mut buf).expect("console output");
+            assert!(n > 0, "console closed before {needle:?}");
+            seen.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&seen).into_owned()
+    }
+
+    /// A login action that blocks until the test sets `flag`.
+    fn wait_for_flag(flag: Arc<AtomicBool>) -> LoginAction {
+        LoginAction::Hook(Arc::new(move |_| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !flag.load(Ordering::SeqCst) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(20));
+            }
+        }))
+    }
+
+    fn wait_until(what: &str, done: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "{what}");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn console_client_gets_rescue_shell_from_agent() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let instance_dir = temp.path().to_path_buf();
+        let agent_ready = Arc::new(AtomicBool::new(false));
+        let hub = Arc::new(ConsoleHub::new(agent_ready.clone()));
+        let (boot_may_finish, booted, detached) = (
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+        let exec_code = Arc::new(Mutex::new(None));
+        let mut actions = boot::agent_login_actions(agent_ready.clone()).unwrap();
+        actions.push(wait_for_flag(boot_may_finish.clone()));
+        actions.push(console::boot_finished_action(hub.clone()));
+        {
+            let booted = booted.clone();
+            actions.push(LoginAction::Hook(Arc::new(move |_| {
+                booted.store(true, Ordering::SeqCst)
+            })));
+        }
+        // Stands in for agent requests made after boot, once the console was handed back.
+        actions.push(wait_for_flag(detached.clone()));
+        {
+            let exec_code = exec_code.clone();
+            actions.push(LoginAction::Agent(Arc::new(move |agent, _| {
+                let output = agent.exec("true", Duration::from_secs(5))?;
+                *exec_code.lock().unwrap() = Some(output.code);
+                Ok(())
+            })));
+        }
+        let login_actions = boot::boot_login_actions(&[], &actions);
+        let vm_input_tx = Arc::new(Mutex::new(None));
+        let socket_path = instance_dir.join(VM_CONSOLE_SOCKET_NAME);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        {
+            let (hub, vm_input_tx) = (hub.clone(), vm_input_tx.clone());
+            thread::spawn(move || console::serve(listener, hub, vm_input_tx));
+        }
+        let guest_thread = {
+            let (vm_input_tx, instance_dir) = (vm_input_tx.clone(), instance_dir.clone());
+            thread::spawn(move || {
+                run_scripted_guest(
+                    FakeGuest::default(),
+                    login_actions,
+                    instance_dir,
+                    vm_input_tx,
+                    hub,
+                )
+                .map_err(|err| err.to_string())
+            })
+        };
+
+        wait_until("agent never became ready", || {
+            agent_ready.load(Ordering::SeqCst)
+        });
+        let mut early = UnixStream::connect(&socket_path).unwrap();
+        read_console_until(&mut early, "still booting");
+        assert!(agent_ready.load(Ordering::SeqCst));
+        boot_may_finish.store(true, Ordering::SeqCst);
+        wait_until("boot never finished", || booted.load(Ordering::SeqCst));
+
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        read_console_until(&mut client, "root@vibebox:~# ");
+        assert!(!agent_ready.load(Ordering::SeqCst));
+
+        let mut second = UnixStream::connect(&socket_path).unwrap();
+        read_console_until(&mut second, "already attached");
+
+        client.write_all(b"echo rescue\n").unwrap();
+        read_console_until(&mut client, "echo rescue\r\n");
+        client.write_all(b"exit\n").unwrap();
+        read_console_until(&mut client, " ready ");
+        drop(client);
+        wait_until("agent never took the console back", || {
+            agent_ready.load(Ordering::SeqCst)
+        });
+
+        detached.store(true, Ordering::SeqCst);
+        wait_until("agent exec never answered", || {
+            exec_code.lock().unwrap().is_some()
+        });
+        assert_eq!(*exec_code.lock().unwrap(), Some(0));
+
+        wait_for_vm_input(&vm_input_tx)
+            .send(agent::shutdown_input())
+            .unwrap();
+        guest_thread.join().unwrap().expect("scripted boot");
+    }
+
+    #[test]
+    fn scripted_guest_reports_provision_failure() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let login_actions =
+            boot::boot_login_actions(&[], &boot::provision_login_actions().unwrap());
+
+        let err = run_scripted_guest(
+            FakeGuest::new(GuestBehavior::ProvisionFailed),
+            login_actions,
+            temp.path().to_path_buf(),
+            Arc::new(Mutex::new(None)),
+            Arc::default(),
+        )
+        .unwrap_err();
+
+        assert!(
+            err.to_string().contains("VIBEBOX_PROVISION_FAILED"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn scripted_guest_reports_ssh_setup_failure() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let instance_dir = temp.path().to_path_buf();
+        let config = Arc::new(Mutex::new(
+            load_or_create_instance_config(&instance_dir).expect("instance config"),
+        ));
+        let mut extra_actions = boot::agent_login_actions(Arc::default()).unwrap();
+        extra_actions.extend(build_ssh_login_actions(
+            &config,
+            &instance_dir,
+            "project",
+            "/project",
+            "/root/.vibebox",
+            "",
+            SecurityProfile::Standard,
+        ));
+
+        let err = run_scripted_guest(
+            FakeGuest::new(GuestBehavior::SshSetupFailed),
+            boot::boot_login_actions(&[], &extra_actions),
+            instance_dir.clone(),
+            Arc::new(Mutex::new(None)),
+            Arc::default(),
+        )
+        .unwrap_err();
+
+        assert!(
+            err.to_string().contains("ssh setup exited with status 1"),
+            "unexpected error: {err}"
+        );
+        assert_eq!(read_instance_vm_ip(&instance_dir).unwrap(), None);
+    }
+
+    #[test]
+    fn scripted_guest_times_out_without_login_prompt() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let login_actions = vec![LoginAction::Expect(Expectation::text(
+            "login: ",
+            Duration::from_millis(200),
+        ))];
+
+        let err = run_scripted_guest(
+            FakeGuest::new(GuestBehavior::NoLoginPrompt),
+            login_actions,
+            temp.path().to_path_buf(),
+            Arc::new(Mutex::new(None)),
+            Arc::default(),
+        )
+        .unwrap_err();
+
+        assert!(
+            err.to_string().contains("timed out"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn run_manager_with_backend_boots_the_given_backend() {
+        let temp = tempfile::Builder::new()
+            .prefix("vb")
+            .tempdir_in("/tmp")
+            .expect("tempdir");
+        let project = temp.path().join("demo");
+        fs::create_dir_all(&project).unwrap();
+        let args = vm::VmArg {
+            cpu_count: 2,
+            ram_bytes: 2048 * 1024 * 1024,
+            disk_bytes: 5 * 1024 * 1024 * 1024,
+            no_default_mounts: false,
+            mounts: Vec::new(),
+            security: Default::default(),
+            allow_sensitive: false,
+            project_mode: Default::default(),
+            commands: Default::default(),
+            hooks: Default::default(),
+        };
+
+        let instance_dir = project.join(INSTANCE_DIR_NAME);
+        let client = thread::spawn({
+            let instance_dir = instance_dir.clone();
+            move || {
+                let socket_path = instance_dir.join(VM_MANAGER_SOCKET_NAME);
+                let deadline = Instant::now() + Duration::from_secs(10);
+                let stream = loop {
+                    if let Ok(stream) = UnixStream::connect(&socket_path) {
+                        break stream;
+                    }
+                    assert!(Instant::now() < deadline, "manager socket never came up");
+                    thread::sleep(Duration::from_millis(20));
+                };
+                while read_instance_vm_ip(&instance_dir).ok().flatten().as_deref()
+                    != Some(FAKE_GUEST_IPV4)
+                {
+                    assert!(Instant::now() < deadline, "guest never booted");
+                    thread::sleep(Duration::from_millis(20));
+                }
+                // The last client leaving powers the guest off once the grace period ends.
+                drop(stream);
+            }
+        });
+
+        run_manager_with_backend(&project, args, 50, &MockVmBackend).expect("manager run");
+        client.join().unwrap();
+
+        let console_log = fs::read_to_string(instance_dir.join(VM_ROOT_LOG_NAME)).unwrap();
+        assert!(console_log.contains("vibebox login: "), "{console_log}");
+        assert!(!instance_dir.join(VM_MANAGER_SOCKET_NAME).exists());
+    }
+
+    #[test]
+    fn manager_log_rotates_only_under_the_fd_writing_it() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join(VM_MANAGER_LOG_NAME);
+        let open = |path: &Path| {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap()
+        };
+        let mut stderr = open(&path);
+        stderr.write_all(b"previous boot\n").unwrap();
+
+        let mut other = open(&temp.path().join("other.log"));
+        assert!(!rotate_log_onto(&path, other.as_raw_fd()));
+        other.write_all(b"lost the race\n").unwrap();
+        assert!(!logs::log_path(temp.path(), logs::LogKind::Manager, 1).exists());
+
+        assert!(rotate_log_onto(&path, stderr.as_raw_fd()));
+        stderr.write_all(b"this boot\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(logs::log_path(temp.path(), logs::LogKind::Manager, 1)).unwrap(),
+            "previous boot\n"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "this boot\n");
+    }
+
     #[test]
     fn manager_powers_off_after_grace_when_no_refs() {
         let _temp = tempfile::Builder::new()
@@ -916,7 +1789,8 @@ mod tests {
         let vm_input_tx = Arc::new(Mutex::new(Some(vm_tx)));
 
         let manager_thread = thread::spawn(move || {
-            manager_event_loop(event_rx, vm_input_tx, 50).expect("event loop");
+            manager_event_loop(event_rx, vm_input_tx, Arc::default(), 50, &Hooks::default())
+                .expect("event loop");
         });
 
         event_tx.send(ManagerEvent::Inc(None)).unwrap();
@@ -936,13 +1810,71 @@ mod tests {
         let _ = manager_thread.join();
     }
 
+    #[test]
+    fn manager_powers_off_on_stop_request_with_clients_attached() {
+        let (event_tx, event_rx) = mpsc::channel::<ManagerEvent>();
+        let (vm_tx, vm_rx) = mpsc::channel::<VmInput>();
+        let vm_input_tx = Arc::new(Mutex::new(Some(vm_tx)));
+
+        let manager_thread = thread::spawn(move || {
+            manager_event_loop(
+                event_rx,
+                vm_input_tx,
+                Arc::default(),
+                60_000,
+                &Hooks::default(),
+            )
+            .expect("event loop");
+        });
+
+        event_tx.send(ManagerEvent::Inc(None)).unwrap();
+        event_tx.send(ManagerEvent::Stop).unwrap();
+        let msg = vm_rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("poweroff");
+        match msg {
+            VmInput::Bytes(data) => {
+                assert_eq!(data, b"systemctl poweroff\n");
+            }
+            _ => panic!("unexpected vm input"),
+        }
+        let _ = event_tx.send(ManagerEvent::VmExited(None));
+        let _ = manager_thread.join();
+    }
+
+    #[test]
+    fn pinned_manager_waits_for_unpin_before_powering_off() {
+        let (event_tx, event_rx) = mpsc::channel::<ManagerEvent>();
+        let (vm_tx, vm_rx) = mpsc::channel::<VmInput>();
+        let vm_input_tx = Arc::new(Mutex::new(Some(vm_tx)));
+
+        let manager_thread = thread::spawn(move || {
+            manager_event_loop(event_rx, vm_input_tx, Arc::default(), 50, &Hooks::default())
+                .expect("event loop");
+        });
+
+        event_tx.send(ManagerEvent::Inc(None)).unwrap();
+        event_tx.send(ManagerEvent::Pin(true)).unwrap();
+        event_tx.send(ManagerEvent::Dec(None)).unwrap();
+        assert!(vm_rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+        event_tx.send(ManagerEvent::Pin(false)).unwrap();
+        let msg = vm_rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("poweroff");
+        assert!(matches!(msg, VmInput::Bytes(ref data) if data == b"systemctl poweroff\n"));
+        let _ = event_tx.send(ManagerEvent::VmExited(None));
+        let _ = manager_thread.join();
+    }
+
     #[test]
     fn manager_force_exits_when_vm_input_never_ready() {
         let (event_tx, event_rx) = mpsc::channel::<ManagerEvent>();
         let vm_input_tx = Arc::new(Mutex::new(None));
 
         let manager_thread = thread::spawn(move || {
-            let _ = manager_event_loop(event_rx, vm_input_tx, 10);
+            let _ =
+                manager_event_loop(event_rx, vm_input_tx, Arc::default(), 10, &Hooks::default());
         });
 
         event_tx.send(ManagerEvent::Inc(None)).unwrap();
@@ -963,7 +1895,14 @@ mod tests {
         let vm_input_for_thread = vm_input_tx.clone();
 
         let manager_thread = thread::spawn(move || {
-            manager_event_loop(event_rx, vm_input_for_thread, 10).expect("event loop");
+            manager_event_loop(
+                event_rx,
+                vm_input_for_thread,
+                Arc::default(),
+                10,
+                &Hooks::default(),
+            )
+            .expect("event loop");
         });
 
         event_tx.send(ManagerEvent::Inc(None)).unwrap();
@@ -984,4 +1923,35 @@ mod tests {
         let _ = event_tx.send(ManagerEvent::VmExited(None));
         let _ = manager_thread.join();
     }
+
+    #[test]
+    fn overlay_mode_shares_the_project_read_only_beside_the_overlay() {
+        let project = Path::new("/work/demo");
+        let mut mounts = Vec::new();
+        let link = inject_project_mount(
+            &mut mounts,
+            project,
+            "vibecoder",
+            "demo",
+            ProjectMode::Overlay,
+        )
+        .unwrap();
+        assert_eq!(
+            mounts,
+            ["/work/demo:/usr/local/vibebox-lower/demo:read-only"]
+        );
+        assert_eq!(link.source, format!("{PROJECT_GUEST_BASE}/demo"));
+        assert_eq!(link.target, "/home/vibecoder/demo");
+
+        let mut mounts = Vec::new();
+        let link = inject_project_mount(
+            &mut mounts,
+            project,
+            "vibecoder",
+            "demo",
+            ProjectMode::Mount,
+        );
+        assert!(link.is_none());
+        assert_eq!(mounts, ["/work/demo:~/demo:read-write"]);
+    }
 }
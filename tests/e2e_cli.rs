@@ -46,6 +46,370 @@ fn list_reports_no_sessions_when_empty() {
     );
 }
 
+#[test]
+fn logs_selects_earlier_boots() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let instance_dir = temp.path().join("project").join(".vibebox");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&instance_dir).unwrap();
+    std::fs::write(instance_dir.join("vm_root.log"), "latest boot\n").unwrap();
+    std::fs::write(instance_dir.join("vm_root.log.1"), "crashed boot\n").unwrap();
+    std::fs::write(instance_dir.join("vm_manager.log"), "manager\n").unwrap();
+
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("vibebox")
+            .current_dir(instance_dir.parent().unwrap())
+            .env("HOME", &home)
+            .arg("logs")
+            .args(args)
+            .output()
+            .unwrap();
+        print_output("e2e_cli", &output);
+        assert!(
+            output.status.success(),
+            "expected success, got status: {}",
+            output.status
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(run(&[]), "latest boot\n");
+    assert_eq!(run(&["console", "--boot", "-1"]), "crashed boot\n");
+    assert_eq!(run(&["manager"]), "manager\n");
+    assert!(run(&["provision"]).contains("No provision.log found"));
+}
+
+#[test]
+fn ssh_config_install_is_idempotent() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    let instance_dir = project.join(".vibebox");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&instance_dir).unwrap();
+    std::fs::write(instance_dir.join("ssh_key"), "").unwrap();
+    std::fs::write(instance_dir.join("ssh_key.pub"), "").unwrap();
+    let ssh_config = home.join(".ssh").join("config");
+
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("vibebox")
+            .current_dir(&project)
+            .env("HOME", &home)
+            .arg("ssh-config")
+            .args(args)
+            .output()
+            .unwrap();
+        print_output("e2e_cli", &output);
+        assert!(
+            output.status.success(),
+            "expected success, got status: {}",
+            output.status
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let printed = run(&[]);
+    assert!(printed.contains("Host vibebox-project\n"), "{printed}");
+    assert!(printed.contains("ssh-proxy --project"), "{printed}");
+    assert!(!ssh_config.exists());
+
+    run(&["--install"]);
+    run(&["--install"]);
+    let installed = std::fs::read_to_string(&ssh_config).unwrap();
+    assert_eq!(
+        installed.matches("Host vibebox-project").count(),
+        1,
+        "{installed}"
+    );
+    assert_eq!(installed, printed);
+}
+
+#[test]
+fn ssh_proxy_keeps_logs_off_stderr() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("vibebox.toml"),
+        "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = [\"missing:~/missing\"]\n",
+    )
+    .unwrap();
+
+    // ssh shows a ProxyCommand's stderr to the user, so only the error itself may go there.
+    let output = cargo_bin_cmd!("vibebox")
+        .current_dir(&home)
+        .env("HOME", &home)
+        .args(["--json", "ssh-proxy", "--project"])
+        .arg(&project)
+        .output()
+        .unwrap();
+    print_output("e2e_cli", &output);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 1, "{stderr}");
+    let report: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(report["error"]["code"], "config_invalid");
+    // Nor does it leave a .vibebox behind in the directory ssh was started from.
+    assert!(!home.join(".vibebox").exists());
+}
+
+#[test]
+fn cp_rejects_paths_without_a_guest_side() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+
+    let output = cargo_bin_cmd!("vibebox")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .args(["cp", "a.txt", "b.txt"])
+        .output()
+        .unwrap();
+    print_output("e2e_cli", &output);
+    assert!(!output.status.success(), "expected failure");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("must be a guest path"), "{stderr}");
+    assert!(
+        !project.join(".vibebox").join("vm.sock").exists(),
+        "no vm manager should start for invalid paths"
+    );
+}
+
+#[test]
+fn config_refuses_sensitive_mounts() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(home.join(".ssh")).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("vibebox.toml"),
+        "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = [\"~/.ssh:~/.ssh\"]\n\n\
+[supervisor]\nauto_shutdown_ms = 20000\n",
+    )
+    .unwrap();
+
+    let output = cargo_bin_cmd!("vibebox")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .arg("explain")
+        .output()
+        .unwrap();
+    print_output("e2e_cli", &output);
+    assert!(!output.status.success(), "expected failure");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("exposes ~/.ssh (ssh keys)"), "{stderr}");
+    assert!(stderr.contains("allow_sensitive"), "{stderr}");
+}
+
+#[test]
+fn run_rejects_undefined_commands_before_booting() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("vibebox.toml"),
+        "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n\
+[supervisor]\nauto_shutdown_ms = 20000\n\n[commands.test]\ndescription = \"Run tests\"\n\
+script = \"cargo test\"\n",
+    )
+    .unwrap();
+
+    let output = cargo_bin_cmd!("vibebox")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .args(["run", ":lint", "--fix"])
+        .output()
+        .unwrap();
+    print_output("e2e_cli", &output);
+    assert!(!output.status.success(), "expected failure");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no command :lint in [commands]; defined: :test"),
+        "{stderr}"
+    );
+    assert!(!project.join(".vibebox").join("vm.sock").exists());
+}
+
+#[test]
+fn explain_json_describes_sandbox() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(home.join(".codex")).unwrap();
+    std::fs::create_dir_all(home.join(".claude")).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+
+    let output = cargo_bin_cmd!("vibebox")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .args(["explain", "--json"])
+        .output()
+        .unwrap();
+    print_output("e2e_cli", &output);
+    assert!(
+        output.status.success(),
+        "expected success, got status: {}",
+        output.status
+    );
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["security"]["profile"], "standard");
+    assert_eq!(explanation["resources"]["cpu_count"], 2);
+    assert_eq!(explanation["storage"]["config"], "vibebox.toml");
+    assert_eq!(explanation["storage"]["instance_dir"], ".vibebox/");
+    assert_eq!(explanation["ssh_user"], "vibecoder");
+    assert_eq!(explanation["mounts"].as_array().unwrap().len(), 4);
+    assert_eq!(explanation["network"][0]["egress"], "open");
+}
+
+#[test]
+fn json_errors_carry_a_stable_code_and_hints() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(home.join(".ssh")).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+
+    let error_of = |args: &[&str]| {
+        let output = cargo_bin_cmd!("vibebox")
+            .current_dir(&project)
+            .env("HOME", &home)
+            .args(args)
+            .output()
+            .unwrap();
+        print_output("e2e_cli", &output);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr.lines().last().unwrap_or_default().to_string();
+        let report: serde_json::Value = serde_json::from_str(&line).unwrap();
+        report["error"].clone()
+    };
+
+    let error = error_of(&["--json", "cp", "a.txt", "b.txt"]);
+    assert_eq!(error["code"], "invalid_argument");
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("must be a guest path")
+    );
+
+    std::fs::write(
+        project.join("vibebox.toml"),
+        "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = [\"~/.ssh:~/.ssh\"]\n",
+    )
+    .unwrap();
+    let error = error_of(&["explain", "--json"]);
+    assert_eq!(error["code"], "config_invalid");
+    assert!(!error["hints"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn session_flag_is_checked_before_anything_runs() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+
+    let error_of = |args: &[&str]| {
+        let output = cargo_bin_cmd!("vibebox")
+            .current_dir(&project)
+            .env("HOME", &home)
+            .args(args)
+            .output()
+            .unwrap();
+        print_output("e2e_cli", &output);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr.lines().last().unwrap_or_default().to_string();
+        let report: serde_json::Value = serde_json::from_str(&line).unwrap();
+        report["error"].clone()
+    };
+
+    let error = error_of(&["--json", "--session", "feature-x", "list"]);
+    assert_eq!(error["code"], "invalid_argument");
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("--session only applies")
+    );
+
+    let error = error_of(&[
+        "--json",
+        "--session",
+        "feature-x",
+        "logs",
+        "--from",
+        "other",
+    ]);
+    assert_eq!(error["code"], "invalid_argument");
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("either --session or logs --from")
+    );
+
+    let error = error_of(&["--json", "--session", "../escape", "logs"]);
+    assert_eq!(error["code"], "invalid_argument");
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid session name")
+    );
+    assert!(!project.join(".vibebox-sessions").exists());
+    assert!(!project.join("escape").exists());
+}
+
+#[test]
+fn overlay_commands_need_overlay_mode_before_booting() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = temp.path().join("project");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("vibebox.toml"),
+        "[box]\ncpu_count = 2\nram_mb = 2048\ndisk_gb = 5\nmounts = []\n\n\
+[supervisor]\nauto_shutdown_ms = 20000\n",
+    )
+    .unwrap();
+
+    for command in ["diff", "apply", "discard"] {
+        let output = cargo_bin_cmd!("vibebox")
+            .current_dir(&project)
+            .env("HOME", &home)
+            .args(["--json", command, "src"])
+            .output()
+            .unwrap();
+        print_output("e2e_cli", &output);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr.lines().last().unwrap_or_default();
+        let report: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(report["error"]["code"], "config_invalid", "{command}");
+        assert!(
+            report["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("[box].project_mode is \"mount\""),
+            "{command}: {line}"
+        );
+    }
+    assert!(!project.join(".vibebox").join("vm.sock").exists());
+}
+
 fn print_output(prefix: &str, output: &std::process::Output) {
     let stdout = String::from_utf8_lossy(&output.stdout);
     for line in stdout.lines() {
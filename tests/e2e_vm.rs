@@ -1,3 +1,6 @@
+// The helpers below only back the macOS test.
+#![cfg_attr(not(target_os = "macos"), allow(dead_code, unused_imports))]
+
 use std::{
     fs,
     io::{BufRead, BufReader, Read},
@@ -1,9 +1,9 @@
-#![cfg(all(feature = "mock-vm", target_os = "macos"))]
+#![cfg(feature = "mock-vm")]
 
 use std::{
     fs,
-    io::{BufRead, BufReader, Read},
-    os::unix::net::UnixStream,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
     path::{Path, PathBuf},
     process::{Child, Command, Stdio},
     sync::mpsc,
@@ -230,6 +230,571 @@ fn mock_vm_reconnect_resets_shutdown() {
     assert!(status.success(), "vm manager exited with {status}");
 }
 
+#[test]
+fn mock_vm_boot_records_guest_ipv4() {
+    let temp = TempDir::new().unwrap();
+    let mut supervisor = spawn_supervisor(&temp, 101, 600, "e2e_vm_boot".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_boot",
+    );
+
+    let instance_dir = supervisor.socket_path.parent().unwrap().to_path_buf();
+    let instance_path = instance_dir.join("instance.toml");
+    let start = Instant::now();
+    loop {
+        let content = fs::read_to_string(&instance_path).unwrap_or_default();
+        if content.contains("vm_ipv4 = \"192.168.64.2\"") {
+            break;
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!(
+                "vm ipv4 never recorded in {}: {content}",
+                instance_path.display()
+            );
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let console_log = fs::read_to_string(instance_dir.join("vm_root.log")).unwrap();
+    assert!(
+        console_log.contains("vibebox login: "),
+        "expected login prompt in console log, got: {console_log}"
+    );
+
+    supervisor.clients.clear();
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    let status = supervisor.child.wait().unwrap();
+    assert!(status.success(), "vm manager exited with {status}");
+}
+
+#[test]
+fn mock_vm_sandbox_reports_status_and_stops() {
+    let temp = TempDir::new().unwrap();
+    let mut supervisor = spawn_supervisor(&temp, 104, 60_000, "e2e_vm_sandbox".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_sandbox",
+    );
+    let project = supervisor.socket_path.parent().unwrap().parent().unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    let sandbox = vibebox::Sandbox::builder(project).build().unwrap();
+
+    let start = Instant::now();
+    let status = loop {
+        let status = sandbox.status();
+        if status.vm_ip.is_some() {
+            break status;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "{status:?}");
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert!(status.running);
+    assert_eq!(status.manager_pid, Some(supervisor.child.id()));
+    assert_eq!(status.vm_ip.as_deref(), Some("192.168.64.2"));
+    assert!(status.session_id.is_some());
+
+    // Stops even though another client is still attached.
+    sandbox.stop().unwrap();
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    assert!(supervisor.child.wait().unwrap().success());
+    let status = sandbox.status();
+    assert!(!status.running && status.vm_ip.is_none(), "{status:?}");
+    assert!(matches!(
+        sandbox.exec("true"),
+        Err(vibebox::SandboxError::NotRunning)
+    ));
+    assert_eq!(std::env::current_dir().unwrap(), cwd);
+}
+
+#[test]
+fn mock_vm_ephemeral_managers_share_a_project_and_clean_up() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home-107");
+    let cache_home = temp.path().join("cache-107");
+    let project = temp.path().join("project-107");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&cache_home).unwrap();
+    fs::create_dir_all(&project).unwrap();
+    write_config(&project, 60_000, "");
+
+    let mut managers = Vec::new();
+    for name in ["ephemeral-a", "ephemeral-b"] {
+        let instance_dir = temp.path().join(name);
+        let mut child = Command::new(assert_cmd::cargo_bin!("vibebox-supervisor"))
+            .current_dir(&project)
+            .env("HOME", &home)
+            .env("XDG_CACHE_HOME", &cache_home)
+            .env("VIBEBOX_INTERNAL", "1")
+            .env("VIBEBOX_EPHEMERAL_DIR", &instance_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        if let Some(stderr) = child.stderr.take() {
+            spawn_prefix_reader(format!("e2e_{name}"), "stderr", stderr);
+        }
+        // Connect once and hold on: a probe that disconnects would end an ephemeral VM.
+        let socket_path = instance_dir.join("vm.sock");
+        let client = connect_client_with_retry(&socket_path, Duration::from_secs(10)).unwrap();
+        managers.push((child, client, instance_dir));
+    }
+
+    let ids: Vec<String> = managers
+        .iter()
+        .map(|(_, _, instance_dir)| {
+            let raw = fs::read_to_string(instance_dir.join("instance.toml")).unwrap();
+            let value: toml::Value = toml::from_str(&raw).unwrap();
+            value["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_ne!(ids[0], ids[1]);
+    // The default guest gets `.vibebox` shared, so the named session's keys must not be in it.
+    assert!(!project.join(".vibebox").join("sessions").exists());
+    assert!(!project.join(".vibebox").join("vm.sock").exists());
+    assert!(!home.join(".vibebox").join("sessions").exists());
+
+    // The configured auto_shutdown_ms does not apply: the last client leaving ends the VM.
+    for (mut child, client, instance_dir) in managers {
+        drop(client);
+        wait_for_exit(&mut child, Duration::from_secs(10));
+        assert!(child.wait().unwrap().success());
+        assert!(
+            !instance_dir.exists(),
+            "{} was kept",
+            instance_dir.display()
+        );
+    }
+}
+
+#[test]
+fn mock_vm_named_sessions_run_side_by_side() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home-108");
+    let cache_home = temp.path().join("cache-108");
+    let project = temp.path().join("project-108");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&cache_home).unwrap();
+    fs::create_dir_all(&project).unwrap();
+    write_config(&project, 300, "");
+
+    let mut managers = Vec::new();
+    for name in [None, Some("feature-x")] {
+        let mut cmd = Command::new(assert_cmd::cargo_bin!("vibebox-supervisor"));
+        cmd.current_dir(&project)
+            .env("HOME", &home)
+            .env("XDG_CACHE_HOME", &cache_home)
+            .env("VIBEBOX_INTERNAL", "1")
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let instance_dir = match name {
+            Some(name) => {
+                cmd.env("VIBEBOX_SESSION", name);
+                project.join(".vibebox-sessions").join(name)
+            }
+            None => project.join(".vibebox"),
+        };
+        let mut child = cmd.spawn().unwrap();
+        if let Some(stderr) = child.stderr.take() {
+            spawn_prefix_reader(format!("e2e_named_{name:?}"), "stderr", stderr);
+        }
+        let socket_path = instance_dir.join("vm.sock");
+        let client = connect_client_with_retry(&socket_path, Duration::from_secs(10)).unwrap();
+        managers.push((child, client, instance_dir));
+    }
+
+    let ids: Vec<String> = managers
+        .iter()
+        .map(|(_, _, instance_dir)| {
+            let raw = fs::read_to_string(instance_dir.join("instance.toml")).unwrap();
+            let value: toml::Value = toml::from_str(&raw).unwrap();
+            value["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_ne!(ids[0], ids[1]);
+
+    // Each session has its own manager: letting go of one leaves the other running.
+    let (mut named, client, named_dir) = managers.pop().unwrap();
+    drop(client);
+    wait_for_exit(&mut named, Duration::from_secs(10));
+    assert!(named.wait().unwrap().success());
+    assert!(named_dir.join("instance.toml").exists());
+    let (mut default, client, _) = managers.pop().unwrap();
+    assert_manager_alive_for(&mut default, Duration::from_millis(500), "default session");
+    drop(client);
+    wait_for_exit(&mut default, Duration::from_secs(10));
+}
+
+#[test]
+fn mock_vm_daemon_drives_sessions_over_json_rpc() {
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home-106");
+    let cache_home = temp.path().join("cache-106");
+    let project = temp.path().join("project-106");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&cache_home).unwrap();
+    fs::create_dir_all(&project).unwrap();
+    write_config(&project, 60_000, "");
+    let socket_path = temp.path().join("daemon.sock");
+
+    let mut daemon = Command::new(assert_cmd::cargo_bin!("vibebox"))
+        .current_dir(temp.path())
+        .env("HOME", &home)
+        .env("XDG_CACHE_HOME", &cache_home)
+        .arg("daemon")
+        .arg("--socket")
+        .arg(&socket_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    if let Some(stderr) = daemon.stderr.take() {
+        spawn_prefix_reader("e2e_daemon".to_string(), "stderr", stderr);
+    }
+    wait_for_socket(&socket_path, Duration::from_secs(10));
+    let mode = fs::metadata(&socket_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let mut rpc = RpcClient::connect(&socket_path);
+    rpc.call(1, "events.subscribe", serde_json::json!({}));
+    let created = rpc.call(
+        2,
+        "session.create",
+        serde_json::json!({ "directory": project, "auto_shutdown_ms": 60_000 }),
+    );
+    let session = created["session"].as_str().unwrap().to_string();
+    assert!(!session.is_empty());
+    let started = rpc.call(
+        3,
+        "session.start",
+        serde_json::json!({ "session": session }),
+    );
+    assert_eq!(started["running"], true, "{started}");
+    let event = rpc.wait_for_event("started", &session);
+    assert_eq!(event["session"]["name"], "project-106");
+
+    let listed = rpc.call(4, "sessions.list", serde_json::json!({}));
+    let row = &listed.as_array().unwrap()[0];
+    assert_eq!(row["id"], session.as_str());
+    assert_eq!(row["active"], true);
+
+    let error = rpc.call_err(5, "session.start", serde_json::json!({ "session": "nope" }));
+    assert_eq!(error["data"]["code"], "invalid_argument");
+
+    let stopped = rpc.call(6, "session.stop", serde_json::json!({ "session": session }));
+    assert_eq!(stopped["stopped"], true);
+    rpc.wait_for_event("stopped", &session);
+    assert!(!project.join(".vibebox").join("vm.sock").exists());
+
+    let _ = daemon.kill();
+    let _ = daemon.wait();
+}
+
+struct RpcClient {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+    /// Notifications that arrived while waiting for a response.
+    pending: Vec<serde_json::Value>,
+}
+
+impl RpcClient {
+    fn connect(path: &Path) -> Self {
+        let writer = UnixStream::connect(path).unwrap();
+        writer
+            .set_read_timeout(Some(Duration::from_secs(20)))
+            .unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        Self {
+            writer,
+            reader,
+            pending: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) -> serde_json::Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        log_line("e2e_daemon", line.trim());
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn response(&mut self, id: u64, method: &str, params: serde_json::Value) -> serde_json::Value {
+        let request =
+            serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        writeln!(self.writer, "{request}").unwrap();
+        loop {
+            let message = self.next();
+            if message["id"] == id {
+                return message;
+            }
+            self.pending.push(message);
+        }
+    }
+
+    fn call(&mut self, id: u64, method: &str, params: serde_json::Value) -> serde_json::Value {
+        let response = self.response(id, method, params);
+        assert!(response.get("error").is_none(), "{response}");
+        response["result"].clone()
+    }
+
+    fn call_err(&mut self, id: u64, method: &str, params: serde_json::Value) -> serde_json::Value {
+        self.response(id, method, params)["error"].clone()
+    }
+
+    fn wait_for_event(&mut self, event: &str, session: &str) -> serde_json::Value {
+        let matches = |message: &serde_json::Value| {
+            message["method"] == "session.event"
+                && message["params"]["event"] == event
+                && message["params"]["session"]["id"] == session
+        };
+        if let Some(index) = self.pending.iter().position(matches) {
+            return self.pending.remove(index)["params"].clone();
+        }
+        loop {
+            let message = self.next();
+            if matches(&message) {
+                return message["params"].clone();
+            }
+        }
+    }
+}
+
+#[test]
+fn mock_vm_console_attach_and_detach() {
+    let temp = TempDir::new().unwrap();
+    let mut supervisor = spawn_supervisor(&temp, 102, 600, "e2e_vm_console".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_console",
+    );
+    let instance_dir = supervisor.socket_path.parent().unwrap().to_path_buf();
+    let project = instance_dir.parent().unwrap().to_path_buf();
+    let start = Instant::now();
+    while !fs::read_to_string(instance_dir.join("instance.toml"))
+        .unwrap_or_default()
+        .contains("vm_ipv4")
+    {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "guest never finished booting"
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let mut console = Command::new(assert_cmd::cargo_bin!("vibebox"))
+        .args(["console", "--detach-keys", "ctrl-q"])
+        .current_dir(&project)
+        .env("HOME", temp.path().join("home-102"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = console.stdin.take().unwrap();
+    let mut stdout = console.stdout.take().unwrap();
+    let (output_tx, output_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; 256];
+        while let Ok(n) = stdout.read(&mut buf) {
+            if n == 0 || output_tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    stdin.write_all(b"echo rescue\n").unwrap();
+    let mut seen = Vec::new();
+    while !String::from_utf8_lossy(&seen).contains("echo rescue") {
+        let chunk = output_rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap_or_else(|_| {
+                panic!(
+                    "console never echoed input: {}",
+                    String::from_utf8_lossy(&seen)
+                )
+            });
+        seen.extend(chunk);
+    }
+    stdin.write_all(b"\x11").unwrap();
+    let output = console.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "vibebox console exited with {}",
+        output.status
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("detached"), "{stderr}");
+
+    // The rescue shell still owns the console, so the manager falls back to a typed poweroff.
+    supervisor.clients.clear();
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    let status = supervisor.child.wait().unwrap();
+    assert!(status.success(), "vm manager exited with {status}");
+}
+
+#[test]
+fn mock_vm_answers_guest_control_requests() {
+    let temp = TempDir::new().unwrap();
+    let mut supervisor = spawn_supervisor(&temp, 103, 300, "e2e_vm_control".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_control",
+    );
+    let control_dir = supervisor.socket_path.parent().unwrap().join("control");
+
+    let reply = control_request(&control_dir, "1", "pin");
+    assert!(reply.starts_with("ok\nPinned"), "{reply}");
+    let start = Instant::now();
+    loop {
+        let reply = control_request(&control_dir, "2", "status");
+        if reply.contains("Clients: 1\n") {
+            assert!(reply.contains("Pinned: yes\n"), "{reply}");
+            break;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "{reply}");
+    }
+    let reply = control_request(&control_dir, "3", "reboot");
+    assert!(reply.starts_with("error\nunknown request"), "{reply}");
+
+    supervisor.clients.clear();
+    assert_manager_alive_for(
+        &mut supervisor.child,
+        Duration::from_millis(900),
+        "pinned vm manager exited without clients",
+    );
+
+    let reply = control_request(&control_dir, "4", "stop");
+    assert_eq!(reply, "ok\nStopping the VM.\n");
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    let status = supervisor.child.wait().unwrap();
+    assert!(status.success(), "vm manager exited with {status}");
+}
+
+/// Does what the guest's `vibebox-host` does through the shared control directory.
+fn control_request(control_dir: &Path, id: &str, operation: &str) -> String {
+    let tmp = control_dir.join(format!("{id}.tmp"));
+    fs::write(&tmp, format!("{operation}\n")).unwrap();
+    fs::rename(&tmp, control_dir.join(format!("{id}.req"))).unwrap();
+    let response = control_dir.join(format!("{id}.resp"));
+    let start = Instant::now();
+    while !response.exists() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "no answer to {operation}"
+        );
+        thread::sleep(Duration::from_millis(50));
+    }
+    let reply = fs::read_to_string(&response).unwrap();
+    fs::remove_file(response).unwrap();
+    reply
+}
+
+#[test]
+fn mock_vm_runs_lifecycle_hooks() {
+    let temp = TempDir::new().unwrap();
+    let hook = r#"echo "$VIBEBOX_HOOK $VIBEBOX_PROJECT_NAME ${VIBEBOX_VM_IP:-} ${VIBEBOX_CLIENTS:-}" >> hooks.log"#;
+    let hooks = format!(
+        "\n[hooks]\npre_start = '{hook}'\npost_boot = '{hook}'\non_attach = '{hook}'\n\
+on_detach = '{hook}'\npost_stop = '{hook}'\n"
+    );
+    allow_hooks(&temp, 104, 300, &hooks);
+    let mut supervisor =
+        spawn_supervisor_with_config(&temp, 104, 300, &hooks, "e2e_vm_hooks".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_hooks",
+    );
+    let project = supervisor.socket_path.parent().unwrap().parent().unwrap();
+    let log_path = project.join("hooks.log");
+    let start = Instant::now();
+    while !fs::read_to_string(&log_path)
+        .unwrap_or_default()
+        .contains("post_boot")
+    {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "post_boot hook never ran"
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    supervisor.clients.clear();
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    let status = supervisor.child.wait().unwrap();
+    assert!(status.success(), "vm manager exited with {status}");
+
+    let log = fs::read_to_string(&log_path).unwrap();
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    assert_eq!(lines.first(), Some(&"pre_start project-104"), "{log}");
+    assert_eq!(lines.last(), Some(&"post_stop project-104"), "{log}");
+    assert!(
+        lines.contains(&"post_boot project-104 192.168.64.2"),
+        "{log}"
+    );
+    assert!(lines.contains(&"on_attach project-104  1"), "{log}");
+    assert!(lines.contains(&"on_detach project-104  0"), "{log}");
+}
+
+#[test]
+fn mock_vm_skips_hooks_that_were_not_allowed() {
+    let temp = TempDir::new().unwrap();
+    let hooks = "\n[hooks]\npre_start = 'touch pre_start.ran'\n";
+    allow_hooks(
+        &temp,
+        105,
+        300,
+        "\n[hooks]\npre_start = 'touch other.ran'\n",
+    );
+    let mut supervisor =
+        spawn_supervisor_with_config(&temp, 105, 300, hooks, "e2e_vm_untrusted".to_string());
+    supervisor.clients = connect_clients(
+        &supervisor.socket_path,
+        1,
+        Duration::from_secs(2),
+        true,
+        "e2e_vm_untrusted",
+    );
+    supervisor.clients.clear();
+    wait_for_exit(&mut supervisor.child, Duration::from_secs(10));
+    assert!(supervisor.child.wait().unwrap().success());
+
+    let project = temp.path().join("project-105");
+    assert!(!project.join("pre_start.ran").exists());
+    assert!(!project.join("other.ran").exists());
+}
+
+/// What a user does with `vibebox allow-hooks` before the session in `spawn_supervisor_with_config`.
+fn allow_hooks(temp: &TempDir, idx: usize, auto_shutdown_ms: u64, extra_config: &str) {
+    let home = temp.path().join(format!("home-{idx}"));
+    let project = temp.path().join(format!("project-{idx}"));
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&project).unwrap();
+    write_config(&project, auto_shutdown_ms, extra_config);
+    let status = Command::new(assert_cmd::cargo_bin!("vibebox"))
+        .arg("allow-hooks")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
 struct Supervisor {
     child: Child,
     socket_path: PathBuf,
@@ -237,7 +802,7 @@ struct Supervisor {
     label: String,
 }
 
-fn write_config(project: &Path, auto_shutdown_ms: u64) {
+fn write_config(project: &Path, auto_shutdown_ms: u64, extra: &str) {
     let config = format!(
         r#"[box]
 cpu_count = 2
@@ -247,7 +812,7 @@ mounts = []
 
 [supervisor]
 auto_shutdown_ms = {auto_shutdown_ms}
-"#
+{extra}"#
     );
     fs::write(project.join("vibebox.toml"), config).unwrap();
 }
@@ -257,6 +822,16 @@ fn spawn_supervisor(
     idx: usize,
     auto_shutdown_ms: u64,
     label: String,
+) -> Supervisor {
+    spawn_supervisor_with_config(temp, idx, auto_shutdown_ms, "", label)
+}
+
+fn spawn_supervisor_with_config(
+    temp: &TempDir,
+    idx: usize,
+    auto_shutdown_ms: u64,
+    extra_config: &str,
+    label: String,
 ) -> Supervisor {
     let home = temp.path().join(format!("home-{idx}"));
     let cache_home = temp.path().join(format!("cache-{idx}"));
@@ -264,7 +839,7 @@ fn spawn_supervisor(
     fs::create_dir_all(&home).unwrap();
     fs::create_dir_all(&cache_home).unwrap();
     fs::create_dir_all(&project).unwrap();
-    write_config(&project, auto_shutdown_ms);
+    write_config(&project, auto_shutdown_ms, extra_config);
 
     let mut child = Command::new(assert_cmd::cargo_bin!("vibebox-supervisor"))
         .current_dir(&project)
@@ -55,6 +55,8 @@ fn build_mount_rows_includes_defaults_and_custom_mounts() {
     let cfg = config::Config {
         box_cfg,
         supervisor: config::SupervisorConfig::default(),
+        security: config::SecurityConfig::default(),
+        ..Default::default()
     };
 
     let rows = explain::build_mount_rows(&project, &cfg).unwrap();
@@ -74,6 +76,32 @@ fn build_mount_rows_includes_defaults_and_custom_mounts() {
     assert_eq!(rows[2].guest, "~/data");
     assert_eq!(rows[2].mode, "read-only");
     assert_eq!(rows[2].default_mount, "no");
+    assert!(rows.iter().all(|row| row.guard == "ok"));
+}
+
+#[test]
+fn build_mount_rows_records_sensitive_mounts() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = home.join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::create_dir_all(home.join(".ssh")).unwrap();
+
+    let _home_guard = EnvGuard::set("HOME", &home);
+
+    let box_cfg = config::BoxConfig {
+        mounts: vec!["~/.ssh:~/.ssh:read-only".to_string()],
+        allow_sensitive: true,
+        ..Default::default()
+    };
+    let cfg = config::Config {
+        box_cfg,
+        ..Default::default()
+    };
+
+    let rows = explain::build_mount_rows(&project, &cfg).unwrap();
+    assert_eq!(rows[2].guard, "allowed: exposes ~/.ssh (ssh keys)");
 }
 
 #[test]
@@ -82,7 +110,9 @@ fn build_network_rows_pending_without_instance_file() {
     let project = temp.path().join("project");
     fs::create_dir_all(&project).unwrap();
 
-    let rows = explain::build_network_rows(&project).unwrap();
+    let rows =
+        explain::build_network_rows(&project.join(INSTANCE_DIR_NAME), &config::Config::default())
+            .unwrap();
 
     assert_eq!(rows.len(), 1);
     assert_eq!(rows[0].network_type, "NAT");
@@ -103,7 +133,9 @@ fn build_network_rows_uses_instance_vm_ip() {
     )
     .unwrap();
 
-    let rows = explain::build_network_rows(&project).unwrap();
+    let rows =
+        explain::build_network_rows(&project.join(INSTANCE_DIR_NAME), &config::Config::default())
+            .unwrap();
 
     assert_eq!(rows.len(), 1);
     assert_eq!(rows[0].network_type, "NAT");
@@ -111,3 +143,94 @@ fn build_network_rows_uses_instance_vm_ip() {
     assert_eq!(rows[0].host_to_vm, "ssh: 10.1.2.3:22");
     assert_eq!(rows[0].vm_to_host, "none");
 }
+
+#[test]
+fn build_mount_rows_rejects_duplicate_guests() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = home.join("project");
+    fs::create_dir_all(&project).unwrap();
+
+    let _home_guard = EnvGuard::set("HOME", &home);
+
+    let mapped = config::Config {
+        box_cfg: config::BoxConfig {
+            mounts: vec![".:/home/vibecoder/project/".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let rows = explain::build_mount_rows(&project, &mapped).unwrap();
+    assert_eq!(rows.len(), 2, "project mount should not be injected twice");
+
+    let duplicate = config::Config {
+        box_cfg: config::BoxConfig {
+            mounts: vec!["a:/opt/data".to_string(), "b:/opt/./data".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let err = explain::build_mount_rows(&project, &duplicate).unwrap_err();
+    assert!(
+        err.to_string().contains("both target guest path /opt/data"),
+        "{err}"
+    );
+}
+
+#[test]
+fn build_mount_rows_shows_the_overlay_and_its_read_only_share() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = home.join("project");
+    fs::create_dir_all(&project).unwrap();
+
+    let _home_guard = EnvGuard::set("HOME", &home);
+
+    let cfg = config::Config {
+        box_cfg: config::BoxConfig {
+            mounts: Vec::new(),
+            project_mode: config::ProjectMode::Overlay,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let rows = explain::build_mount_rows(&project, &cfg).unwrap();
+
+    assert_eq!(rows[0].guest, "~/project");
+    assert_eq!(rows[0].mode, "overlay");
+    assert_eq!(rows[1].host, "~/project");
+    assert_eq!(rows[1].mount_point, "/usr/local/vibebox-lower/project");
+    assert_eq!(rows[1].mode, "read-only");
+}
+
+#[test]
+fn build_mount_rows_shares_the_mise_cache_read_only_under_strict() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    let temp = TempDir::new().unwrap();
+    let home = temp.path().join("home");
+    let project = home.join("project");
+    fs::create_dir_all(&project).unwrap();
+    let _home_guard = EnvGuard::set("HOME", &home);
+
+    let cfg = config::Config {
+        box_cfg: config::BoxConfig {
+            mounts: Vec::new(),
+            ..Default::default()
+        },
+        security: config::SecurityConfig {
+            profile: config::SecurityProfile::Strict,
+        },
+        ..Default::default()
+    };
+
+    let rows = explain::build_mount_rows(&project, &cfg).unwrap();
+
+    let mise = rows
+        .iter()
+        .find(|row| row.guest == "/root/.local/share/mise")
+        .unwrap();
+    assert_eq!(mise.mode, "read-only");
+    assert_eq!(rows[0].mode, "read-write");
+}